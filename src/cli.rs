@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::{
     crate_authors, crate_description, crate_version, value_parser, Arg, ArgMatches, Command,
 };
@@ -24,9 +24,10 @@ pub enum SmtType {
     Boolector,
     #[cfg(feature = "z3")]
     Z3,
+    External,
 }
 
-#[derive(Debug, PartialEq, Eq, EnumString, EnumVariantNames, IntoStaticStr)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumString, EnumVariantNames, IntoStaticStr)]
 #[strum(serialize_all = "kebab_case")]
 pub enum SatType {
     None,
@@ -36,6 +37,45 @@ pub enum SatType {
     Varisat,
     #[cfg(feature = "cadical")]
     Cadical,
+    External,
+}
+
+// Solver racing others in a portfolio, given as `smt:<SOLVER>` or
+// `sat:<SOLVER>` on the command line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PortfolioSolver {
+    Smt(SmtType),
+    Sat(SatType),
+}
+
+impl FromStr for PortfolioSolver {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("smt", name)) => match SmtType::from_str(name)? {
+                SmtType::Generic => bail!("'{}' is no SMT solver to race", s),
+                smt_type => Ok(Self::Smt(smt_type)),
+            },
+            Some(("sat", name)) => match SatType::from_str(name)? {
+                SatType::None => bail!("'{}' is no SAT solver to race", s),
+                sat_type => Ok(Self::Sat(sat_type)),
+            },
+            _ => bail!("expecting 'smt:<SOLVER>' or 'sat:<SOLVER>', got '{}'", s),
+        }
+    }
+}
+
+impl PortfolioSolver {
+    // Option giving the command of an external solver, which is required to
+    // race it.
+    pub fn command_option(&self) -> Option<&'static str> {
+        match self {
+            Self::Smt(SmtType::External) => Some("--solver-cmd"),
+            Self::Sat(SatType::External) => Some("--sat-solver-cmd"),
+            _ => None,
+        }
+    }
 }
 
 const DEFAULT_MEMORY_SIZE: &str = "1"; // 1 MiB
@@ -77,6 +117,32 @@ pub fn args() -> Command {
                         .default_value(DEFAULT_MEMORY_SIZE)
                         .value_parser(value_parser_memory_size()),
                 )
+                .arg(
+                    Arg::new("replay-file")
+                        .help("Recorded input replayed as stdin, reports the bad state reached")
+                        .short('r')
+                        .long("replay")
+                        .num_args(1)
+                        .value_name("FILE")
+                )
+                .arg(
+                    Arg::new("max-heap")
+                        .help("Number of machine-words usable as heap (checked during replay)")
+                        .long("max-heap")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MAX_HEAP)
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("max-stack")
+                        .help("Number of machine-words usable as stack (checked during replay)")
+                        .long("max-stack")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MAX_STACK)
+                        .value_parser(value_parser!(u32)),
+                )
                 .arg(
                     Arg::new("extras")
                         .help("Arguments passed to emulated program")
@@ -121,6 +187,18 @@ pub fn args() -> Command {
                         .long("dimacs")
                         .num_args(0)
                 )
+                .arg(
+                    Arg::new("aiger")
+                        .help("Output AIGER instead of BTOR2, binary if the output file ends in '.aig'")
+                        .long("aiger")
+                        .num_args(0)
+                )
+                .arg(
+                    Arg::new("smt2")
+                        .help("Output SMT-LIB2 of the unrolled model instead of BTOR2")
+                        .long("smt2")
+                        .num_args(0)
+                )
                 .arg(
                     Arg::new("emulate")
                         .help("Start emulation from created model")
@@ -188,6 +266,13 @@ pub fn args() -> Command {
                         .long("out")
                         .num_args(1)
                 )
+                .arg(
+                    Arg::new("report-file")
+                        .help("Output path for a JSON report of verdicts, model sizes and timings")
+                        .long("report")
+                        .num_args(1)
+                        .value_name("FILE")
+                )
                 .arg(
                     Arg::new("prune-model")
                         .help("Prunes sequential part from model")
@@ -205,6 +290,14 @@ pub fn args() -> Command {
                         .value_parser(value_parser_smt_type())
                         .default_value(Into::<&str>::into(SmtType::Generic)),
                 )
+                .arg(
+                    Arg::new("solver-cmd")
+                        .help("Command running an SMT-LIB solver on stdin, used with '--solver external'")
+                        .long("solver-cmd")
+                        .num_args(1)
+                        .value_name("COMMAND")
+                        .required_if_eq("smt-solver", "external"),
+                )
                 .arg(
                     Arg::new("sat-solver")
                         .help("SAT solver used for bad-state reasoning")
@@ -214,6 +307,22 @@ pub fn args() -> Command {
                         .value_parser(value_parser_sat_type())
                         .default_value(Into::<&str>::into(SatType::None)),
                 )
+                .arg(
+                    Arg::new("sat-solver-cmd")
+                        .help("Command running a SAT solver on a DIMACS file, used with '--sat-solver external'")
+                        .long("sat-solver-cmd")
+                        .num_args(1)
+                        .value_name("COMMAND")
+                        .required_if_eq("sat-solver", "external"),
+                )
+                .arg(
+                    Arg::new("sat-timeout")
+                        .help("Timeout for each SAT solver query in milliseconds")
+                        .long("sat-timeout")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .value_parser(value_parser!(u64)),
+                )
                 .arg(
                     Arg::new("solver-timeout")
                         .help("Timeout for each SMT solver query in milliseconds")
@@ -223,9 +332,27 @@ pub fn args() -> Command {
                         .value_name("NUMBER")
                         .value_parser(value_parser!(u64)),
                 )
+                .arg(
+                    Arg::new("jobs")
+                        .help("Number of threads deciding bad states in parallel")
+                        .short('j')
+                        .long("jobs")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value("1")
+                        .value_parser(value_parser!(u64).range(1..)),
+                )
+                .arg(
+                    Arg::new("portfolio")
+                        .help("Solvers racing on each bad state, e.g. 'smt:z3,sat:cadical'")
+                        .long("portfolio")
+                        .num_args(1)
+                        .value_name("SOLVERS")
+                        .value_delimiter(','),
+                )
                 .arg(
                     Arg::new("terminate-on-bad")
-                        .help("Terminate once bad state becomes satisfiable")
+                        .help("Terminate once bad state becomes satisfiable, exiting with code 10")
                         .long("terminate-on-bad")
                         .num_args(0)
                 )
@@ -235,6 +362,21 @@ pub fn args() -> Command {
                         .long("one-query")
                         .num_args(0)
                 )
+                .arg(
+                    Arg::new("witness-file")
+                        .help("Output path for the stdin input reaching a satisfiable bad state")
+                        .short('w')
+                        .long("witness")
+                        .num_args(1)
+                        .value_name("FILE")
+                )
+                .arg(
+                    Arg::new("btor2-witness-file")
+                        .help("Output path for a BTOR2 witness of a satisfiable bad state")
+                        .long("btor2-witness")
+                        .num_args(1)
+                        .value_name("FILE")
+                )
                 .arg(
                     Arg::new("unroll-model")
                         .help("Number of instructions to unroll from model")
@@ -260,6 +402,346 @@ pub fn args() -> Command {
                         .num_args(1..)
                 )
         )
+        .subcommand(
+            Command::new("simulate")
+                .about("Simulate a BTOR2 model for a RISC-V ELF binary step by step")
+                .arg(
+                    Arg::new("input-file")
+                        .help("RISC-V ELF binary (or BTOR2 file) to be simulated")
+                        .num_args(1)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("from-btor2")
+                        .help("Pass this flag if the input file is a BTOR2 file.")
+                        .short('f')
+                        .long("from-btor2")
+                        .num_args(0)
+                )
+                .arg(
+                    Arg::new("steps")
+                        .help("Number of steps to simulate")
+                        .short('n')
+                        .long("steps")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value("1")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("inputs")
+                        .help("Concrete values consumed by inputs in the order they are read")
+                        .short('i')
+                        .long("inputs")
+                        .num_args(1)
+                )
+                .arg(
+                    Arg::new("witness-file")
+                        .help("BTOR2 witness to be checked against the model")
+                        .short('w')
+                        .long("witness")
+                        .num_args(1)
+                        .value_name("FILE")
+                )
+                .arg(
+                    Arg::new("max-heap")
+                        .help("Number of machine-words usable as heap")
+                        .long("max-heap")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MAX_HEAP)
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("max-stack")
+                        .help("Number of machine-words usable as stack")
+                        .long("max-stack")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MAX_STACK)
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("memory")
+                        .help("Total size of memory in MiB [possible: 1 .. 1024]")
+                        .long("memory")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MEMORY_SIZE)
+                        .value_parser(value_parser_memory_size()),
+                )
+                .arg(
+                    Arg::new("extras")
+                        .help("Arguments passed to emulated program")
+                        .value_name("ARGUMENTS")
+                        .last(true)
+                        .allow_hyphen_values(true)
+                        .num_args(1..)
+                )
+        )
+        .subcommand(
+            Command::new("bmc")
+                .about("Check a model for reachable bad states by iterative deepening")
+                .arg(
+                    Arg::new("input-file")
+                        .help("RISC-V ELF binary (or BTOR2 file) to be checked")
+                        .num_args(1)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("from-btor2")
+                        .help("Pass this flag if the input file is a BTOR2 file.")
+                        .short('f')
+                        .long("from-btor2")
+                        .num_args(0)
+                )
+                .arg(
+                    Arg::new("max-depth")
+                        .help("Maximum number of steps to unroll the model")
+                        .short('n')
+                        .long("max-depth")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value("100")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("time-budget")
+                        .help("Stop deepening once this many seconds have passed")
+                        .long("time-budget")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("all-kinds")
+                        .help("Keep deepening until every kind of bad state is reachable")
+                        .long("all-kinds")
+                        .num_args(0)
+                )
+                .arg(
+                    Arg::new("induction")
+                        .help("Prove bad states unreachable at every depth by k-induction")
+                        .long("induction")
+                        .num_args(0)
+                )
+                .arg(
+                    Arg::new("discretize-memory")
+                        .help("Discretize memory (don't use array logic)")
+                        .long("discretize-memory")
+                        .num_args(0)
+                )
+                .arg(
+                    Arg::new("smt-solver")
+                        .help("SMT solver used to decide bad states")
+                        .short('s')
+                        .long("solver")
+                        .num_args(1)
+                        .value_name("SOLVER")
+                        .value_parser(value_parser_smt_type())
+                        .default_value(Into::<&str>::into(SmtType::Generic)),
+                )
+                .arg(
+                    Arg::new("solver-cmd")
+                        .help("Command running an SMT-LIB solver on stdin, used with '--solver external'")
+                        .long("solver-cmd")
+                        .num_args(1)
+                        .value_name("COMMAND")
+                        .required_if_eq("smt-solver", "external"),
+                )
+                .arg(
+                    Arg::new("solver-timeout")
+                        .help("Timeout for each SMT solver query in milliseconds")
+                        .short('t')
+                        .long("timeout")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("sat-solver")
+                        .help("SAT solver used to decide bitblasted bad states instead")
+                        .long("sat-solver")
+                        .num_args(1)
+                        .value_name("SOLVER")
+                        .value_parser(value_parser_sat_type())
+                        .default_value(Into::<&str>::into(SatType::None)),
+                )
+                .arg(
+                    Arg::new("sat-solver-cmd")
+                        .help("Command running a SAT solver on a DIMACS file, used with '--sat-solver external'")
+                        .long("sat-solver-cmd")
+                        .num_args(1)
+                        .value_name("COMMAND")
+                        .required_if_eq("sat-solver", "external"),
+                )
+                .arg(
+                    Arg::new("sat-timeout")
+                        .help("Timeout for each SAT solver query in milliseconds")
+                        .long("sat-timeout")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("witness-file")
+                        .help("Output path for the stdin input reaching the first reachable bad state")
+                        .short('w')
+                        .long("witness")
+                        .num_args(1)
+                        .value_name("FILE")
+                )
+                .arg(
+                    Arg::new("max-heap")
+                        .help("Number of machine-words usable as heap")
+                        .long("max-heap")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MAX_HEAP)
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("max-stack")
+                        .help("Number of machine-words usable as stack")
+                        .long("max-stack")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MAX_STACK)
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("memory")
+                        .help("Total size of memory in MiB [possible: 1 .. 1024]")
+                        .long("memory")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MEMORY_SIZE)
+                        .value_parser(value_parser_memory_size()),
+                )
+                .arg(
+                    Arg::new("extras")
+                        .help("Arguments passed to emulated program")
+                        .value_name("ARGUMENTS")
+                        .last(true)
+                        .allow_hyphen_values(true)
+                        .num_args(1..)
+                )
+        )
+        .subcommand(
+            Command::new("prove")
+                .about("Prove bad states unreachable by IC3 on the bitblasted model")
+                .arg(
+                    Arg::new("input-file")
+                        .help("RISC-V ELF binary (or BTOR2 file) to be checked")
+                        .num_args(1)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("from-btor2")
+                        .help("Pass this flag if the input file is a BTOR2 file.")
+                        .short('f')
+                        .long("from-btor2")
+                        .num_args(0)
+                )
+                .arg(
+                    Arg::new("sat-solver")
+                        .help("SAT solver used as oracle (cadical or varisat)")
+                        .long("sat-solver")
+                        .num_args(1)
+                        .value_name("SOLVER")
+                        .value_parser(value_parser_sat_type())
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("max-frames")
+                        .help("Maximum number of frames before giving up")
+                        .long("max-frames")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value("100")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("time-budget")
+                        .help("Give up once this many seconds have passed")
+                        .long("time-budget")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("witness-file")
+                        .help("Output path for the stdin input reaching the first reachable bad state")
+                        .short('w')
+                        .long("witness")
+                        .num_args(1)
+                        .value_name("FILE")
+                )
+                .arg(
+                    Arg::new("max-heap")
+                        .help("Number of machine-words usable as heap")
+                        .long("max-heap")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MAX_HEAP)
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("max-stack")
+                        .help("Number of machine-words usable as stack")
+                        .long("max-stack")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MAX_STACK)
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("memory")
+                        .help("Total size of memory in MiB [possible: 1 .. 1024]")
+                        .long("memory")
+                        .num_args(1)
+                        .value_name("NUMBER")
+                        .default_value(DEFAULT_MEMORY_SIZE)
+                        .value_parser(value_parser_memory_size()),
+                )
+                .arg(
+                    Arg::new("extras")
+                        .help("Arguments passed to emulated program")
+                        .value_name("ARGUMENTS")
+                        .last(true)
+                        .allow_hyphen_values(true)
+                        .num_args(1..)
+                )
+        )
+        .subcommand(
+            Command::new("decode-sat")
+                .about("Decode the model of a SAT solver into the inputs of a bitblasted model")
+                .arg(
+                    Arg::new("cnf-file")
+                        .help("DIMACS file written by 'beator --dimacs'")
+                        .num_args(1)
+                        .value_name("CNF_FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("solution-file")
+                        .help("Output of the SAT solver holding 'v' lines")
+                        .num_args(1)
+                        .value_name("SAT_FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("witness-file")
+                        .help("Output path for the decoded stdin input")
+                        .short('w')
+                        .long("witness")
+                        .num_args(1)
+                        .value_name("FILE")
+                )
+        )
         .subcommand(
             Command::new("qubot")
                 .about("Create a QUBO model for a RISC-V ELF binary")
@@ -284,6 +766,13 @@ pub fn args() -> Command {
                         .long("out")
                         .num_args(1)
                 )
+                .arg(
+                    Arg::new("report-file")
+                        .help("Output path for a JSON report of verdicts, model sizes and timings")
+                        .long("report")
+                        .num_args(1)
+                        .value_name("FILE")
+                )
                 .arg(
                     Arg::new("max-heap")
                         .help("Number of machine-words usable as heap")
@@ -328,6 +817,14 @@ pub fn args() -> Command {
                         .value_parser(value_parser_smt_type())
                         .default_value(Into::<&str>::into(SmtType::Generic)),
                 )
+                .arg(
+                    Arg::new("solver-cmd")
+                        .help("Command running an SMT-LIB solver on stdin, used with '--solver external'")
+                        .long("solver-cmd")
+                        .num_args(1)
+                        .value_name("COMMAND")
+                        .required_if_eq("smt-solver", "external"),
+                )
                 .arg(
                     Arg::new("solver-timeout")
                         .help("Timeout for each SMT solver query in milliseconds")
@@ -359,6 +856,13 @@ pub fn args() -> Command {
                         .long("from-dimacs")
                         .num_args(0)
                 )
+                .arg(
+                    Arg::new("from-aiger")
+                        .help("Consume combinational AIGER instead of RISC-V inputs")
+                        .long("from-aiger")
+                        .num_args(0)
+                        .conflicts_with("from-dimacs")
+                )
                 .arg(
                     Arg::new("extras")
                         .help("Arguments passed to emulated program")
@@ -519,4 +1023,13 @@ mod tests {
             "Input file can be declared before flags"
         );
     }
+
+    #[test]
+    fn test_portfolio_external_solvers_need_command() {
+        let smt = PortfolioSolver::from_str("smt:external").unwrap();
+        let sat = PortfolioSolver::from_str("sat:external").unwrap();
+
+        assert_eq!(smt.command_option(), Some("--solver-cmd"));
+        assert_eq!(sat.command_option(), Some("--sat-solver-cmd"));
+    }
 }
@@ -1,9 +1,10 @@
 //! # Disassemble RISC-V instructions
 
 use anyhow::Result;
+use byteorder::{ByteOrder, LittleEndian};
 use log::info;
 use riscu::{
-    load_object_file,
+    decode, instruction_length, load_object_file,
     types::{BType, IType, JType, RType, SType, UType},
     DecodedProgram, Instruction, Program, RiscuError,
 };
@@ -49,12 +50,51 @@ impl From<Disassembly> for DecodedProgram {
     }
 }
 
+/// Iterates over the raw instructions of a code segment starting at
+/// `address`, yielding the address, length in bytes and encoding of each
+/// instruction. Compressed instructions (RV64C) are 2 bytes long and only
+/// occupy the lower half of the yielded encoding.
+pub fn raw_instructions(address: u64, code: &[u8]) -> RawInstructions<'_> {
+    RawInstructions {
+        address,
+        code,
+        offset: 0,
+    }
+}
+
+pub struct RawInstructions<'a> {
+    address: u64,
+    code: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for RawInstructions<'a> {
+    type Item = (u64, usize, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = &self.code[self.offset..];
+        if remaining.len() < size_of::<u16>() {
+            return None;
+        }
+        let length = instruction_length(LittleEndian::read_u16(remaining));
+        let raw = match length {
+            2 => LittleEndian::read_u16(remaining) as u32,
+            _ if remaining.len() >= size_of::<u32>() => LittleEndian::read_u32(remaining),
+            _ => return None,
+        };
+        let pc = self.address + self.offset as u64;
+        self.offset += length;
+        Some((pc, length, raw))
+    }
+}
+
 impl fmt::Display for Disassembly {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.decoded
-            .iter_locations()
-            .zip(self.decoded.iter_instructions())
-            .try_for_each(|(pc, i)| print_instruction(f, pc, i))
+        raw_instructions(self.decoded.code.address, &self.decoded.code.content)
+            .try_for_each(|(pc, length, raw)| match decode(raw) {
+                Ok(i) => print_instruction(f, pc, i),
+                Err(_) => print_unknown(f, pc, length, raw),
+            })
             .and_then(|_| {
                 enumerate_with(
                     self.decoded.data.content.as_slice().iter(),
@@ -145,6 +185,10 @@ fn print_instruction(f: &mut fmt::Formatter<'_>, pc: u64, i: Instruction) -> fmt
     }
 }
 
+fn print_unknown(f: &mut fmt::Formatter<'_>, pc: u64, length: usize, raw: u32) -> fmt::Result {
+    writeln!(f, "{:#x}: .{}byte {:#x}", pc, length, raw)
+}
+
 fn print_rtype(f: &mut fmt::Formatter<'_>, pc: u64, op: &str, rtype: RType) -> fmt::Result {
     writeln!(
         f,
@@ -212,3 +256,89 @@ fn print_shift(f: &mut fmt::Formatter<'_>, pc: u64, op: &str, itype: IType) -> f
         itype.imm() & 0x3f
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use riscu::Register;
+
+    const C_ADDI_A0_1: u16 = 0x0505; // c.addi a0,1
+    const C_LI_A0_M1: u16 = 0x557d; // c.li a0,-1
+    const C_MV_A0_A1: u16 = 0x852e; // c.mv a0,a1
+    const C_JR_RA: u16 = 0x8082; // c.jr ra
+    const C_ILLEGAL: u16 = 0x0000; // defined to be illegal
+    const ADDI_A0_A0_1: u32 = 0x0015_0513; // addi a0,a0,1
+
+    struct Unknown(u64, usize, u32);
+
+    impl fmt::Display for Unknown {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            print_unknown(f, self.0, self.1, self.2)
+        }
+    }
+
+    fn itype_of(raw: u16) -> IType {
+        match decode(raw as u32).expect("valid instruction") {
+            Instruction::Addi(itype) | Instruction::Jalr(itype) => itype,
+            i => panic!("unexpected instruction {:?}", i),
+        }
+    }
+
+    #[test]
+    fn raw_instructions_split_compressed_and_regular() {
+        let mut code = Vec::new();
+        code.extend_from_slice(&C_ADDI_A0_1.to_le_bytes());
+        code.extend_from_slice(&ADDI_A0_A0_1.to_le_bytes());
+        code.extend_from_slice(&C_ILLEGAL.to_le_bytes());
+        // A truncated regular instruction at the end is not yielded.
+        code.extend_from_slice(&(ADDI_A0_A0_1 as u16).to_le_bytes());
+
+        let raw: Vec<_> = raw_instructions(0x1000, &code).collect();
+        assert_eq!(
+            raw,
+            vec![
+                (0x1000, 2, C_ADDI_A0_1 as u32),
+                (0x1002, 4, ADDI_A0_A0_1),
+                (0x1006, 2, C_ILLEGAL as u32),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_compressed_instructions() {
+        let addi = itype_of(C_ADDI_A0_1);
+        assert_eq!(
+            (addi.rd(), addi.rs1(), addi.imm()),
+            (Register::A0, Register::A0, 1)
+        );
+
+        let li = itype_of(C_LI_A0_M1);
+        assert_eq!(
+            (li.rd(), li.rs1(), li.imm()),
+            (Register::A0, Register::Zero, -1)
+        );
+
+        match decode(C_MV_A0_A1 as u32).expect("valid instruction") {
+            Instruction::Add(rtype) => assert_eq!(
+                (rtype.rd(), rtype.rs1(), rtype.rs2()),
+                (Register::A0, Register::Zero, Register::A1)
+            ),
+            i => panic!("unexpected instruction {:?}", i),
+        }
+
+        let jr = itype_of(C_JR_RA);
+        assert_eq!(
+            (jr.rd(), jr.rs1(), jr.imm()),
+            (Register::Zero, Register::Ra, 0)
+        );
+    }
+
+    #[test]
+    fn illegal_compressed_instructions_are_printed_raw() {
+        assert!(decode(C_ILLEGAL as u32).is_err());
+        assert_eq!(
+            Unknown(0x1006, 2, C_ILLEGAL as u32).to_string(),
+            "0x1006: .2byte 0x0\n"
+        );
+    }
+}
@@ -5,8 +5,10 @@ use log::{debug, info, trace, warn};
 use riscu::{instruction_length, types::*, Instruction, Program, Register};
 use std::cmp::min;
 use std::fs::File;
-use std::io::{self, Read, Stdin, Stdout, Write};
+use std::io::{self, Cursor, Read, Stdin, Stdout, Write};
 use std::mem::size_of;
+use std::ops::Range;
+use strum::IntoStaticStr;
 
 //
 // Public Interface
@@ -14,16 +16,35 @@ use std::mem::size_of;
 
 pub type EmulatorValue = u64;
 
+// The bad states checked by the model builder, named the same way. They
+// are detected during concrete execution to confirm counterexamples.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, IntoStaticStr)]
+#[strum(serialize_all = "kebab_case")]
+pub enum BadState {
+    MemoryAccessBelowData,
+    MemoryAccessBetweenDataAndHeap,
+    MemoryAccessBetweenMaxAndDynHeap,
+    MemoryAccessBetweenHeapAndStack,
+    MemoryAccessBetweenDynAndMaxStack,
+    MemoryAccessAboveStack,
+    DivisionByZero,
+    RemainderByZero,
+    NonZeroExitCode,
+}
+
 #[derive(Debug)]
 pub struct EmulatorState {
     registers: Vec<EmulatorValue>,
     memory: Vec<u8>,
     program_counter: EmulatorValue,
     program_break: EmulatorValue,
+    data_range: Range<EmulatorValue>,
     opened: Vec<File>,
     running: bool,
     stdin: Stdin,
     stdout: Stdout,
+    replay: Option<Replay>,
+    bad_state: Option<BadState>,
 }
 
 impl EmulatorState {
@@ -33,10 +54,13 @@ impl EmulatorState {
             memory: vec![0; memory_size],
             program_counter: 0,
             program_break: 0,
+            data_range: 0..0,
             opened: Vec::new(),
             running: false,
             stdin: io::stdin(),
             stdout: io::stdout(),
+            replay: None,
+            bad_state: None,
         }
     }
 
@@ -46,6 +70,7 @@ impl EmulatorState {
         self.set_reg(Register::Sp, self.memory.len() as u64);
         self.program_counter = initial_program_counter(program);
         self.program_break = initial_program_break(program);
+        self.data_range = initial_data_range(program);
         self.load_code_segment(program);
         self.load_data_segment(program);
         self.load_stack_segment(argv);
@@ -58,6 +83,18 @@ impl EmulatorState {
         self.load_code_segment(program);
     }
 
+    // Switches the emulator into replay mode, where the recorded `input`
+    // is read in place of the host `stdin`. Memory accesses are checked
+    // against the segments the model builder assumes, which depend on
+    // its `max_heap_size` and `max_stack_size` (both in bytes).
+    pub fn replay(&mut self, input: Vec<u8>, max_heap_size: u64, max_stack_size: u64) {
+        self.replay = Some(Replay {
+            input: Cursor::new(input),
+            max_heap_size,
+            max_stack_size,
+        });
+    }
+
     // Start emulation.
     pub fn run(&mut self) {
         self.running = true;
@@ -67,6 +104,11 @@ impl EmulatorState {
             execute(self, decoded);
         }
     }
+
+    // The bad state that stopped emulation, if any.
+    pub fn bad_state(&self) -> Option<BadState> {
+        self.bad_state
+    }
 }
 
 //
@@ -78,6 +120,13 @@ const WORD_SIZE_MASK: u64 = riscu::WORD_SIZE as u64 - 1;
 const MAX_FILENAME_LENGTH: usize = 128;
 const FIRST_REAL_FD: usize = 3;
 
+#[derive(Debug)]
+struct Replay {
+    input: Cursor<Vec<u8>>,
+    max_heap_size: u64,
+    max_stack_size: u64,
+}
+
 fn initial_program_counter(program: &Program) -> EmulatorValue {
     program.instruction_range.start
 }
@@ -88,6 +137,13 @@ fn initial_program_break(program: &Program) -> EmulatorValue {
     next_multiple_of(data_end, PAGE_SIZE as u64)
 }
 
+fn initial_data_range(program: &Program) -> Range<EmulatorValue> {
+    let data_size = program.data.content.len();
+    let data_start = program.data.address & !WORD_SIZE_MASK;
+    let data_end = program.data.address + data_size as u64;
+    data_start..next_multiple_of(data_end, riscu::WORD_SIZE as u64)
+}
+
 impl EmulatorState {
     fn pc_add(&mut self, imm: u64) {
         self.program_counter = self.program_counter.wrapping_add(imm);
@@ -198,7 +254,10 @@ impl EmulatorState {
 
     fn fd_read(&mut self, fd: EmulatorValue) -> &mut dyn Read {
         match fd {
-            0 => &mut self.stdin,
+            0 => match &mut self.replay {
+                Some(replay) => &mut replay.input,
+                None => &mut self.stdin,
+            },
             1 => panic!("reading from `stdout` is a bad idea"),
             2 => panic!("reading from `stderr` is a bad idea"),
             _ => &mut self.opened[fd as usize - FIRST_REAL_FD],
@@ -213,11 +272,74 @@ impl EmulatorState {
             _ => &mut self.opened[fd as usize - FIRST_REAL_FD],
         }
     }
+
+    fn reach_bad_state(&mut self, bad_state: BadState) {
+        info!(
+            "reached bad state '{}' at pc={:#x}",
+            Into::<&str>::into(bad_state),
+            self.program_counter
+        );
+        self.bad_state = Some(bad_state);
+        self.running = false;
+    }
+
+    // Division and remainder by zero stop emulation in both modes. Only
+    // replay mode expects bad states, otherwise the program is faulty.
+    fn reach_zero_divisor(&mut self, bad_state: BadState) {
+        if self.replay.is_none() {
+            warn!(
+                "{} at pc={:#x}, stopping emulation",
+                Into::<&str>::into(bad_state),
+                self.program_counter
+            );
+        }
+        self.reach_bad_state(bad_state);
+    }
+
+    // Checks the accessed word at `adr` the same way the model builder
+    // checks for segmentation faults. This is only done in replay mode,
+    // otherwise the maximum heap and stack sizes are unknown.
+    fn check_access(&mut self, adr: EmulatorValue) -> bool {
+        let (max_heap_size, max_stack_size) = match &self.replay {
+            Some(replay) => (replay.max_heap_size, replay.max_stack_size),
+            None => return true,
+        };
+        let word = adr & !WORD_SIZE_MASK;
+        let heap_start = next_multiple_of(self.data_range.end, PAGE_SIZE as u64);
+        let heap_max_end = heap_start + max_heap_size;
+        let stack_end = self.memory.len() as u64;
+        let stack_max_start = stack_end - max_stack_size;
+        let sp = self.get_reg(Register::Sp);
+        let bad_state = if word < self.data_range.start {
+            BadState::MemoryAccessBelowData
+        } else if word >= self.data_range.end && word < heap_start {
+            BadState::MemoryAccessBetweenDataAndHeap
+        } else if word >= heap_max_end && word < self.program_break {
+            BadState::MemoryAccessBetweenMaxAndDynHeap
+        } else if word >= self.program_break && word < sp {
+            BadState::MemoryAccessBetweenHeapAndStack
+        } else if word >= sp && word < stack_max_start {
+            BadState::MemoryAccessBetweenDynAndMaxStack
+        } else if word >= stack_end {
+            BadState::MemoryAccessAboveStack
+        } else {
+            return true;
+        };
+        self.reach_bad_state(bad_state);
+        false
+    }
 }
 
+// Fetches the instruction at the current program counter. Compressed
+// instructions (RV64C) only occupy two bytes, we avoid reading beyond
+// them so that the upper half of the result is always zero.
 fn fetch(state: &mut EmulatorState) -> u32 {
     assert!(state.program_counter & INSTRUCTION_SIZE_MASK == 0);
-    state.get_mem_unaligned::<u32>(state.program_counter)
+    if state.current_instruction_lenght() == size_of::<u16>() {
+        state.get_mem_typed::<u16>(state.program_counter) as u32
+    } else {
+        state.get_mem_unaligned::<u32>(state.program_counter)
+    }
 }
 
 fn decode(instruction_half_word: u32) -> Instruction {
@@ -242,11 +364,13 @@ fn execute(state: &mut EmulatorState, instr: Instruction) {
         Instruction::Ld(itype) => exec_ld(state, itype),
         Instruction::Lbu(itype) => exec_lbu(state, itype),
         Instruction::Lhu(itype) => exec_lhu(state, itype),
+        Instruction::Lwu(itype) => exec_lwu(state, itype),
         Instruction::Sb(stype) => exec_sb(state, stype),
         Instruction::Sh(stype) => exec_sh(state, stype),
         Instruction::Sw(stype) => exec_sw(state, stype),
         Instruction::Sd(stype) => exec_sd(state, stype),
         Instruction::Addi(itype) => exec_addi(state, itype),
+        Instruction::Slti(itype) => exec_slti(state, itype),
         Instruction::Sltiu(itype) => exec_sltiu(state, itype),
         Instruction::Xori(itype) => exec_xori(state, itype),
         Instruction::Ori(itype) => exec_ori(state, itype),
@@ -263,11 +387,15 @@ fn execute(state: &mut EmulatorState, instr: Instruction) {
         Instruction::Sll(rtype) => exec_sll(state, rtype),
         Instruction::Slt(rtype) => exec_slt(state, rtype),
         Instruction::Sltu(rtype) => exec_sltu(state, rtype),
+        Instruction::Xor(rtype) => exec_xor(state, rtype),
         Instruction::Srl(rtype) => exec_srl(state, rtype),
         Instruction::Sra(rtype) => exec_sra(state, rtype),
         Instruction::Or(rtype) => exec_or(state, rtype),
         Instruction::And(rtype) => exec_and(state, rtype),
         Instruction::Mul(rtype) => exec_mul(state, rtype),
+        Instruction::Mulh(rtype) => exec_mulh(state, rtype),
+        Instruction::Mulhsu(rtype) => exec_mulhsu(state, rtype),
+        Instruction::Mulhu(rtype) => exec_mulhu(state, rtype),
         Instruction::Div(rtype) => exec_div(state, rtype),
         Instruction::Divu(rtype) => exec_divu(state, rtype),
         Instruction::Rem(rtype) => exec_rem(state, rtype),
@@ -275,9 +403,13 @@ fn execute(state: &mut EmulatorState, instr: Instruction) {
         Instruction::Addw(rtype) => exec_addw(state, rtype),
         Instruction::Subw(rtype) => exec_subw(state, rtype),
         Instruction::Sllw(rtype) => exec_sllw(state, rtype),
+        Instruction::Srlw(rtype) => exec_srlw(state, rtype),
+        Instruction::Sraw(rtype) => exec_sraw(state, rtype),
         Instruction::Mulw(rtype) => exec_mulw(state, rtype),
         Instruction::Divw(rtype) => exec_divw(state, rtype),
+        Instruction::Divuw(rtype) => exec_divuw(state, rtype),
         Instruction::Remw(rtype) => exec_remw(state, rtype),
+        Instruction::Remuw(rtype) => exec_remuw(state, rtype),
         Instruction::Ecall(_itype) => exec_ecall(state),
         // TODO: Cover all needed instructions here.
         _ => unimplemented!("not implemented: {:?}", instr),
@@ -431,6 +563,9 @@ fn exec_bgeu(state: &mut EmulatorState, btype: BType) {
 fn exec_lb(state: &mut EmulatorState, itype: IType) {
     let rs1_value = state.get_reg(itype.rs1());
     let address = rs1_value.wrapping_add(itype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     let rd_value = state.get_mem_typed::<i8>(address) as u64;
     trace_itype(state, "lb", itype, rd_value);
     state.set_reg(itype.rd(), rd_value);
@@ -442,6 +577,9 @@ fn exec_lb(state: &mut EmulatorState, itype: IType) {
 fn exec_lbu(state: &mut EmulatorState, itype: IType) {
     let rs1_value = state.get_reg(itype.rs1());
     let address = rs1_value.wrapping_add(itype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     let rd_value = state.get_mem_typed::<u8>(address) as u64;
     trace_itype(state, "lbu", itype, rd_value);
     state.set_reg(itype.rd(), rd_value);
@@ -453,6 +591,9 @@ fn exec_lbu(state: &mut EmulatorState, itype: IType) {
 fn exec_lh(state: &mut EmulatorState, itype: IType) {
     let rs1_value = state.get_reg(itype.rs1());
     let address = rs1_value.wrapping_add(itype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     let rd_value = state.get_mem_typed::<i16>(address) as u64;
     trace_itype(state, "lh", itype, rd_value);
     state.set_reg(itype.rd(), rd_value);
@@ -464,6 +605,9 @@ fn exec_lh(state: &mut EmulatorState, itype: IType) {
 fn exec_lhu(state: &mut EmulatorState, itype: IType) {
     let rs1_value = state.get_reg(itype.rs1());
     let address = rs1_value.wrapping_add(itype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     let rd_value = state.get_mem_typed::<u16>(address) as u64;
     trace_itype(state, "lhu", itype, rd_value);
     state.set_reg(itype.rd(), rd_value);
@@ -475,17 +619,37 @@ fn exec_lhu(state: &mut EmulatorState, itype: IType) {
 fn exec_lw(state: &mut EmulatorState, itype: IType) {
     let rs1_value = state.get_reg(itype.rs1());
     let address = rs1_value.wrapping_add(itype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     let rd_value = state.get_mem_typed::<i32>(address) as u64;
     trace_itype(state, "lw", itype, rd_value);
     state.set_reg(itype.rd(), rd_value);
     state.pc_next();
 }
 
+// rd = z64(mem32[rs1 + s64(imm{12})])
+// pc = pc + instruction_length
+fn exec_lwu(state: &mut EmulatorState, itype: IType) {
+    let rs1_value = state.get_reg(itype.rs1());
+    let address = rs1_value.wrapping_add(itype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
+    let rd_value = state.get_mem_typed::<u32>(address) as u64;
+    trace_itype(state, "lwu", itype, rd_value);
+    state.set_reg(itype.rd(), rd_value);
+    state.pc_next();
+}
+
 // rd = mem[rs1 + s64(imm{12})]
 // pc = pc + instruction_length
 fn exec_ld(state: &mut EmulatorState, itype: IType) {
     let rs1_value = state.get_reg(itype.rs1());
     let address = rs1_value.wrapping_add(itype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     let rd_value = state.get_mem(address);
     trace_itype(state, "ld", itype, rd_value);
     state.set_reg(itype.rd(), rd_value);
@@ -498,6 +662,9 @@ fn exec_sb(state: &mut EmulatorState, stype: SType) {
     let rs1_value = state.get_reg(stype.rs1());
     let rs2_value = state.get_reg(stype.rs2());
     let address = rs1_value.wrapping_add(stype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     trace_stype(state, "sb", stype, address);
     state.set_mem_typed::<u8>(address, rs2_value as u8);
     state.pc_next();
@@ -509,6 +676,9 @@ fn exec_sh(state: &mut EmulatorState, stype: SType) {
     let rs1_value = state.get_reg(stype.rs1());
     let rs2_value = state.get_reg(stype.rs2());
     let address = rs1_value.wrapping_add(stype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     trace_stype(state, "sh", stype, address);
     state.set_mem_typed::<u16>(address, rs2_value as u16);
     state.pc_next();
@@ -520,6 +690,9 @@ fn exec_sw(state: &mut EmulatorState, stype: SType) {
     let rs1_value = state.get_reg(stype.rs1());
     let rs2_value = state.get_reg(stype.rs2());
     let address = rs1_value.wrapping_add(stype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     trace_stype(state, "sw", stype, address);
     state.set_mem_typed::<u32>(address, rs2_value as u32);
     state.pc_next();
@@ -531,6 +704,9 @@ fn exec_sd(state: &mut EmulatorState, stype: SType) {
     let rs1_value = state.get_reg(stype.rs1());
     let rs2_value = state.get_reg(stype.rs2());
     let address = rs1_value.wrapping_add(stype.imm() as u64);
+    if !state.check_access(address) {
+        return;
+    }
     trace_stype(state, "sd", stype, address);
     state.set_mem(address, rs2_value);
     state.pc_next();
@@ -556,6 +732,18 @@ fn exec_addiw(state: &mut EmulatorState, itype: IType) {
     state.pc_next();
 }
 
+// rd = 1                     ||| if (rs1 <s s64(imm{12}))
+// rd = 0                     ||| otherwise
+// pc = pc + instruction_length
+fn exec_slti(state: &mut EmulatorState, itype: IType) {
+    let rs1_value = state.get_reg(itype.rs1());
+    let condition = (rs1_value as i64) < (itype.imm() as i64);
+    let rd_value = EmulatorValue::from(condition);
+    trace_itype(state, "slti", itype, rd_value);
+    state.set_reg(itype.rd(), rd_value);
+    state.pc_next();
+}
+
 // rd = 1                     ||| if (rs1 <u s64(imm{12}))
 // rd = 0                     ||| otherwise
 // pc = pc + instruction_length
@@ -735,6 +923,17 @@ fn exec_srl(state: &mut EmulatorState, rtype: RType) {
     state.pc_next();
 }
 
+// rd = s64(rs1{32} >>u z32(rs2{5}))
+// pc = pc + instruction_length
+fn exec_srlw(state: &mut EmulatorState, rtype: RType) {
+    let rs1_value = state.get_reg(rtype.rs1());
+    let rs2_value = state.get_reg(rtype.rs2());
+    let rd_value = (rs1_value as u32).wrapping_shr(rs2_value as u32) as i32 as u64;
+    trace_rtype(state, "srlw", rtype, rd_value);
+    state.set_reg(rtype.rd(), rd_value);
+    state.pc_next();
+}
+
 // rd = rs1 >>s z32(rs2{6})
 // pc = pc + instruction_length
 fn exec_sra(state: &mut EmulatorState, rtype: RType) {
@@ -746,6 +945,17 @@ fn exec_sra(state: &mut EmulatorState, rtype: RType) {
     state.pc_next();
 }
 
+// rd = s64(rs1{32} >>s z32(rs2{5}))
+// pc = pc + instruction_length
+fn exec_sraw(state: &mut EmulatorState, rtype: RType) {
+    let rs1_value = state.get_reg(rtype.rs1());
+    let rs2_value = state.get_reg(rtype.rs2());
+    let rd_value = (rs1_value as i32).wrapping_shr(rs2_value as u32) as u64;
+    trace_rtype(state, "sraw", rtype, rd_value);
+    state.set_reg(rtype.rd(), rd_value);
+    state.pc_next();
+}
+
 // rd = 1                     ||| if (rs1 <s rs2)
 // rd = 0                     ||| otherwise
 // pc = pc + 4
@@ -772,6 +982,17 @@ fn exec_sltu(state: &mut EmulatorState, rtype: RType) {
     state.pc_next();
 }
 
+// rd = rs1 ^ rs2
+// pc = pc + instruction_length
+fn exec_xor(state: &mut EmulatorState, rtype: RType) {
+    let rs1_value = state.get_reg(rtype.rs1());
+    let rs2_value = state.get_reg(rtype.rs2());
+    let rd_value = rs1_value ^ rs2_value;
+    trace_rtype(state, "xor", rtype, rd_value);
+    state.set_reg(rtype.rd(), rd_value);
+    state.pc_next();
+}
+
 // rd = rs1 | rs2
 // pc = pc + instruction_length
 fn exec_or(state: &mut EmulatorState, rtype: RType) {
@@ -805,6 +1026,42 @@ fn exec_mul(state: &mut EmulatorState, rtype: RType) {
     state.pc_next();
 }
 
+// rd = (s128(rs1) * s128(rs2)) >> 64
+// pc = pc + instruction_length
+fn exec_mulh(state: &mut EmulatorState, rtype: RType) {
+    let rs1_value = state.get_reg(rtype.rs1());
+    let rs2_value = state.get_reg(rtype.rs2());
+    let product = (rs1_value as i64 as i128) * (rs2_value as i64 as i128);
+    let rd_value = (product >> 64) as u64;
+    trace_rtype(state, "mulh", rtype, rd_value);
+    state.set_reg(rtype.rd(), rd_value);
+    state.pc_next();
+}
+
+// rd = (s128(rs1) * z128(rs2)) >> 64
+// pc = pc + instruction_length
+fn exec_mulhsu(state: &mut EmulatorState, rtype: RType) {
+    let rs1_value = state.get_reg(rtype.rs1());
+    let rs2_value = state.get_reg(rtype.rs2());
+    let product = (rs1_value as i64 as i128) * (rs2_value as i128);
+    let rd_value = (product >> 64) as u64;
+    trace_rtype(state, "mulhsu", rtype, rd_value);
+    state.set_reg(rtype.rd(), rd_value);
+    state.pc_next();
+}
+
+// rd = (z128(rs1) * z128(rs2)) >> 64
+// pc = pc + instruction_length
+fn exec_mulhu(state: &mut EmulatorState, rtype: RType) {
+    let rs1_value = state.get_reg(rtype.rs1());
+    let rs2_value = state.get_reg(rtype.rs2());
+    let product = (rs1_value as u128) * (rs2_value as u128);
+    let rd_value = (product >> 64) as u64;
+    trace_rtype(state, "mulhu", rtype, rd_value);
+    state.set_reg(rtype.rd(), rd_value);
+    state.pc_next();
+}
+
 // rd = s64(rs1{32} * rs2{32})
 // pc = pc + instruction_length
 fn exec_mulw(state: &mut EmulatorState, rtype: RType) {
@@ -821,7 +1078,9 @@ fn exec_mulw(state: &mut EmulatorState, rtype: RType) {
 fn exec_div(state: &mut EmulatorState, rtype: RType) {
     let rs1_value = state.get_reg(rtype.rs1());
     let rs2_value = state.get_reg(rtype.rs2());
-    assert!(rs2_value != 0, "check for non-zero divisor");
+    if rs2_value == 0 {
+        return state.reach_zero_divisor(BadState::DivisionByZero);
+    }
     let rd_value = (rs1_value as i64).wrapping_div(rs2_value as i64) as u64;
     trace_rtype(state, "div", rtype, rd_value);
     state.set_reg(rtype.rd(), rd_value);
@@ -833,7 +1092,9 @@ fn exec_div(state: &mut EmulatorState, rtype: RType) {
 fn exec_divw(state: &mut EmulatorState, rtype: RType) {
     let rs1_value = state.get_reg(rtype.rs1());
     let rs2_value = state.get_reg(rtype.rs2());
-    assert!((rs2_value as i32) != 0, "check for non-zero divisor");
+    if (rs2_value as i32) == 0 {
+        return state.reach_zero_divisor(BadState::DivisionByZero);
+    }
     let rd_value = (rs1_value as i32).wrapping_div(rs2_value as i32) as u64;
     trace_rtype(state, "divw", rtype, rd_value);
     state.set_reg(rtype.rd(), rd_value);
@@ -845,19 +1106,37 @@ fn exec_divw(state: &mut EmulatorState, rtype: RType) {
 fn exec_divu(state: &mut EmulatorState, rtype: RType) {
     let rs1_value = state.get_reg(rtype.rs1());
     let rs2_value = state.get_reg(rtype.rs2());
-    assert!(rs2_value != 0, "check for non-zero divisor");
+    if rs2_value == 0 {
+        return state.reach_zero_divisor(BadState::DivisionByZero);
+    }
     let rd_value = rs1_value.wrapping_div(rs2_value);
     trace_rtype(state, "divu", rtype, rd_value);
     state.set_reg(rtype.rd(), rd_value);
     state.pc_next();
 }
 
+// rd = s64(rs1{32} /u rs2{32})
+// pc = pc + instruction_length
+fn exec_divuw(state: &mut EmulatorState, rtype: RType) {
+    let rs1_value = state.get_reg(rtype.rs1());
+    let rs2_value = state.get_reg(rtype.rs2());
+    if (rs2_value as u32) == 0 {
+        return state.reach_zero_divisor(BadState::DivisionByZero);
+    }
+    let rd_value = (rs1_value as u32).wrapping_div(rs2_value as u32) as i32 as u64;
+    trace_rtype(state, "divuw", rtype, rd_value);
+    state.set_reg(rtype.rd(), rd_value);
+    state.pc_next();
+}
+
 // rd = rs1 %s rs2
 // pc = pc + 4
 fn exec_rem(state: &mut EmulatorState, rtype: RType) {
     let rs1_value = state.get_reg(rtype.rs1());
     let rs2_value = state.get_reg(rtype.rs2());
-    assert!(rs2_value != 0, "check for non-zero divisor");
+    if rs2_value == 0 {
+        return state.reach_zero_divisor(BadState::RemainderByZero);
+    }
     let rd_value = (rs1_value as i64).wrapping_rem(rs2_value as i64) as u64;
     trace_rtype(state, "rem", rtype, rd_value);
     state.set_reg(rtype.rd(), rd_value);
@@ -869,7 +1148,9 @@ fn exec_rem(state: &mut EmulatorState, rtype: RType) {
 fn exec_remw(state: &mut EmulatorState, rtype: RType) {
     let rs1_value = state.get_reg(rtype.rs1());
     let rs2_value = state.get_reg(rtype.rs2());
-    assert!((rs2_value as i32) != 0, "check for non-zero divisor");
+    if (rs2_value as i32) == 0 {
+        return state.reach_zero_divisor(BadState::RemainderByZero);
+    }
     let rd_value = (rs1_value as i32).wrapping_rem(rs2_value as i32) as u64;
     trace_rtype(state, "remw", rtype, rd_value);
     state.set_reg(rtype.rd(), rd_value);
@@ -881,13 +1162,29 @@ fn exec_remw(state: &mut EmulatorState, rtype: RType) {
 fn exec_remu(state: &mut EmulatorState, rtype: RType) {
     let rs1_value = state.get_reg(rtype.rs1());
     let rs2_value = state.get_reg(rtype.rs2());
-    assert!(rs2_value != 0, "check for non-zero divisor");
+    if rs2_value == 0 {
+        return state.reach_zero_divisor(BadState::RemainderByZero);
+    }
     let rd_value = rs1_value.wrapping_rem(rs2_value);
     trace_rtype(state, "remu", rtype, rd_value);
     state.set_reg(rtype.rd(), rd_value);
     state.pc_next();
 }
 
+// rd = s64(rs1{32} %u rs2{32})
+// pc = pc + instruction_length
+fn exec_remuw(state: &mut EmulatorState, rtype: RType) {
+    let rs1_value = state.get_reg(rtype.rs1());
+    let rs2_value = state.get_reg(rtype.rs2());
+    if (rs2_value as u32) == 0 {
+        return state.reach_zero_divisor(BadState::RemainderByZero);
+    }
+    let rd_value = (rs1_value as u32).wrapping_rem(rs2_value as u32) as i32 as u64;
+    trace_rtype(state, "remuw", rtype, rd_value);
+    state.set_reg(rtype.rd(), rd_value);
+    state.pc_next();
+}
+
 fn exec_ecall(state: &mut EmulatorState) {
     let a7_value = state.get_reg(Register::A7);
     if a7_value == SyscallId::Exit as u64 {
@@ -896,6 +1193,9 @@ fn exec_ecall(state: &mut EmulatorState) {
         io::stdout().flush().expect("stdout flush success");
         info!("program exiting with exit code {}", exit_code);
         state.running = false;
+        if exit_code != 0 {
+            state.reach_bad_state(BadState::NonZeroExitCode);
+        }
     } else if a7_value == SyscallId::Read as u64 {
         syscall_read(state);
     } else if a7_value == SyscallId::Write as u64 {
@@ -1186,3 +1486,223 @@ impl MyLittleEndian for u32 {
         LittleEndian::read_u32(bytes)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMORY_SIZE: usize = 1024;
+
+    const DATA_ADDRESS: u64 = 0x100;
+    const DATA_WORD: u64 = 0xdead_beef_8765_4321;
+
+    // Encodes an R-type instruction `a0 = a1 op a2`.
+    fn encode_rtype(opcode: u32, funct3: u32, funct7: u32) -> u32 {
+        let (rd, rs1, rs2) = (10, 11, 12);
+        funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+    }
+
+    // Encodes an M-extension R-type instruction `a0 = a1 op a2`.
+    fn encode_rtype_m(opcode: u32, funct3: u32) -> u32 {
+        encode_rtype(opcode, funct3, 1)
+    }
+
+    // Encodes an I-type instruction `a0 = a1 op imm`.
+    fn encode_itype(opcode: u32, funct3: u32, imm: i32) -> u32 {
+        let (rd, rs1) = (10, 11);
+        (imm as u32 & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+    }
+
+    // Executes the instruction at address zero, with `a0` initially set to 7
+    // and `DATA_WORD` stored at `DATA_ADDRESS` in memory.
+    fn execute_with(state: &mut EmulatorState, instr: u32, a1: u64, a2: u64) {
+        state.running = true;
+        state.set_reg(Register::A0, 7);
+        state.set_reg(Register::A1, a1);
+        state.set_reg(Register::A2, a2);
+        state.set_mem(DATA_ADDRESS, DATA_WORD);
+        state.set_mem_typed::<u32>(state.program_counter, instr);
+        let fetched = fetch(state);
+        execute(state, decode(fetched));
+    }
+
+    fn execute_with_divisor(state: &mut EmulatorState, instr: u32, divisor: u64) {
+        execute_with(state, instr, 42, divisor);
+    }
+
+    // Checks `a0` after executing the instruction for all `(a1, a2, a0)`.
+    fn check_rtype(instr: u32, cases: &[(u64, u64, u64)]) {
+        for &(a1, a2, a0) in cases {
+            let mut state = EmulatorState::new(MEMORY_SIZE);
+            execute_with(&mut state, instr, a1, a2);
+            let result = state.get_reg(Register::A0);
+            assert_eq!(result, a0, "{:#x} with a1={:#x} a2={:#x}", instr, a1, a2);
+            assert_eq!(state.program_counter, 4);
+        }
+    }
+
+    fn neg(value: i64) -> u64 {
+        value as u64
+    }
+
+    fn zero_divisors() -> Vec<(u32, u64, BadState)> {
+        vec![
+            (encode_rtype_m(0x33, 4), 0, BadState::DivisionByZero), // div
+            (encode_rtype_m(0x33, 5), 0, BadState::DivisionByZero), // divu
+            (encode_rtype_m(0x33, 6), 0, BadState::RemainderByZero), // rem
+            (encode_rtype_m(0x33, 7), 0, BadState::RemainderByZero), // remu
+            (encode_rtype_m(0x3b, 4), 1 << 32, BadState::DivisionByZero), // divw
+            (encode_rtype_m(0x3b, 5), 1 << 32, BadState::DivisionByZero), // divuw
+            (encode_rtype_m(0x3b, 6), 1 << 32, BadState::RemainderByZero), // remw
+            (encode_rtype_m(0x3b, 7), 1 << 32, BadState::RemainderByZero), // remuw
+        ]
+    }
+
+    fn assert_stopped_at(state: &EmulatorState, bad_state: BadState) {
+        assert!(!state.running, "emulation stops");
+        assert_eq!(state.bad_state(), Some(bad_state));
+        assert_eq!(state.get_reg(Register::A0), 7, "rd is not written");
+        assert_eq!(state.program_counter, 0, "pc is not advanced");
+    }
+
+    #[test]
+    fn division_by_zero_stops_emulation() {
+        for (instr, divisor, bad_state) in zero_divisors() {
+            let mut state = EmulatorState::new(MEMORY_SIZE);
+            execute_with_divisor(&mut state, instr, divisor);
+            assert_stopped_at(&state, bad_state);
+        }
+    }
+
+    #[test]
+    fn division_by_zero_is_bad_state_in_replay() {
+        for (instr, divisor, bad_state) in zero_divisors() {
+            let mut state = EmulatorState::new(MEMORY_SIZE);
+            state.replay(Vec::new(), 0, 0);
+            execute_with_divisor(&mut state, instr, divisor);
+            assert_stopped_at(&state, bad_state);
+        }
+    }
+
+    #[test]
+    fn division_by_non_zero_continues() {
+        let mut state = EmulatorState::new(MEMORY_SIZE);
+        execute_with_divisor(&mut state, encode_rtype_m(0x33, 4), 5);
+        assert!(state.running);
+        assert_eq!(state.bad_state(), None);
+        assert_eq!(state.get_reg(Register::A0), 8);
+        assert_eq!(state.program_counter, 4);
+    }
+
+    #[test]
+    fn exec_slti_sign_extends_immediate() {
+        for &(a1, imm, a0) in &[(neg(-2), -1, 1), (0, -1, 0), (i64::MIN as u64, 2047, 1)] {
+            let mut state = EmulatorState::new(MEMORY_SIZE);
+            execute_with(&mut state, encode_itype(0x13, 2, imm), a1, 0);
+            assert_eq!(state.get_reg(Register::A0), a0);
+        }
+    }
+
+    #[test]
+    fn exec_xor() {
+        let xor = encode_rtype(0x33, 4, 0);
+        check_rtype(
+            xor,
+            &[(0xff00, 0x0ff0, 0xf0f0), (u64::MAX, 1, u64::MAX - 1)],
+        );
+    }
+
+    #[test]
+    fn exec_lwu_zero_extends() {
+        for &(offset, a0) in &[(0, 0x8765_4321), (4, 0xdead_beef)] {
+            let mut state = EmulatorState::new(MEMORY_SIZE);
+            execute_with(&mut state, encode_itype(0x03, 6, offset), DATA_ADDRESS, 0);
+            assert_eq!(state.get_reg(Register::A0), a0);
+        }
+    }
+
+    #[test]
+    fn exec_srlw_and_sraw_use_low_32_bits() {
+        let srlw = encode_rtype(0x3b, 5, 0);
+        check_rtype(
+            srlw,
+            &[
+                (0x8000_0000, 0, 0xffff_ffff_8000_0000),
+                (0xffff_ffff_8000_0000, 31, 1),
+                (0x8000_0000, 33, 0x4000_0000),
+                (0x1_0000_0000, 0, 0),
+            ],
+        );
+        let sraw = encode_rtype(0x3b, 5, 0x20);
+        check_rtype(
+            sraw,
+            &[
+                (0x8000_0000, 31, u64::MAX),
+                (0x1_7fff_ffff, 36, 0x07ff_ffff),
+                (0x8000_0000, 32, 0xffff_ffff_8000_0000),
+            ],
+        );
+    }
+
+    #[test]
+    fn exec_mulh_variants_return_upper_bits() {
+        check_rtype(
+            encode_rtype_m(0x33, 1), // mulh
+            &[
+                (u64::MAX, u64::MAX, 0),
+                (i64::MIN as u64, i64::MIN as u64, 0x4000_0000_0000_0000),
+                (i64::MIN as u64, i64::MAX as u64, 0xc000_0000_0000_0000),
+                (neg(-3), 5, u64::MAX),
+            ],
+        );
+        check_rtype(
+            encode_rtype_m(0x33, 2), // mulhsu
+            &[
+                (neg(-1), u64::MAX, u64::MAX),
+                (i64::MIN as u64, u64::MAX, 0x8000_0000_0000_0000),
+                (1, u64::MAX, 0),
+            ],
+        );
+        check_rtype(
+            encode_rtype_m(0x33, 3), // mulhu
+            &[
+                (u64::MAX, u64::MAX, 0xffff_ffff_ffff_fffe),
+                (1 << 32, 1 << 32, 1),
+                (0xffff_ffff, 0xffff_ffff, 0),
+                (u64::MAX, 2, 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn exec_unsigned_word_division_zero_extends_operands() {
+        check_rtype(
+            encode_rtype_m(0x3b, 5), // divuw
+            &[
+                (0xffff_fffe, 1, 0xffff_ffff_ffff_fffe),
+                (0x1_0000_0006, 0x7_0000_0003, 2),
+                (0x8000_0000, 1, 0xffff_ffff_8000_0000),
+            ],
+        );
+        check_rtype(
+            encode_rtype_m(0x3b, 7), // remuw
+            &[
+                (0xffff_fffe, 0x1_0000_0003, 2),
+                (0x9_8000_0000, 0xffff_ffff, 0xffff_ffff_8000_0000),
+            ],
+        );
+    }
+
+    #[test]
+    fn fetch_compressed_instruction_only() {
+        let mut state = EmulatorState::new(MEMORY_SIZE);
+        state.set_reg(Register::A0, 41);
+        // A compressed `c.addi a0,1` followed by unrelated bytes.
+        state.set_mem_typed::<u32>(0, 0xffff_0505);
+        let fetched = fetch(&mut state);
+        assert_eq!(fetched, 0x0505);
+        execute(&mut state, decode(fetched));
+        assert_eq!(state.get_reg(Register::A0), 42);
+        assert_eq!(state.program_counter, 2);
+    }
+}
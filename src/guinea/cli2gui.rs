@@ -22,6 +22,7 @@ use crate::unicorn::smt_solver::boolector_impl;
 use crate::unicorn::smt_solver::none_impl;
 #[cfg(feature = "z3")]
 use crate::unicorn::smt_solver::z3solver_impl;
+use crate::unicorn::smt_solver::SMTOptions;
 use crate::unicorn::unroller::{prune_model, renumber_model, unroll_model};
 use crate::unicorn::{write_model, Model};
 
@@ -209,30 +210,42 @@ fn prune(ui: &mut Ui, data: &mut Guineacorn) {
         data.cli2gui.pruned = true;
         prune_model(data.model.as_mut().unwrap());
 
-        match data.cli2gui.solver {
+        let options = SMTOptions {
+            timeout: data.cli2gui.timeout,
+            command: None,
+        };
+        let result = match data.cli2gui.solver {
             SmtType::Generic => optimize_model_with_solver::<none_impl::NoneSolver>(
                 data.model.as_mut().unwrap(),
-                data.cli2gui.timeout,
+                &options,
                 data.cli2gui.minimize,
                 false,
                 false,
+                &mut vec![],
             ),
             #[cfg(feature = "boolector")]
             SmtType::Boolector => optimize_model_with_solver::<boolector_impl::BoolectorSolver>(
                 data.model.as_mut().unwrap(),
-                data.cli2gui.timeout,
+                &options,
                 data.cli2gui.minimize,
                 false,
                 false,
+                &mut vec![],
             ),
             #[cfg(feature = "z3")]
             SmtType::Z3 => optimize_model_with_solver::<z3solver_impl::Z3SolverWrapper>(
                 data.model.as_mut().unwrap(),
-                data.cli2gui.timeout,
+                &options,
                 data.cli2gui.minimize,
                 false,
                 false,
+                &mut vec![],
             ),
+            SmtType::External => unreachable!("external solvers are not offered by the GUI"),
+        };
+        if let Err(e) = result {
+            data.error = Some(e);
+            return;
         }
         renumber_model(data.model.as_mut().unwrap());
         data.cli2gui.output = Some(stringify_model(data.model.as_ref().unwrap()));
@@ -277,7 +290,8 @@ fn do_unroll(data: &mut Guineacorn) {
     data.loading_data.progress_receiver = Some(receiver);
 
     let concurrent_unrolling = move || {
-        let mut model = parse_btor2_string(&serial_model, &parameters.memory_data);
+        let mut model = parse_btor2_string(&serial_model, &parameters.memory_data)
+            .expect("serialized model is valid BTOR2");
         model.lines.clear();
 
         for n in 0..parameters.desired_unrolls {
@@ -295,31 +309,40 @@ fn do_unroll(data: &mut Guineacorn) {
             sender.send(n as f32).expect("Could not send progress.");
         }
 
-        match parameters.solver {
+        let options = SMTOptions {
+            timeout: parameters.timeout,
+            command: None,
+        };
+        let result = match parameters.solver {
             SmtType::Generic => optimize_model_with_solver::<none_impl::NoneSolver>(
                 &mut model,
-                parameters.timeout,
+                &options,
                 parameters.minimize,
                 false,
                 false,
+                &mut vec![],
             ),
             #[cfg(feature = "boolector")]
             SmtType::Boolector => optimize_model_with_solver::<boolector_impl::BoolectorSolver>(
                 &mut model,
-                parameters.timeout,
+                &options,
                 parameters.minimize,
                 false,
                 false,
+                &mut vec![],
             ),
             #[cfg(feature = "z3")]
             SmtType::Z3 => optimize_model_with_solver::<z3solver_impl::Z3SolverWrapper>(
                 &mut model,
-                parameters.timeout,
+                &options,
                 parameters.minimize,
                 false,
                 false,
+                &mut vec![],
             ),
-        }
+            SmtType::External => unreachable!("external solvers are not offered by the GUI"),
+        };
+        result.expect("bad states are not terminated on");
 
         renumber_model(&mut model);
         stringify_model(&model)
@@ -345,13 +368,15 @@ fn is_concurrent(data: &mut Guineacorn) -> bool {
             let thread = std::mem::replace(&mut data.loading_data.processing_thread, None).unwrap();
             let serial_model = thread.join();
             match serial_model {
-                Ok(model) => {
-                    let mut model = parse_btor2_string(&model, &data.cli2gui.memory_data);
-                    renumber_model(&mut model);
-
-                    data.model = Some(model);
-                    data.cli2gui.output = Some(stringify_model(data.model.as_ref().unwrap()));
-                }
+                Ok(model) => match parse_btor2_string(&model, &data.cli2gui.memory_data) {
+                    Ok(mut model) => {
+                        renumber_model(&mut model);
+
+                        data.model = Some(model);
+                        data.cli2gui.output = Some(stringify_model(data.model.as_ref().unwrap()));
+                    }
+                    Err(e) => data.error = Some(e),
+                },
                 Err(e) => data.error = Some(to_proper_error(&e)),
             }
             false
@@ -57,14 +57,16 @@ pub(crate) fn load(
             return;
         }
 
-        if let Ok(mut model) = try_catch(|| parse_btor2_file(&path)) {
-            renumber_model(&mut model);
-            data.model = Some(model);
-            after_fn(data);
-            return;
+        match try_catch(|| parse_btor2_file(&path)) {
+            Ok(Ok(mut model)) => {
+                renumber_model(&mut model);
+                data.model = Some(model);
+                after_fn(data);
+            }
+            // Malformed models are reported with the offending line and column.
+            Ok(Err(error)) => data.error = Some(error),
+            Err(_) => data.error = Some(Error::msg("File is neither a binary nor a model")),
         }
-
-        data.error = Some(Error::msg("File is neither a binary nor a model"));
     }
 }
 
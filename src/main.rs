@@ -7,39 +7,75 @@ mod unicorn;
 #[cfg(feature = "gui")]
 use crate::guinea::gui::gui;
 use crate::quantum_annealing::dwave_api::sample_quantum_annealer;
-use crate::unicorn::bitblasting::bitblast_model;
-use crate::unicorn::bitblasting_dimacs::write_dimacs_model;
+use crate::unicorn::aiger_parser::load_aiger_as_gatemodel;
+use crate::unicorn::bitblasting::{bitblast_model, bitblast_sequential_model};
+use crate::unicorn::bitblasting_aiger::write_aiger_model;
+use crate::unicorn::bitblasting_dimacs::{decode_dimacs_witness, write_dimacs_model};
 use crate::unicorn::bitblasting_printer::write_btor2_model;
+use crate::unicorn::bmc::{bmc_with_sat, bmc_with_smt, BmcOptions, BmcStop};
 use crate::unicorn::btor2file_parser::parse_btor2_file;
 use crate::unicorn::builder::generate_model;
 use crate::unicorn::codegen::compile_model_into_program;
 use crate::unicorn::dimacs_parser::load_dimacs_as_gatemodel;
 use crate::unicorn::emulate_loader::load_model_into_emulator;
+use crate::unicorn::ic3::{prove_with_sat, Ic3Options, Ic3Proof};
+use crate::unicorn::induction::{prove_with_smt, Proof};
 use crate::unicorn::memory::replace_memory;
 use crate::unicorn::optimize::{optimize_model_with_input, optimize_model_with_solver};
+use crate::unicorn::portfolio::{solve_bad_states_parallel, PortfolioOptions};
 use crate::unicorn::qubot::{InputEvaluator, Qubot};
-use crate::unicorn::sat_solver::solve_bad_states;
+use crate::unicorn::report::{BadStateFound, BadStateVerdict, ModelSize, Report, Verdict};
+use crate::unicorn::sat_solver::{solve_bad_states, SATOptions};
+use crate::unicorn::simulator::{check_btor2_witness, Simulator};
+use crate::unicorn::smt2_printer::write_smt2_model;
 use crate::unicorn::smt_solver::*;
-use crate::unicorn::unroller::{prune_model, renumber_model, unroll_model};
-use crate::unicorn::write_model;
+use crate::unicorn::unroller::{prune_model, renumber_model, split_unrolled_name, unroll_model};
+use crate::unicorn::witness::Witness;
+use crate::unicorn::{write_model, Model, Node};
 
 use ::unicorn::disassemble::disassemble;
 use ::unicorn::emulate::EmulatorState;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use bytesize::ByteSize;
-use cli::{collect_arg_values, expect_arg, expect_optional_arg, LogLevel, SatType, SmtType};
+use clap::ArgMatches;
+use cli::{
+    collect_arg_values, expect_arg, expect_optional_arg, LogLevel, PortfolioSolver, SatType,
+    SmtType,
+};
 use env_logger::{Env, TimestampPrecision};
+use log::{info, warn};
 use riscu::load_object_file;
 use std::{
+    ascii,
+    collections::{HashMap, VecDeque},
     env,
-    fs::File,
-    io::{stdout, Write},
+    fs::{self, File},
+    io::{stdout, BufReader, Write},
+    mem::size_of,
     path::PathBuf,
+    process,
     str::FromStr,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-fn main() -> Result<()> {
+// Exit code of runs stopped by a reachable bad state, i.e. `beator` with
+// `--terminate-on-bad` (in every mode) as well as `bmc` and `prove`, which
+// always stop at a counterexample. Chosen like the exit code of SAT solvers
+// reporting a satisfiable formula.
+const EXIT_CODE_BAD_STATE: i32 = 10;
+
+fn main() {
+    if let Err(error) = run() {
+        if let Some(bad_state) = error.downcast_ref::<BadStateFound>() {
+            warn!("Terminating, {}", bad_state);
+            process::exit(EXIT_CODE_BAD_STATE);
+        }
+        eprintln!("Error: {:?}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
     let matches = cli::args().get_matches();
 
     // process global flags
@@ -57,6 +93,9 @@ fn main() -> Result<()> {
         Some(("emulate", args)) => {
             let input = expect_arg::<PathBuf>(args, "input-file")?;
             let memory_size = ByteSize::mib(*args.get_one("memory").unwrap()).as_u64();
+            let replay = expect_optional_arg::<PathBuf>(args, "replay-file")?;
+            let max_heap = *args.get_one::<u32>("max-heap").unwrap();
+            let max_stack = *args.get_one::<u32>("max-stack").unwrap();
             let arg0 = expect_arg::<String>(args, "input-file")?;
             let extras = collect_arg_values(args, "extras");
 
@@ -64,8 +103,30 @@ fn main() -> Result<()> {
             let program = load_object_file(input)?;
             let mut emulator = EmulatorState::new(memory_size as usize);
             emulator.bootstrap(&program, &argv);
+            if let Some(ref replay_path) = replay {
+                let recorded = fs::read(replay_path)
+                    .with_context(|| format!("Failed to read replay file {:?}", replay_path))?;
+                let word_size = size_of::<u64>() as u64;
+                emulator.replay(
+                    recorded,
+                    max_heap as u64 * word_size,
+                    max_stack as u64 * word_size,
+                );
+            }
             emulator.run();
 
+            if replay.is_some() {
+                match emulator.bad_state() {
+                    Some(bad_state) => {
+                        info!(
+                            "Replay reached bad state '{}'",
+                            Into::<&str>::into(bad_state)
+                        )
+                    }
+                    None => bail!("Replay did not reach any bad state"),
+                }
+            }
+
             Ok(())
         }
         Some(("beator", args)) | Some(("qubot", args)) => {
@@ -76,6 +137,7 @@ fn main() -> Result<()> {
             let unroll = args.get_one::<usize>("unroll-model").cloned();
             let smt_solver = expect_arg::<SmtType>(args, "smt-solver")?;
             let solver_timeout = args.get_one::<u64>("solver-timeout");
+            let solver_command = expect_optional_arg::<String>(args, "solver-cmd")?;
             let max_heap = *args.get_one::<u32>("max-heap").unwrap();
             let max_stack = *args.get_one::<u32>("max-stack").unwrap();
             let memory_size = ByteSize::mib(*args.get_one("memory").unwrap()).as_u64();
@@ -83,27 +145,98 @@ fn main() -> Result<()> {
             let inputs = expect_optional_arg::<String>(args, "inputs")?;
             let prune = !is_beator || args.get_flag("prune-model");
             let minimize = is_beator && !args.get_flag("fast-minimize");
-            let discretize = !is_beator || args.get_flag("discretize-memory");
+            let portfolio = if is_beator {
+                portfolio_options(args, &smt_solver, solver_timeout, &solver_command)?
+            } else {
+                None
+            };
+            // SAT solvers of a portfolio need the memory to be discretized.
+            let discretize = !is_beator
+                || args.get_flag("discretize-memory")
+                || portfolio.as_ref().map_or(false, |p| {
+                    p.solvers
+                        .iter()
+                        .any(|s| matches!(s, PortfolioSolver::Sat(_)))
+                });
             let terminate_on_bad = is_beator && args.get_flag("terminate-on-bad");
             let one_query = is_beator && args.get_flag("one-query");
+            if portfolio.is_some() && one_query {
+                bail!("deciding bad states in parallel cannot be combined with one query");
+            }
+            let witness_file = if is_beator {
+                expect_optional_arg::<PathBuf>(args, "witness-file")?
+            } else {
+                None
+            };
+            let btor2_witness_file = if is_beator {
+                expect_optional_arg::<PathBuf>(args, "btor2-witness-file")?
+            } else {
+                None
+            };
             let renumber = !is_beator || output.is_some();
             let input_is_btor2 = args.get_flag("from-btor2");
             let input_is_dimacs = !is_beator && args.get_flag("from-dimacs");
+            let input_is_aiger = !is_beator && args.get_flag("from-aiger");
             let compile_model = is_beator && args.get_flag("compile");
             let emulate_model = is_beator && args.get_flag("emulate");
             let arg0 = expect_arg::<String>(args, "input-file")?;
             let extras = collect_arg_values(args, "extras");
+            let report_file = expect_optional_arg::<PathBuf>(args, "report-file")?;
+            let mut report = Report::new(matches.subcommand().unwrap().0, &input);
+            let mut verdicts = vec![];
+            let mut witnesses: Vec<Witness> = vec![];
+            let write_witnesses = |witnesses: &[Witness]| -> Result<()> {
+                if let Some(ref witness_path) = witness_file {
+                    match witnesses.first() {
+                        Some(witness) => witness.write_stdin_file(witness_path)?,
+                        None => warn!("No bad state found to be satisfiable, no witness written"),
+                    }
+                }
+                if let Some(ref witness_path) = btor2_witness_file {
+                    match witnesses.iter().find(|w| w.belongs_to_bad_state()) {
+                        Some(witness) => {
+                            // The witness refers to the sequential model, which
+                            // has been unrolled in place, so it is built again
+                            // and numbered the way `write_model` prints it.
+                            let mut sequential = if !input_is_btor2 {
+                                let arg0 = expect_arg::<String>(args, "input-file")?;
+                                let extras = collect_arg_values(args, "extras");
+                                let argv = [vec![arg0], extras].concat();
+                                let program = load_object_file(&input)?;
+                                generate_model(&program, memory_size, max_heap, max_stack, &argv)?
+                            } else {
+                                parse_btor2_file(&input)?
+                            };
+                            renumber_model(&mut sequential);
+                            let file = File::create(witness_path)?;
+                            witness.write_btor2_witness(&sequential, file)?
+                        }
+                        None if witnesses.is_empty() => {
+                            warn!("No bad state found to be satisfiable, no witness written")
+                        }
+                        None => {
+                            warn!("Witness does not single out a bad state, no witness written")
+                        }
+                    }
+                }
+                Ok(())
+            };
 
-            let mut model = if !input_is_dimacs {
+            let mut model = if !input_is_dimacs && !input_is_aiger {
+                let start = Instant::now();
                 let mut model = if !input_is_btor2 {
                     let program = load_object_file(&input)?;
                     let argv = [vec![arg0], extras].concat();
                     generate_model(&program, memory_size, max_heap, max_stack, &argv)?
                 } else {
-                    parse_btor2_file(&input)
+                    parse_btor2_file(&input)?
                 };
+                report.record_phase("generate", start);
+                report.record_origins(&model);
 
                 if let Some(unroll_depth) = unroll {
+                    let start = Instant::now();
+                    let size = ModelSize::of(&model);
                     model.lines.clear();
                     let mut input_values: Vec<u64> = if has_concrete_inputs {
                         inputs
@@ -121,32 +254,79 @@ fn main() -> Result<()> {
                             optimize_model_with_input(&mut model, &mut input_values)
                         }
                     }
+                    report.record_pass("unroll", start, size, &model);
+                    report.record_bad_states(&model);
                     if prune {
+                        let start = Instant::now();
+                        let size = ModelSize::of(&model);
                         prune_model(&mut model);
+                        report.record_pass("prune", start, size, &model);
                     }
                     if discretize {
+                        let start = Instant::now();
+                        let size = ModelSize::of(&model);
                         // TODO: We perform a quick constant-folding pass before we discretize. This
                         // introduces some overhead when no SMT solver is used, but helps otherwise.
                         // It is not yet clear how to implement this in a cleaner way.
                         optimize_model_with_input(&mut model, &mut vec![]);
                         replace_memory(&mut model);
+                        report.record_pass("discretize", start, size, &model);
                     }
-                    let timeout = solver_timeout.map(|&ms| Duration::from_millis(ms));
-                    match smt_solver {
+                    let options = SMTOptions {
+                        timeout: solver_timeout.map(|&ms| Duration::from_millis(ms)),
+                        command: solver_command.clone(),
+                    };
+                    let start = Instant::now();
+                    let size = ModelSize::of(&model);
+                    // Bad states are decided in parallel afterwards, the
+                    // optimization is limited to constant folding then.
+                    let smt_solver = if portfolio.is_some() {
+                        SmtType::Generic
+                    } else {
+                        smt_solver.clone()
+                    };
+                    let minimize = minimize && portfolio.is_none();
+                    let result = match smt_solver {
                         #[rustfmt::skip]
                         SmtType::Generic => {
-                            optimize_model_with_solver::<none_impl::NoneSolver>(&mut model, timeout, minimize, terminate_on_bad, one_query)
+                            optimize_model_with_solver::<none_impl::NoneSolver>(&mut model, &options, minimize, terminate_on_bad, one_query, &mut verdicts)
                         },
                         #[rustfmt::skip]
                         #[cfg(feature = "boolector")]
                         SmtType::Boolector => {
-                            optimize_model_with_solver::<boolector_impl::BoolectorSolver>(&mut model, timeout, minimize, terminate_on_bad, one_query)
+                            optimize_model_with_solver::<boolector_impl::BoolectorSolver>(&mut model, &options, minimize, terminate_on_bad, one_query, &mut verdicts)
                         },
                         #[rustfmt::skip]
                         #[cfg(feature = "z3")]
                         SmtType::Z3 => {
-                            optimize_model_with_solver::<z3solver_impl::Z3SolverWrapper>(&mut model, timeout, minimize, terminate_on_bad, one_query)
+                            optimize_model_with_solver::<z3solver_impl::Z3SolverWrapper>(&mut model, &options, minimize, terminate_on_bad, one_query, &mut verdicts)
                         },
+                        #[rustfmt::skip]
+                        SmtType::External => {
+                            optimize_model_with_solver::<external_impl::ExternalSolver>(&mut model, &options, minimize, terminate_on_bad, one_query, &mut verdicts)
+                        },
+                    };
+                    report.record_pass("optimize", start, size, &model);
+                    if result.is_err() {
+                        write_report(&mut report, &verdicts, Some(&model), &report_file)?;
+                    }
+                    collect_witnesses(result, &mut witnesses, &write_witnesses)?;
+                    if let Some(ref portfolio) = portfolio {
+                        let start = Instant::now();
+                        let size = ModelSize::of(&model);
+                        verdicts
+                            .retain(|v| !matches!(v.verdict, Verdict::Timeout | Verdict::Unknown));
+                        let result = solve_bad_states_parallel(
+                            &mut model,
+                            portfolio,
+                            terminate_on_bad,
+                            &mut verdicts,
+                        );
+                        report.record_pass("portfolio", start, size, &model);
+                        if result.is_err() {
+                            write_report(&mut report, &verdicts, Some(&model), &report_file)?;
+                        }
+                        collect_witnesses(result, &mut witnesses, &write_witnesses)?;
                     }
                     if renumber {
                         renumber_model(&mut model);
@@ -161,6 +341,7 @@ fn main() -> Result<()> {
             if compile_model {
                 assert!(!input_is_btor2, "cannot compile arbitrary BTOR2");
                 assert!(!input_is_dimacs, "cannot compile arbitrary DIMACS");
+                assert!(!input_is_aiger, "cannot compile arbitrary AIGER");
                 assert!(!discretize, "cannot compile with discretized memory");
 
                 // TODO: Just a workaround to get `argv` again.
@@ -180,6 +361,7 @@ fn main() -> Result<()> {
             if emulate_model {
                 assert!(!input_is_btor2, "cannot emulate arbitrary BTOR2");
                 assert!(!input_is_dimacs, "cannot emulate arbitrary DIMACS");
+                assert!(!input_is_aiger, "cannot emulate arbitrary AIGER");
                 assert!(!discretize, "cannot emulate with discretized memory");
 
                 let program = load_object_file(&input)?;
@@ -194,23 +376,58 @@ fn main() -> Result<()> {
                 let sat_solver = expect_arg::<SatType>(args, "sat-solver")?;
                 let bitblast = args.get_flag("bitblast") || (sat_solver != SatType::None);
                 let dimacs = args.get_flag("dimacs");
+                let aiger = args.get_flag("aiger");
+                let smt2 = args.get_flag("smt2");
                 let output_to_stdout =
                     output == Some(PathBuf::from("")) || output == Some(PathBuf::from("-"));
                 assert!(bitblast || !dimacs, "printing DIMACS requires bitblasting");
+                assert!(bitblast || !aiger, "printing AIGER requires bitblasting");
+                assert!(!dimacs || !aiger, "cannot print both DIMACS and AIGER");
+                assert!(
+                    !bitblast || !smt2,
+                    "printing SMT-LIB requires a word-level model"
+                );
 
                 if bitblast {
+                    let start = Instant::now();
                     if !discretize {
                         replace_memory(model.as_mut().unwrap());
                     }
-                    let gate_model = bitblast_model(&model.unwrap(), true, 64);
-
-                    if sat_solver != SatType::None {
-                        solve_bad_states(&gate_model, sat_solver, terminate_on_bad, one_query)?
+                    // Without unrolling, the states of the model become latches.
+                    let gate_model = if unroll.is_some() {
+                        bitblast_model(model.as_ref().unwrap(), true, 64)
+                    } else {
+                        bitblast_sequential_model(model.as_ref().unwrap(), true, 64)
+                    };
+                    report.record_phase("bitblast", start);
+
+                    if sat_solver != SatType::None && portfolio.is_none() {
+                        let sat_timeout = args.get_one::<u64>("sat-timeout");
+                        let sat_options = SATOptions {
+                            timeout: sat_timeout.map(|&ms| Duration::from_millis(ms)),
+                            command: expect_optional_arg::<String>(args, "sat-solver-cmd")?,
+                        };
+                        let start = Instant::now();
+                        let result = solve_bad_states(
+                            &gate_model,
+                            sat_solver,
+                            &sat_options,
+                            terminate_on_bad,
+                            one_query,
+                            &mut verdicts,
+                        );
+                        report.record_phase("sat", start);
+                        if result.is_err() {
+                            write_report(&mut report, &verdicts, model.as_ref(), &report_file)?;
+                        }
+                        collect_witnesses(result, &mut witnesses, &write_witnesses)?;
                     }
 
                     if output_to_stdout {
                         if dimacs {
                             write_dimacs_model(&gate_model, stdout())?;
+                        } else if aiger {
+                            write_aiger_model(&gate_model, false, stdout())?;
                         } else {
                             write_btor2_model(&gate_model, stdout())?;
                         }
@@ -218,27 +435,47 @@ fn main() -> Result<()> {
                         let file = File::create(output_path)?;
                         if dimacs {
                             write_dimacs_model(&gate_model, file)?;
+                        } else if aiger {
+                            let binary = output_path.extension().map_or(false, |e| e == "aig");
+                            write_aiger_model(&gate_model, binary, file)?;
                         } else {
                             write_btor2_model(&gate_model, file)?;
                         }
                     }
                 } else if output_to_stdout {
-                    write_model(&model.unwrap(), stdout())?;
+                    if smt2 {
+                        write_smt2_model(model.as_ref().unwrap(), one_query, stdout())?;
+                    } else {
+                        write_model(model.as_ref().unwrap(), stdout())?;
+                    }
                 } else if let Some(ref output_path) = output {
                     let file = File::create(output_path)?;
-                    write_model(&model.unwrap(), file)?;
+                    if smt2 {
+                        write_smt2_model(model.as_ref().unwrap(), one_query, file)?;
+                    } else {
+                        write_model(model.as_ref().unwrap(), file)?;
+                    }
                 }
+
+                write_witnesses(&witnesses)?;
             } else {
                 let is_ising = args.get_flag("ising");
 
-                let gate_model = if !input_is_dimacs {
-                    bitblast_model(&model.unwrap(), true, 64)
-                } else {
+                let start = Instant::now();
+                let gate_model = if input_is_dimacs {
                     load_dimacs_as_gatemodel(&input)?
+                } else if input_is_aiger {
+                    load_aiger_as_gatemodel(&input)?
+                } else {
+                    bitblast_model(model.as_ref().unwrap(), true, 64)
                 };
+                report.record_phase("bitblast", start);
 
+                let start = Instant::now();
                 let mut qubot = Qubot::new(&gate_model, is_ising);
                 let bad_state_qubits = qubot.build_qubo();
+                report.record_phase("qubo", start);
+                report.record_qubits(qubot.qubo.get_count_variables(), bad_state_qubits.len());
                 if let Some(ref output_path) = output {
                     let file = File::create(output_path)?;
                     qubot.dump_model(file, bad_state_qubits.clone())?;
@@ -276,6 +513,321 @@ fn main() -> Result<()> {
                 }
             }
 
+            write_report(&mut report, &verdicts, model.as_ref(), &report_file)?;
+
+            Ok(())
+        }
+        Some(("simulate", args)) => {
+            let input = expect_arg::<PathBuf>(args, "input-file")?;
+            let steps = *args.get_one::<usize>("steps").unwrap();
+            let inputs = expect_optional_arg::<String>(args, "inputs")?;
+            let witness_file = expect_optional_arg::<PathBuf>(args, "witness-file")?;
+            let max_heap = *args.get_one::<u32>("max-heap").unwrap();
+            let max_stack = *args.get_one::<u32>("max-stack").unwrap();
+            let memory_size = ByteSize::mib(*args.get_one("memory").unwrap()).as_u64();
+            let arg0 = expect_arg::<String>(args, "input-file")?;
+            let extras = collect_arg_values(args, "extras");
+
+            let model = if !args.get_flag("from-btor2") {
+                let program = load_object_file(&input)?;
+                let argv = [vec![arg0], extras].concat();
+                generate_model(&program, memory_size, max_heap, max_stack, &argv)?
+            } else {
+                parse_btor2_file(&input)?
+            };
+
+            if let Some(ref witness_path) = witness_file {
+                let file = File::open(witness_path)
+                    .with_context(|| format!("Failed to open witness file {:?}", witness_path))?;
+                let (bad_state, step) = check_btor2_witness(&model, BufReader::new(file))?;
+                if let Node::Bad { nid, name, .. } = &*bad_state.borrow() {
+                    info!(
+                        "Witness reaches bad state {} '{}' at step {}",
+                        nid,
+                        name.as_deref().unwrap_or("?"),
+                        step
+                    );
+                }
+                return Ok(());
+            }
+
+            let mut input_values: VecDeque<u64> = match inputs {
+                Some(values) => values
+                    .split(',')
+                    .map(u64::from_str)
+                    .collect::<Result<_, _>>()
+                    .context("Inputs have to be comma-separated numbers")?,
+                None => VecDeque::new(),
+            };
+            let mut simulator = Simulator::new(&model);
+            let mut previous_states = HashMap::new();
+            for _ in 0..steps {
+                let result = simulator.step(|_| input_values.pop_front().unwrap_or(0));
+                for (name, value) in result.states {
+                    if previous_states.insert(name.clone(), value) != Some(value) {
+                        info!("step {}: {} = {:#x}", result.step, name, value);
+                    }
+                }
+                if !result.bad_states.is_empty() {
+                    for name in result.bad_state_names() {
+                        info!("step {}: reached bad state '{}'", result.step, name);
+                    }
+                    break;
+                }
+            }
+
+            Ok(())
+        }
+        Some(("bmc", args)) => {
+            let input = expect_arg::<PathBuf>(args, "input-file")?;
+            let smt_solver = expect_arg::<SmtType>(args, "smt-solver")?;
+            let sat_solver = expect_arg::<SatType>(args, "sat-solver")?;
+            let witness_file = expect_optional_arg::<PathBuf>(args, "witness-file")?;
+            let max_heap = *args.get_one::<u32>("max-heap").unwrap();
+            let max_stack = *args.get_one::<u32>("max-stack").unwrap();
+            let memory_size = ByteSize::mib(*args.get_one("memory").unwrap()).as_u64();
+            let arg0 = expect_arg::<String>(args, "input-file")?;
+            let extras = collect_arg_values(args, "extras");
+            let options = BmcOptions {
+                max_depth: *args.get_one::<usize>("max-depth").unwrap(),
+                time_budget: args
+                    .get_one::<u64>("time-budget")
+                    .map(|&s| Duration::from_secs(s)),
+                all_kinds: args.get_flag("all-kinds"),
+            };
+            let smt_options = SMTOptions {
+                timeout: args
+                    .get_one::<u64>("solver-timeout")
+                    .map(|&ms| Duration::from_millis(ms)),
+                command: expect_optional_arg::<String>(args, "solver-cmd")?,
+            };
+            let sat_options = SATOptions {
+                timeout: args
+                    .get_one::<u64>("sat-timeout")
+                    .map(|&ms| Duration::from_millis(ms)),
+                command: expect_optional_arg::<String>(args, "sat-solver-cmd")?,
+            };
+            let induction = args.get_flag("induction");
+            if smt_solver == SmtType::Generic && sat_solver == SatType::None {
+                bail!("bounded model checking requires an SMT or SAT solver");
+            }
+            if induction && (smt_solver == SmtType::Generic || sat_solver != SatType::None) {
+                bail!("k-induction is only supported with an SMT solver");
+            }
+
+            let mut model = if !args.get_flag("from-btor2") {
+                let program = load_object_file(&input)?;
+                let argv = [vec![arg0], extras].concat();
+                generate_model(&program, memory_size, max_heap, max_stack, &argv)?
+            } else {
+                parse_btor2_file(&input)?
+            };
+            if args.get_flag("discretize-memory") || sat_solver != SatType::None {
+                replace_memory(&mut model);
+            }
+
+            let counterexample = if induction {
+                let result = match smt_solver {
+                    SmtType::Generic => unreachable!(),
+                    #[rustfmt::skip]
+                    #[cfg(feature = "boolector")]
+                    SmtType::Boolector => {
+                        prove_with_smt::<boolector_impl::BoolectorSolver>(&model, &smt_options, &options)?
+                    },
+                    #[rustfmt::skip]
+                    #[cfg(feature = "z3")]
+                    SmtType::Z3 => {
+                        prove_with_smt::<z3solver_impl::Z3SolverWrapper>(&model, &smt_options, &options)?
+                    },
+                    #[rustfmt::skip]
+                    SmtType::External => {
+                        prove_with_smt::<external_impl::ExternalSolver>(&model, &smt_options, &options)?
+                    },
+                };
+                let mut counterexample = None;
+                for (kind, proof) in result.kinds.iter().zip(result.proofs) {
+                    match proof {
+                        Proof::Proved { k } => println!("{}: proved ({}-inductive)", kind, k),
+                        Proof::Counterexample(c) => {
+                            println!("{}: counterexample at depth {}", kind, c.depth);
+                            if counterexample.is_none() {
+                                counterexample = Some(c);
+                            }
+                        }
+                        Proof::Unknown => {
+                            println!("{}: unknown up to depth {}", kind, result.depth)
+                        }
+                    }
+                }
+                counterexample
+            } else {
+                let result = if sat_solver != SatType::None {
+                    bmc_with_sat(&mut model, sat_solver, &sat_options, &options)?
+                } else {
+                    match smt_solver {
+                        SmtType::Generic => unreachable!(),
+                        #[rustfmt::skip]
+                        #[cfg(feature = "boolector")]
+                        SmtType::Boolector => {
+                            bmc_with_smt::<boolector_impl::BoolectorSolver>(&mut model, &smt_options, &options)?
+                        },
+                        #[rustfmt::skip]
+                        #[cfg(feature = "z3")]
+                        SmtType::Z3 => {
+                            bmc_with_smt::<z3solver_impl::Z3SolverWrapper>(&mut model, &smt_options, &options)?
+                        },
+                        #[rustfmt::skip]
+                        SmtType::External => {
+                            bmc_with_smt::<external_impl::ExternalSolver>(&mut model, &smt_options, &options)?
+                        },
+                    }
+                };
+
+                for kind in &result.kinds {
+                    match result.counterexamples.iter().find(|c| &c.kind == kind) {
+                        Some(c) => println!("{}: counterexample at depth {}", kind, c.depth),
+                        None => println!("{}: none up to depth {}", kind, result.depth),
+                    }
+                }
+                match result.stop {
+                    BmcStop::Counterexample => (),
+                    BmcStop::DepthBudget => info!("Depth budget of {} exhausted", result.depth),
+                    BmcStop::TimeBudget => info!("Time budget exhausted at depth {}", result.depth),
+                }
+
+                result.counterexamples.into_iter().next()
+            };
+
+            if let Some(ref witness_path) = witness_file {
+                match &counterexample {
+                    Some(c) => c.witness.write_stdin_file(witness_path)?,
+                    None => warn!("No bad state found to be reachable, no witness written"),
+                }
+            }
+
+            match counterexample {
+                Some(c) => Err(BadStateFound {
+                    name: c.kind,
+                    depth: Some(c.depth),
+                    witness: Some(c.witness),
+                }
+                .into()),
+                None => Ok(()),
+            }
+        }
+        Some(("prove", args)) => {
+            let input = expect_arg::<PathBuf>(args, "input-file")?;
+            let sat_solver = expect_arg::<SatType>(args, "sat-solver")?;
+            let witness_file = expect_optional_arg::<PathBuf>(args, "witness-file")?;
+            let max_heap = *args.get_one::<u32>("max-heap").unwrap();
+            let max_stack = *args.get_one::<u32>("max-stack").unwrap();
+            let memory_size = ByteSize::mib(*args.get_one("memory").unwrap()).as_u64();
+            let arg0 = expect_arg::<String>(args, "input-file")?;
+            let extras = collect_arg_values(args, "extras");
+            let options = Ic3Options {
+                max_frames: *args.get_one::<usize>("max-frames").unwrap(),
+                time_budget: args
+                    .get_one::<u64>("time-budget")
+                    .map(|&s| Duration::from_secs(s)),
+            };
+
+            let mut model = if !args.get_flag("from-btor2") {
+                let program = load_object_file(&input)?;
+                let argv = [vec![arg0], extras].concat();
+                generate_model(&program, memory_size, max_heap, max_stack, &argv)?
+            } else {
+                parse_btor2_file(&input)?
+            };
+            replace_memory(&mut model);
+
+            let result = prove_with_sat(&model, sat_solver, &options)?;
+            let mut counterexample = None;
+            for (kind, proof) in result.kinds.iter().zip(result.proofs) {
+                match proof {
+                    Ic3Proof::Invariant(clauses) => {
+                        println!("{}: proved, inductive invariant:", kind);
+                        if clauses.is_empty() {
+                            println!("  true");
+                        }
+                        for clause in clauses {
+                            let literals: Vec<String> = clause
+                                .iter()
+                                .map(|(name, value)| {
+                                    format!("{}{}", if *value { "" } else { "!" }, name)
+                                })
+                                .collect();
+                            println!("  ({})", literals.join(" | "));
+                        }
+                    }
+                    Ic3Proof::Counterexample { trace, witness } => {
+                        println!("{}: counterexample at depth {}:", kind, trace.len() - 1);
+                        for (n, step) in trace.iter().enumerate() {
+                            let values: Vec<String> = step
+                                .states
+                                .iter()
+                                .chain(&step.inputs)
+                                .map(|(name, value)| format!("{}={:#x}", name, value))
+                                .collect();
+                            println!("  {}: {}", n, values.join(" "));
+                        }
+                        if counterexample.is_none() {
+                            counterexample = Some((kind.clone(), trace.len() - 1, witness));
+                        }
+                    }
+                    Ic3Proof::Unknown => println!("{}: unknown", kind),
+                }
+            }
+
+            if let Some(ref witness_path) = witness_file {
+                match &counterexample {
+                    Some((_, _, witness)) => witness.write_stdin_file(witness_path)?,
+                    None => warn!("No bad state found to be reachable, no witness written"),
+                }
+            }
+
+            match counterexample {
+                Some((name, depth, witness)) => Err(BadStateFound {
+                    name,
+                    depth: Some(depth),
+                    witness: Some(witness),
+                }
+                .into()),
+                None => Ok(()),
+            }
+        }
+        Some(("decode-sat", args)) => {
+            let cnf_file = expect_arg::<PathBuf>(args, "cnf-file")?;
+            let solution_file = expect_arg::<PathBuf>(args, "solution-file")?;
+            let witness_file = expect_optional_arg::<PathBuf>(args, "witness-file")?;
+
+            let witness = decode_dimacs_witness(&cnf_file, &solution_file)?;
+            // Inputs are listed in the order they are consumed by unrolling.
+            let mut assignments: Vec<(&str, u64)> = witness.assignments().collect();
+            assignments.sort_by_key(|(name, _)| {
+                let (origin, depth) = split_unrolled_name(name);
+                (depth, origin.to_string())
+            });
+            for (name, value) in assignments {
+                println!("{}: {} ({:#x})", name, value, value);
+            }
+            let bytes = witness.stdin_bytes();
+            if !bytes.is_empty() {
+                let decimal: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+                let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                let text: String = bytes
+                    .iter()
+                    .flat_map(|&b| ascii::escape_default(b))
+                    .map(char::from)
+                    .collect();
+                println!("stdin ({} bytes):", bytes.len());
+                println!("  decimal: {}", decimal.join(" "));
+                println!("  hex: {}", hex.join(" "));
+                println!("  ascii: {}", text);
+            }
+
+            if let Some(ref witness_path) = witness_file {
+                witness.write_stdin_file(witness_path)?;
+            }
             Ok(())
         }
         Some(("dwave", args)) => {
@@ -294,6 +846,103 @@ fn main() -> Result<()> {
     }
 }
 
+// Options for deciding bad states in parallel, only if more than one job or
+// an explicit portfolio is requested. The portfolio defaults to the solvers
+// given by `--solver` and `--sat-solver`.
+fn portfolio_options(
+    args: &ArgMatches,
+    smt_solver: &SmtType,
+    solver_timeout: Option<&u64>,
+    solver_command: &Option<String>,
+) -> Result<Option<PortfolioOptions>> {
+    let jobs = *args.get_one::<u64>("jobs").unwrap() as usize;
+    let mut solvers = collect_arg_values(args, "portfolio")
+        .iter()
+        .map(|s| PortfolioSolver::from_str(s))
+        .collect::<Result<Vec<_>>>()?;
+    if jobs == 1 && solvers.is_empty() {
+        return Ok(None);
+    }
+    let sat_solver = expect_arg::<SatType>(args, "sat-solver")?;
+    if solvers.is_empty() {
+        if *smt_solver != SmtType::Generic {
+            solvers.push(PortfolioSolver::Smt(smt_solver.clone()));
+        }
+        if sat_solver != SatType::None {
+            solvers.push(PortfolioSolver::Sat(sat_solver));
+        }
+    }
+    if solvers.is_empty() {
+        bail!("deciding bad states in parallel requires an SMT or SAT solver");
+    }
+    let sat_command = expect_optional_arg::<String>(args, "sat-solver-cmd")?;
+    for solver in &solvers {
+        let (kind, has_command) = match solver {
+            PortfolioSolver::Smt(_) => ("SMT", solver_command.is_some()),
+            PortfolioSolver::Sat(_) => ("SAT", sat_command.is_some()),
+        };
+        if let Some(option) = solver.command_option() {
+            if !has_command {
+                bail!("racing an external {} solver requires {}", kind, option);
+            }
+        }
+    }
+    Ok(Some(PortfolioOptions {
+        jobs,
+        solvers,
+        smt_options: SMTOptions {
+            timeout: solver_timeout.map(|&ms| Duration::from_millis(ms)),
+            command: solver_command.clone(),
+        },
+        sat_options: SATOptions {
+            timeout: args
+                .get_one::<u64>("sat-timeout")
+                .map(|&ms| Duration::from_millis(ms)),
+            command: sat_command,
+        },
+    }))
+}
+
+// Adds the witnesses of a solver run, a run stopped by a reachable bad state
+// still gets its witness files written before the error is passed on.
+fn collect_witnesses<F>(
+    result: Result<Vec<Witness>>,
+    witnesses: &mut Vec<Witness>,
+    write_witnesses: &F,
+) -> Result<()>
+where
+    F: Fn(&[Witness]) -> Result<()>,
+{
+    match result {
+        Ok(found) => {
+            witnesses.extend(found);
+            Ok(())
+        }
+        Err(error) => {
+            if let Some(found) = error.downcast_ref::<BadStateFound>() {
+                witnesses.extend(found.witness.clone());
+                write_witnesses(witnesses)?;
+            }
+            Err(error)
+        }
+    }
+}
+
+fn write_report(
+    report: &mut Report,
+    verdicts: &[BadStateVerdict],
+    model: Option<&Model>,
+    report_file: &Option<PathBuf>,
+) -> Result<()> {
+    if let Some(ref report_path) = report_file {
+        report.record_verdicts(verdicts);
+        let file = File::create(report_path)
+            .with_context(|| format!("Failed to create report file {:?}", report_path))?;
+        report.write_json(model, file)?;
+    }
+    Ok(())
+}
+
 fn init_logger(cli_log_level: LogLevel) -> Result<()> {
     let log_level_env_var = "MONSTER_LOG";
     let log_style_env_var = "MONSTER_LOG_STYLE";
@@ -0,0 +1,473 @@
+use crate::unicorn::bitblasting::{Gate, GateModel, GateRef, HashableGateRef};
+use crate::unicorn::parse_error::ParseError;
+use crate::unicorn::{Node, NodeRef, NodeType};
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+//
+// Public Interface
+//
+
+// Loads a combinational circuit given in ASCII (`aag`) or binary (`aig`)
+// AIGER format. Bad properties become bad states, or the outputs in case the
+// circuit has no bad properties.
+pub fn load_aiger_as_gatemodel(path: &Path) -> Result<GateModel> {
+    let mut parser = AigerParser::new();
+    parser.parse_aiger_file(path)?;
+    parser.into_model()
+}
+
+//
+// Private Implementation
+//
+
+type Literal = u64;
+
+// Operands of an AND gate, together with the line defining it.
+type Definition = (Literal, Literal, usize);
+
+struct AigerParser {
+    file: String,
+    bytes: Vec<u8>,
+    position: usize,
+    line: usize,
+    max_var: usize,
+    defined: Vec<bool>,
+    inputs: Vec<(usize, String)>,
+    definitions: Vec<Option<Definition>>,
+    outputs: Vec<(Literal, usize, String)>,
+    bad_states: Vec<(Literal, usize, String)>,
+    constraints: Vec<(Literal, usize)>,
+    gates: Vec<Option<GateRef>>,
+    negations: Vec<Option<GateRef>>,
+    expanded: Vec<bool>,
+    const_false: GateRef,
+    const_true: GateRef,
+}
+
+impl AigerParser {
+    fn new() -> Self {
+        Self {
+            file: String::new(),
+            bytes: Vec::new(),
+            position: 0,
+            line: 0,
+            max_var: 0,
+            defined: Vec::new(),
+            inputs: Vec::new(),
+            definitions: Vec::new(),
+            outputs: Vec::new(),
+            bad_states: Vec::new(),
+            constraints: Vec::new(),
+            gates: Vec::new(),
+            negations: Vec::new(),
+            expanded: Vec::new(),
+            const_false: GateRef::from(Gate::ConstFalse),
+            const_true: GateRef::from(Gate::ConstTrue),
+        }
+    }
+
+    fn error(&self, line: usize, column: usize, token: &str, expected: &str) -> anyhow::Error {
+        ParseError::new(&self.file, line, column, token, expected).into()
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        if self.position >= self.bytes.len() {
+            return None;
+        }
+        let rest = &self.bytes[self.position..];
+        let length = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+        let line = String::from_utf8_lossy(&rest[..length]).to_string();
+        self.position += length + 1;
+        self.line += 1;
+        Some(line)
+    }
+
+    fn expect_line(&mut self, expected: &str) -> Result<String> {
+        match self.read_line() {
+            Some(line) => Ok(line),
+            None => Err(self.error(self.line + 1, 1, "", expected)),
+        }
+    }
+
+    // Parses between `min` and `max` numbers separated by single spaces, the
+    // line is given from the given column on.
+    fn parse_numbers(
+        &self,
+        line: &str,
+        mut column: usize,
+        (min, max): (usize, usize),
+        expected: &str,
+    ) -> Result<Vec<u64>> {
+        let mut numbers = Vec::new();
+        for token in line.split(' ') {
+            match u64::from_str(token) {
+                Ok(number) if numbers.len() < max => numbers.push(number),
+                _ => return Err(self.error(self.line, column, token, expected)),
+            }
+            column += token.len() + 1;
+        }
+        if numbers.len() < min {
+            return Err(self.error(self.line, column, "", expected));
+        }
+        Ok(numbers)
+    }
+
+    fn parse_literal(&self, line: &str) -> Result<Literal> {
+        let expected = format!("a literal of variables 0 to {}", self.max_var);
+        let literal = self.parse_numbers(line, 1, (1, 1), &expected)?[0];
+        if literal as usize > 2 * self.max_var + 1 {
+            return Err(self.error(self.line, 1, line, &expected));
+        }
+        Ok(literal)
+    }
+
+    fn define_variable(&mut self, lhs: Literal, column: usize) -> Result<usize> {
+        let var = (lhs / 2) as usize;
+        if lhs % 2 == 1 || var == 0 || var > self.max_var || self.defined[var] {
+            let expected = "an even literal of an undefined variable";
+            return Err(self.error(self.line, column, &lhs.to_string(), expected));
+        }
+        self.defined[var] = true;
+        Ok(var)
+    }
+
+    // Deltas of binary AND gates are given in seven-bit groups, least
+    // significant group first and with the highest bit set in all but the
+    // last group.
+    fn read_delta(&mut self) -> Result<u64> {
+        let mut delta = 0;
+        let mut shift = 0;
+        loop {
+            let byte = match self.bytes.get(self.position) {
+                Some(&byte) if shift < 64 => byte,
+                _ => return Err(self.error(self.line + 1, 1, "", "a binary AND gate")),
+            };
+            self.position += 1;
+            delta |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(delta);
+            }
+            shift += 7;
+        }
+    }
+
+    fn parse_aiger_file(&mut self, path: &Path) -> Result<()> {
+        self.file = path.display().to_string();
+        self.bytes =
+            fs::read(path).with_context(|| format!("Failed to read AIGER file {:?}", path))?;
+
+        // Recognize header line.
+        let expected = "header 'aag|aig <M> <I> <L> <O> <A> [<B> <C> <J> <F>]'";
+        let header = self.expect_line(expected)?;
+        let (format, numbers) = header.split_once(' ').unwrap_or((header.as_str(), ""));
+        let binary = match format {
+            "aag" => false,
+            "aig" => true,
+            _ => return Err(self.error(1, 1, format, expected)),
+        };
+        let mut counts = self.parse_numbers(numbers, format.len() + 2, (5, 9), expected)?;
+        counts.resize(9, 0);
+        let column = |index: usize| {
+            let prefix = header.split(' ').take(index + 1);
+            prefix.map(|token| token.len() + 1).sum::<usize>() + 1
+        };
+        for (index, what) in [
+            (2, "latches"),
+            (7, "justice properties"),
+            (8, "fairness constraints"),
+        ] {
+            if counts[index] != 0 {
+                let expected = format!("no {} in a combinational circuit", what);
+                let token = counts[index].to_string();
+                return Err(self.error(1, column(index), &token, &expected));
+            }
+        }
+        let max_var = counts[0] as usize;
+        let num_inputs = counts[1] as usize;
+        let num_outputs = counts[3] as usize;
+        let num_ands = counts[4] as usize;
+        let num_bad = counts[5] as usize;
+        let num_constraints = counts[6] as usize;
+        if binary && max_var != num_inputs + num_ands {
+            let expected = format!(
+                "{} variables as given by inputs and AND gates",
+                num_inputs + num_ands
+            );
+            return Err(self.error(1, column(0), &max_var.to_string(), &expected));
+        }
+        self.max_var = max_var;
+        self.defined = vec![false; max_var + 1];
+        self.definitions = vec![None; max_var + 1];
+        self.gates = vec![None; max_var + 1];
+        self.negations = vec![None; max_var + 1];
+        self.expanded = vec![false; max_var + 1];
+
+        // Inputs are implicit in the binary format.
+        for i in 0..num_inputs {
+            if binary {
+                self.inputs.push((i + 1, format!("i{}", i)));
+            } else {
+                let line = self.expect_line("an input literal")?;
+                let lhs = self.parse_literal(&line)?;
+                let var = self.define_variable(lhs, 1)?;
+                self.inputs.push((var, format!("i{}", i)));
+            }
+        }
+        for i in 0..num_outputs {
+            let line = self.expect_line("an output literal")?;
+            let literal = self.parse_literal(&line)?;
+            self.outputs.push((literal, self.line, format!("o{}", i)));
+        }
+        for i in 0..num_bad {
+            let line = self.expect_line("a bad state literal")?;
+            let literal = self.parse_literal(&line)?;
+            self.bad_states
+                .push((literal, self.line, format!("b{}", i)));
+        }
+        for _ in 0..num_constraints {
+            let line = self.expect_line("an invariant constraint literal")?;
+            let literal = self.parse_literal(&line)?;
+            self.constraints.push((literal, self.line));
+        }
+
+        // Recognize AND gates.
+        for i in 0..num_ands {
+            if binary {
+                let lhs = 2 * (num_inputs + i + 1) as u64;
+                let delta0 = self.read_delta()?;
+                let delta1 = self.read_delta()?;
+                if delta0 > lhs || delta1 > lhs - delta0 {
+                    let expected = format!("operands of AND gate {} below it", lhs);
+                    return Err(self.error(self.line + 1, 1, "", &expected));
+                }
+                let rhs0 = lhs - delta0;
+                self.definitions[(lhs / 2) as usize] = Some((rhs0, rhs0 - delta1, self.line + 1));
+            } else {
+                let expected = format!(
+                    "AND gate '<lhs> <rhs0> <rhs1>' of variables 0 to {}",
+                    max_var
+                );
+                let line = self.expect_line(&expected)?;
+                let literals = self.parse_numbers(&line, 1, (3, 3), &expected)?;
+                if literals.iter().any(|&l| l as usize > 2 * max_var + 1) {
+                    return Err(self.error(self.line, 1, &line, &expected));
+                }
+                let var = self.define_variable(literals[0], 1)?;
+                self.definitions[var] = Some((literals[1], literals[2], self.line));
+            }
+        }
+
+        // Recognize symbol table, up to the optional comment section.
+        while let Some(line) = self.read_line() {
+            if line == "c" {
+                break;
+            }
+            let expected = "a symbol '<i|l|o|b|c|j|f><position> <name>' or comment section 'c'";
+            let (position, name) = match line.split_once(' ') {
+                Some((position, name)) if !position.is_empty() && !name.is_empty() => {
+                    (position, name)
+                }
+                _ => return Err(self.error(self.line, 1, &line, expected)),
+            };
+            let mut chars = position.chars();
+            let kind = chars.next();
+            let index = match usize::from_str(chars.as_str()) {
+                Ok(index) => index,
+                Err(_) => return Err(self.error(self.line, 1, position, expected)),
+            };
+            let symbol = match kind {
+                Some('i') => self.inputs.get_mut(index).map(|(_, symbol)| symbol),
+                Some('o') => self.outputs.get_mut(index).map(|(_, _, symbol)| symbol),
+                Some('b') => self.bad_states.get_mut(index).map(|(_, _, symbol)| symbol),
+                Some('l') | Some('c') | Some('j') | Some('f') => continue,
+                _ => return Err(self.error(self.line, 1, position, expected)),
+            };
+            match symbol {
+                Some(symbol) => *symbol = name.to_string(),
+                None => return Err(self.error(self.line, 1, position, "position of a symbol")),
+            }
+        }
+        Ok(())
+    }
+
+    fn literal_gate(&mut self, literal: Literal) -> GateRef {
+        let var = (literal / 2) as usize;
+        if var == 0 {
+            return match literal {
+                0 => self.const_false.clone(),
+                _ => self.const_true.clone(),
+            };
+        }
+        let gate = self.gates[var].clone().expect("variable resolved before");
+        if literal % 2 == 0 {
+            return gate;
+        }
+        if self.negations[var].is_none() {
+            self.negations[var] = Some(GateRef::from(Gate::Not { value: gate }));
+        }
+        self.negations[var].clone().unwrap()
+    }
+
+    // Builds the gates of the given literal and all gates it depends on,
+    // without recursion as circuits tend to be deep.
+    fn resolve(&mut self, literal: Literal, line: usize) -> Result<GateRef> {
+        let mut stack = vec![(literal / 2) as usize];
+        while let Some(&var) = stack.last() {
+            if var == 0 || self.gates[var].is_some() {
+                stack.pop();
+                continue;
+            }
+            let (rhs0, rhs1, and_line) = match self.definitions[var] {
+                Some(definition) => definition,
+                None => {
+                    let token = (2 * var).to_string();
+                    let expected = "a literal of an input or AND gate";
+                    return Err(self.error(line, 1, &token, expected));
+                }
+            };
+            let pending: Vec<usize> = [rhs0, rhs1]
+                .iter()
+                .map(|&l| (l / 2) as usize)
+                .filter(|&v| v != 0 && self.gates[v].is_none())
+                .collect();
+            if pending.is_empty() {
+                let left = self.literal_gate(rhs0);
+                let right = self.literal_gate(rhs1);
+                self.gates[var] = Some(GateRef::from(Gate::And { left, right }));
+                stack.pop();
+            } else if self.expanded[var] {
+                let token = (2 * var).to_string();
+                return Err(self.error(and_line, 1, &token, "an AND gate without cycles"));
+            } else {
+                self.expanded[var] = true;
+                stack.extend(pending);
+            }
+        }
+        Ok(self.literal_gate(literal))
+    }
+
+    fn into_model(mut self) -> Result<GateModel> {
+        let mut input_gates = Vec::new();
+        for (nid, (var, name)) in self.inputs.clone().into_iter().enumerate() {
+            let gate = GateRef::from(Gate::InputBit { name: name.clone() });
+            self.gates[var] = Some(gate.clone());
+            let node = NodeRef::from(Node::Input {
+                nid: nid as u64,
+                sort: NodeType::Bit,
+                name,
+            });
+            input_gates.push((node, vec![gate]));
+        }
+        let bad_states = if self.bad_states.is_empty() {
+            std::mem::take(&mut self.outputs)
+        } else {
+            std::mem::take(&mut self.bad_states)
+        };
+        let mut bad_state_gates = Vec::new();
+        let mut bad_state_nodes = Vec::new();
+        // Nids follow the ones of the inputs, each bad state takes two.
+        let mut nid = input_gates.len() as u64;
+        for (literal, line, name) in bad_states {
+            bad_state_gates.push(self.resolve(literal, line)?);
+            // The circuit of the output is only available as gate, the
+            // condition is a named placeholder for it.
+            let cond = NodeRef::from(Node::Input {
+                nid,
+                sort: NodeType::Bit,
+                name: name.clone(),
+            });
+            bad_state_nodes.push(NodeRef::from(Node::Bad {
+                nid: nid + 1,
+                cond,
+                name: Some(name),
+            }));
+            nid += 2;
+        }
+        let mut constraints = IndexMap::new();
+        for (literal, line) in std::mem::take(&mut self.constraints) {
+            let gate = self.resolve(literal, line)?;
+            constraints.insert(HashableGateRef::from(gate), true);
+        }
+        Ok(GateModel {
+            bad_state_gates,
+            bad_state_nodes,
+            constraints,
+            input_gates,
+            mapping: HashMap::new(),
+            mapping_adders: HashMap::new(),
+            constraint_based_dependencies: HashMap::new(),
+            latches: Vec::new(),
+            bad_states_sequential: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::get_nid;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn load(content: &[u8]) -> Result<GateModel> {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(content).unwrap();
+        load_aiger_as_gatemodel(file.path())
+    }
+
+    fn is_and_of_inputs(gate: &GateRef) -> bool {
+        matches!(&*gate.borrow(), Gate::And { left, right } if
+            matches!(&*left.borrow(), Gate::Not { value } if
+                matches!(&*value.borrow(), Gate::InputBit { name } if name == "y")) &&
+            matches!(&*right.borrow(), Gate::InputBit { name } if name == "x"))
+    }
+
+    #[test]
+    fn parse_ascii_circuit() {
+        let gate_model =
+            load(b"aag 3 2 0 1 1\n2\n4\n6\n6 5 2\ni0 x\ni1 y\no0 bad\nc\nanything\n").unwrap();
+
+        assert_eq!(gate_model.input_gates.len(), 2);
+        assert!(
+            matches!(&*gate_model.input_gates[1].0.borrow(), Node::Input { name, .. } if name == "y")
+        );
+        assert_eq!(gate_model.bad_state_gates.len(), 1);
+        assert!(is_and_of_inputs(&gate_model.bad_state_gates[0]));
+        assert!(
+            matches!(&*gate_model.bad_state_nodes[0].borrow(), Node::Bad { name: Some(name), .. } if name == "bad")
+        );
+        assert!(
+            matches!(&*gate_model.bad_state_nodes[0].borrow(), Node::Bad { nid: 3, cond, .. } if get_nid(cond) == 2)
+        );
+    }
+
+    #[test]
+    fn parse_binary_circuit() {
+        let gate_model = load(b"aig 3 2 0 1 1\n6\n\x01\x03i0 x\ni1 y\n").unwrap();
+
+        assert_eq!(gate_model.bad_state_gates.len(), 1);
+        assert!(is_and_of_inputs(&gate_model.bad_state_gates[0]));
+    }
+
+    #[test]
+    fn reject_sequential_circuit() {
+        let error = load(b"aag 2 1 1 0 0 1\n2\n4 2\n6\n").unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<ParseError>().unwrap().expected,
+            "no latches in a combinational circuit"
+        );
+    }
+
+    #[test]
+    fn reject_cyclic_and_gates() {
+        let error = load(b"aag 2 0 0 1 2\n2\n2 4 1\n4 2 1\n").unwrap_err();
+
+        assert_eq!(error.downcast_ref::<ParseError>().unwrap().line, 3);
+    }
+}
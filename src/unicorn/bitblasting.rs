@@ -1,4 +1,5 @@
 use crate::unicorn::{HashableNodeRef, Model, Node, NodeRef, NodeType};
+use indexmap::IndexMap;
 use log::{info, warn};
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -11,17 +12,38 @@ use std::rc::Rc;
 
 pub fn bitblast_model(model: &Model, constant_propagation: bool, word_size: u64) -> GateModel {
     let bitblasting = BitBlasting::new(model, constant_propagation, word_size);
-    bitblasting.process_model()
+    bitblasting.process_model(false)
+}
+
+// Same as above, but keeps the sequential part of the model: states become
+// latches and sequential bad states are bit-blasted as well.
+pub fn bitblast_sequential_model(
+    model: &Model,
+    constant_propagation: bool,
+    word_size: u64,
+) -> GateModel {
+    let bitblasting = BitBlasting::new(model, constant_propagation, word_size);
+    bitblasting.process_model(true)
 }
 
 pub struct GateModel {
     pub bad_state_gates: Vec<GateRef>,
     pub bad_state_nodes: Vec<NodeRef>,
-    pub constraints: HashMap<HashableGateRef, bool>, // this is for remainder and division, these are constraint based.
+    pub constraints: IndexMap<HashableGateRef, bool>, // this is for remainder and division, these are constraint based, kept in order of creation.
     pub input_gates: Vec<(NodeRef, Vec<GateRef>)>,
     pub mapping: HashMap<HashableNodeRef, Vec<GateRef>>, // maps a btor2 operator to its resulting bitvector of gates
     pub mapping_adders: HashMap<HashableGateRef, GateRef>,
     pub constraint_based_dependencies: HashMap<HashableGateRef, (NodeRef, NodeRef)>, // used or division and remainder, and when qubot whats to test an input (InputEvaluator).
+    pub latches: Vec<(NodeRef, Vec<Latch>)>, // maps each state of a sequential model to its latches, one per bit
+    pub bad_states_sequential: Vec<(NodeRef, GateRef)>,
+}
+
+// A single bit of a state. The current value is an input bit that is not part
+// of `GateModel::input_gates`, the value after a step is given by `next`.
+pub struct Latch {
+    pub current: GateRef,
+    pub init: Option<GateRef>,
+    pub next: GateRef,
 }
 
 pub type GateRef = Rc<RefCell<Gate>>;
@@ -435,7 +457,7 @@ fn bitwise_equal(left_operand: &[GateRef], right_operand: &[GateRef]) -> Vec<Gat
 struct BitBlasting<'a> {
     mapping: HashMap<HashableNodeRef, Vec<GateRef>>, // maps a btor2 operator to its resulting bitvector of gates
     // constant_propagation: bool, // TODO: make this flag work. Currently we always perform constant propagation
-    constraints: HashMap<HashableGateRef, bool>, // this is for remainder and division, these are constraint based.
+    constraints: IndexMap<HashableGateRef, bool>, // this is for remainder and division, these are constraint based.
     word_size: u64, // I use this attribute as a variable because maybe we will do variable-length addresses? I only use this for reads and writes.
     model: &'a Model, // BTOR2 model
     addresses_gates: Vec<Vec<GateRef>>, // memory addresses represented as vectors of (constant-)gates
@@ -449,7 +471,7 @@ impl<'a> BitBlasting<'a> {
         Self {
             mapping: HashMap::new(),
             //constant_propagation: constant_propagation_,
-            constraints: HashMap::new(),
+            constraints: IndexMap::new(),
             word_size: word_size_,
             model: model_,
             addresses_gates: get_addresses_gates(model_, &word_size_),
@@ -969,7 +991,7 @@ impl<'a> BitBlasting<'a> {
     fn record_constraint(&mut self, gate: &GateRef, value: bool) {
         let key = HashableGateRef::from(gate.clone());
 
-        if let std::collections::hash_map::Entry::Vacant(e) = self.constraints.entry(key) {
+        if let indexmap::map::Entry::Vacant(e) = self.constraints.entry(key) {
             e.insert(value);
         } else {
             panic!("Trying to set constraint, but constraint already exists")
@@ -1460,20 +1482,85 @@ impl<'a> BitBlasting<'a> {
                 }
                 self.record_mapping(node, replacement)
             }
-            Node::Next { .. } => panic!("cannot bitblast sequentials"),
+            Node::Next { .. } => panic!("sequentials are bitblasted as latches"),
             Node::Comment(_) => panic!("cannot bitblast comments"),
         }
     }
 
+    // The current values of all states are recorded up front, the init and
+    // next values of states might refer to any of them.
+    fn process_latches(&mut self) -> Vec<(NodeRef, Vec<Latch>)> {
+        let model = self.model;
+        let mut states: Vec<(NodeRef, NodeRef)> = Vec::new();
+        for sequential in &model.sequentials {
+            if let Node::Next { state, next, .. } = &*sequential.borrow() {
+                if let Node::State {
+                    sort, name, nid, ..
+                } = &*state.borrow()
+                {
+                    assert!(*sort != NodeType::Memory, "cannot bitblast memory states");
+                    let name = name.clone().unwrap_or_else(|| format!("state-{}", nid));
+                    let mut replacement: Vec<GateRef> = Vec::new();
+                    for i in 0..sort.bitsize() {
+                        let name = format!("{}[bit={}]", name, i);
+                        replacement.push(GateRef::from(Gate::InputBit { name }));
+                    }
+                    self.record_mapping(state, replacement);
+                } else {
+                    panic!("expecting 'State' node here");
+                }
+                states.push((state.clone(), next.clone()));
+            } else {
+                panic!("expecting 'Next' node here");
+            }
+        }
+        let mut latches = Vec::new();
+        for (state, next) in states {
+            let current = self.visit(&state);
+            let init = match &*state.borrow() {
+                Node::State {
+                    init: Some(value), ..
+                } => Some(self.visit(value)),
+                _ => None,
+            };
+            let next = self.visit(&next);
+            assert!(current.len() == next.len());
+            let bits = (0..current.len())
+                .map(|i| Latch {
+                    current: current[i].clone(),
+                    init: init.as_ref().map(|init| init[i].clone()),
+                    next: next[i].clone(),
+                })
+                .collect();
+            latches.push((state, bits));
+        }
+        latches
+    }
+
     // Returns a bit-blasted version of the model with a one-to-one mapping
     // between `GateModel::bad_state_gates` and `GateModel::bad_state_nodes`.
-    fn process_model(mut self) -> GateModel {
+    // For a sequential model, states become latches and its sequential bad
+    // states are kept separately in `GateModel::bad_states_sequential`.
+    fn process_model(mut self, sequential: bool) -> GateModel {
+        let latches = if sequential {
+            self.process_latches()
+        } else {
+            Vec::new()
+        };
         let mut bad_state_gates: Vec<GateRef> = Vec::new();
         for node in &self.model.bad_states_initial {
             let bitblasted_bad_state = self.process(node);
             assert!(bitblasted_bad_state.len() == 1);
             bad_state_gates.push(bitblasted_bad_state[0].clone());
         }
+        let mut bad_states_sequential = Vec::new();
+        if sequential {
+            for node in &self.model.bad_states_sequential {
+                let bitblasted_bad_state = self.process(node);
+                assert!(bitblasted_bad_state.len() == 1);
+                bad_states_sequential.push((node.clone(), bitblasted_bad_state[0].clone()));
+            }
+        }
         GateModel {
             bad_state_gates,
             bad_state_nodes: self.model.bad_states_initial.clone(),
@@ -1482,6 +1569,8 @@ impl<'a> BitBlasting<'a> {
             mapping: self.mapping,
             mapping_adders: self.mapping_adders,
             constraint_based_dependencies: self.constraint_based_dependencies,
+            latches,
+            bad_states_sequential,
         }
     }
 }
@@ -1845,4 +1934,86 @@ mod tests {
 
         assert!(are_there_2_constants(&const_true, &var, &const_true));
     }
+
+    // A bit toggling at each step, bad once it is set together with an input.
+    fn toggle_model() -> Model {
+        let toggle = NodeRef::from(Node::State {
+            nid: 2,
+            sort: NodeType::Bit,
+            init: Some(NodeRef::from(Node::Const {
+                nid: 1,
+                sort: NodeType::Bit,
+                imm: 0,
+            })),
+            name: Some("toggle".to_string()),
+        });
+        let input = NodeRef::from(Node::Input {
+            nid: 3,
+            sort: NodeType::Bit,
+            name: "input".to_string(),
+        });
+        let next = NodeRef::from(Node::Next {
+            nid: 5,
+            sort: NodeType::Bit,
+            state: toggle.clone(),
+            next: NodeRef::from(Node::Not {
+                nid: 4,
+                sort: NodeType::Bit,
+                value: toggle.clone(),
+            }),
+        });
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 7,
+            cond: NodeRef::from(Node::And {
+                nid: 6,
+                sort: NodeType::Bit,
+                left: toggle,
+                right: input,
+            }),
+            name: Some("both".to_string()),
+        });
+        Model {
+            lines: vec![],
+            sequentials: vec![next],
+            bad_states_initial: vec![],
+            bad_states_sequential: vec![bad_state],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    #[test]
+    fn bitblast_sequentials_as_latches() {
+        let gate_model = bitblast_sequential_model(&toggle_model(), true, 64);
+
+        assert!(gate_model.latches.len() == 1);
+        let latches = &gate_model.latches[0].1;
+        assert!(latches.len() == 1);
+        assert!(matches!(
+            &*latches[0].current.borrow(),
+            Gate::InputBit { name } if name == "toggle[bit=0]"
+        ));
+        assert!(matches!(
+            &*latches[0].init.as_ref().unwrap().borrow(),
+            Gate::ConstFalse
+        ));
+        assert!(matches!(
+            &*latches[0].next.borrow(),
+            Gate::Not { value } if Rc::ptr_eq(value, &latches[0].current)
+        ));
+        assert!(gate_model.input_gates.len() == 1);
+        assert!(gate_model.bad_state_gates.is_empty());
+        assert!(gate_model.bad_states_sequential.len() == 1);
+    }
+
+    #[test]
+    fn bitblast_without_sequentials() {
+        let gate_model = bitblast_model(&toggle_model(), true, 64);
+
+        assert!(gate_model.latches.is_empty());
+        assert!(gate_model.input_gates.is_empty());
+        assert!(gate_model.bad_states_sequential.is_empty());
+    }
 }
@@ -0,0 +1,583 @@
+use crate::unicorn::bitblasting::{get_constant, Gate, GateModel, GateRef, HashableGateRef};
+use crate::unicorn::{Node, NodeRef};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::io::Write;
+
+//
+// Public Interface
+//
+
+// Writes the gate model as an and-inverter graph in AIGER format, either in
+// ASCII (`aag`) or in binary (`aig`) format. Bad states become outputs, or
+// bad properties in case there are constraints (which require AIGER 1.9).
+pub fn write_aiger_model<W>(gate_model: &GateModel, binary: bool, out: W) -> Result<()>
+where
+    W: Write,
+{
+    let zip = gate_model
+        .bad_state_nodes
+        .iter()
+        .zip(gate_model.bad_state_gates.iter());
+    let mut aiger = AigerWriter::new();
+    for (_, latches) in &gate_model.latches {
+        for latch in latches {
+            aiger.add_latch(&latch.current);
+        }
+    }
+    for (_, latches) in &gate_model.latches {
+        for latch in latches {
+            aiger.convert_latch(&latch.current, latch.init.as_ref(), &latch.next)?;
+        }
+    }
+    for (bad_state, gate) in zip {
+        aiger.convert_bad_state(bad_state, gate);
+    }
+    for (bad_state, gate) in &gate_model.bad_states_sequential {
+        aiger.convert_bad_state(bad_state, gate);
+    }
+    // Constraints are kept in order of creation, which keeps the output stable.
+    for (gate, val) in &gate_model.constraints {
+        aiger.convert_constraint(&gate.value, *val);
+    }
+    if binary {
+        aiger.write_binary(out)?;
+    } else {
+        aiger.write_ascii(out)?;
+    }
+    Ok(())
+}
+
+//
+// Private Implementation
+//
+
+// Literals are twice the variable, plus one if negated. Variable zero is the
+// constant, hence literals `0` and `1` are `false` and `true`.
+type Literal = u64;
+
+const FALSE: Literal = 0;
+const TRUE: Literal = 1;
+
+struct Latch {
+    var: u64,
+    next: Literal,
+    reset: Literal,
+    name: String,
+}
+
+// Variables are numbered in order of creation while converting, they are
+// renumbered when writing as AIGER requires inputs first, then latches, then
+// AND gates in topological order.
+struct AigerWriter {
+    mapping: HashMap<HashableGateRef, Literal>,
+    current_var: u64,
+    inputs: Vec<(u64, String)>,
+    latches: Vec<Latch>,
+    ands: Vec<(u64, Literal, Literal)>,
+    and_mapping: HashMap<(Literal, Literal), Literal>,
+    bad_states: Vec<(Literal, String)>,
+    constraints: Vec<Literal>,
+}
+
+impl AigerWriter {
+    fn new() -> Self {
+        Self {
+            mapping: HashMap::new(),
+            current_var: 1,
+            inputs: Vec::new(),
+            latches: Vec::new(),
+            ands: Vec::new(),
+            and_mapping: HashMap::new(),
+            bad_states: Vec::new(),
+            constraints: Vec::new(),
+        }
+    }
+
+    fn next_var(&mut self) -> u64 {
+        let var = self.current_var;
+        self.current_var += 1;
+        var
+    }
+
+    fn and(&mut self, left: Literal, right: Literal) -> Literal {
+        if left == FALSE || right == FALSE || left == right ^ 1 {
+            return FALSE;
+        }
+        if left == TRUE || left == right {
+            return right;
+        }
+        if right == TRUE {
+            return left;
+        }
+        let key = (left.max(right), left.min(right));
+        if let Some(&literal) = self.and_mapping.get(&key) {
+            return literal;
+        }
+        let var = self.next_var();
+        self.ands.push((var, key.0, key.1));
+        self.and_mapping.insert(key, 2 * var);
+        2 * var
+    }
+
+    fn or(&mut self, left: Literal, right: Literal) -> Literal {
+        self.and(left ^ 1, right ^ 1) ^ 1
+    }
+
+    fn xor(&mut self, left: Literal, right: Literal) -> Literal {
+        let left_only = self.and(left, right ^ 1);
+        let right_only = self.and(left ^ 1, right);
+        self.or(left_only, right_only)
+    }
+
+    fn input(&mut self, name: &str) -> Literal {
+        let var = self.next_var();
+        self.inputs.push((var, name.to_string()));
+        2 * var
+    }
+
+    fn add_latch(&mut self, current: &GateRef) {
+        let name = if let Gate::InputBit { name } = &*current.borrow() {
+            name.clone()
+        } else {
+            panic!("expecting 'InputBit' gate here");
+        };
+        let var = self.next_var();
+        self.latches.push(Latch {
+            var,
+            next: FALSE,
+            reset: FALSE,
+            name,
+        });
+        let key = HashableGateRef::from(current.clone());
+        self.mapping.insert(key, 2 * var);
+    }
+
+    fn convert_latch(
+        &mut self,
+        current: &GateRef,
+        init: Option<&GateRef>,
+        next: &GateRef,
+    ) -> Result<()> {
+        let literal = self.visit(current);
+        let reset = match init.map(get_constant) {
+            Some(Some(false)) => FALSE,
+            Some(Some(true)) => TRUE,
+            // An uninitialized latch is given its own literal as reset value.
+            None => literal,
+            Some(None) => bail!("AIGER requires constant initial values of latches"),
+        };
+        let next = self.visit(next);
+        let latch = self
+            .latches
+            .iter_mut()
+            .find(|latch| 2 * latch.var == literal)
+            .expect("latch was added before");
+        latch.next = next;
+        latch.reset = reset;
+        Ok(())
+    }
+
+    fn convert_bad_state(&mut self, bad_state: &NodeRef, gate: &GateRef) {
+        let literal = self.visit(gate);
+        if let Node::Bad { name, .. } = &*bad_state.borrow() {
+            let name = name.as_deref().unwrap_or("?").to_string();
+            self.bad_states.push((literal, name));
+        } else {
+            panic!("expecting 'Bad' node here");
+        }
+    }
+
+    fn convert_constraint(&mut self, gate: &GateRef, val: bool) {
+        let literal = self.visit(gate);
+        let constraint_literal = if val { literal } else { literal ^ 1 };
+        self.constraints.push(constraint_literal);
+    }
+
+    fn visit(&mut self, gate: &GateRef) -> Literal {
+        let key = HashableGateRef::from(gate.clone());
+        self.mapping.get(&key).copied().unwrap_or_else(|| {
+            let literal = self.process(gate);
+            assert!(!self.mapping.contains_key(&key));
+            self.mapping.insert(key, literal);
+            literal
+        })
+    }
+
+    #[rustfmt::skip]
+    fn process(&mut self, gate: &GateRef) -> Literal {
+        match &*gate.borrow() {
+            Gate::ConstTrue => TRUE,
+            Gate::ConstFalse => FALSE,
+            Gate::InputBit { name } => self.input(name),
+            Gate::Not { value } => self.visit(value) ^ 1,
+            Gate::And { left, right } => {
+                let left_literal = self.visit(left);
+                let right_literal = self.visit(right);
+                self.and(left_literal, right_literal)
+            }
+            Gate::Nand { left, right } => {
+                let left_literal = self.visit(left);
+                let right_literal = self.visit(right);
+                self.and(left_literal, right_literal) ^ 1
+            }
+            Gate::Or { left, right } => {
+                let left_literal = self.visit(left);
+                let right_literal = self.visit(right);
+                self.or(left_literal, right_literal)
+            }
+            Gate::Matriarch1 { cond, right } => {
+                let cond_literal = self.visit(cond);
+                let right_literal = self.visit(right);
+                // Modeling as `M := and(not(A), B)` here:
+                self.and(cond_literal ^ 1, right_literal)
+            }
+            Gate::CarryHalfAdder { left, right } => {
+                let left_literal = self.visit(left);
+                let right_literal = self.visit(right);
+                // Modeling as `C := and(A, B)` here:
+                self.and(left_literal, right_literal)
+            }
+            Gate::ResultHalfAdder { input1, input2 } => {
+                let input1_literal = self.visit(input1);
+                let input2_literal = self.visit(input2);
+                // Modeling as `S := xor(A, B)` here:
+                self.xor(input1_literal, input2_literal)
+            }
+            Gate::CarryFullAdder { input1, input2, input3 } => {
+                let input1_literal = self.visit(input1);
+                let input2_literal = self.visit(input2);
+                let input3_literal = self.visit(input3);
+                // Modeling as `C_out := or(and(xor(A, B), C_in), and(A, B))` here:
+                let inner_xor = self.xor(input1_literal, input2_literal);
+                let inner_and1 = self.and(inner_xor, input3_literal);
+                let inner_and2 = self.and(input1_literal, input2_literal);
+                self.or(inner_and1, inner_and2)
+            }
+            Gate::ResultFullAdder { input1, input2, input3 } => {
+                let input1_literal = self.visit(input1);
+                let input2_literal = self.visit(input2);
+                let input3_literal = self.visit(input3);
+                // Modeling as `S := xor(xor(A, B), C_in)` here:
+                let inner_xor = self.xor(input1_literal, input2_literal);
+                self.xor(inner_xor, input3_literal)
+            }
+            Gate::Quotient { name, .. } | Gate::Remainder { name, .. } => self.input(name),
+        }
+    }
+
+    // Maps variables in order of creation to the numbering required by AIGER.
+    fn renumbering(&self) -> Vec<u64> {
+        let mut renumbering = vec![0; self.current_var as usize];
+        let vars = self
+            .inputs
+            .iter()
+            .map(|(var, _)| *var)
+            .chain(self.latches.iter().map(|latch| latch.var))
+            .chain(self.ands.iter().map(|(var, _, _)| *var));
+        for (i, var) in vars.enumerate() {
+            renumbering[var as usize] = i as u64 + 1;
+        }
+        renumbering
+    }
+
+    fn write_header<W: Write>(&self, format: &str, mut out: W) -> Result<()> {
+        let num_inputs = self.inputs.len();
+        let num_latches = self.latches.len();
+        let num_ands = self.ands.len();
+        let num_vars = num_inputs + num_latches + num_ands;
+        write!(
+            out,
+            "{} {} {} {}",
+            format, num_vars, num_inputs, num_latches
+        )?;
+        if self.constraints.is_empty() {
+            writeln!(out, " {} {}", self.bad_states.len(), num_ands)?;
+        } else {
+            let num_bad = self.bad_states.len();
+            let num_constraints = self.constraints.len();
+            writeln!(out, " 0 {} {} {}", num_ands, num_bad, num_constraints)?;
+        }
+        Ok(())
+    }
+
+    fn write_outputs<W: Write>(
+        &self,
+        renumber: &dyn Fn(Literal) -> Literal,
+        mut out: W,
+    ) -> Result<()> {
+        for (literal, _) in &self.bad_states {
+            writeln!(out, "{}", renumber(*literal))?;
+        }
+        for literal in &self.constraints {
+            writeln!(out, "{}", renumber(*literal))?;
+        }
+        Ok(())
+    }
+
+    fn write_symbols<W: Write>(&self, mut out: W) -> Result<()> {
+        for (i, (_, name)) in self.inputs.iter().enumerate() {
+            writeln!(out, "i{} {}", i, name)?;
+        }
+        for (i, latch) in self.latches.iter().enumerate() {
+            writeln!(out, "l{} {}", i, latch.name)?;
+        }
+        let kind = if self.constraints.is_empty() {
+            "o"
+        } else {
+            "b"
+        };
+        for (i, (_, name)) in self.bad_states.iter().enumerate() {
+            writeln!(out, "{}{} {}", kind, i, name)?;
+        }
+        writeln!(out, "c\ncksystemsgroup.github.io/unicorn")?;
+        Ok(())
+    }
+
+    fn write_ascii<W: Write>(&self, mut out: W) -> Result<()> {
+        let renumbering = self.renumbering();
+        let renumber = |literal: Literal| 2 * renumbering[(literal / 2) as usize] + literal % 2;
+        self.write_header("aag", &mut out)?;
+        for (var, _) in &self.inputs {
+            writeln!(out, "{}", renumber(2 * var))?;
+        }
+        for latch in &self.latches {
+            let current = renumber(2 * latch.var);
+            let next = renumber(latch.next);
+            match latch.reset {
+                FALSE => writeln!(out, "{} {}", current, next)?,
+                reset => writeln!(out, "{} {} {}", current, next, renumber(reset))?,
+            }
+        }
+        self.write_outputs(&renumber, &mut out)?;
+        for (var, left, right) in &self.ands {
+            let (left, right) = ordered(renumber(*left), renumber(*right));
+            writeln!(out, "{} {} {}", renumber(2 * var), left, right)?;
+        }
+        self.write_symbols(&mut out)?;
+        Ok(())
+    }
+
+    // Inputs and the current values of latches are implicit in the binary
+    // format, AND gates are given as deltas to their operands.
+    fn write_binary<W: Write>(&self, mut out: W) -> Result<()> {
+        let renumbering = self.renumbering();
+        let renumber = |literal: Literal| 2 * renumbering[(literal / 2) as usize] + literal % 2;
+        self.write_header("aig", &mut out)?;
+        for latch in &self.latches {
+            let next = renumber(latch.next);
+            match latch.reset {
+                FALSE => writeln!(out, "{}", next)?,
+                reset => writeln!(out, "{} {}", next, renumber(reset))?,
+            }
+        }
+        self.write_outputs(&renumber, &mut out)?;
+        for (var, left, right) in &self.ands {
+            let lhs = renumber(2 * var);
+            let (left, right) = ordered(renumber(*left), renumber(*right));
+            assert!(lhs > left && left >= right);
+            write_delta(lhs - left, &mut out)?;
+            write_delta(left - right, &mut out)?;
+        }
+        self.write_symbols(&mut out)?;
+        Ok(())
+    }
+}
+
+fn ordered(left: Literal, right: Literal) -> (Literal, Literal) {
+    (left.max(right), left.min(right))
+}
+
+// Deltas are written in seven-bit groups, least significant group first and
+// with the highest bit set in all but the last group.
+fn write_delta<W: Write>(mut delta: u64, mut out: W) -> Result<()> {
+    while delta >= 0x80 {
+        out.write_all(&[(delta & 0x7f) as u8 | 0x80])?;
+        delta >>= 7;
+    }
+    out.write_all(&[delta as u8])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::aiger_parser::load_aiger_as_gatemodel;
+    use crate::unicorn::bitblasting::bitblast_model;
+    use crate::unicorn::{Model, NodeType};
+    use indexmap::IndexMap;
+    use tempfile::NamedTempFile;
+
+    // A bad state checking whether the quotient of two inputs is zero.
+    fn division_model() -> Model {
+        let input = |nid: u64, name: &str| {
+            NodeRef::from(Node::Input {
+                nid,
+                sort: NodeType::Input1Byte,
+                name: name.to_string(),
+            })
+        };
+        let ext = |nid: u64, value: NodeRef| {
+            NodeRef::from(Node::Ext {
+                nid,
+                from: NodeType::Input1Byte,
+                value,
+            })
+        };
+        let divu = NodeRef::from(Node::Divu {
+            nid: 5,
+            left: ext(3, input(1, "x")),
+            right: ext(4, input(2, "y")),
+        });
+        let zero = NodeRef::from(Node::Const {
+            nid: 6,
+            sort: NodeType::Word,
+            imm: 0,
+        });
+        let eq = NodeRef::from(Node::Eq {
+            nid: 7,
+            left: divu,
+            right: zero,
+        });
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 8,
+            cond: eq,
+            name: Some("quotient-is-zero".to_string()),
+        });
+        Model {
+            lines: vec![],
+            sequentials: vec![],
+            bad_states_initial: vec![bad_state],
+            bad_states_sequential: vec![],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    // A bad state given directly as the gate `Matriarch1(a, b) = and(not(a), b)`.
+    fn matriarch_model() -> GateModel {
+        let input = |name: &str| {
+            GateRef::from(Gate::InputBit {
+                name: name.to_string(),
+            })
+        };
+        let (a, b) = (input("a"), input("b"));
+        let matriarch = GateRef::from(Gate::Matriarch1 {
+            cond: a.clone(),
+            right: b.clone(),
+        });
+        let input_node = |nid: u64, name: &str| {
+            NodeRef::from(Node::Input {
+                nid,
+                sort: NodeType::Bit,
+                name: name.to_string(),
+            })
+        };
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 3,
+            cond: input_node(2, "m"),
+            name: Some("m".to_string()),
+        });
+        GateModel {
+            bad_state_gates: vec![matriarch],
+            bad_state_nodes: vec![bad_state],
+            constraints: IndexMap::new(),
+            input_gates: vec![(input_node(0, "a"), vec![a]), (input_node(1, "b"), vec![b])],
+            mapping: HashMap::new(),
+            mapping_adders: HashMap::new(),
+            constraint_based_dependencies: HashMap::new(),
+            latches: Vec::new(),
+            bad_states_sequential: Vec::new(),
+        }
+    }
+
+    fn write_and_load(gate_model: &GateModel, binary: bool) -> (Vec<u8>, GateModel) {
+        let mut file = NamedTempFile::new().unwrap();
+        write_aiger_model(gate_model, binary, &mut file).unwrap();
+        let out = std::fs::read(file.path()).unwrap();
+        (out, load_aiger_as_gatemodel(file.path()).unwrap())
+    }
+
+    fn is_input(gate: &GateRef, expected: &str) -> bool {
+        matches!(&*gate.borrow(), Gate::InputBit { name } if name == expected)
+    }
+
+    // The only AND gate is `and(b, not(a))`, operands in descending order.
+    fn is_lowered_matriarch(gate: &GateRef) -> bool {
+        matches!(&*gate.borrow(), Gate::And { left, right } if
+            is_input(left, "b") &&
+            matches!(&*right.borrow(), Gate::Not { value } if is_input(value, "a")))
+    }
+
+    #[test]
+    fn write_exact_ascii_model() {
+        let (out, loaded) = write_and_load(&matriarch_model(), false);
+
+        let expected = "aag 3 2 0 1 1\n2\n4\n6\n6 4 3\ni0 a\ni1 b\no0 m\n\
+                        c\ncksystemsgroup.github.io/unicorn\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(loaded.bad_state_gates.len(), 1);
+        assert!(is_lowered_matriarch(&loaded.bad_state_gates[0]));
+    }
+
+    #[test]
+    fn write_exact_binary_model() {
+        let (out, loaded) = write_and_load(&matriarch_model(), true);
+
+        let expected = b"aig 3 2 0 1 1\n6\n\x02\x01i0 a\ni1 b\no0 m\n\
+                         c\ncksystemsgroup.github.io/unicorn\n";
+        assert_eq!(out, expected.to_vec());
+        assert_eq!(loaded.bad_state_gates.len(), 1);
+        assert!(is_lowered_matriarch(&loaded.bad_state_gates[0]));
+    }
+
+    #[test]
+    fn write_delta_in_seven_bit_groups() {
+        let mut out = Vec::new();
+        write_delta(1, &mut out).unwrap();
+        write_delta(0x80, &mut out).unwrap();
+        write_delta(16387, &mut out).unwrap();
+
+        assert_eq!(out, vec![0x01, 0x80, 0x01, 0x83, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn lower_gates_to_ands_and_inverters() {
+        let mut aiger = AigerWriter::new();
+        let a = GateRef::from(Gate::InputBit {
+            name: "a".to_string(),
+        });
+        let b = GateRef::from(Gate::InputBit {
+            name: "b".to_string(),
+        });
+        let or = GateRef::from(Gate::Or {
+            left: a.clone(),
+            right: b.clone(),
+        });
+        let matriarch = GateRef::from(Gate::Matriarch1 {
+            cond: a,
+            right: or.clone(),
+        });
+
+        assert_eq!(aiger.visit(&or), 7);
+        assert_eq!(aiger.visit(&matriarch), 8);
+        assert_eq!(aiger.ands, vec![(3, 5, 3), (4, 7, 3)]);
+        assert_eq!(aiger.inputs.len(), 2);
+    }
+
+    #[test]
+    fn write_constraints_deterministically() {
+        let write = || {
+            let gate_model = bitblast_model(&division_model(), true, 64);
+            let mut out = Vec::new();
+            write_aiger_model(&gate_model, false, &mut out).unwrap();
+            out
+        };
+
+        let out = write();
+        assert!(String::from_utf8_lossy(&out).starts_with("aag"));
+        assert_eq!(out, write());
+    }
+}
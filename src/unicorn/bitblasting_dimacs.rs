@@ -1,8 +1,13 @@
 use crate::unicorn::bitblasting::{GateModel, GateRef};
 use crate::unicorn::cnf::{CNFBuilder, CNFContainer};
+use crate::unicorn::sat_solver::external_impl::parse_output;
+use crate::unicorn::witness::Witness;
 use crate::unicorn::{Node, NodeRef};
-use anyhow::Result;
+use anyhow::{bail, ensure, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
 use std::io::Write;
+use std::path::Path;
 
 //
 // Public Interface
@@ -12,6 +17,10 @@ pub fn write_dimacs_model<W>(gate_model: &GateModel, out: W) -> Result<()>
 where
     W: Write,
 {
+    ensure!(
+        gate_model.latches.is_empty(),
+        "printing DIMACS requires a combinational model, unroll it first"
+    );
     let zip = gate_model
         .bad_state_nodes
         .iter()
@@ -27,10 +36,60 @@ where
     Ok(())
 }
 
+// Reconstructs the values of all input words from the model of a CNF file
+// written above, as given by the `v` lines of a SAT solver (or the result
+// file of MiniSat). Input bits are identified by the variable names recorded
+// in the comments of the file.
+pub fn decode_dimacs_witness(cnf_path: &Path, solution_path: &Path) -> Result<Witness> {
+    let cnf = fs::read_to_string(cnf_path)
+        .with_context(|| format!("Failed to read DIMACS file {:?}", cnf_path))?;
+    if cnf.lines().next() != Some(HEADER) {
+        bail!(
+            "{:?} does not look like a CNF file written by unicorn",
+            cnf_path
+        );
+    }
+    let solution = fs::read_to_string(solution_path)
+        .with_context(|| format!("Failed to read SAT solver output {:?}", solution_path))?;
+    let (verdict, model) = parse_output(&solution);
+    match verdict {
+        Some(true) => (),
+        Some(false) => bail!("formula is unsatisfiable, there is no model to decode"),
+        None if model.is_empty() => bail!("{:?} holds no model of a SAT solver", solution_path),
+        None => (),
+    }
+
+    let mut words: BTreeMap<String, u64> = BTreeMap::new();
+    for line in cnf.lines() {
+        let (variable, name) = match line.strip_prefix("c   ").and_then(|l| l.split_once(": ")) {
+            Some(mapping) => mapping,
+            None => continue,
+        };
+        // Only input bits are named `<name>[bit=<index>]`, bad states are not.
+        let (word, bit) = match name.strip_suffix(']').and_then(|n| n.rsplit_once("[bit=")) {
+            Some(split) => split,
+            None => continue,
+        };
+        let variable = variable.parse::<i32>();
+        let bit = bit.parse::<u32>();
+        let (variable, bit) = match (variable, bit) {
+            (Ok(variable), Ok(bit)) if bit < 64 => (variable, bit),
+            _ => bail!("malformed variable name mapping '{}'", line),
+        };
+        let value = words.entry(word.to_string()).or_insert(0);
+        if model.contains(&variable) {
+            *value |= 1 << bit;
+        }
+    }
+    Ok(Witness::from_assignments(words))
+}
+
 //
 // Private Implementation
 //
 
+const HEADER: &str = "c cksystemsgroup.github.io/unicorn";
+
 type Variable = u32;
 
 #[derive(Clone)]
@@ -145,7 +204,7 @@ impl DimacsWriter {
         let num_names = self.cnf().variable_names.len();
         let num_clauses = self.cnf().formula.len() + 1;
         let num_vars = self.cnf().current_var - 1;
-        writeln!(out, "c cksystemsgroup.github.io/unicorn")?;
+        writeln!(out, "{}", HEADER)?;
         writeln!(out, "c CNF contains a total of {} clauses.", num_clauses)?;
         writeln!(out, "c CNF contains a total of {} variables.", num_vars)?;
         writeln!(out, "c CNF clauses cover {} bad states.", num_bad)?;
@@ -161,3 +220,54 @@ impl DimacsWriter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn file_of(content: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "{}", content).unwrap();
+        file
+    }
+
+    fn cnf_file() -> NamedTempFile {
+        file_of(concat!(
+            "c cksystemsgroup.github.io/unicorn\n",
+            "c Variable name mapping (3 names):\n",
+            "c   1: 1-byte-input[n=0][bit=0]\n",
+            "c   2: 1-byte-input[n=0][bit=6]\n",
+            "c   3: bad[n=0]\n",
+            "p cnf 3 1\n",
+            "3 0\n",
+        ))
+    }
+
+    #[test]
+    fn decode_input_words_from_model() {
+        let solution = file_of("s SATISFIABLE\nv 1 2 3 0\n");
+        let witness = decode_dimacs_witness(cnf_file().path(), solution.path()).unwrap();
+
+        assert_eq!(witness.value("1-byte-input[n=0]"), Some(0x41));
+        assert_eq!(witness.value("bad[n=0]"), None);
+        assert_eq!(witness.stdin_bytes(), b"A".to_vec());
+    }
+
+    #[test]
+    fn decode_input_words_from_minisat_result() {
+        let solution = file_of("SAT\n1 -2 3 0\n");
+        let witness = decode_dimacs_witness(cnf_file().path(), solution.path()).unwrap();
+
+        assert_eq!(witness.value("1-byte-input[n=0]"), Some(0x01));
+        assert!(decode_dimacs_witness(cnf_file().path(), file_of("UNSAT\n").path()).is_err());
+    }
+
+    #[test]
+    fn decode_fails_without_model() {
+        let solution = file_of("s UNSATISFIABLE\n");
+
+        assert!(decode_dimacs_witness(cnf_file().path(), solution.path()).is_err());
+        assert!(decode_dimacs_witness(solution.path(), solution.path()).is_err());
+    }
+}
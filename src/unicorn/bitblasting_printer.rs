@@ -1,7 +1,7 @@
-use crate::unicorn::bitblasting::{Gate, GateModel, GateRef, HashableGateRef};
+use crate::unicorn::bitblasting::{Gate, GateModel, GateRef, HashableGateRef, Latch};
 use crate::unicorn::{Nid, Node, NodeRef};
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 
 //
@@ -16,11 +16,20 @@ where
         .bad_state_nodes
         .iter()
         .zip(gate_model.bad_state_gates.iter());
-    let mut printer = GateModelPrinter::new(out);
+    let latches: Vec<&Latch> = gate_model
+        .latches
+        .iter()
+        .flat_map(|(_, latches)| latches)
+        .collect();
+    let mut printer = GateModelPrinter::new(out, &latches);
     printer.print_file_header()?;
+    printer.print_latches(&latches)?;
     for (bad_state, gate) in zip {
         printer.print_bad_state(bad_state, gate)?;
     }
+    for (bad_state, gate) in &gate_model.bad_states_sequential {
+        printer.print_bad_state(bad_state, gate)?;
+    }
     for (gate, val) in &gate_model.constraints {
         printer.print_constraint(&gate.value, *val)?;
     }
@@ -35,18 +44,34 @@ where
 struct GateModelPrinter<W> {
     current_nid: Nid,
     nid_mapping: HashMap<HashableGateRef, Nid>,
+    latch_gates: HashSet<HashableGateRef>, // current values of latches, these are printed as states
     out: W,
 }
 
 impl<W: Write> GateModelPrinter<W> {
-    fn new(out: W) -> Self {
+    fn new(out: W, latches: &[&Latch]) -> Self {
         Self {
             current_nid: 70000000,
             nid_mapping: HashMap::new(),
+            latch_gates: latches
+                .iter()
+                .map(|latch| HashableGateRef::from(latch.current.clone()))
+                .collect(),
             out,
         }
     }
 
+    // Input bits of a sequential model are fresh at each step, only latches
+    // keep their value. Combinational models print all of them as states.
+    fn input_keyword(&self, gate: &GateRef) -> &'static str {
+        let key = HashableGateRef::from(gate.clone());
+        if self.latch_gates.is_empty() || self.latch_gates.contains(&key) {
+            "state"
+        } else {
+            "input"
+        }
+    }
+
     fn next_nid(&mut self) -> Nid {
         let nid = self.current_nid;
         self.current_nid += 1;
@@ -77,8 +102,9 @@ impl<W: Write> GateModelPrinter<W> {
                 Ok(gate_nid)
             }
             Gate::InputBit { name } => {
+                let keyword = self.input_keyword(gate);
                 let gate_nid = self.next_nid();
-                writeln!(self.out, "{} state 1 {}", gate_nid, name)?;
+                writeln!(self.out, "{} {} 1 {}", gate_nid, keyword, name)?;
                 Ok(gate_nid)
             }
             Gate::Not { value } => {
@@ -161,8 +187,9 @@ impl<W: Write> GateModelPrinter<W> {
                 Ok(gate_nid)
             }
             Gate::Quotient { name, .. } | Gate::Remainder { name, .. } => {
+                let keyword = self.input_keyword(gate);
                 let gate_nid = self.next_nid();
-                writeln!(self.out, "{} state 1 {}", gate_nid, name)?;
+                writeln!(self.out, "{} {} 1 {}", gate_nid, keyword, name)?;
                 Ok(gate_nid)
             }
         }
@@ -175,6 +202,28 @@ impl<W: Write> GateModelPrinter<W> {
         Ok(())
     }
 
+    fn print_latches(&mut self, latches: &[&Latch]) -> Result<()> {
+        if latches.is_empty() {
+            return Ok(());
+        }
+        writeln!(self.out, "; latches\n")?;
+        let state_nids: Vec<Nid> = latches.iter().map(|l| self.visit(&l.current)).collect();
+        for (latch, state_nid) in latches.iter().zip(&state_nids) {
+            if let Some(init) = &latch.init {
+                let init_nid = self.visit(init);
+                let nid = self.next_nid();
+                writeln!(self.out, "{} init 1 {} {}", nid, state_nid, init_nid)?;
+            }
+        }
+        for (latch, state_nid) in latches.iter().zip(&state_nids) {
+            let next_nid = self.visit(&latch.next);
+            let nid = self.next_nid();
+            writeln!(self.out, "{} next 1 {} {}", nid, state_nid, next_nid)?;
+        }
+        writeln!(self.out)?;
+        Ok(())
+    }
+
     fn print_bad_state(&mut self, bad_state: &NodeRef, gate: &GateRef) -> Result<()> {
         let bad_condition_nid = self.visit(gate);
         if let Node::Bad { name, .. } = &*bad_state.borrow() {
@@ -211,3 +260,71 @@ impl<W: Write> GateModelPrinter<W> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::bitblasting::bitblast_sequential_model;
+    use crate::unicorn::{Model, NodeType};
+
+    #[test]
+    fn print_latches_with_init_and_next() {
+        let flag = NodeRef::from(Node::State {
+            nid: 2,
+            sort: NodeType::Bit,
+            init: Some(NodeRef::from(Node::Const {
+                nid: 1,
+                sort: NodeType::Bit,
+                imm: 0,
+            })),
+            name: Some("flag".to_string()),
+        });
+        let input = NodeRef::from(Node::Input {
+            nid: 3,
+            sort: NodeType::Bit,
+            name: "raise".to_string(),
+        });
+        let next = NodeRef::from(Node::Next {
+            nid: 4,
+            sort: NodeType::Bit,
+            state: flag.clone(),
+            next: input,
+        });
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 5,
+            cond: flag,
+            name: Some("raised".to_string()),
+        });
+        let model = Model {
+            lines: vec![],
+            sequentials: vec![next],
+            bad_states_initial: vec![],
+            bad_states_sequential: vec![bad_state],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        };
+        let mut out = Vec::new();
+        write_btor2_model(&bitblast_sequential_model(&model, true, 64), &mut out).unwrap();
+        let lines: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with(';'))
+            .map(String::from)
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "1 sort bitvec 1 ; Boolean",
+                "70000000 state 1 flag[bit=0]",
+                "70000001 constd 1 0",
+                "70000002 init 1 70000000 70000001",
+                "70000003 input 1 raise[bit=0]",
+                "70000004 next 1 70000000 70000003",
+                "70000005 bad 70000000 raised",
+            ]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use crate::unicorn::bitblasting::bitblast_model;
+use crate::unicorn::report::{BadStateVerdict, Verdict};
+use crate::unicorn::sat_solver::{solve_bad_states, SATOptions};
+use crate::unicorn::smt_solver::{SMTOptions, SMTSolution, SMTSolver};
+use crate::unicorn::unroller::{split_unrolled_name, unroll_model};
+use crate::unicorn::witness::Witness;
+use crate::unicorn::{Model, Node, NodeRef};
+use crate::SatType;
+use anyhow::Result;
+use log::{debug, info, warn};
+use std::time::{Duration, Instant};
+
+//
+// Public Interface
+//
+
+pub struct BmcOptions {
+    pub max_depth: usize,
+    pub time_budget: Option<Duration>,
+    pub all_kinds: bool,
+}
+
+/// Shortest depth at which a kind of bad state (i.e. a bad state of the
+/// sequential model) was found to be reachable.
+#[derive(Debug)]
+pub struct Counterexample {
+    pub kind: String,
+    pub name: String,
+    pub depth: usize,
+    pub witness: Witness,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BmcStop {
+    Counterexample,
+    DepthBudget,
+    TimeBudget,
+}
+
+pub struct BmcResult {
+    pub kinds: Vec<String>,
+    pub counterexamples: Vec<Counterexample>,
+    pub depth: usize,
+    pub stop: BmcStop,
+}
+
+// Performs bounded model checking with iterative deepening. The sequential
+// model is unrolled one step at a time and only the bad states added by the
+// latest step are checked. Stops at the first depth with a reachable bad
+// state, or once all kinds of bad states are reachable with `all_kinds`.
+pub fn bmc_with_smt<S: SMTSolver>(
+    model: &mut Model,
+    smt_options: &SMTOptions,
+    options: &BmcOptions,
+) -> Result<BmcResult> {
+    debug!("Checking model using '{}' SMT solver ...", S::name());
+    let mut solver = S::new(smt_options);
+    bmc_impl(model, options, |_, bad_states| {
+        let mut verdicts = Vec::new();
+        let mut witnesses = Vec::new();
+        for bad_state in bad_states {
+            let cond = if let Node::Bad { cond, .. } = &*bad_state.borrow() {
+                cond.clone()
+            } else {
+                panic!("expecting 'Bad' node here");
+            };
+            let verdict = match solver.solve(&cond) {
+                SMTSolution::Sat(witness) => {
+                    witnesses.push(witness.for_bad_state(bad_state));
+                    Verdict::Sat
+                }
+                SMTSolution::Unsat => {
+                    // The solver keeps translations of the unrolled prefix
+                    // and learns that this bad state is unreachable.
+                    solver.assert_false(&cond);
+                    Verdict::Unsat
+                }
+                SMTSolution::Timeout => Verdict::Timeout,
+            };
+            verdicts.push(BadStateVerdict {
+                bad_state: bad_state.clone(),
+                verdict,
+                solver: S::name(),
+            });
+        }
+        Ok((verdicts, witnesses))
+    })
+}
+
+// Same as above, but bitblasts the bad states of each step and decides them
+// with the given SAT solver. Memory has to be discretized already.
+pub fn bmc_with_sat(
+    model: &mut Model,
+    sat_type: SatType,
+    sat_options: &SATOptions,
+    options: &BmcOptions,
+) -> Result<BmcResult> {
+    debug!("Checking model using {:?} SAT solver ...", sat_type);
+    bmc_impl(model, options, |model, bad_states| {
+        let step = Model {
+            lines: vec![],
+            sequentials: vec![],
+            bad_states_initial: bad_states.to_vec(),
+            bad_states_sequential: vec![],
+            data_range: model.data_range.clone(),
+            heap_range: model.heap_range.clone(),
+            stack_range: model.stack_range.clone(),
+            memory_size: model.memory_size,
+        };
+        let gate_model = bitblast_model(&step, true, 64);
+        let mut verdicts = Vec::new();
+        let witnesses = solve_bad_states(
+            &gate_model,
+            sat_type,
+            sat_options,
+            false,
+            false,
+            &mut verdicts,
+        )?;
+        Ok((verdicts, witnesses))
+    })
+}
+
+//
+// Private Implementation
+//
+
+fn bad_state_name(bad_state: &NodeRef) -> String {
+    if let Node::Bad { name, .. } = &*bad_state.borrow() {
+        name.as_deref().unwrap_or("?").to_string()
+    } else {
+        panic!("expecting 'Bad' node here");
+    }
+}
+
+// The given function decides the bad states of a single step. It yields
+// the verdict for each of them and the witnesses of satisfiable ones, in the
+// order of their verdicts.
+fn bmc_impl<F>(model: &mut Model, options: &BmcOptions, mut check: F) -> Result<BmcResult>
+where
+    F: FnMut(&Model, &[NodeRef]) -> Result<(Vec<BadStateVerdict>, Vec<Witness>)>,
+{
+    let start = Instant::now();
+    let kinds: Vec<String> = model
+        .bad_states_sequential
+        .iter()
+        .map(bad_state_name)
+        .collect();
+    let mut counterexamples: Vec<Counterexample> = Vec::new();
+    let result = |counterexamples, depth, stop| BmcResult {
+        kinds: kinds.clone(),
+        counterexamples,
+        depth,
+        stop,
+    };
+    model.lines.clear();
+    model.bad_states_initial.clear();
+    for depth in 0..options.max_depth {
+        if options.time_budget.map_or(false, |t| start.elapsed() >= t) {
+            return Ok(result(counterexamples, depth, BmcStop::TimeBudget));
+        }
+        unroll_model(model, depth);
+        // Checked bad states are dropped, so that only the copies of the
+        // current step remain. Kinds already found reachable are skipped.
+        let bad_states: Vec<NodeRef> = model
+            .bad_states_initial
+            .drain(..)
+            .filter(|bad_state| {
+                let name = bad_state_name(bad_state);
+                let kind = split_unrolled_name(&name).0;
+                !counterexamples.iter().any(|c| c.kind == kind)
+            })
+            .collect();
+        debug!(
+            "Checking {} bad states at depth {} ...",
+            bad_states.len(),
+            depth
+        );
+        let (verdicts, witnesses) = check(model, &bad_states)?;
+        let mut witnesses = witnesses.into_iter();
+        for verdict in verdicts {
+            let name = bad_state_name(&verdict.bad_state);
+            match verdict.verdict {
+                Verdict::Sat => {
+                    warn!("Bad state '{}' is reachable at depth {}!", name, depth);
+                    counterexamples.push(Counterexample {
+                        kind: split_unrolled_name(&name).0.to_string(),
+                        name,
+                        depth,
+                        witness: witnesses.next().unwrap_or_default(),
+                    });
+                }
+                Verdict::Timeout | Verdict::Unknown => {
+                    warn!("Bad state '{}' is unknown at depth {}", name, depth);
+                }
+                Verdict::Unsat | Verdict::Removed => (),
+            }
+        }
+        let all_found = counterexamples.len() == kinds.len();
+        if !counterexamples.is_empty() && (!options.all_kinds || all_found) {
+            return Ok(result(counterexamples, depth + 1, BmcStop::Counterexample));
+        }
+        info!("Checked all bad states at depth {}", depth);
+    }
+    Ok(result(
+        counterexamples,
+        options.max_depth,
+        BmcStop::DepthBudget,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::NodeType;
+
+    fn word(nid: u64, imm: u64) -> NodeRef {
+        NodeRef::from(Node::Const {
+            nid,
+            sort: NodeType::Word,
+            imm,
+        })
+    }
+
+    fn bad_state(nid: u64, cond: &NodeRef, name: &str) -> NodeRef {
+        NodeRef::from(Node::Bad {
+            nid,
+            cond: cond.clone(),
+            name: Some(name.to_string()),
+        })
+    }
+
+    // A counter starting at zero, with bad states for some of its values.
+    fn counter_model() -> Model {
+        let counter = NodeRef::from(Node::State {
+            nid: 2,
+            sort: NodeType::Word,
+            init: Some(word(1, 0)),
+            name: Some("counter".to_string()),
+        });
+        let add = NodeRef::from(Node::Add {
+            nid: 4,
+            left: counter.clone(),
+            right: word(3, 1),
+        });
+        let next = NodeRef::from(Node::Next {
+            nid: 5,
+            sort: NodeType::Word,
+            state: counter.clone(),
+            next: add,
+        });
+        let is_two = NodeRef::from(Node::Eq {
+            nid: 7,
+            left: counter.clone(),
+            right: word(6, 2),
+        });
+        let is_five = NodeRef::from(Node::Eq {
+            nid: 9,
+            left: counter,
+            right: word(8, 5),
+        });
+        Model {
+            lines: vec![],
+            sequentials: vec![next],
+            bad_states_initial: vec![],
+            bad_states_sequential: vec![
+                bad_state(10, &is_two, "two"),
+                bad_state(11, &is_five, "five"),
+            ],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    // Stands in for a solver, the counter equals the unroll depth.
+    fn check(
+        _model: &Model,
+        bad_states: &[NodeRef],
+    ) -> Result<(Vec<BadStateVerdict>, Vec<Witness>)> {
+        let verdicts = bad_states
+            .iter()
+            .map(|bad_state| {
+                let name = bad_state_name(bad_state);
+                let verdict = match split_unrolled_name(&name) {
+                    ("two", Some(2)) | ("five", Some(5)) => Verdict::Sat,
+                    _ => Verdict::Unsat,
+                };
+                BadStateVerdict {
+                    bad_state: bad_state.clone(),
+                    verdict,
+                    solver: "test",
+                }
+            })
+            .collect();
+        Ok((verdicts, vec![]))
+    }
+
+    fn options(max_depth: usize, all_kinds: bool) -> BmcOptions {
+        BmcOptions {
+            max_depth,
+            time_budget: None,
+            all_kinds,
+        }
+    }
+
+    #[test]
+    fn stops_at_first_counterexample() {
+        let mut model = counter_model();
+        let result = bmc_impl(&mut model, &options(10, false), check).unwrap();
+
+        assert_eq!(result.stop, BmcStop::Counterexample);
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.counterexamples.len(), 1);
+        assert_eq!(result.counterexamples[0].kind, "two");
+        assert_eq!(result.counterexamples[0].name, "two[n=2]");
+    }
+
+    #[test]
+    fn finds_shortest_depth_per_kind() {
+        let mut model = counter_model();
+        let result = bmc_impl(&mut model, &options(10, true), check).unwrap();
+        let depths: Vec<(&str, usize)> = result
+            .counterexamples
+            .iter()
+            .map(|c| (c.kind.as_str(), c.depth))
+            .collect();
+
+        assert_eq!(result.stop, BmcStop::Counterexample);
+        assert_eq!(depths, vec![("two", 2), ("five", 5)]);
+    }
+
+    #[test]
+    fn respects_depth_budget() {
+        let mut model = counter_model();
+        let result = bmc_impl(&mut model, &options(2, true), check).unwrap();
+
+        assert_eq!(result.stop, BmcStop::DepthBudget);
+        assert_eq!(result.depth, 2);
+        assert!(result.counterexamples.is_empty());
+    }
+}
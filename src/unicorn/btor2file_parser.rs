@@ -1,6 +1,10 @@
-use std::collections::HashMap;
+use crate::unicorn::parse_error::ParseError;
+use anyhow::{Context, Result};
+use log::warn;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::iter;
 use std::ops::Range;
 use std::path::Path;
 
@@ -12,38 +16,32 @@ use crate::unicorn::{get_nodetype, Model, Nid, Node, NodeRef, NodeType};
 // Public Interface
 //
 
-pub fn parse_btor2_file(path: &Path) -> Model {
-    let mut parser = BTOR2Parser::new();
-    parser.parse_file(path);
-    parser.run_inits();
-    let (bad_states_initial, bad_states_sequential) = parser
-        .get_bad_states()
-        .into_iter()
-        .partition(has_depth_in_name);
-    Model {
-        lines: Vec::new(),
-        sequentials: parser.get_sequentials(),
-        bad_states_initial,
-        bad_states_sequential,
-        data_range: Range { start: 0, end: 0 },
-        heap_range: Range { start: 0, end: 0 },
-        stack_range: Range { start: 0, end: 0 },
-        memory_size: 0,
-    }
+pub fn parse_btor2_file(path: &Path) -> Result<Model> {
+    let mut parser = BTOR2Parser::new(&path.display().to_string());
+    parser.parse_file(path)?;
+    parser.into_model()
+}
+
+// Parses a model from BTOR2 text, e.g. as written by `write_model`. The text
+// does not carry the memory layout, it needs to be restored by the caller.
+pub fn parse_btor2_text(text: &str) -> Result<Model> {
+    let mut parser = BTOR2Parser::new("<model>");
+    parser.parse_string(text)?;
+    parser.into_model()
 }
 
 #[cfg(feature = "gui")]
-pub fn parse_btor2_string(string: &str, data: &MemoryData) -> Model {
+pub fn parse_btor2_string(string: &str, data: &MemoryData) -> Result<Model> {
     use bytesize::ByteSize;
     use std::mem::size_of;
     use unicorn::engine::system::PAGE_SIZE;
     use unicorn::util::next_multiple_of;
 
-    let mut parser = BTOR2Parser::new();
-    parser.parse_string(string);
-    parser.run_inits();
+    let mut parser = BTOR2Parser::new("<model>");
+    parser.parse_string(string)?;
+    parser.run_inits()?;
     let (bad_states_initial, bad_states_sequential) = parser
-        .get_bad_states()
+        .get_bad_states()?
         .into_iter()
         .partition(has_depth_in_name);
 
@@ -53,46 +51,163 @@ pub fn parse_btor2_string(string: &str, data: &MemoryData) -> Model {
         ByteSize::mib(data.memory_size).as_u64() - data.max_stack as u64 * size_of::<u64>() as u64;
     let stack_end = ByteSize::mib(data.memory_size).as_u64();
 
-    Model {
+    Ok(Model {
         lines: Vec::new(),
-        sequentials: parser.get_sequentials(),
+        sequentials: parser.get_sequentials()?,
         bad_states_initial,
         bad_states_sequential,
         data_range: data.data_start..data.data_end,
         heap_range: heap_start..heap_end,
         stack_range: stack_start..stack_end,
         memory_size: ByteSize::mib(data.memory_size).as_u64(),
-    }
+    })
 }
 
 //
 // Private Implementation
 //
 
+// BTOR2 bit-vectors of any width up to 64 bits are supported. Widths which
+// have a corresponding `NodeType` are represented natively, all others are
+// represented by a machine word holding the zero-extended value. Since there
+// is no way to truncate a machine word, some operators (e.g. `slice`) yield a
+// machine word even for widths that could be represented natively. Hence the
+// representation of each node is tracked alongside its actual width.
+#[derive(Clone)]
+struct Operand {
+    node: NodeRef,
+    sort: NodeType,
+    width: usize,
+}
+
+enum Sort {
+    Bitvec(usize),
+    Array,
+}
+
+// Condition that all constraints hold, together with its update in case of
+// sequential models.
+type Constraint = (NodeRef, Option<NodeRef>);
+
+// Tokens of a single line together with their (1-based) column, which is
+// kept around to point at the offending token in diagnostics.
+#[derive(Clone)]
+struct Line {
+    number: usize,
+    tokens: Vec<(usize, String)>,
+    end: usize,
+}
+
 struct BTOR2Parser {
-    mapping: HashMap<Nid, NodeRef>,
-    lines: HashMap<Nid, Vec<String>>,
+    file: String,
+    mapping: HashMap<Nid, Operand>,
+    lines: HashMap<Nid, Line>,
+    states: HashMap<Nid, NodeRef>,
+    inits: HashMap<Nid, Nid>,
+    nexts: HashSet<Nid>,
+    processing: HashSet<Nid>,
+    constraint: Option<Option<Constraint>>,
+    current_nid: Nid,
 }
 
 fn has_depth_in_name(bad_state: &NodeRef) -> bool {
-    if let Node::Bad {
-        name: Some(name), ..
-    } = &*bad_state.borrow()
-    {
-        name.contains("[n=")
-    } else {
-        panic!("expecting 'Bad' node here");
+    match &*bad_state.borrow() {
+        Node::Bad {
+            name: Some(name), ..
+        } => name.contains("[n="),
+        Node::Bad { name: None, .. } => false,
+        _ => panic!("expecting 'Bad' node here"),
+    }
+}
+
+fn get_representation(width: usize) -> NodeType {
+    match width {
+        1 | 8 | 16 | 24 | 32 | 40 | 48 | 56 | 64 => get_nodetype(width),
+        2..=63 => NodeType::Word,
+        _ => panic!("Unsupported bit-vector width {} (at most 64 bits)", width),
+    }
+}
+
+fn get_mask(width: usize) -> u64 {
+    u64::MAX >> (64 - width)
+}
+
+impl Line {
+    fn new(number: usize, text: &str) -> Self {
+        let content = text.find(';').map_or(text, |index| &text[..index]);
+        let mut tokens = Vec::new();
+        let mut start = None;
+        for (index, c) in content
+            .char_indices()
+            .chain(iter::once((content.len(), ' ')))
+        {
+            match (start, c.is_whitespace()) {
+                (Some(begin), true) => {
+                    tokens.push((begin + 1, content[begin..index].to_string()));
+                    start = None;
+                }
+                (None, false) => start = Some(index),
+                _ => (),
+            }
+        }
+        Self {
+            number,
+            tokens,
+            end: content.trim_end().len() + 1,
+        }
+    }
+
+    fn operator(&self) -> String {
+        self.tokens[1].1.to_lowercase()
     }
 }
 
 impl BTOR2Parser {
-    fn new() -> Self {
+    fn new(file: &str) -> Self {
         Self {
+            file: file.to_string(),
             mapping: HashMap::new(),
             lines: HashMap::new(),
+            states: HashMap::new(),
+            inits: HashMap::new(),
+            nexts: HashSet::new(),
+            processing: HashSet::new(),
+            constraint: None,
+            current_nid: 0,
+        }
+    }
+
+    fn error(&self, line: &Line, index: usize, expected: &str) -> anyhow::Error {
+        let (column, token) = match line.tokens.get(index) {
+            Some((column, token)) => (*column, token.as_str()),
+            None => (line.end, ""),
+        };
+        ParseError::new(&self.file, line.number, column, token, expected).into()
+    }
+
+    fn token<'a>(&self, line: &'a Line, index: usize, expected: &str) -> Result<&'a str> {
+        match line.tokens.get(index) {
+            Some((_, token)) => Ok(token),
+            None => Err(self.error(line, index, expected)),
         }
     }
 
+    fn parse_nid(&self, line: &Line, index: usize) -> Result<Nid> {
+        let expected = "a node id";
+        let token = self.token(line, index, expected)?;
+        token
+            .parse::<Nid>()
+            .map_err(|_| self.error(line, index, expected))
+    }
+
+    fn parse_number(&self, line: &Line, index: usize) -> Result<usize> {
+        let expected = "a number";
+        let token = self.token(line, index, expected)?;
+        token
+            .parse::<usize>()
+            .map_err(|_| self.error(line, index, expected))
+    }
+
     fn read_lines<P>(&self, filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
     where
         P: AsRef<Path>,
@@ -101,295 +216,1126 @@ impl BTOR2Parser {
         Ok(io::BufReader::new(file).lines())
     }
 
-    fn parse_lines(&mut self, lines: &[String]) {
-        for line in lines {
-            let mut cleaned_line = line.trim();
-            if let Some(comment_start_index) = cleaned_line.find(';') {
-                cleaned_line = &cleaned_line[..comment_start_index];
+    fn parse_lines(&mut self, lines: &[String]) -> Result<()> {
+        for (index, text) in lines.iter().enumerate() {
+            let line = Line::new(index + 1, text);
+            if line.tokens.is_empty() {
+                continue;
             }
-
-            let elements: Vec<&str> = cleaned_line.split(' ').collect();
-
-            let mut parsed_line: Vec<String> = Vec::new();
-
-            for element in elements {
-                if !element.is_empty() {
-                    parsed_line.push(element.to_string());
-                }
+            let nid = self.parse_nid(&line, 0)?;
+            self.token(&line, 1, "an operator")?;
+            if self.lines.contains_key(&nid) {
+                return Err(self.error(&line, 0, "a node id that is not yet defined"));
             }
+            self.lines.insert(nid, line);
+        }
 
-            if !parsed_line.is_empty() {
-                if let Ok(nid) = parsed_line[0].parse::<u64>() {
-                    self.lines.insert(nid, parsed_line);
-                } else {
-                    panic!("could not parse line->'{:?}'", parsed_line);
+        // Remember which states are initialized or updated, states are
+        // translated at their first use and need to know this upfront.
+        for (nid, line) in &self.lines {
+            match line.operator().as_str() {
+                "init" => {
+                    let state = self.parse_nid(line, 3)?;
+                    self.inits.insert(state, *nid);
+                }
+                "next" => {
+                    let state = self.parse_nid(line, 3)?;
+                    self.nexts.insert(state);
                 }
+                _ => (),
             }
         }
+        self.current_nid = self.lines.keys().max().map_or(0, |nid| nid + 1);
+        Ok(())
     }
 
-    fn parse_file(&mut self, path: &Path) {
-        if let Ok(lines) = self.read_lines(path) {
-            let flattened_lines: Vec<String> = lines.flatten().collect();
-            self.parse_lines(&flattened_lines);
-        } else {
-            println!("Error reading file ({:?})", path);
-        }
+    fn parse_file(&mut self, path: &Path) -> Result<()> {
+        let lines = self
+            .read_lines(path)
+            .and_then(|lines| lines.collect::<io::Result<Vec<String>>>())
+            .with_context(|| format!("Failed to read BTOR2 file {:?}", path))?;
+        self.parse_lines(&lines)
     }
 
-    #[cfg(feature = "gui")]
-    fn parse_string(&mut self, string: &str) {
+    fn parse_string(&mut self, string: &str) -> Result<()> {
         let lines: Vec<String> = string.lines().map(String::from).collect();
-        self.parse_lines(&lines);
+        self.parse_lines(&lines)
     }
 
-    fn get_sort(&self, nid: Nid) -> NodeType {
-        let line = self.lines.get(&nid).unwrap().clone();
-        if line[2] == "bitvec" {
-            if let Ok(answer) = line[3].parse::<usize>() {
-                return get_nodetype(answer);
-            }
-            panic!("Unsupported bitvec: {:?}", line);
-        } else if line[2] == "array" {
-            if let Ok(sort_idx) = line[3].parse::<Nid>() {
-                if let Ok(sort_val) = line[4].parse::<Nid>() {
-                    if self.get_sort(sort_idx) == NodeType::Word
-                        && self.get_sort(sort_val) == NodeType::Word
-                    {
-                        return NodeType::Memory;
+    fn into_model(mut self) -> Result<Model> {
+        self.run_inits()?;
+        let (bad_states_initial, bad_states_sequential) = self
+            .get_bad_states()?
+            .into_iter()
+            .partition(has_depth_in_name);
+        Ok(Model {
+            lines: Vec::new(),
+            sequentials: self.get_sequentials()?,
+            bad_states_initial,
+            bad_states_sequential,
+            data_range: Range { start: 0, end: 0 },
+            heap_range: Range { start: 0, end: 0 },
+            stack_range: Range { start: 0, end: 0 },
+            memory_size: 0,
+        })
+    }
+
+    // Looks up the sort referenced by the token at the given index.
+    fn get_sort(&self, line: &Line, index: usize) -> Result<Sort> {
+        let nid = self.parse_nid(line, index)?;
+        let sort_line = match self.lines.get(&nid) {
+            Some(sort_line) if sort_line.operator() == "sort" => sort_line,
+            _ => return Err(self.error(line, index, "id of a sort")),
+        };
+        match self.token(sort_line, 2, "'bitvec' or 'array'")? {
+            "bitvec" => match self.parse_number(sort_line, 3)? {
+                width @ 1..=64 => Ok(Sort::Bitvec(width)),
+                _ => Err(self.error(sort_line, 3, "a width of at most 64 bits")),
+            },
+            "array" => {
+                for index in [3, 4] {
+                    if !matches!(self.get_sort(sort_line, index)?, Sort::Bitvec(64)) {
+                        return Err(self.error(sort_line, index, "a sort of 64-bit bit-vectors"));
                     }
                 }
+                Ok(Sort::Array)
             }
-            panic!("Unsupported array: {:?}", line);
+            _ => Err(self.error(sort_line, 2, "'bitvec' or 'array'")),
+        }
+    }
+
+    fn next_nid(&mut self) -> Nid {
+        let nid = self.current_nid;
+        self.current_nid += 1;
+        nid
+    }
+
+    // Creates an auxiliary node that does not appear in the BTOR2 file.
+    fn new_node<F>(&mut self, f: F) -> NodeRef
+    where
+        F: FnOnce(Nid) -> Node,
+    {
+        let nid = self.next_nid();
+        NodeRef::from(f(nid))
+    }
+
+    fn new_const(&mut self, sort: NodeType, imm: u64) -> NodeRef {
+        self.new_node(|nid| Node::Const { nid, sort, imm })
+    }
+
+    fn new_not(&mut self, sort: NodeType, value: NodeRef) -> NodeRef {
+        self.new_node(|nid| Node::Not { nid, sort, value })
+    }
+
+    fn new_and(&mut self, sort: NodeType, left: NodeRef, right: NodeRef) -> NodeRef {
+        self.new_node(|nid| Node::And {
+            nid,
+            sort,
+            left,
+            right,
+        })
+    }
+
+    fn new_or(&mut self, sort: NodeType, left: NodeRef, right: NodeRef) -> NodeRef {
+        self.new_node(|nid| Node::Or {
+            nid,
+            sort,
+            left,
+            right,
+        })
+    }
+
+    fn new_xor(&mut self, sort: NodeType, left: NodeRef, right: NodeRef) -> NodeRef {
+        let or = self.new_or(sort.clone(), left.clone(), right.clone());
+        let and = self.new_and(sort.clone(), left, right);
+        let nand = self.new_not(sort.clone(), and);
+        self.new_and(sort, or, nand)
+    }
+
+    fn new_ite(&mut self, sort: NodeType, cond: NodeRef, left: NodeRef, right: NodeRef) -> NodeRef {
+        self.new_node(|nid| Node::Ite {
+            nid,
+            sort,
+            cond,
+            left,
+            right,
+        })
+    }
+
+    fn new_ult(&mut self, left: NodeRef, right: NodeRef) -> NodeRef {
+        self.new_node(|nid| Node::Ult { nid, left, right })
+    }
+
+    fn new_eq(&mut self, left: NodeRef, right: NodeRef) -> NodeRef {
+        self.new_node(|nid| Node::Eq { nid, left, right })
+    }
+
+    fn new_word_const(&mut self, imm: u64) -> NodeRef {
+        self.new_const(NodeType::Word, imm)
+    }
+
+    fn bitvec(node: NodeRef, width: usize) -> Operand {
+        Operand {
+            node,
+            sort: get_representation(width),
+            width,
+        }
+    }
+
+    fn word(node: NodeRef, width: usize) -> Operand {
+        Operand {
+            node,
+            sort: NodeType::Word,
+            width,
+        }
+    }
+
+    // Translates the operand referenced by the token at the given index, a
+    // leading minus sign denotes its bitwise negation.
+    fn operand(&mut self, line: &Line, index: usize) -> Result<Operand> {
+        let expected = "id of a node";
+        let token = self.token(line, index, expected)?;
+        let (negated, token) = match token.strip_prefix('-') {
+            Some(token) => (true, token),
+            None => (false, token),
+        };
+        let nid = token
+            .parse::<Nid>()
+            .map_err(|_| self.error(line, index, expected))?;
+        match self.lines.get(&nid) {
+            Some(operand_line) if operand_line.operator() != "sort" => (),
+            _ => return Err(self.error(line, index, expected)),
+        }
+        let operand = self.referenced_node(line, index, nid)?;
+        if !negated {
+            return Ok(operand);
+        }
+        if operand.sort == NodeType::Memory {
+            return Err(self.error(line, index, "a bit-vector operand"));
+        }
+        let Operand { node, sort, width } = operand;
+        let not = self.new_not(sort.clone(), node);
+        let node = self.truncate(not, sort.clone(), width);
+        Ok(Operand { node, sort, width })
+    }
+
+    fn bitvec_operand(&mut self, line: &Line, index: usize) -> Result<Operand> {
+        let operand = self.operand(line, index)?;
+        if operand.sort == NodeType::Memory {
+            return Err(self.error(line, index, "a bit-vector operand"));
+        }
+        Ok(operand)
+    }
+
+    fn array_operand(&mut self, line: &Line, index: usize) -> Result<Operand> {
+        let operand = self.operand(line, index)?;
+        if operand.sort != NodeType::Memory {
+            return Err(self.error(line, index, "an array operand"));
+        }
+        Ok(operand)
+    }
+
+    fn operand_of_width(&mut self, line: &Line, index: usize, width: usize) -> Result<Operand> {
+        let operand = self.bitvec_operand(line, index)?;
+        if operand.width != width {
+            let expected = format!("an operand of width {}", width);
+            return Err(self.error(line, index, &expected));
+        }
+        Ok(operand)
+    }
+
+    // Translates an operand that has to be of the same sort as another one.
+    fn matching_operand(&mut self, line: &Line, index: usize, other: &Operand) -> Result<Operand> {
+        if other.sort == NodeType::Memory {
+            self.array_operand(line, index)
         } else {
-            panic!("Invalid sort: {:?}", line);
+            self.operand_of_width(line, index, other.width)
         }
     }
 
-    fn process_node(&mut self, nid: Nid) -> NodeRef {
-        if self.mapping.contains_key(&nid) {
-            return self.mapping.get(&nid).unwrap().clone();
+    // Clears all bits of a node beyond the given width if its representation
+    // is wider than that.
+    fn truncate(&mut self, node: NodeRef, sort: NodeType, width: usize) -> NodeRef {
+        if sort.bitsize() == width {
+            node
+        } else {
+            let mask = self.new_const(sort.clone(), get_mask(width));
+            self.new_and(sort, node, mask)
         }
+    }
 
-        let mut current_node: Option<NodeRef> = None;
-        let line = self.lines.get(&nid).unwrap().clone();
-        let operator_name = line[1].to_lowercase();
-        let sort = if let Ok(sort_nid) = line[2].parse::<Nid>() {
-            if operator_name != "bad" {
-                self.get_sort(sort_nid)
-            } else {
-                NodeType::Bit
+    fn widen(&mut self, operand: &Operand) -> NodeRef {
+        match &operand.sort {
+            NodeType::Word => operand.node.clone(),
+            NodeType::Bit => {
+                let one = self.new_word_const(1);
+                let zero = self.new_word_const(0);
+                self.new_ite(NodeType::Word, operand.node.clone(), one, zero)
+            }
+            NodeType::Memory => panic!("cannot widen memory"),
+            from => {
+                let from = from.clone();
+                let value = operand.node.clone();
+                self.new_node(|nid| Node::Ext { nid, from, value })
             }
+        }
+    }
+
+    // Brings both operands into the same representation. Bits are widened as
+    // well, comparisons and arithmetic are only defined on bit-vectors.
+    fn unify(&mut self, left: &Operand, right: &Operand) -> (NodeRef, NodeRef, NodeType) {
+        assert_eq!(left.width, right.width, "operands differ in width");
+        if left.sort == right.sort && left.sort != NodeType::Bit {
+            (left.node.clone(), right.node.clone(), left.sort.clone())
         } else {
-            panic!("error parsing nid {} ", line[0]);
+            (self.widen(left), self.widen(right), NodeType::Word)
+        }
+    }
+
+    fn to_bit(&mut self, node: NodeRef, sort: NodeType) -> NodeRef {
+        let zero = self.new_const(sort, 0);
+        self.new_ult(zero, node)
+    }
+
+    // Yields the machine word holding the sign-extended value.
+    fn sign_extend(&mut self, operand: &Operand) -> NodeRef {
+        let value = self.widen(operand);
+        if operand.width == 64 {
+            return value;
+        }
+        let half = self.new_word_const(1 << (operand.width - 1));
+        let is_positive = self.new_ult(value.clone(), half);
+        let upper = self.new_word_const(!get_mask(operand.width));
+        let extended = self.new_or(NodeType::Word, value.clone(), upper);
+        self.new_ite(NodeType::Word, is_positive, value, extended)
+    }
+
+    fn is_negative(&mut self, operand: &Operand) -> NodeRef {
+        let value = self.widen(operand);
+        let half = self.new_word_const(1 << (operand.width - 1));
+        let is_positive = self.new_ult(value, half);
+        self.new_not(NodeType::Bit, is_positive)
+    }
+
+    // Flips the sign bit, which maps signed to unsigned order.
+    fn flip_sign(&mut self, operand: &Operand) -> NodeRef {
+        let value = self.widen(operand);
+        let half = self.new_word_const(1 << (operand.width - 1));
+        let is_positive = self.new_ult(value.clone(), half.clone());
+        let add = self.new_node(|nid| Node::Add {
+            nid,
+            left: value.clone(),
+            right: half.clone(),
+        });
+        let sub = self.new_node(|nid| Node::Sub {
+            nid,
+            left: value,
+            right: half,
+        });
+        self.new_ite(NodeType::Word, is_positive, add, sub)
+    }
+
+    fn compare(&mut self, operator: &str, left: &Operand, right: &Operand) -> NodeRef {
+        let (left, right) = if operator.starts_with('s') {
+            (self.flip_sign(left), self.flip_sign(right))
+        } else {
+            let (left, right, _) = self.unify(left, right);
+            (left, right)
+        };
+        match &operator[1..] {
+            "lt" => self.new_ult(left, right),
+            "gt" => self.new_ult(right, left),
+            "lte" => {
+                let ult = self.new_ult(right, left);
+                self.new_not(NodeType::Bit, ult)
+            }
+            "gte" => {
+                let ult = self.new_ult(left, right);
+                self.new_not(NodeType::Bit, ult)
+            }
+            _ => panic!("unknown comparison operator: {}", operator),
+        }
+    }
+
+    // Arithmetic on single bits is performed on machine words, the result is
+    // turned back into a bit afterwards.
+    fn arithmetic(&mut self, operator: &str, left: &Operand, right: &Operand) -> Operand {
+        match operator {
+            "sdiv" | "srem" | "smod" => return self.signed_division(operator, left, right),
+            "sll" | "srl" | "sra" | "rol" | "ror" => return self.shift(operator, left, right),
+            _ => (),
+        }
+        let (left_node, right_node, sort) = self.unify(left, right);
+        let node = match operator {
+            "add" => self.new_node(|nid| Node::Add {
+                nid,
+                left: left_node,
+                right: right_node,
+            }),
+            "sub" => self.new_node(|nid| Node::Sub {
+                nid,
+                left: left_node,
+                right: right_node,
+            }),
+            "mul" => self.new_node(|nid| Node::Mul {
+                nid,
+                left: left_node,
+                right: right_node,
+            }),
+            "udiv" => self.new_node(|nid| Node::Divu {
+                nid,
+                left: left_node,
+                right: right_node,
+            }),
+            "urem" => self.new_node(|nid| Node::Rem {
+                nid,
+                left: left_node,
+                right: right_node,
+            }),
+            _ => panic!("unknown arithmetic operator: {}", operator),
         };
+        self.result(node, sort, left.width)
+    }
 
-        match operator_name.as_str() {
-            "constd" => {
-                if let Ok(imm) = line[3].parse::<u64>() {
-                    current_node = Some(NodeRef::from(Node::Const { nid, sort, imm }));
+    // Shifts are performed on machine words, shifting by the full width (or
+    // more) then behaves the same for all widths.
+    fn shift(&mut self, operator: &str, left: &Operand, right: &Operand) -> Operand {
+        let width = left.width;
+        let value = self.widen(left);
+        let amount = self.widen(right);
+        let node = match operator {
+            "sll" => self.new_node(|nid| Node::Sll {
+                nid,
+                left: value,
+                right: amount,
+            }),
+            "srl" => self.new_node(|nid| Node::Srl {
+                nid,
+                left: value,
+                right: amount,
+            }),
+            "sra" => {
+                let extended = self.sign_extend(left);
+                let negated = self.new_not(NodeType::Word, extended);
+                let shifted = self.new_node(|nid| Node::Srl {
+                    nid,
+                    left: negated,
+                    right: amount.clone(),
+                });
+                let negative = self.new_not(NodeType::Word, shifted);
+                let positive = self.new_node(|nid| Node::Srl {
+                    nid,
+                    left: value,
+                    right: amount,
+                });
+                let is_negative = self.is_negative(left);
+                self.new_ite(NodeType::Word, is_negative, negative, positive)
+            }
+            "rol" | "ror" => {
+                let modulus = self.new_word_const(width as u64);
+                let amount = self.new_node(|nid| Node::Rem {
+                    nid,
+                    left: amount,
+                    right: modulus.clone(),
+                });
+                let complement = self.new_node(|nid| Node::Sub {
+                    nid,
+                    left: modulus,
+                    right: amount.clone(),
+                });
+                let (amount_left, amount_right) = if operator == "rol" {
+                    (amount, complement)
                 } else {
-                    panic!("not valid imm for const operator ({})", line[3]);
+                    (complement, amount)
+                };
+                let left_part = self.new_node(|nid| Node::Sll {
+                    nid,
+                    left: value.clone(),
+                    right: amount_left,
+                });
+                let right_part = self.new_node(|nid| Node::Srl {
+                    nid,
+                    left: value,
+                    right: amount_right,
+                });
+                self.new_or(NodeType::Word, left_part, right_part)
+            }
+            _ => panic!("unknown shift operator: {}", operator),
+        };
+        self.result(node, NodeType::Word, width)
+    }
+
+    // Signed division and remainder are defined by means of the signed
+    // division on machine words, following SMT-LIB semantics.
+    fn signed_division(&mut self, operator: &str, left: &Operand, right: &Operand) -> Operand {
+        let width = left.width;
+        let dividend = self.sign_extend(left);
+        let divisor = self.sign_extend(right);
+        let quotient = self.new_node(|nid| Node::Div {
+            nid,
+            left: dividend.clone(),
+            right: divisor.clone(),
+        });
+        if operator == "sdiv" {
+            return self.result(quotient, NodeType::Word, width);
+        }
+        let product = self.new_node(|nid| Node::Mul {
+            nid,
+            left: quotient,
+            right: divisor.clone(),
+        });
+        let remainder = self.new_node(|nid| Node::Sub {
+            nid,
+            left: dividend.clone(),
+            right: product,
+        });
+        if operator == "srem" {
+            return self.result(remainder, NodeType::Word, width);
+        }
+        // The result of `smod` takes the sign of the divisor instead.
+        let zero = self.new_word_const(0);
+        let is_zero = self.new_eq(remainder.clone(), zero);
+        let remainder_sign = self.is_negative(&Self::word(remainder.clone(), 64));
+        let divisor_sign = self.is_negative(&Self::word(divisor.clone(), 64));
+        let different_signs = self.new_xor(NodeType::Bit, remainder_sign, divisor_sign);
+        let same_signs = self.new_not(NodeType::Bit, different_signs);
+        let keep = self.new_or(NodeType::Bit, is_zero, same_signs);
+        let adjusted = self.new_node(|nid| Node::Add {
+            nid,
+            left: remainder.clone(),
+            right: divisor,
+        });
+        let modulo = self.new_ite(NodeType::Word, keep, remainder, adjusted);
+        self.result(modulo, NodeType::Word, width)
+    }
+
+    fn overflow(&mut self, operator: &str, left: &Operand, right: &Operand) -> NodeRef {
+        let width = left.width;
+        match operator {
+            "uaddo" => {
+                let (left_node, right_node) = (self.widen(left), self.widen(right));
+                let sum = self.new_node(|nid| Node::Add {
+                    nid,
+                    left: left_node.clone(),
+                    right: right_node,
+                });
+                if width == 64 {
+                    self.new_ult(sum, left_node)
+                } else {
+                    let mask = self.new_word_const(get_mask(width));
+                    self.new_ult(mask, sum)
+                }
+            }
+            "usubo" => {
+                let (left_node, right_node, _) = self.unify(left, right);
+                self.new_ult(left_node, right_node)
+            }
+            "saddo" | "ssubo" => {
+                let result = self.arithmetic(&operator[1..4], left, right);
+                let left_sign = self.is_negative(left);
+                let right_sign = self.is_negative(right);
+                let result_sign = self.is_negative(&result);
+                let signs = self.new_xor(NodeType::Bit, left_sign.clone(), right_sign);
+                let same_signs = if operator == "saddo" {
+                    self.new_not(NodeType::Bit, signs)
+                } else {
+                    signs
+                };
+                let flipped = self.new_xor(NodeType::Bit, left_sign, result_sign);
+                self.new_and(NodeType::Bit, same_signs, flipped)
+            }
+            // Products of operands wider than 32 bits do not fit into a
+            // machine word, the caller rejects those.
+            "umulo" | "smulo" => {
+                if operator == "umulo" {
+                    let (left_node, right_node) = (self.widen(left), self.widen(right));
+                    let product = self.new_node(|nid| Node::Mul {
+                        nid,
+                        left: left_node,
+                        right: right_node,
+                    });
+                    let mask = self.new_word_const(get_mask(width));
+                    self.new_ult(mask, product)
+                } else {
+                    let (left_node, right_node) = (self.sign_extend(left), self.sign_extend(right));
+                    let product = self.new_node(|nid| Node::Mul {
+                        nid,
+                        left: left_node,
+                        right: right_node,
+                    });
+                    let truncated = self.truncate(product.clone(), NodeType::Word, width);
+                    let extended = self.sign_extend(&Self::word(truncated, width));
+                    let eq = self.new_eq(extended, product);
+                    self.new_not(NodeType::Bit, eq)
                 }
             }
+            "sdivo" => {
+                let (left_node, right_node) = (self.widen(left), self.widen(right));
+                let min = self.new_word_const(1 << (width - 1));
+                let ones = self.new_word_const(get_mask(width));
+                let is_min = self.new_eq(left_node, min);
+                let is_ones = self.new_eq(right_node, ones);
+                self.new_and(NodeType::Bit, is_min, is_ones)
+            }
+            _ => panic!("unknown overflow operator: {}", operator),
+        }
+    }
+
+    // Creates the operand for a node of the given representation, which has
+    // to be truncated to the given width first.
+    fn result(&mut self, node: NodeRef, sort: NodeType, width: usize) -> Operand {
+        if width == 1 {
+            let node = self.truncate(node, sort.clone(), width);
+            Self::bitvec(self.to_bit(node, sort), 1)
+        } else {
+            let node = self.truncate(node, sort, width);
+            Operand { node, sort, width }
+        }
+    }
+
+    fn process_node(&mut self, nid: Nid) -> Result<Operand> {
+        if let Some(operand) = self.mapping.get(&nid) {
+            return Ok(operand.clone());
+        }
+
+        let line = self.lines.get(&nid).unwrap().clone();
+        self.processing.insert(nid);
+        let operator_name = line.operator();
+        let sort = match operator_name.as_str() {
+            "bad" | "constraint" | "output" | "fair" | "justice" => Sort::Bitvec(1),
+            _ => self.get_sort(&line, 2)?,
+        };
+        let width = match &sort {
+            Sort::Bitvec(width) => *width,
+            Sort::Array => 0,
+        };
+        let is_array = matches!(sort, Sort::Array);
+        match operator_name.as_str() {
+            "state" | "init" | "next" | "ite" | "eq" | "neq" | "read" | "write" => (),
+            _ if is_array => return Err(self.error(&line, 2, "id of a bit-vector sort")),
+            _ => (),
+        }
+
+        let operand = match operator_name.as_str() {
+            "const" | "constd" | "consth" | "zero" | "one" | "ones" => {
+                let imm = match operator_name.as_str() {
+                    "const" => {
+                        let token = self.token(&line, 3, "a binary constant")?;
+                        u64::from_str_radix(token, 2)
+                            .map_err(|_| self.error(&line, 3, "a binary constant"))?
+                    }
+                    "consth" => {
+                        let token = self.token(&line, 3, "a hexadecimal constant")?;
+                        u64::from_str_radix(token, 16)
+                            .map_err(|_| self.error(&line, 3, "a hexadecimal constant"))?
+                    }
+                    "constd" => {
+                        let token = self.token(&line, 3, "a decimal constant")?;
+                        token
+                            .parse::<u64>()
+                            .ok()
+                            .or_else(|| token.parse::<i64>().ok().map(|imm| imm as u64))
+                            .ok_or_else(|| self.error(&line, 3, "a decimal constant"))?
+                    }
+                    "zero" => 0,
+                    "one" => 1,
+                    _ => u64::MAX,
+                };
+                let sort = get_representation(width);
+                let imm = imm & get_mask(width);
+                Self::bitvec(NodeRef::from(Node::Const { nid, sort, imm }), width)
+            }
             "input" => {
-                current_node = Some(NodeRef::from(Node::Input {
+                let name = self
+                    .token(&line, 3, "a symbol")
+                    .map_or_else(|_| "input".to_string(), String::from);
+                let sort = get_representation(width);
+                let input = NodeRef::from(Node::Input {
                     nid,
-                    sort,
-                    name: "input".to_string(),
-                }))
-            }
-            "init" => {
-                if let Ok(nid_state) = line[3].parse::<Nid>() {
-                    if let Ok(nid_value) = line[4].parse::<Nid>() {
-                        let state_ref = self.process_node(nid_state);
-                        let value_ref = self.process_node(nid_value);
-                        match &*state_ref.borrow() {
-                            Node::State { name, .. } => {
-                                let modified_state = NodeRef::from(Node::State {
-                                    nid: nid_state,
-                                    sort,
-                                    init: Some(value_ref),
-                                    name: name.clone(),
-                                });
-                                current_node = Some(modified_state.clone());
-                                self.mapping.insert(nid_state, modified_state);
-                            }
-                            _ => {
-                                panic!("invalid init!");
-                            }
-                        };
-                    }
-                }
+                    sort: sort.clone(),
+                    name,
+                });
+                let node = self.truncate(input, sort, width);
+                Self::bitvec(node, width)
             }
             "state" => {
-                let name = line.get(3).cloned();
-                current_node = Some(NodeRef::from(Node::State {
+                let name = self.token(&line, 3, "a symbol").ok().map(String::from);
+                let init = match self.inits.get(&nid).cloned() {
+                    Some(init_nid) => {
+                        let init_line = self.lines.get(&init_nid).unwrap().clone();
+                        let value = self.operand(&init_line, 4)?;
+                        Some((init_line, value))
+                    }
+                    None => None,
+                };
+                // Only machine words can be updated (or initialized) by
+                // arbitrary operands.
+                let is_word = |value: &Operand| value.sort == NodeType::Word;
+                let sort = match sort {
+                    Sort::Array => NodeType::Memory,
+                    _ if width == 1 => NodeType::Bit,
+                    _ if self.nexts.contains(&nid) => NodeType::Word,
+                    _ if init.as_ref().map_or(false, |(_, value)| is_word(value)) => NodeType::Word,
+                    _ => get_representation(width),
+                };
+                let init = match init {
+                    Some((init_line, value)) => {
+                        Some(self.convert(&init_line, 4, value, width, sort.clone())?)
+                    }
+                    None => None,
+                };
+                let uninitialized = init.is_none();
+                let state = NodeRef::from(Node::State {
                     nid,
-                    sort,
-                    init: None,
+                    sort: sort.clone(),
+                    init,
                     name,
-                }));
+                });
+                self.states.insert(nid, state.clone());
+                let node = if uninitialized && sort != NodeType::Memory {
+                    self.truncate(state, sort.clone(), width)
+                } else {
+                    state
+                };
+                Operand { node, sort, width }
             }
-            "not" => {
-                if let Ok(nid_value) = line[3].parse::<Nid>() {
-                    let value = self.process_node(nid_value);
-                    current_node = Some(NodeRef::from(Node::Not { nid, sort, value }))
+            "init" => self.state_operand(&line)?,
+            "next" => {
+                let state_width = self.state_operand(&line)?.width;
+                let nid_state = self.parse_nid(&line, 3)?;
+                let state = self.states.get(&nid_state).unwrap().clone();
+                let sort = if let Node::State { sort, .. } = &*state.borrow() {
+                    sort.clone()
+                } else {
+                    panic!("expecting 'State' node here");
+                };
+                let value = self.operand(&line, 4)?;
+                let next = self.convert(&line, 4, value, state_width, sort.clone())?;
+                Operand {
+                    node: NodeRef::from(Node::Next {
+                        nid,
+                        sort: sort.clone(),
+                        state,
+                        next,
+                    }),
+                    sort,
+                    width,
                 }
             }
-            "bad" => {
-                if let Ok(nid_value) = line[2].parse::<Nid>() {
-                    let name = line.get(3).cloned();
-                    let value = self.process_node(nid_value);
-                    current_node = Some(NodeRef::from(Node::Bad {
-                        nid,
-                        cond: value,
-                        name,
-                    }))
+            "bad" | "constraint" | "output" | "fair" | "justice" => {
+                let name = self.token(&line, 3, "a symbol").ok().map(String::from);
+                let cond = self.operand_of_width(&line, 2, 1)?.node;
+                let node = if operator_name == "bad" {
+                    NodeRef::from(Node::Bad { nid, cond, name })
+                } else {
+                    cond
+                };
+                Self::bitvec(node, 1)
+            }
+            "not" => {
+                let Operand { node, sort, .. } = self.operand_of_width(&line, 3, width)?;
+                let not = NodeRef::from(Node::Not {
+                    nid,
+                    sort: sort.clone(),
+                    value: node,
+                });
+                let node = self.truncate(not, sort.clone(), width);
+                Operand { node, sort, width }
+            }
+            "inc" | "dec" | "neg" => {
+                let value = self.operand_of_width(&line, 3, width)?;
+                let one = Self::bitvec(self.new_const(get_representation(width), 1), width);
+                let zero = Self::bitvec(self.new_const(get_representation(width), 0), width);
+                match operator_name.as_str() {
+                    "inc" => self.arithmetic("add", &value, &one),
+                    "dec" => self.arithmetic("sub", &value, &one),
+                    _ => self.arithmetic("sub", &zero, &value),
                 }
             }
-            "ite" => {
-                // TODO: handle negative conditions
-                if let Ok(nid_condition) = line[3].parse::<Nid>() {
-                    if let Ok(nid_truth_part) = line[4].parse::<Nid>() {
-                        if let Ok(nid_false_part) = line[5].parse::<Nid>() {
-                            let cond = self.process_node(nid_condition);
-                            let left = self.process_node(nid_truth_part);
-                            let right = self.process_node(nid_false_part);
-
-                            current_node = Some(NodeRef::from(Node::Ite {
+            "redand" | "redor" | "redxor" => {
+                self.expect_width(&line, width, 1)?;
+                let value = self.bitvec_operand(&line, 3)?;
+                let node = if value.width == 1 {
+                    value.node
+                } else if operator_name == "redor" {
+                    self.to_bit(value.node, value.sort)
+                } else if operator_name == "redand" {
+                    let ones = self.new_const(value.sort.clone(), get_mask(value.width));
+                    self.new_eq(value.node, ones)
+                } else {
+                    let mut parity = self.widen(&value);
+                    for shift in [32_usize, 16, 8, 4, 2, 1] {
+                        if shift < value.width {
+                            let amount = self.new_word_const(shift as u64);
+                            let shifted = self.new_node(|nid| Node::Srl {
                                 nid,
-                                sort,
-                                cond,
-                                left,
-                                right,
-                            }))
+                                left: parity.clone(),
+                                right: amount,
+                            });
+                            parity = self.new_xor(NodeType::Word, parity, shifted);
                         }
                     }
-                }
+                    let parity = self.truncate(parity, NodeType::Word, 1);
+                    self.to_bit(parity, NodeType::Word)
+                };
+                Self::bitvec(node, 1)
             }
-            "add" | "sub" | "mul" | "udiv" | "urem" | "ult" | "eq" | "and" | "next" | "sll"
-            | "srl" => {
-                if let Ok(nid_left) = line[3].parse::<Nid>() {
-                    if let Ok(nid_right) = line[4].parse::<Nid>() {
-                        let left = self.process_node(nid_left);
-                        let right = self.process_node(nid_right);
-
-                        let temp_node = match operator_name.as_str() {
-                            "add" => Node::Add { nid, left, right },
-                            "sub" => Node::Sub { nid, left, right },
-                            "mul" => Node::Mul { nid, left, right },
-                            "udiv" => Node::Divu { nid, left, right },
-                            "urem" => Node::Rem { nid, left, right },
-                            "ult" => Node::Ult { nid, left, right },
-                            "eq" => Node::Eq { nid, left, right },
-                            "and" => Node::And {
-                                nid,
-                                sort,
-                                left,
-                                right,
-                            },
-                            "next" => Node::Next {
-                                nid,
-                                sort,
-                                state: left,
-                                next: right,
-                            },
-                            "sll" => Node::Sll { nid, left, right },
-                            "srl" => Node::Srl { nid, left, right },
-                            _ => {
-                                panic!("This piece of code should be unreacheable");
-                            }
-                        };
-                        current_node = Some(NodeRef::from(temp_node));
-                    }
+            "uext" | "sext" => {
+                let value = self.bitvec_operand(&line, 3)?;
+                let bits_ext = self.parse_number(&line, 4)?;
+                if value.width > width {
+                    let expected = format!("an operand of at most {} bits", width);
+                    return Err(self.error(&line, 3, &expected));
+                }
+                if value.width + bits_ext != width {
+                    let expected = format!("an extension by {} bits", width - value.width);
+                    return Err(self.error(&line, 4, &expected));
+                }
+                if bits_ext == 0 {
+                    value
+                } else if operator_name == "uext" {
+                    Self::word(self.widen(&value), width)
+                } else {
+                    let extended = self.sign_extend(&value);
+                    let node = self.truncate(extended, NodeType::Word, width);
+                    Self::word(node, width)
                 }
             }
-            "uext" => {
-                if let Ok(nid_value) = line[3].parse::<Nid>() {
-                    if let Ok(bits_ext) = line[4].parse::<usize>() {
-                        let value = self.process_node(nid_value);
-                        let bits_dest = sort.bitsize();
-                        let bits_src = bits_dest - bits_ext;
-                        current_node = Some(NodeRef::from(Node::Ext {
+            "slice" => {
+                let value = self.bitvec_operand(&line, 3)?;
+                let upper = self.parse_number(&line, 4)?;
+                let lower = self.parse_number(&line, 5)?;
+                if upper >= value.width {
+                    let expected = format!("an upper bit index below {}", value.width);
+                    return Err(self.error(&line, 4, &expected));
+                }
+                if upper < lower || upper - lower + 1 != width {
+                    let expected = format!("a lower bit index yielding {} bits", width);
+                    return Err(self.error(&line, 5, &expected));
+                }
+                if lower == 0 && width == value.width {
+                    value
+                } else {
+                    let mut node = self.widen(&value);
+                    if lower > 0 {
+                        let amount = self.new_word_const(lower as u64);
+                        node = self.new_node(|nid| Node::Srl {
                             nid,
-                            from: get_nodetype(bits_src),
-                            value,
-                        }))
+                            left: node,
+                            right: amount,
+                        });
                     }
+                    self.result(node, NodeType::Word, width)
                 }
             }
+            "ite" => {
+                let cond = self.operand_of_width(&line, 3, 1)?.node;
+                let left = if is_array {
+                    self.array_operand(&line, 4)?
+                } else {
+                    self.operand_of_width(&line, 4, width)?
+                };
+                let right = self.matching_operand(&line, 5, &left)?;
+                let (left, right, sort) = if left.sort == right.sort {
+                    (left.node, right.node, left.sort)
+                } else {
+                    let (left, right, _) = self.unify(&left, &right);
+                    (left, right, NodeType::Word)
+                };
+                let node = NodeRef::from(Node::Ite {
+                    nid,
+                    sort: sort.clone(),
+                    cond,
+                    left,
+                    right,
+                });
+                Operand { node, sort, width }
+            }
+            // Arrays map 64-bit indices to 64-bit elements, see `get_sort`.
             "read" => {
-                if let Ok(nid_memory) = line[3].parse::<Nid>() {
-                    if let Ok(nid_address) = line[4].parse::<Nid>() {
-                        let memory = self.process_node(nid_memory);
-                        let address = self.process_node(nid_address);
-                        current_node = Some(NodeRef::from(Node::Read {
-                            nid,
-                            memory,
-                            address,
-                        }))
-                    }
-                }
+                self.expect_width(&line, width, 64)?;
+                let memory = self.array_operand(&line, 3)?.node;
+                let address = self.operand_of_width(&line, 4, 64)?.node;
+                Self::word(
+                    NodeRef::from(Node::Read {
+                        nid,
+                        memory,
+                        address,
+                    }),
+                    width,
+                )
             }
             "write" => {
-                if let Ok(nid_memory) = line[3].parse::<Nid>() {
-                    if let Ok(nid_address) = line[4].parse::<Nid>() {
-                        if let Ok(nid_value) = line[5].parse::<Nid>() {
-                            let memory = self.process_node(nid_memory);
-                            let address = self.process_node(nid_address);
-                            let value = self.process_node(nid_value);
-                            current_node = Some(NodeRef::from(Node::Write {
-                                nid,
-                                memory,
-                                address,
-                                value,
-                            }))
-                        }
+                if !is_array {
+                    return Err(self.error(&line, 2, "id of an array sort"));
+                }
+                let memory = self.array_operand(&line, 3)?.node;
+                let address = self.operand_of_width(&line, 4, 64)?.node;
+                let value = self.operand_of_width(&line, 5, 64)?.node;
+                Operand {
+                    node: NodeRef::from(Node::Write {
+                        nid,
+                        memory,
+                        address,
+                        value,
+                    }),
+                    sort: NodeType::Memory,
+                    width,
+                }
+            }
+            "and" | "or" | "xor" | "nand" | "nor" | "xnor" | "implies" | "iff" => {
+                let left = self.operand_of_width(&line, 3, width)?;
+                let right = self.matching_operand(&line, 4, &left)?;
+                let (left, right, sort) = if left.sort == right.sort {
+                    (left.node, right.node, left.sort)
+                } else {
+                    let (left, right, _) = self.unify(&left, &right);
+                    (left, right, NodeType::Word)
+                };
+                let node = match operator_name.as_str() {
+                    "and" => NodeRef::from(Node::And {
+                        nid,
+                        sort: sort.clone(),
+                        left,
+                        right,
+                    }),
+                    "or" => NodeRef::from(Node::Or {
+                        nid,
+                        sort: sort.clone(),
+                        left,
+                        right,
+                    }),
+                    "xor" => self.new_xor(sort.clone(), left, right),
+                    "nand" => {
+                        let and = self.new_and(sort.clone(), left, right);
+                        self.new_not(sort.clone(), and)
+                    }
+                    "nor" => {
+                        let or = self.new_or(sort.clone(), left, right);
+                        self.new_not(sort.clone(), or)
                     }
+                    "implies" => {
+                        let not = self.new_not(sort.clone(), left);
+                        self.new_or(sort.clone(), not, right)
+                    }
+                    _ => {
+                        let xor = self.new_xor(sort.clone(), left, right);
+                        self.new_not(sort.clone(), xor)
+                    }
+                };
+                let node = self.truncate(node, sort.clone(), width);
+                Operand { node, sort, width }
+            }
+            "eq" | "neq" => {
+                self.expect_width(&line, width, 1)?;
+                let left = self.operand(&line, 3)?;
+                let right = self.matching_operand(&line, 4, &left)?;
+                let (left, right) = if left.sort == NodeType::Memory {
+                    (left.node, right.node)
+                } else {
+                    let (left, right, _) = self.unify(&left, &right);
+                    (left, right)
+                };
+                let eq = NodeRef::from(Node::Eq { nid, left, right });
+                if operator_name == "eq" {
+                    Self::bitvec(eq, 1)
+                } else {
+                    Self::bitvec(self.new_not(NodeType::Bit, eq), 1)
                 }
             }
-            _ => {
-                panic!("Unsupported BTOR2 operator: {}", operator_name);
+            "ult" | "ulte" | "ugt" | "ugte" | "slt" | "slte" | "sgt" | "sgte" => {
+                self.expect_width(&line, width, 1)?;
+                let left = self.bitvec_operand(&line, 3)?;
+                let right = self.matching_operand(&line, 4, &left)?;
+                Self::bitvec(self.compare(&operator_name, &left, &right), 1)
+            }
+            "add" | "sub" | "mul" | "udiv" | "urem" | "sdiv" | "srem" | "smod" | "sll" | "srl"
+            | "sra" | "rol" | "ror" => {
+                let left = self.operand_of_width(&line, 3, width)?;
+                let right = self.matching_operand(&line, 4, &left)?;
+                self.arithmetic(&operator_name, &left, &right)
+            }
+            "uaddo" | "saddo" | "usubo" | "ssubo" | "umulo" | "smulo" | "sdivo" => {
+                self.expect_width(&line, width, 1)?;
+                let left = self.bitvec_operand(&line, 3)?;
+                let right = self.matching_operand(&line, 4, &left)?;
+                if operator_name.ends_with("mulo") && left.width > 32 {
+                    return Err(self.error(&line, 3, "an operand of at most 32 bits"));
+                }
+                Self::bitvec(self.overflow(&operator_name, &left, &right), 1)
+            }
+            "concat" => {
+                let left = self.bitvec_operand(&line, 3)?;
+                let right = self.bitvec_operand(&line, 4)?;
+                self.expect_width(&line, width, left.width + right.width)?;
+                let left_node = self.widen(&left);
+                let right_node = self.widen(&right);
+                let amount = self.new_word_const(right.width as u64);
+                let shifted = self.new_node(|nid| Node::Sll {
+                    nid,
+                    left: left_node,
+                    right: amount,
+                });
+                Self::word(self.new_or(NodeType::Word, shifted, right_node), width)
             }
+            _ => return Err(self.error(&line, 1, "a supported BTOR2 operator")),
+        };
+
+        self.processing.remove(&nid);
+        self.mapping.insert(nid, operand.clone());
+        Ok(operand)
+    }
+
+    // Checks the width of the sort given for a node by the token at index 2.
+    fn expect_width(&self, line: &Line, width: usize, expected: usize) -> Result<()> {
+        if width == expected {
+            Ok(())
+        } else {
+            let expected = format!("id of a sort of width {}", expected);
+            Err(self.error(line, 2, &expected))
         }
+    }
 
-        if let Some(answer) = current_node {
-            self.mapping.insert(nid, answer.clone());
-            answer
+    // Translates the node referenced by the token at the given index. Nodes
+    // still being translated further up would be needed by themselves.
+    fn referenced_node(&mut self, line: &Line, index: usize, nid: Nid) -> Result<Operand> {
+        if self.processing.contains(&nid) {
+            return Err(self.error(line, index, "an acyclic operand"));
+        }
+        self.process_node(nid)
+    }
+
+    // Yields the state referenced by the token at index 3 of `init` and `next`.
+    fn state_operand(&mut self, line: &Line) -> Result<Operand> {
+        let nid = self.parse_nid(line, 3)?;
+        let is_state = matches!(self.lines.get(&nid), Some(state) if state.operator() == "state");
+        if is_state {
+            self.referenced_node(line, 3, nid)
         } else {
-            panic!("could not parse {:?}", line);
+            Err(self.error(line, 3, "id of a state"))
         }
     }
 
-    fn run_inits(&mut self) {
-        for (nid, line) in self.lines.clone() {
-            if line[1].to_lowercase() == "init" {
-                self.process_node(nid);
-            }
+    // Converts an operand into the given representation of a state, the token
+    // at the given index refers to the operand.
+    fn convert(
+        &mut self,
+        line: &Line,
+        index: usize,
+        operand: Operand,
+        width: usize,
+        sort: NodeType,
+    ) -> Result<NodeRef> {
+        let is_array = sort == NodeType::Memory;
+        if is_array != (operand.sort == NodeType::Memory) || (!is_array && operand.width != width) {
+            return Err(self.error(line, index, "an operand of the same sort as the state"));
         }
+        if operand.sort == sort {
+            Ok(operand.node)
+        } else if sort == NodeType::Word {
+            Ok(self.widen(&operand))
+        } else {
+            panic!("cannot convert {:?} into {:?}", operand.sort, sort);
+        }
+    }
+
+    fn get_nids(&self, operator: &str) -> Vec<Nid> {
+        let mut nids: Vec<Nid> = self
+            .lines
+            .iter()
+            .filter(|(_, line)| line.operator() == operator)
+            .map(|(nid, _)| *nid)
+            .collect();
+        nids.sort_unstable();
+        nids
+    }
+
+    fn run_inits(&mut self) -> Result<()> {
+        for nid in self.get_nids("init") {
+            self.process_node(nid)?;
+        }
+        Ok(())
     }
 
-    fn get_sequentials(&mut self) -> Vec<NodeRef> {
+    fn get_sequentials(&mut self) -> Result<Vec<NodeRef>> {
         let mut result: Vec<NodeRef> = Vec::new();
 
-        for (nid, line) in self.lines.clone() {
-            if line[1].to_lowercase() == "next" {
-                result.push(self.process_node(nid));
-            }
+        for nid in self.get_nids("next") {
+            result.push(self.process_node(nid)?.node);
+        }
+        if let Some((_, Some(next))) = self.get_constraint()? {
+            result.push(next);
         }
-        result
+        Ok(result)
     }
 
-    fn get_bad_states(&mut self) -> Vec<NodeRef> {
+    fn get_bad_states(&mut self) -> Result<Vec<NodeRef>> {
         let mut result: Vec<NodeRef> = Vec::new();
 
-        for (nid, line) in self.lines.clone() {
-            if line[1].to_lowercase() == "bad" {
-                result.push(self.process_node(nid));
+        for nid in self
+            .get_nids("fair")
+            .into_iter()
+            .chain(self.get_nids("justice"))
+        {
+            warn!("Ignoring liveness property {} of BTOR2 file", nid);
+        }
+        let constraint = self.get_constraint()?;
+        for nid in self.get_nids("bad") {
+            let bad_state = self.process_node(nid)?.node;
+            if let Some((holds, _)) = &constraint {
+                // Bad states only count if all constraints hold.
+                if let Node::Bad { nid, cond, name } = &*bad_state.borrow() {
+                    let cond = self.new_and(NodeType::Bit, cond.clone(), holds.clone());
+                    result.push(NodeRef::from(Node::Bad {
+                        nid: *nid,
+                        cond,
+                        name: name.clone(),
+                    }));
+                }
+            } else {
+                result.push(bad_state);
+            }
+        }
+        Ok(result)
+    }
+
+    // BTOR2 constraints have to hold in every step up to (and including) the
+    // one reaching a bad state. In sequential models a latch records whether
+    // this has been the case so far, yielding the condition and its update.
+    fn get_constraint(&mut self) -> Result<Option<Constraint>> {
+        if self.constraint.is_none() {
+            let mut holds: Option<NodeRef> = None;
+            for nid in self.get_nids("constraint") {
+                let cond = self.process_node(nid)?.node;
+                holds = Some(match holds {
+                    Some(holds) => self.new_and(NodeType::Bit, holds, cond),
+                    None => cond,
+                });
             }
+            let constraint = holds.map(|holds| {
+                if self.nexts.is_empty() {
+                    (holds, None)
+                } else {
+                    let init = self.new_const(NodeType::Bit, 1);
+                    let state = self.new_node(|nid| Node::State {
+                        nid,
+                        sort: NodeType::Bit,
+                        init: Some(init),
+                        name: Some("constraints-held".to_string()),
+                    });
+                    let cond = self.new_and(NodeType::Bit, state.clone(), holds);
+                    let next = self.new_node(|nid| Node::Next {
+                        nid,
+                        sort: NodeType::Bit,
+                        state,
+                        next: cond.clone(),
+                    });
+                    (cond, Some(next))
+                }
+            });
+            self.constraint = Some(constraint);
         }
-        result
+        Ok(self.constraint.clone().unwrap())
     }
 }
 
@@ -397,24 +1343,34 @@ impl BTOR2Parser {
 mod tests_btor2_parser {
     use crate::unicorn::bitblasting::bitblast_model;
     use crate::unicorn::qubot::{InputEvaluator, Qubot};
+    use crate::unicorn::simulator::Simulator;
 
     use super::*;
 
-    fn get_model(file: &str) -> Model {
-        let mut parser = BTOR2Parser::new();
+    fn try_get_model(file: &str) -> Result<Model> {
+        let mut parser = BTOR2Parser::new("test.btor2");
         let lines: Vec<String> = file.split('\n').map(|s| s.to_string()).collect();
-        parser.parse_lines(&lines);
-        parser.run_inits();
-        Model {
+        parser.parse_lines(&lines)?;
+        parser.run_inits()?;
+        Ok(Model {
             lines: Vec::new(),
-            sequentials: parser.get_sequentials(),
-            bad_states_initial: parser.get_bad_states(),
+            sequentials: parser.get_sequentials()?,
+            bad_states_initial: parser.get_bad_states()?,
             bad_states_sequential: Vec::new(),
             data_range: Range { start: 0, end: 0 },
             heap_range: Range { start: 0, end: 0 },
             stack_range: Range { start: 0, end: 0 },
             memory_size: 0,
-        }
+        })
+    }
+
+    fn get_model(file: &str) -> Model {
+        try_get_model(file).expect("valid BTOR2 model")
+    }
+
+    fn get_error(file: &str) -> ParseError {
+        let error = try_get_model(file).err().expect("malformed BTOR2 model");
+        error.downcast::<ParseError>().expect("parse error")
     }
     #[test]
     fn test_add() {
@@ -836,4 +1792,208 @@ mod tests_btor2_parser {
             }
         }
     }
+
+    fn reaches_bad_state(file: &str, inputs: &[(&str, u64)]) -> bool {
+        let model = get_model(file);
+        let mut simulator = Simulator::new(&model);
+        let result = simulator.step(|input| {
+            if let Node::Input { name, .. } = &*input.borrow() {
+                let value = inputs.iter().find(|(n, _)| *n == name.as_str());
+                value.expect("value for every input").1
+            } else {
+                panic!("expecting 'Input' node here");
+            }
+        });
+        !result.bad_states.is_empty()
+    }
+
+    fn sign_extend_5(value: u64) -> i64 {
+        ((value << 59) as i64) >> 59
+    }
+
+    #[test]
+    fn test_signed_operators_on_odd_width() {
+        for operator in ["sdiv", "srem", "smod", "sra", "slt"] {
+            let file = format!(
+                "1 sort bitvec 5
+                2 sort bitvec 1
+                3 input 1 x
+                4 input 1 y
+                5 {} {} 3 4
+                6 input {} z
+                7 eq 2 5 6
+                8 bad 7
+                ",
+                operator,
+                if operator == "slt" { 2 } else { 1 },
+                if operator == "slt" { 2 } else { 1 },
+            );
+            for x in 0..32 {
+                for y in 0..32 {
+                    let (sx, sy) = (sign_extend_5(x), sign_extend_5(y));
+                    let expected = match operator {
+                        "sdiv" if sy == 0 => {
+                            if sx < 0 {
+                                1
+                            } else {
+                                31
+                            }
+                        }
+                        "sdiv" => sx.wrapping_div(sy) as u64 & 31,
+                        "srem" | "smod" if sy == 0 => x,
+                        "srem" => (sx % sy) as u64 & 31,
+                        "smod" => {
+                            let r = sx % sy;
+                            let r = if r != 0 && (r < 0) != (sy < 0) {
+                                r + sy
+                            } else {
+                                r
+                            };
+                            r as u64 & 31
+                        }
+                        "sra" => (sx >> y.min(4)) as u64 & 31,
+                        _ => (sx < sy) as u64,
+                    };
+                    let wrong = if operator == "slt" {
+                        1 - expected
+                    } else {
+                        (expected + 1) & 31
+                    };
+                    let inputs = |z| [("x", x), ("y", y), ("z", z)];
+                    assert!(
+                        reaches_bad_state(&file, &inputs(expected)),
+                        "{} {} {}",
+                        operator,
+                        x,
+                        y
+                    );
+                    assert!(
+                        !reaches_bad_state(&file, &inputs(wrong)),
+                        "{} {} {}",
+                        operator,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_slice_concat_and_extension() {
+        let file = "1 sort bitvec 8
+        2 sort bitvec 4
+        3 sort bitvec 12
+        4 sort bitvec 1
+        5 input 1 x
+        6 slice 2 5 3 0
+        7 slice 2 5 7 4
+        8 concat 1 6 7
+        9 sext 3 8 4
+        10 slice 4 9 11 11
+        11 input 3 y
+        12 eq 4 9 11
+        13 and 4 12 -10
+        14 bad 13
+        ";
+        for x in 0..256 {
+            let swapped = ((x & 0xf) << 4) | (x >> 4);
+            let extended = if swapped & 0x80 != 0 {
+                swapped | 0xf00
+            } else {
+                swapped
+            };
+            let negative = extended & 0x800 != 0;
+            assert_eq!(
+                reaches_bad_state(file, &[("x", x), ("y", extended)]),
+                !negative
+            );
+        }
+    }
+
+    #[test]
+    fn test_constraint() {
+        let file = "1 sort bitvec 3
+        2 sort bitvec 1
+        3 input 1 x
+        4 constd 1 5
+        5 ult 2 3 4
+        6 constraint 5
+        7 consth 1 6
+        8 ugte 2 3 7
+        9 bad 8
+        ";
+        for x in 0..8 {
+            assert!(!reaches_bad_state(file, &[("x", x)]));
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = get_error("1 sort bitvec 8\n2 input 1 x\n3 add 1 2 5\n4 bad 3\n");
+        assert_eq!(
+            error,
+            ParseError::new("test.btor2", 3, 11, "5", "id of a node")
+        );
+
+        let error = get_error("1 sort bitvec 8\n2 input 1\n3 add 1 2 2\n4 bad 3\n");
+        assert_eq!(error.line, 4);
+        assert_eq!(error.expected, "an operand of width 1");
+
+        let error = get_error("1 sort bitvec 8\n  2 constd 1 x12 ; comment\n3 bad 2\n");
+        assert_eq!(
+            error.to_string(),
+            "test.btor2:2:14: unexpected 'x12', expected a decimal constant"
+        );
+
+        let error = get_error("1 sort bitvec 8\n2 frobnicate 1\n3 bad 2\n");
+        assert_eq!((error.line, error.column), (2, 3));
+
+        let error = get_error("1 sort bitvec 1\n2 input 1\n3 bad\n");
+        assert_eq!(
+            error.to_string(),
+            "test.btor2:3:6: unexpected end of line, expected id of a node"
+        );
+    }
+
+    #[test]
+    fn test_cyclic_operand_errors() {
+        let error = get_error("1 sort bitvec 1\n2 input 1\n3 and 1 2 3\n4 bad 3\n");
+        assert_eq!(
+            error,
+            ParseError::new("test.btor2", 3, 11, "3", "an acyclic operand")
+        );
+
+        let error = get_error("1 sort bitvec 1\n2 state 1 s\n3 init 1 2 2\n4 bad 2\n");
+        assert_eq!((error.line, error.column), (3, 12));
+        assert_eq!(error.expected, "an acyclic operand");
+
+        let error =
+            get_error("1 sort bitvec 1\n2 input 1\n3 ite 1 2 4 2\n4 ite 1 2 3 2\n5 bad 4\n");
+        assert_eq!(error.line, 3);
+        assert_eq!(error.expected, "an acyclic operand");
+    }
+
+    #[test]
+    fn test_array_sort_errors() {
+        let arrays = "1 sort bitvec 64\n2 sort array 1 1\n3 state 2 mem\n4 sort bitvec 8\n";
+
+        let error = get_error(&format!("{}5 input 1\n6 read 4 3 5\n7 bad 6\n", arrays));
+        assert_eq!((error.line, error.column), (6, 8));
+        assert_eq!(error.expected, "id of a sort of width 64");
+
+        let error = get_error(&format!(
+            "{}5 input 4\n6 read 1 3 5\n7 sort bitvec 1\n8 redor 7 6\n9 bad 8\n",
+            arrays
+        ));
+        assert_eq!((error.line, error.column), (6, 12));
+        assert_eq!(error.expected, "an operand of width 64");
+
+        let error = get_error(&format!(
+            "{}5 input 1\n6 write 1 3 5 5\n7 next 2 3 6\n",
+            arrays
+        ));
+        assert_eq!((error.line, error.column), (6, 9));
+        assert_eq!(error.expected, "id of an array sort");
+    }
 }
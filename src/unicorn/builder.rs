@@ -8,6 +8,7 @@ use std::collections::HashMap;
 use std::mem::size_of;
 use std::ops::Range;
 use std::rc::Rc;
+use unicorn::disassemble::raw_instructions;
 use unicorn::engine::system::{prepare_unix_stack, SyscallId, NUMBER_OF_REGISTERS};
 use unicorn::util::next_multiple_of;
 
@@ -32,7 +33,6 @@ pub fn generate_model(
 // Private Implementation
 //
 
-const INSTRUCTION_SIZE: u64 = riscu::INSTRUCTION_SIZE as u64;
 const PAGE_SIZE: u64 = unicorn::engine::system::PAGE_SIZE as u64;
 const WORD_SIZE_MASK: u64 = riscu::WORD_SIZE as u64 - 1;
 const BITS_PER_BYTE: u64 = 8;
@@ -70,6 +70,7 @@ struct ModelBuilder {
     stack_range: Range<u64>,
     current_nid: Nid,
     pc: u64,
+    instruction_length: u64, // length of instruction at PC
 }
 
 struct InEdge {
@@ -120,6 +121,7 @@ impl ModelBuilder {
             stack_range: 0..0,
             current_nid: 0,
             pc: 0,
+            instruction_length: 0,
         }
     }
 
@@ -140,6 +142,10 @@ impl ModelBuilder {
         self.pc.wrapping_add(imm)
     }
 
+    fn pc_next(&self) -> u64 {
+        self.pc_add(self.instruction_length)
+    }
+
     fn pc_flag(&self) -> NodeRef {
         self.pc_flags[&self.pc].clone()
     }
@@ -397,6 +403,69 @@ impl ModelBuilder {
         self.new_sub(srl1_node, srl2_node)
     }
 
+    // We represent `sext32(a)` as `(a << 32) >>s 32` instead.
+    fn new_sext32(&mut self, value: NodeRef) -> NodeRef {
+        let thirtytwo = self.new_const(32);
+        let sll_node = self.new_sll(value, thirtytwo.clone());
+        self.new_sra(sll_node, thirtytwo)
+    }
+
+    // We represent `zext32(a)` as `(a << 32) >>u 32` instead.
+    fn new_zext32(&mut self, value: NodeRef) -> NodeRef {
+        let thirtytwo = self.new_const(32);
+        let sll_node = self.new_sll(value, thirtytwo.clone());
+        self.new_srl(sll_node, thirtytwo)
+    }
+
+    // We represent `srem(a, b)` as `sub(a, mul(div(a, b), b))` instead.
+    // Note that this matches RISC-V semantics for division by zero and
+    // for signed overflow, because the product vanishes respectively
+    // wraps around to the dividend in those cases.
+    fn new_srem(&mut self, left: NodeRef, right: NodeRef) -> NodeRef {
+        let div_node = self.new_div(left.clone(), right.clone());
+        let mul_node = self.new_mul(div_node, right);
+        self.new_sub(left, mul_node)
+    }
+
+    // We represent `mulhu(a, b)` by splitting both operands into 32-bit
+    // halves and summing up the partial products as follows:
+    //   p00 := lo(a) * lo(b)
+    //   p01 := lo(a) * hi(b)
+    //   p10 := hi(a) * lo(b)
+    //   p11 := hi(a) * hi(b)
+    //   mid := (p00 >>u 32) + lo(p01) + lo(p10)
+    //   return p11 + (p01 >>u 32) + (p10 >>u 32) + (mid >>u 32)
+    fn new_mulhu(&mut self, left: NodeRef, right: NodeRef) -> NodeRef {
+        let thirtytwo = self.new_const(32);
+        let lo_mask = self.new_const(u32::MAX as u64);
+        let left_lo = self.new_and_word(left.clone(), lo_mask.clone());
+        let left_hi = self.new_srl(left, thirtytwo.clone());
+        let right_lo = self.new_and_word(right.clone(), lo_mask.clone());
+        let right_hi = self.new_srl(right, thirtytwo.clone());
+        let p00 = self.new_mul(left_lo.clone(), right_lo.clone());
+        let p01 = self.new_mul(left_lo, right_hi.clone());
+        let p10 = self.new_mul(left_hi.clone(), right_lo);
+        let p11 = self.new_mul(left_hi, right_hi);
+        let p00_hi = self.new_srl(p00, thirtytwo.clone());
+        let p01_lo = self.new_and_word(p01.clone(), lo_mask.clone());
+        let p10_lo = self.new_and_word(p10.clone(), lo_mask);
+        let mid_sum = self.new_add(p00_hi, p01_lo);
+        let mid = self.new_add(mid_sum, p10_lo);
+        let mid_hi = self.new_srl(mid, thirtytwo.clone());
+        let p01_hi = self.new_srl(p01, thirtytwo.clone());
+        let p10_hi = self.new_srl(p10, thirtytwo);
+        let hi_sum1 = self.new_add(p11, p01_hi);
+        let hi_sum2 = self.new_add(hi_sum1, p10_hi);
+        self.new_add(hi_sum2, mid_hi)
+    }
+
+    // Returns `b` if `a` is negative and `0` otherwise, which is the term
+    // to subtract from `mulhu` for each operand treated as signed.
+    fn new_mulh_correction(&mut self, sign_of: NodeRef, value: NodeRef) -> NodeRef {
+        let slt_node = self.new_slt(sign_of, self.zero_word.clone());
+        self.new_ite(slt_node, value, self.zero_word.clone(), NodeType::Word)
+    }
+
     fn new_state(&mut self, init: Option<NodeRef>, name: String, sort: NodeType) -> NodeRef {
         let nid_increase = u64::from(init.is_some());
         let state_node = self.add_node(Node::State {
@@ -556,6 +625,27 @@ impl ModelBuilder {
         self.reg_flow_ite(rtype.rd(), sra_node);
     }
 
+    // We represent `srlw(a, n)` as `sext32(zext32(a) >>u n[0:4])` instead.
+    fn model_srlw(&mut self, rtype: RType) {
+        // Only the low 5 bits of rs2 are considered for the shift amount.
+        let mask_node = self.new_const(0x1f);
+        let amount_node = self.new_and_word(self.reg_node(rtype.rs2()), mask_node);
+        let zext_node = self.new_zext32(self.reg_node(rtype.rs1()));
+        let srl_node = self.new_srl(zext_node, amount_node);
+        let sext_node = self.new_sext32(srl_node);
+        self.reg_flow_ite(rtype.rd(), sext_node);
+    }
+
+    // We represent `sraw(a, n)` as `sext32(a) >>s n[0:4]` instead.
+    fn model_sraw(&mut self, rtype: RType) {
+        // Only the low 5 bits of rs2 are considered for the shift amount.
+        let mask_node = self.new_const(0x1f);
+        let amount_node = self.new_and_word(self.reg_node(rtype.rs2()), mask_node);
+        let sext_node = self.new_sext32(self.reg_node(rtype.rs1()));
+        let sra_node = self.new_sra(sext_node, amount_node);
+        self.reg_flow_ite(rtype.rd(), sra_node);
+    }
+
     fn model_srli(&mut self, itype: IType) {
         assert!(itype.imm() < 64, "immediate within bounds");
         let imm_node = self.new_const(itype.imm() as u64);
@@ -678,6 +768,12 @@ impl ModelBuilder {
         self.reg_flow_ite(itype.rd(), load_node);
     }
 
+    fn model_lwu(&mut self, itype: IType) {
+        let address_node = self.model_address(itype.rs1(), itype.imm() as u64);
+        let load_node = self.model_l(32, address_node, false);
+        self.reg_flow_ite(itype.rd(), load_node);
+    }
+
     fn model_ld(&mut self, itype: IType) {
         let address_node = self.model_address(itype.rs1(), itype.imm() as u64);
         self.access_flow = self.new_ite(
@@ -796,6 +892,11 @@ impl ModelBuilder {
         self.reg_flow_ite(rtype.rd(), or_node);
     }
 
+    fn model_xor(&mut self, rtype: RType) {
+        let xor_node = self.new_xor(self.reg_node(rtype.rs1()), self.reg_node(rtype.rs2()));
+        self.reg_flow_ite(rtype.rd(), xor_node);
+    }
+
     fn model_and(&mut self, rtype: RType) {
         let and_node = self.new_and_word(self.reg_node(rtype.rs1()), self.reg_node(rtype.rs2()));
         self.reg_flow_ite(rtype.rd(), and_node);
@@ -847,6 +948,33 @@ impl ModelBuilder {
         self.reg_flow_ite(rtype.rd(), sext_mul_node);
     }
 
+    // We represent `mulh(a, b)` as `mulhu(a, b) - (a <s 0 ? b : 0) - (b <s 0 ? a : 0)` instead.
+    fn model_mulh(&mut self, rtype: RType) {
+        let left = self.reg_node(rtype.rs1());
+        let right = self.reg_node(rtype.rs2());
+        let mulhu_node = self.new_mulhu(left.clone(), right.clone());
+        let left_correction = self.new_mulh_correction(left.clone(), right.clone());
+        let right_correction = self.new_mulh_correction(right, left);
+        let sub_node = self.new_sub(mulhu_node, left_correction);
+        let mulh_node = self.new_sub(sub_node, right_correction);
+        self.reg_flow_ite(rtype.rd(), mulh_node);
+    }
+
+    // We represent `mulhsu(a, b)` as `mulhu(a, b) - (a <s 0 ? b : 0)` instead.
+    fn model_mulhsu(&mut self, rtype: RType) {
+        let left = self.reg_node(rtype.rs1());
+        let right = self.reg_node(rtype.rs2());
+        let mulhu_node = self.new_mulhu(left.clone(), right.clone());
+        let left_correction = self.new_mulh_correction(left, right);
+        let mulhsu_node = self.new_sub(mulhu_node, left_correction);
+        self.reg_flow_ite(rtype.rd(), mulhsu_node);
+    }
+
+    fn model_mulhu(&mut self, rtype: RType) {
+        let mulhu_node = self.new_mulhu(self.reg_node(rtype.rs1()), self.reg_node(rtype.rs2()));
+        self.reg_flow_ite(rtype.rd(), mulhu_node);
+    }
+
     fn model_divu(&mut self, rtype: RType) {
         self.division_flow = self.new_ite(
             self.pc_flag(),
@@ -884,6 +1012,21 @@ impl ModelBuilder {
         self.reg_flow_ite(rtype.rd(), divw_node);
     }
 
+    // We represent `divuw(a, b)` as `sext32(zext32(a) /u zext32(b))` instead.
+    fn model_divuw(&mut self, rtype: RType) {
+        let left_zext = self.new_zext32(self.reg_node(rtype.rs1()));
+        let right_zext = self.new_zext32(self.reg_node(rtype.rs2()));
+        self.division_flow = self.new_ite(
+            self.pc_flag(),
+            right_zext.clone(),
+            self.division_flow.clone(),
+            NodeType::Word,
+        );
+        let div_node = self.new_divu(left_zext, right_zext);
+        let sext_node = self.new_sext32(div_node);
+        self.reg_flow_ite(rtype.rd(), sext_node);
+    }
+
     fn model_remu(&mut self, rtype: RType) {
         self.remainder_flow = self.new_ite(
             self.pc_flag(),
@@ -895,6 +1038,48 @@ impl ModelBuilder {
         self.reg_flow_ite(rtype.rd(), rem_node);
     }
 
+    fn model_rem(&mut self, rtype: RType) {
+        self.remainder_flow = self.new_ite(
+            self.pc_flag(),
+            self.reg_node(rtype.rs2()),
+            self.remainder_flow.clone(),
+            NodeType::Word,
+        );
+        let rem_node = self.new_srem(self.reg_node(rtype.rs1()), self.reg_node(rtype.rs2()));
+        self.reg_flow_ite(rtype.rd(), rem_node);
+    }
+
+    // We represent `remw(a, b)` as `srem(sext32(a), sext32(b))` instead.
+    // Note that the result always fits into 32 bits and hence is already
+    // properly sign-extended.
+    fn model_remw(&mut self, rtype: RType) {
+        let left_sext = self.new_sext32(self.reg_node(rtype.rs1()));
+        let right_sext = self.new_sext32(self.reg_node(rtype.rs2()));
+        self.remainder_flow = self.new_ite(
+            self.pc_flag(),
+            right_sext.clone(),
+            self.remainder_flow.clone(),
+            NodeType::Word,
+        );
+        let rem_node = self.new_srem(left_sext, right_sext);
+        self.reg_flow_ite(rtype.rd(), rem_node);
+    }
+
+    // We represent `remuw(a, b)` as `sext32(zext32(a) %u zext32(b))` instead.
+    fn model_remuw(&mut self, rtype: RType) {
+        let left_zext = self.new_zext32(self.reg_node(rtype.rs1()));
+        let right_zext = self.new_zext32(self.reg_node(rtype.rs2()));
+        self.remainder_flow = self.new_ite(
+            self.pc_flag(),
+            right_zext.clone(),
+            self.remainder_flow.clone(),
+            NodeType::Word,
+        );
+        let rem_node = self.new_rem(left_zext, right_zext);
+        let sext_node = self.new_sext32(rem_node);
+        self.reg_flow_ite(rtype.rd(), sext_node);
+    }
+
     fn model_sltu(&mut self, rtype: RType) {
         let ult_node = self.new_ult(self.reg_node(rtype.rs1()), self.reg_node(rtype.rs2()));
         let ext_node = self.add_node(Node::Ext {
@@ -905,6 +1090,29 @@ impl ModelBuilder {
         self.reg_flow_ite(rtype.rd(), ext_node);
     }
 
+    fn model_slt(&mut self, rtype: RType) {
+        let slt_node = self.new_slt(self.reg_node(rtype.rs1()), self.reg_node(rtype.rs2()));
+        let ext_node = self.add_node(Node::Ext {
+            nid: self.current_nid,
+            from: NodeType::Bit,
+            value: slt_node,
+        });
+        self.reg_flow_ite(rtype.rd(), ext_node);
+    }
+
+    fn model_slti(&mut self, itype: IType) {
+        let imm = itype.imm() as u64;
+        let imm_node = self.new_const(imm);
+
+        let slt_node = self.new_slt(self.reg_node(itype.rs1()), imm_node);
+        let ext_node = self.add_node(Node::Ext {
+            nid: self.current_nid,
+            from: NodeType::Bit,
+            value: slt_node,
+        });
+        self.reg_flow_ite(itype.rd(), ext_node);
+    }
+
     fn model_sltiu(&mut self, itype: IType) {
         let imm = itype.imm() as u64;
         let imm_node = self.new_const(imm);
@@ -961,7 +1169,7 @@ impl ModelBuilder {
         if jtype.rd() == Register::Zero {
             return;
         };
-        let link_node = self.new_const(self.pc_add(INSTRUCTION_SIZE));
+        let link_node = self.new_const(self.pc_next());
         self.reg_flow_ite(jtype.rd(), link_node);
     }
 
@@ -969,7 +1177,7 @@ impl ModelBuilder {
         if itype.rd() == Register::Zero {
             return;
         };
-        let link_node = self.new_const(self.pc_add(INSTRUCTION_SIZE));
+        let link_node = self.new_const(self.pc_next());
         self.reg_flow_ite(itype.rd(), link_node);
     }
 
@@ -996,11 +1204,13 @@ impl ModelBuilder {
             Instruction::Ld(itype) => self.model_ld(itype),
             Instruction::Lbu(itype) => self.model_lbu(itype),
             Instruction::Lhu(itype) => self.model_lhu(itype),
+            Instruction::Lwu(itype) => self.model_lwu(itype),
             Instruction::Sb(stype) => self.model_sb(stype),
             Instruction::Sh(stype) => self.model_sh(stype),
             Instruction::Sw(stype) => self.model_sw(stype),
             Instruction::Sd(stype) => self.model_sd(stype),
             Instruction::Addi(itype) => self.model_addi(itype),
+            Instruction::Slti(itype) => self.model_slti(itype),
             Instruction::Sltiu(itype) => self.model_sltiu(itype),
             Instruction::Xori(itype) => self.model_xori(itype),
             Instruction::Ori(itype) => self.model_ori(itype),
@@ -1015,20 +1225,31 @@ impl ModelBuilder {
             Instruction::Add(rtype) => self.model_add(rtype),
             Instruction::Sub(rtype) => self.model_sub(rtype),
             Instruction::Sll(rtype) => self.model_sll(rtype),
+            Instruction::Slt(rtype) => self.model_slt(rtype),
             Instruction::Sltu(rtype) => self.model_sltu(rtype),
+            Instruction::Xor(rtype) => self.model_xor(rtype),
             Instruction::Srl(rtype) => self.model_srl(rtype),
             Instruction::Sra(rtype) => self.model_sra(rtype),
             Instruction::Or(rtype) => self.model_or(rtype),
             Instruction::And(rtype) => self.model_and(rtype),
             Instruction::Mul(rtype) => self.model_mul(rtype),
+            Instruction::Mulh(rtype) => self.model_mulh(rtype),
+            Instruction::Mulhsu(rtype) => self.model_mulhsu(rtype),
+            Instruction::Mulhu(rtype) => self.model_mulhu(rtype),
             Instruction::Div(rtype) => self.model_div(rtype),
             Instruction::Divu(rtype) => self.model_divu(rtype),
+            Instruction::Rem(rtype) => self.model_rem(rtype),
             Instruction::Remu(rtype) => self.model_remu(rtype),
             Instruction::Addw(rtype) => self.model_addw(rtype),
-            Instruction::Divw(rtype) => self.model_divw(rtype),
             Instruction::Subw(rtype) => self.model_subw(rtype),
             Instruction::Sllw(rtype) => self.model_sllw(rtype),
+            Instruction::Srlw(rtype) => self.model_srlw(rtype),
+            Instruction::Sraw(rtype) => self.model_sraw(rtype),
             Instruction::Mulw(rtype) => self.model_mulw(rtype),
+            Instruction::Divw(rtype) => self.model_divw(rtype),
+            Instruction::Divuw(rtype) => self.model_divuw(rtype),
+            Instruction::Remw(rtype) => self.model_remw(rtype),
+            Instruction::Remuw(rtype) => self.model_remuw(rtype),
             Instruction::Beq(btype) => self.model_beq(btype, &mut branch_true, &mut branch_false),
             Instruction::Bne(btype) => self.model_bne(btype, &mut branch_true, &mut branch_false),
             Instruction::Blt(btype) => self.model_blt(btype, &mut branch_true, &mut branch_false),
@@ -1056,6 +1277,7 @@ impl ModelBuilder {
             | Instruction::Sw(_)
             | Instruction::Sd(_)
             | Instruction::Addi(_)
+            | Instruction::Slti(_)
             | Instruction::Sltiu(_)
             | Instruction::Xori(_)
             | Instruction::Ori(_)
@@ -1070,26 +1292,32 @@ impl ModelBuilder {
             | Instruction::Add(_)
             | Instruction::Sub(_)
             | Instruction::Sll(_)
+            | Instruction::Slt(_)
             | Instruction::Sltu(_)
+            | Instruction::Xor(_)
             | Instruction::Srl(_)
             | Instruction::Sra(_)
             | Instruction::Or(_)
             | Instruction::And(_)
             | Instruction::Mul(_)
+            | Instruction::Mulh(_)
+            | Instruction::Mulhsu(_)
+            | Instruction::Mulhu(_)
             | Instruction::Div(_)
             | Instruction::Divu(_)
+            | Instruction::Rem(_)
             | Instruction::Remu(_)
             | Instruction::Addw(_)
             | Instruction::Subw(_)
             | Instruction::Sllw(_)
+            | Instruction::Srlw(_)
+            | Instruction::Sraw(_)
             | Instruction::Mulw(_)
-            | Instruction::Divw(_) => {
-                self.go_to_instruction(
-                    FromInstruction::Regular,
-                    self.pc,
-                    self.pc_add(INSTRUCTION_SIZE),
-                    None,
-                );
+            | Instruction::Divw(_)
+            | Instruction::Divuw(_)
+            | Instruction::Remw(_)
+            | Instruction::Remuw(_) => {
+                self.go_to_instruction(FromInstruction::Regular, self.pc, self.pc_next(), None);
             }
             Instruction::Beq(btype)
             | Instruction::Bne(btype)
@@ -1108,7 +1336,7 @@ impl ModelBuilder {
                 self.go_to_instruction(
                     FromInstruction::Branch,
                     self.pc,
-                    self.pc_add(INSTRUCTION_SIZE),
+                    self.pc_next(),
                     branch_false,
                 );
             }
@@ -1131,12 +1359,7 @@ impl ModelBuilder {
                 self.go_to_anywhere(self.pc, itype.rs1());
             }
             Instruction::Ecall(_) => {
-                self.go_to_instruction(
-                    FromInstruction::SystemCall,
-                    self.pc,
-                    self.pc_add(INSTRUCTION_SIZE),
-                    None,
-                );
+                self.go_to_instruction(FromInstruction::SystemCall, self.pc, self.pc_next(), None);
             }
             _ => todo!("{:?}", inst),
         }
@@ -1276,11 +1499,20 @@ impl ModelBuilder {
         assert_eq!(self.dynamic_flags.len(), NUMBER_OF_REGISTERS - 1);
         assert_eq!(self.dynamic.len(), NUMBER_OF_REGISTERS - 1);
 
+        // Instructions are either 4 bytes or, when compressed (RV64C), 2 bytes
+        // long. Hence PC flags exist at 2-byte granularity, but only for those
+        // addresses at which an instruction actually starts.
+        let code_start = program.instruction_range.start;
+        let instructions = raw_instructions(code_start, &program.instructions()[..])
+            .map(|(pc, length, raw)| decode(raw).map(|inst| (pc, length as u64, inst)))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to decode instructions of program")?;
+
         self.new_comment("64-bit program counter encoded in Boolean flags".to_string());
-        self.pc = program.instruction_range.start;
-        for n in program.instruction_range.clone().step_by(size_of::<u32>()) {
+        for (pc, _, _) in instructions.iter() {
+            self.pc = *pc;
             self.current_nid = 10000000 + self.pc * 100;
-            let initial_value = if n == program.instruction_range.start {
+            let initial_value = if self.pc == code_start {
                 self.one_bit.clone()
             } else {
                 self.zero_bit.clone()
@@ -1291,7 +1523,6 @@ impl ModelBuilder {
                 NodeType::Bit,
             );
             self.pc_flags.insert(self.pc, flag_node);
-            self.pc = self.pc_add(INSTRUCTION_SIZE);
         }
 
         self.new_comment("64-bit virtual memory".to_string());
@@ -1336,19 +1567,12 @@ impl ModelBuilder {
         self.memory_flow = self.memory_node.clone();
 
         self.new_comment("data flow".to_string());
-        self.pc = program.instruction_range.start;
-        program
-            .instructions()
-            .chunks(size_of::<u32>())
-            .map(LittleEndian::read_u32)
-            .try_for_each(|raw| {
-                decode(raw).map(|inst| {
-                    self.current_nid = 30000000 + self.pc * 100;
-                    self.translate_to_model(inst);
-                    self.pc = self.pc_add(INSTRUCTION_SIZE);
-                })
-            })
-            .context("Failed to decode instructions of program")?;
+        for (pc, length, inst) in instructions.iter().cloned() {
+            self.pc = pc;
+            self.instruction_length = length;
+            self.current_nid = 30000000 + self.pc * 100;
+            self.translate_to_model(inst);
+        }
 
         self.new_comment("syscalls".to_string());
         self.current_nid = 40000000;
@@ -1482,10 +1706,10 @@ impl ModelBuilder {
         self.new_next(self.kernel_mode.clone(), kernel_flow, NodeType::Bit);
 
         self.new_comment("control flow".to_string());
-        self.pc = program.instruction_range.start;
         let mut control_flow_update_nodes = HashMap::new();
         let dynamic_dispatches = self.dynamic_dispatches();
-        for _n in program.instruction_range.clone().step_by(size_of::<u32>()) {
+        for (pc, _, _) in instructions.iter() {
+            self.pc = *pc;
             self.current_nid = 50000000 + self.pc * 100;
             let mut control_flow = self.zero_bit.clone();
             for in_edge in self.control_in.remove(&self.pc).unwrap_or_default() {
@@ -1507,7 +1731,6 @@ impl ModelBuilder {
             }
             control_flow_update_nodes.insert(self.pc, control_flow.clone());
             self.new_next(self.pc_flag(), control_flow, NodeType::Bit);
-            self.pc = self.pc_add(INSTRUCTION_SIZE);
         }
 
         self.new_comment("updating registers".to_string());
@@ -1598,3 +1821,248 @@ impl ModelBuilder {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::simulator::evaluate;
+
+    const MEMORY_SIZE: u64 = 0x10000;
+    const DATA_ADDRESS: u64 = 0x1000;
+    const DATA_WORD: u64 = 0xdead_beef_8765_4321;
+
+    // Encodes an R-type instruction `a0 = a1 op a2`.
+    fn encode_rtype(opcode: u32, funct3: u32, funct7: u32) -> u32 {
+        let (rd, rs1, rs2) = (10, 11, 12);
+        funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+    }
+
+    // Encodes an I-type instruction `a0 = a1 op imm`.
+    fn encode_itype(opcode: u32, funct3: u32, imm: i32) -> u32 {
+        let (rd, rs1) = (10, 11);
+        (imm as u32 & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+    }
+
+    // Models the given instruction at address zero in a builder where all
+    // registers except `a1` and `a2` are zero, and `DATA_WORD` is stored at
+    // `DATA_ADDRESS` in memory.
+    fn translate(instruction: u32, a1: u64, a2: u64) -> ModelBuilder {
+        let mut builder = ModelBuilder::new(MEMORY_SIZE, 0, 0);
+        builder.one_bit = builder.add_node(Node::Const {
+            nid: builder.current_nid,
+            sort: NodeType::Bit,
+            imm: 1,
+        });
+        builder.zero_word = builder.new_const(0);
+        builder.division_flow = builder.new_const(1);
+        builder.remainder_flow = builder.division_flow.clone();
+        builder.access_flow = builder.zero_word.clone();
+        let memory = builder.add_node(Node::State {
+            nid: builder.current_nid,
+            sort: NodeType::Memory,
+            init: None,
+            name: Some("memory".to_string()),
+        });
+        let address = builder.new_const(DATA_ADDRESS);
+        let value = builder.new_const(DATA_WORD);
+        builder.memory_node = builder.add_node(Node::Write {
+            nid: builder.current_nid,
+            memory,
+            address,
+            value,
+        });
+        for r in 0..NUMBER_OF_REGISTERS {
+            let value = match Register::from(r as u32) {
+                Register::A1 => a1,
+                Register::A2 => a2,
+                _ => 0,
+            };
+            let register_node = builder.new_const(value);
+            builder.register_nodes.push(register_node);
+        }
+        builder.register_flow = vec![builder.zero_word.clone(); NUMBER_OF_REGISTERS - 1];
+        builder.pc_flags.insert(0, builder.one_bit.clone());
+        builder.instruction_length = 4;
+        builder.translate_to_model(decode(instruction).expect("valid instruction"));
+        builder
+    }
+
+    fn value_of(node: &NodeRef) -> u64 {
+        evaluate(node, |_| unreachable!("no inputs"))
+    }
+
+    // Checks `a0` after executing the instruction for all `(a1, a2, a0)`.
+    fn check_rtype(instruction: u32, cases: &[(u64, u64, u64)]) {
+        for &(a1, a2, a0) in cases {
+            let builder = translate(instruction, a1, a2);
+            let result = value_of(&builder.reg_flow(Register::A0));
+            assert_eq!(
+                result, a0,
+                "{:#x} with a1={:#x} a2={:#x}",
+                instruction, a1, a2
+            );
+        }
+    }
+
+    fn neg(value: i64) -> u64 {
+        value as u64
+    }
+
+    #[test]
+    fn model_slt_compares_signed() {
+        let slt = encode_rtype(0x33, 2, 0);
+        check_rtype(
+            slt,
+            &[
+                (neg(-1), 0, 1),
+                (0, neg(-1), 0),
+                (i64::MIN as u64, i64::MAX as u64, 1),
+                (i64::MAX as u64, i64::MIN as u64, 0),
+                (5, 5, 0),
+            ],
+        );
+    }
+
+    #[test]
+    fn model_slti_sign_extends_immediate() {
+        for &(a1, imm, a0) in &[(neg(-2), -1, 1), (0, -1, 0), (i64::MIN as u64, 2047, 1)] {
+            let builder = translate(encode_itype(0x13, 2, imm), a1, 0);
+            assert_eq!(value_of(&builder.reg_flow(Register::A0)), a0);
+        }
+    }
+
+    #[test]
+    fn model_xor() {
+        let xor = encode_rtype(0x33, 4, 0);
+        check_rtype(
+            xor,
+            &[(0xff00, 0x0ff0, 0xf0f0), (u64::MAX, 1, u64::MAX - 1)],
+        );
+    }
+
+    #[test]
+    fn model_lwu_zero_extends() {
+        for &(offset, a0) in &[(0, 0x8765_4321), (4, 0xdead_beef)] {
+            let builder = translate(encode_itype(0x03, 6, offset), DATA_ADDRESS, 0);
+            assert_eq!(value_of(&builder.reg_flow(Register::A0)), a0);
+            assert_eq!(value_of(&builder.access_flow), DATA_ADDRESS);
+        }
+    }
+
+    #[test]
+    fn model_srlw_and_sraw_use_low_32_bits() {
+        let srlw = encode_rtype(0x3b, 5, 0);
+        check_rtype(
+            srlw,
+            &[
+                (0x8000_0000, 0, 0xffff_ffff_8000_0000),
+                (0xffff_ffff_8000_0000, 31, 1),
+                (0x8000_0000, 33, 0x4000_0000),
+                (0x1_0000_0000, 0, 0),
+            ],
+        );
+        let sraw = encode_rtype(0x3b, 5, 0x20);
+        check_rtype(
+            sraw,
+            &[
+                (0x8000_0000, 31, u64::MAX),
+                (0x1_7fff_ffff, 36, 0x07ff_ffff),
+                (0x8000_0000, 32, 0xffff_ffff_8000_0000),
+            ],
+        );
+    }
+
+    #[test]
+    fn model_mulh_variants_return_upper_bits() {
+        let mulh = encode_rtype(0x33, 1, 1);
+        check_rtype(
+            mulh,
+            &[
+                (u64::MAX, u64::MAX, 0),
+                (i64::MIN as u64, i64::MIN as u64, 0x4000_0000_0000_0000),
+                (i64::MIN as u64, i64::MAX as u64, 0xc000_0000_0000_0000),
+                (neg(-3), 5, u64::MAX),
+            ],
+        );
+        let mulhsu = encode_rtype(0x33, 2, 1);
+        check_rtype(
+            mulhsu,
+            &[
+                (neg(-1), u64::MAX, u64::MAX),
+                (i64::MIN as u64, u64::MAX, 0x8000_0000_0000_0000),
+                (1, u64::MAX, 0),
+            ],
+        );
+        let mulhu = encode_rtype(0x33, 3, 1);
+        check_rtype(
+            mulhu,
+            &[
+                (u64::MAX, u64::MAX, 0xffff_ffff_ffff_fffe),
+                (1 << 32, 1 << 32, 1),
+                (0xffff_ffff, 0xffff_ffff, 0),
+                (u64::MAX, 2, 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn model_rem_follows_risc_v_semantics() {
+        let rem = encode_rtype(0x33, 6, 1);
+        check_rtype(
+            rem,
+            &[
+                (i64::MIN as u64, neg(-1), 0),
+                (neg(-7), 2, neg(-1)),
+                (7, neg(-2), 1),
+                (7, 0, 7),
+                (neg(-7), 0, neg(-7)),
+            ],
+        );
+        let remw = encode_rtype(0x3b, 6, 1);
+        check_rtype(
+            remw,
+            &[
+                (0xffff_ffff_8000_0000, neg(-1), 0),
+                (0x1234_5678_ffff_fff9, 2, neg(-1)),
+                (0x1_0000_0007, 0x2_0000_0002, 1),
+                (7, 1 << 32, 7),
+            ],
+        );
+        let remuw = encode_rtype(0x3b, 7, 1);
+        check_rtype(
+            remuw,
+            &[
+                (0xffff_ffff, 0, u64::MAX),
+                (0xffff_fffe, 0x1_0000_0003, 2),
+                (0x9_8000_0000, 0xffff_ffff, 0xffff_ffff_8000_0000),
+            ],
+        );
+    }
+
+    #[test]
+    fn model_divuw_zero_extends_operands() {
+        let divuw = encode_rtype(0x3b, 5, 1);
+        check_rtype(
+            divuw,
+            &[
+                (0xffff_fffe, 1, 0xffff_ffff_ffff_fffe),
+                (5, 0, u64::MAX),
+                (0x1_0000_0006, 0x7_0000_0003, 2),
+                (0x8000_0000, 1, 0xffff_ffff_8000_0000),
+            ],
+        );
+    }
+
+    #[test]
+    fn model_word_divisors_are_checked_for_zero() {
+        let divuw = encode_rtype(0x3b, 5, 1);
+        let builder = translate(divuw, 5, 1 << 32);
+        assert_eq!(value_of(&builder.division_flow), 0);
+        let remw = encode_rtype(0x3b, 6, 1);
+        let builder = translate(remw, 5, 1 << 32);
+        assert_eq!(value_of(&builder.remainder_flow), 0);
+        let remuw = encode_rtype(0x3b, 7, 1);
+        let builder = translate(remuw, 5, 0x3_0000_0002);
+        assert_eq!(value_of(&builder.remainder_flow), 2);
+    }
+}
@@ -18,6 +18,17 @@ pub trait CNFContainer {
     fn record_variable_name(&mut self, var: Self::Variable, name: String);
 }
 
+// A container that decides its clauses incrementally under assumptions, as
+// needed by model checkers using a SAT solver as an oracle.
+pub trait CNFOracle: CNFContainer {
+    // Yields `None` if the solver gave up on the query.
+    fn solve_with(&mut self, assumptions: &[Self::Literal]) -> Option<bool>;
+    // Value of the given variable after a satisfiable query.
+    fn value(&self, var: Self::Variable) -> bool;
+    // Whether the given assumption was needed to refute the last query.
+    fn failed(&self, literal: &Self::Literal) -> bool;
+}
+
 pub struct CNFBuilder<C: CNFContainer> {
     mapping: HashMap<HashableGateRef, C::Variable>,
     container: C,
@@ -39,6 +50,11 @@ impl<C: CNFContainer> CNFBuilder<C> {
         &mut self.container
     }
 
+    pub fn variable(&self, gate: &GateRef) -> Option<C::Variable> {
+        let key = HashableGateRef::from(gate.clone());
+        self.mapping.get(&key).copied()
+    }
+
     pub fn visit(&mut self, gate: &GateRef) -> C::Variable {
         let key = HashableGateRef::from(gate.clone());
         self.mapping.get(&key).copied().unwrap_or_else(|| {
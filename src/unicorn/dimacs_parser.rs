@@ -1,6 +1,8 @@
 use crate::unicorn::bitblasting::{Gate, GateModel, GateRef};
+use crate::unicorn::parse_error::ParseError;
 use crate::unicorn::{Node, NodeType};
 use anyhow::{Context, Result};
+use indexmap::IndexMap;
 use regex::Regex;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -43,6 +45,7 @@ fn and_gate(a: GateRef, b: GateRef) -> GateRef {
 }
 
 struct DimacsParser {
+    file: String,
     num_variables: usize,
     num_clauses: usize,
     gate_variables: Vec<GateRef>,
@@ -53,6 +56,7 @@ struct DimacsParser {
 impl DimacsParser {
     fn new() -> Self {
         Self {
+            file: String::new(),
             num_variables: 0,
             num_clauses: 0,
             gate_variables: Vec::new(),
@@ -98,24 +102,36 @@ impl DimacsParser {
         GateModel {
             bad_state_gates: vec![gate],
             bad_state_nodes: vec![node],
-            constraints: HashMap::new(),
+            constraints: IndexMap::new(),
             input_gates: Vec::new(),
             mapping: HashMap::new(),
             mapping_adders: HashMap::new(),
             constraint_based_dependencies: HashMap::new(),
+            latches: Vec::new(),
+            bad_states_sequential: Vec::new(),
         }
     }
 
+    fn error(&self, line: usize, column: usize, token: &str, expected: &str) -> anyhow::Error {
+        ParseError::new(&self.file, line, column, token, expected).into()
+    }
+
     fn parse_dimacs_text_file(&mut self, path: &Path) -> Result<()> {
         let re_magic: Regex = Regex::new(r"^p cnf ([0-9]+) ([0-9]+)$").unwrap();
         let re_clause: Regex = Regex::new(r"^((-?[1-9][0-9]* )+)0$").unwrap();
 
-        let mut has_seen_magic_line = false;
+        // Position of the declared number of clauses, which is checked once
+        // all clauses have been read.
+        let mut magic_line: Option<(usize, usize, String)> = None;
+        let mut number = 0;
 
-        let file = File::open(path)?;
+        self.file = path.display().to_string();
+        let file =
+            File::open(path).with_context(|| format!("Failed to open DIMACS file {:?}", path))?;
         let reader = BufReader::new(file);
-        for line in reader.lines() {
-            let line = line.unwrap();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.with_context(|| format!("Failed to read DIMACS file {:?}", path))?;
+            number = index + 1;
 
             // Skip all comment lines.
             if line.starts_with("c ") {
@@ -124,35 +140,77 @@ impl DimacsParser {
 
             // Recognize CNF magic line.
             if let Some(caps) = re_magic.captures(&line) {
-                assert!(!has_seen_magic_line);
+                if magic_line.is_some() {
+                    return Err(self.error(number, 1, &line, "a single problem line"));
+                }
                 let num_variables = caps.get(1).context("missing #variables")?;
                 let num_clauses = caps.get(2).context("missing #clauses")?;
-                self.num_variables = usize::from_str(num_variables.as_str())?;
-                self.num_clauses = usize::from_str(num_clauses.as_str())?;
+                self.num_variables = usize::from_str(num_variables.as_str()).map_err(|_| {
+                    let column = num_variables.start() + 1;
+                    self.error(
+                        number,
+                        column,
+                        num_variables.as_str(),
+                        "number of variables",
+                    )
+                })?;
+                self.num_clauses = usize::from_str(num_clauses.as_str()).map_err(|_| {
+                    let column = num_clauses.start() + 1;
+                    self.error(number, column, num_clauses.as_str(), "number of clauses")
+                })?;
                 self.initialize_variables();
-                has_seen_magic_line = true;
+                let column = num_clauses.start() + 1;
+                magic_line = Some((number, column, num_clauses.as_str().to_string()));
                 continue;
             }
 
             // Recognize a clause line.
             if let Some(caps) = re_clause.captures(&line) {
-                assert!(has_seen_magic_line);
-                let clause = caps.get(1).context("missing literals")?.as_str().trim();
-                self.add_clause(
-                    clause
-                        .split(' ')
-                        .map(|l| i32::from_str(l).expect("range"))
-                        .collect(),
-                );
+                if magic_line.is_none() {
+                    return Err(self.error(
+                        number,
+                        1,
+                        &line,
+                        "problem line 'p cnf <#variables> <#clauses>'",
+                    ));
+                }
+                let clause = caps.get(1).context("missing literals")?.as_str();
+                let mut literals = Vec::new();
+                let mut column = 1;
+                for token in clause.split(' ').filter(|l| !l.is_empty()) {
+                    match i32::from_str(token) {
+                        Ok(literal) if literal.unsigned_abs() as usize <= self.num_variables => {
+                            literals.push(literal)
+                        }
+                        _ => {
+                            let expected =
+                                format!("a literal of variables 1 to {}", self.num_variables);
+                            return Err(self.error(number, column, token, &expected));
+                        }
+                    }
+                    column += token.len() + 1;
+                }
+                self.add_clause(literals);
                 continue;
             }
 
-            panic!("Unrecognized line: {}", line);
+            let expected = "a comment, problem line or zero-terminated clause";
+            return Err(self.error(number, 1, &line, expected));
         }
 
+        match magic_line {
+            None => {
+                let expected = "problem line 'p cnf <#variables> <#clauses>'";
+                return Err(self.error(number + 1, 1, "", expected));
+            }
+            Some((line, column, token)) if self.gate_clauses.len() != self.num_clauses => {
+                let expected = format!("{} clauses as found in the file", self.gate_clauses.len());
+                return Err(self.error(line, column, &token, &expected));
+            }
+            _ => (),
+        }
         assert!(self.gate_variables.len() == self.num_variables);
         assert!(self.gate_negations.len() == self.num_variables);
-        assert!(self.gate_clauses.len() == self.num_clauses);
         Ok(())
     }
 }
@@ -0,0 +1,682 @@
+use crate::unicorn::bitblasting::{bitblast_sequential_model, get_constant, GateModel, GateRef};
+use crate::unicorn::cnf::{CNFBuilder, CNFOracle};
+use crate::unicorn::witness::Witness;
+use crate::unicorn::{Model, Node, NodeRef, NodeType};
+use crate::SatType;
+use anyhow::{anyhow, bail, ensure, Result};
+use log::{debug, info, warn};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "cadical")]
+use crate::unicorn::sat_solver::cadical_impl;
+#[cfg(feature = "varisat")]
+use crate::unicorn::sat_solver::varisat_impl;
+
+//
+// Public Interface
+//
+
+pub struct Ic3Options {
+    pub max_frames: usize,
+    pub time_budget: Option<Duration>,
+}
+
+// Values of all states at a single step of a trace, together with the values
+// of the inputs consumed by that step.
+#[derive(Debug)]
+pub struct TraceStep {
+    pub states: Vec<(String, u64)>,
+    pub inputs: Vec<(String, u64)>,
+}
+
+#[derive(Debug)]
+pub enum Ic3Proof {
+    // Clauses over state bits, the bits are given by name and each clause
+    // holds if one of its bits has the given value.
+    Invariant(Vec<Vec<(String, bool)>>),
+    Counterexample {
+        trace: Vec<TraceStep>,
+        witness: Witness,
+    },
+    Unknown,
+}
+
+pub struct Ic3Result {
+    pub kinds: Vec<String>,
+    pub proofs: Vec<Ic3Proof>,
+}
+
+// Proves each kind of bad state unreachable by property-directed reachability
+// (IC3) on the bit-level transition system of the sequential model. Memory
+// has to be discretized already.
+pub fn prove_with_sat(model: &Model, sat_type: SatType, options: &Ic3Options) -> Result<Ic3Result> {
+    match sat_type {
+        #[cfg(feature = "varisat")]
+        SatType::Varisat => prove::<varisat_impl::VarisatContainer>(model, options),
+        #[cfg(feature = "cadical")]
+        SatType::Cadical => prove::<cadical_impl::CadicalContainer>(model, options),
+        #[cfg(feature = "kissat")]
+        SatType::Kissat => bail!("IC3 requires CaDiCaL or Varisat as SAT solver"),
+        SatType::None | SatType::External => {
+            bail!("IC3 requires CaDiCaL or Varisat as SAT solver")
+        }
+    }
+}
+
+//
+// Private Implementation
+//
+
+// A single state bit, its value after a step is given by the `next` gate.
+struct Latch {
+    name: String,
+    current: GateRef,
+    next: GateRef,
+    init: Option<bool>,
+}
+
+// Bit-level version of the sequential model, with the latches of all states
+// flattened. Inputs of a step are input bits.
+struct TransitionSystem {
+    gate_model: GateModel,
+    latches: Vec<Latch>,
+    states: Vec<(String, Vec<usize>)>,
+    inputs: Vec<(String, Vec<GateRef>)>,
+}
+
+fn prove<C: CNFOracle>(model: &Model, options: &Ic3Options) -> Result<Ic3Result> {
+    debug!("Proving model by IC3 using {} ...", C::name());
+    let start = Instant::now();
+    let system = transition_system(model)?;
+    let bad_states = &system.gate_model.bad_states_sequential;
+    let kinds: Vec<String> = bad_states
+        .iter()
+        .map(|(bad_state, _)| bad_state_name(bad_state))
+        .collect();
+    let mut proofs = Vec::new();
+    for (kind, (_, gate)) in kinds.iter().zip(bad_states) {
+        let proof = Ic3::<C>::new(&system, gate).run(options, start);
+        match &proof {
+            Ic3Proof::Invariant(clauses) => {
+                info!(
+                    "Bad state '{}' is unreachable, {} clauses",
+                    kind,
+                    clauses.len()
+                );
+            }
+            Ic3Proof::Counterexample { trace, .. } => {
+                warn!(
+                    "Bad state '{}' is reachable at depth {}!",
+                    kind,
+                    trace.len() - 1
+                );
+            }
+            Ic3Proof::Unknown => warn!("Bad state '{}' is unknown", kind),
+        }
+        proofs.push(proof);
+    }
+    Ok(Ic3Result { kinds, proofs })
+}
+
+fn bad_state_name(bad_state: &NodeRef) -> String {
+    if let Node::Bad { name, .. } = &*bad_state.borrow() {
+        name.as_deref().unwrap_or("?").to_string()
+    } else {
+        panic!("expecting 'Bad' node here");
+    }
+}
+
+fn state_name(state: &NodeRef) -> String {
+    if let Node::State { nid, name, .. } = &*state.borrow() {
+        name.clone().unwrap_or_else(|| format!("state-{}", nid))
+    } else {
+        panic!("expecting 'State' node here");
+    }
+}
+
+// Inputs are either proper inputs or uninitialized states outside of the
+// sequential part of the model.
+fn input_name(input: &NodeRef) -> String {
+    if let Node::Input { name, .. } = &*input.borrow() {
+        name.clone()
+    } else {
+        state_name(input)
+    }
+}
+
+fn transition_system(model: &Model) -> Result<TransitionSystem> {
+    for sequential in &model.sequentials {
+        if let Node::Next { sort, .. } = &*sequential.borrow() {
+            ensure!(*sort != NodeType::Memory, "IC3 requires discretized memory");
+        } else {
+            panic!("expecting 'Next' node here");
+        }
+    }
+    let gate_model = bitblast_sequential_model(model, true, 64);
+
+    let mut latches = Vec::new();
+    let mut words = Vec::new();
+    for (state, bits) in &gate_model.latches {
+        let name = state_name(state);
+        let mut indices = Vec::new();
+        for (bit, latch) in bits.iter().enumerate() {
+            let init = match &latch.init {
+                Some(init) => Some(get_constant(init).ok_or_else(|| {
+                    anyhow!("IC3 requires constant initial values, '{}' has none", name)
+                })?),
+                None => None,
+            };
+            indices.push(latches.len());
+            latches.push(Latch {
+                name: format!("{}[bit={}]", name, bit),
+                current: latch.current.clone(),
+                next: latch.next.clone(),
+                init,
+            });
+        }
+        words.push((name, indices));
+    }
+
+    let inputs = gate_model
+        .input_gates
+        .iter()
+        .map(|(node, gates)| (input_name(node), gates.clone()))
+        .collect();
+
+    Ok(TransitionSystem {
+        gate_model,
+        latches,
+        states: words,
+        inputs,
+    })
+}
+
+// Cubes are conjunctions of latches (given by index) having the given value.
+type Cube = Vec<(usize, bool)>;
+
+// A state (given by the values of all latches) from which the bad state is
+// reachable, using the given inputs at this state. The parent is the state
+// reached next, it is `None` for states in which the bad state holds.
+struct Obligation {
+    state: Vec<bool>,
+    inputs: Vec<(String, u64)>,
+    level: usize,
+    parent: Option<usize>,
+}
+
+enum Query {
+    Sat(Vec<bool>, Vec<(String, u64)>),
+    Unsat(Cube),
+    Unknown,
+}
+
+enum Blocking {
+    Blocked,
+    Counterexample(usize),
+    Unknown,
+}
+
+struct Ic3<'a, C: CNFOracle> {
+    system: &'a TransitionSystem,
+    builder: CNFBuilder<C>,
+    bad: C::Variable,
+    currents: Vec<C::Variable>,
+    nexts: Vec<C::Variable>,
+    // Clauses blocking the cubes of a frame are only active if the variable
+    // of that frame is assumed. Frame zero (i.e. the initial states) is given
+    // by assumptions instead, hence it has no cubes.
+    levels: Vec<C::Variable>,
+    frames: Vec<Vec<Cube>>,
+    obligations: Vec<Obligation>,
+}
+
+fn literal<C: CNFOracle>(var: C::Variable, value: bool) -> C::Literal {
+    if value {
+        C::var(var)
+    } else {
+        C::neg(var)
+    }
+}
+
+fn cube_of(state: &[bool]) -> Cube {
+    state.iter().copied().enumerate().collect()
+}
+
+impl<'a, C: CNFOracle> Ic3<'a, C> {
+    fn new(system: &'a TransitionSystem, bad_gate: &GateRef) -> Self {
+        let mut builder = CNFBuilder::<C>::new();
+        for (gate, val) in &system.gate_model.constraints {
+            let constraint_var = builder.visit(&gate.value);
+            let constraint_lit = literal::<C>(constraint_var, *val);
+            builder.container_mut().add_clause(&[constraint_lit]);
+        }
+        let bad = builder.visit(bad_gate);
+        let currents = system
+            .latches
+            .iter()
+            .map(|l| builder.visit(&l.current))
+            .collect();
+        let nexts = system
+            .latches
+            .iter()
+            .map(|l| builder.visit(&l.next))
+            .collect();
+        let level_zero = builder.container_mut().new_var();
+        let mut ic3 = Self {
+            system,
+            builder,
+            bad,
+            currents,
+            nexts,
+            levels: vec![level_zero],
+            frames: vec![vec![]],
+            obligations: vec![],
+        };
+        ic3.add_frame();
+        ic3
+    }
+
+    fn run(&mut self, options: &Ic3Options, start: Instant) -> Ic3Proof {
+        let out_of_time = || options.time_budget.map_or(false, |t| start.elapsed() >= t);
+        let mut assumptions = self.assumptions(0);
+        assumptions.push(C::var(self.bad));
+        match self.solve(&assumptions) {
+            Some(true) => {
+                let (state, inputs) = self.assignment();
+                let root = self.oblige(state, inputs, 0, None);
+                return self.counterexample(root);
+            }
+            Some(false) => (),
+            None => return Ic3Proof::Unknown,
+        }
+        loop {
+            let depth = self.frames.len() - 1;
+            if depth > options.max_frames || out_of_time() {
+                return Ic3Proof::Unknown;
+            }
+            debug!("Blocking bad states in frame {} ...", depth);
+            loop {
+                let mut assumptions = self.assumptions(depth);
+                assumptions.push(C::var(self.bad));
+                match self.solve(&assumptions) {
+                    Some(true) => {
+                        let (state, inputs) = self.assignment();
+                        let root = self.oblige(state, inputs, depth, None);
+                        match self.block(root, &out_of_time) {
+                            Blocking::Blocked => (),
+                            Blocking::Counterexample(index) => return self.counterexample(index),
+                            Blocking::Unknown => return Ic3Proof::Unknown,
+                        }
+                    }
+                    Some(false) => break,
+                    None => return Ic3Proof::Unknown,
+                }
+            }
+            self.add_frame();
+            if let Some(level) = self.propagate() {
+                return self.invariant(level);
+            }
+        }
+    }
+
+    fn add_frame(&mut self) {
+        let var = self.builder.container_mut().new_var();
+        self.levels.push(var);
+        self.frames.push(vec![]);
+    }
+
+    // Frame zero holds the initial states, later frames hold all clauses of
+    // frames at or beyond their level.
+    fn assumptions(&self, level: usize) -> Vec<C::Literal> {
+        if level == 0 {
+            let latches = self.system.latches.iter().zip(&self.currents);
+            latches
+                .filter_map(|(latch, &var)| latch.init.map(|init| literal::<C>(var, init)))
+                .collect()
+        } else {
+            self.levels[level..]
+                .iter()
+                .map(|&var| C::var(var))
+                .collect()
+        }
+    }
+
+    fn solve(&mut self, assumptions: &[C::Literal]) -> Option<bool> {
+        self.builder.container_mut().solve_with(assumptions)
+    }
+
+    // Values of the current state and the inputs after a satisfiable query,
+    // bits that never made it into the CNF are considered `false`.
+    fn assignment(&self) -> (Vec<bool>, Vec<(String, u64)>) {
+        let container = self.builder.container();
+        let state = self
+            .currents
+            .iter()
+            .map(|&var| container.value(var))
+            .collect();
+        let inputs = self
+            .system
+            .inputs
+            .iter()
+            .map(|(name, gates)| {
+                let mut value = 0;
+                for (i, gate) in gates.iter().enumerate() {
+                    if let Some(var) = self.builder.variable(gate) {
+                        if container.value(var) {
+                            value |= 1 << i;
+                        }
+                    }
+                }
+                (name.clone(), value)
+            })
+            .collect();
+        (state, inputs)
+    }
+
+    fn oblige(
+        &mut self,
+        state: Vec<bool>,
+        inputs: Vec<(String, u64)>,
+        level: usize,
+        parent: Option<usize>,
+    ) -> usize {
+        self.obligations.push(Obligation {
+            state,
+            inputs,
+            level,
+            parent,
+        });
+        self.obligations.len() - 1
+    }
+
+    fn is_initial(&self, cube: &[(usize, bool)]) -> bool {
+        cube.iter().all(|&(i, value)| {
+            self.system.latches[i]
+                .init
+                .map_or(true, |init| init == value)
+        })
+    }
+
+    // Blocks the state of the given obligation in its frame, by recursively
+    // blocking its predecessors in earlier frames first. Fails if one of the
+    // predecessors is an initial state.
+    fn block<F: Fn() -> bool>(&mut self, root: usize, out_of_time: &F) -> Blocking {
+        let mut queue = vec![root];
+        while !queue.is_empty() {
+            if out_of_time() {
+                return Blocking::Unknown;
+            }
+            // The obligation in the earliest frame is handled first.
+            let position = (0..queue.len())
+                .min_by_key(|&p| self.obligations[queue[p]].level)
+                .expect("non-empty queue");
+            let index = queue.swap_remove(position);
+            let cube = cube_of(&self.obligations[index].state);
+            let level = self.obligations[index].level;
+            if level == 0 || self.is_initial(&cube) {
+                return Blocking::Counterexample(index);
+            }
+            match self.relative_induction(&cube, level - 1) {
+                Query::Sat(state, inputs) => {
+                    let predecessor = self.oblige(state, inputs, level - 1, Some(index));
+                    queue.push(predecessor);
+                    queue.push(index);
+                }
+                Query::Unsat(core) => {
+                    let generalized = self.generalize(&cube, core);
+                    self.add_blocking_clause(&generalized, level);
+                }
+                Query::Unknown => return Blocking::Unknown,
+            }
+        }
+        Blocking::Blocked
+    }
+
+    // Checks whether the given cube is reachable in a single step from the
+    // frame at the given level, without starting in the cube itself.
+    fn relative_induction(&mut self, cube: &[(usize, bool)], level: usize) -> Query {
+        let activation = self.builder.container_mut().new_var();
+        let mut clause = vec![C::neg(activation)];
+        for &(i, value) in cube {
+            clause.push(literal::<C>(self.currents[i], !value));
+        }
+        self.builder.container_mut().add_clause(&clause);
+        let mut assumptions = self.assumptions(level);
+        assumptions.push(C::var(activation));
+        for &(i, value) in cube {
+            assumptions.push(literal::<C>(self.nexts[i], value));
+        }
+        let query = match self.solve(&assumptions) {
+            Some(true) => {
+                let (state, inputs) = self.assignment();
+                Query::Sat(state, inputs)
+            }
+            Some(false) => {
+                let container = self.builder.container();
+                let core = cube
+                    .iter()
+                    .copied()
+                    .filter(|&(i, value)| container.failed(&literal::<C>(self.nexts[i], value)))
+                    .collect();
+                Query::Unsat(core)
+            }
+            None => Query::Unknown,
+        };
+        // The temporary clause is disabled for good.
+        self.builder
+            .container_mut()
+            .add_clause(&[C::neg(activation)]);
+        query
+    }
+
+    // The core of a refuted cube is blocked as well, as long as it does not
+    // contain an initial state. One of the latches of the original cube that
+    // excludes initial states is added back otherwise.
+    fn generalize(&self, cube: &[(usize, bool)], mut core: Cube) -> Cube {
+        if self.is_initial(&core) {
+            let excluding = cube
+                .iter()
+                .copied()
+                .find(|&(i, value)| self.system.latches[i].init == Some(!value))
+                .expect("cube without initial states");
+            core.push(excluding);
+        }
+        core
+    }
+
+    fn add_blocking_clause(&mut self, cube: &[(usize, bool)], level: usize) {
+        let mut clause = vec![C::neg(self.levels[level])];
+        for &(i, value) in cube {
+            clause.push(literal::<C>(self.currents[i], !value));
+        }
+        self.builder.container_mut().add_clause(&clause);
+        self.frames[level].push(cube.to_vec());
+    }
+
+    // Pushes clauses to the next frame if they are inductive relative to
+    // their frame. Yields the level of a frame that became equal to the next
+    // one, i.e. an inductive invariant was found.
+    fn propagate(&mut self) -> Option<usize> {
+        for level in 1..self.frames.len() - 1 {
+            let cubes = std::mem::take(&mut self.frames[level]);
+            for cube in cubes {
+                let mut assumptions = self.assumptions(level);
+                for &(i, value) in &cube {
+                    assumptions.push(literal::<C>(self.nexts[i], value));
+                }
+                if self.solve(&assumptions) == Some(false) {
+                    self.add_blocking_clause(&cube, level + 1);
+                } else {
+                    self.frames[level].push(cube);
+                }
+            }
+            if self.frames[level].is_empty() {
+                return Some(level);
+            }
+        }
+        None
+    }
+
+    fn invariant(&self, level: usize) -> Ic3Proof {
+        let clauses = self.frames[level + 1..]
+            .iter()
+            .flatten()
+            .map(|cube| {
+                cube.iter()
+                    .map(|&(i, value)| (self.system.latches[i].name.clone(), !value))
+                    .collect()
+            })
+            .collect();
+        Ic3Proof::Invariant(clauses)
+    }
+
+    // The trace starts at the initial state of the given obligation and ends
+    // in the state in which the bad state holds.
+    fn counterexample(&self, index: usize) -> Ic3Proof {
+        let mut trace = Vec::new();
+        let mut next = Some(index);
+        while let Some(index) = next {
+            let obligation = &self.obligations[index];
+            let states = self
+                .system
+                .states
+                .iter()
+                .map(|(name, bits)| {
+                    let value = bits
+                        .iter()
+                        .enumerate()
+                        .filter(|&(_, &latch)| obligation.state[latch])
+                        .fold(0, |value, (bit, _)| value | 1 << bit);
+                    (name.clone(), value)
+                })
+                .collect();
+            trace.push(TraceStep {
+                states,
+                inputs: obligation.inputs.clone(),
+            });
+            next = obligation.parent;
+        }
+        let witness = Witness::from_assignments(trace.iter().enumerate().flat_map(|(n, step)| {
+            step.inputs
+                .iter()
+                .map(move |(name, value)| (format!("{}[n={}]", name, n), *value))
+        }));
+        Ic3Proof::Counterexample { trace, witness }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bit(nid: u64, imm: u64) -> NodeRef {
+        NodeRef::from(Node::Const {
+            nid,
+            sort: NodeType::Bit,
+            imm,
+        })
+    }
+
+    fn model_of(sequentials: Vec<NodeRef>, bad_states: Vec<NodeRef>) -> Model {
+        Model {
+            lines: vec![],
+            sequentials,
+            bad_states_initial: vec![],
+            bad_states_sequential: bad_states,
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    // A flag that is never raised, but could be raised by an input.
+    fn flag_model(raised_by_input: bool) -> Model {
+        let flag = NodeRef::from(Node::State {
+            nid: 2,
+            sort: NodeType::Bit,
+            init: Some(bit(1, 0)),
+            name: Some("flag".to_string()),
+        });
+        let next = if raised_by_input {
+            NodeRef::from(Node::Input {
+                nid: 3,
+                sort: NodeType::Bit,
+                name: "raise".to_string(),
+            })
+        } else {
+            flag.clone()
+        };
+        let sequential = NodeRef::from(Node::Next {
+            nid: 4,
+            sort: NodeType::Bit,
+            state: flag.clone(),
+            next,
+        });
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 5,
+            cond: flag,
+            name: Some("raised".to_string()),
+        });
+        model_of(vec![sequential], vec![bad_state])
+    }
+
+    #[test]
+    fn builds_latches_of_state_bits() {
+        let system = transition_system(&flag_model(true)).unwrap();
+
+        assert_eq!(system.latches.len(), 1);
+        assert_eq!(system.latches[0].name, "flag[bit=0]");
+        assert_eq!(system.latches[0].init, Some(false));
+        assert_eq!(system.states, vec![("flag".to_string(), vec![0])]);
+        assert_eq!(system.inputs.len(), 1);
+        assert_eq!(system.inputs[0].0, "raise");
+        assert_eq!(system.gate_model.bad_states_sequential.len(), 1);
+    }
+
+    #[test]
+    fn requires_constant_initial_values() {
+        let model = flag_model(false);
+        if let Node::Next { state, .. } = &*model.sequentials[0].borrow() {
+            if let Node::State { init, .. } = &mut *state.borrow_mut() {
+                *init = Some(NodeRef::from(Node::Input {
+                    nid: 6,
+                    sort: NodeType::Bit,
+                    name: "unknown".to_string(),
+                }));
+            }
+        }
+
+        assert!(transition_system(&model).is_err());
+    }
+
+    #[cfg(feature = "varisat")]
+    #[test]
+    fn proves_or_refutes_raised_flag() {
+        let options = Ic3Options {
+            max_frames: 10,
+            time_budget: None,
+        };
+
+        let model = flag_model(false);
+        let result = prove::<varisat_impl::VarisatContainer>(&model, &options).unwrap();
+        assert_eq!(result.kinds, vec!["raised".to_string()]);
+        match &result.proofs[0] {
+            Ic3Proof::Invariant(clauses) => {
+                assert_eq!(clauses, &vec![vec![("flag[bit=0]".to_string(), false)]]);
+            }
+            proof => panic!("unexpected proof {:?}", proof),
+        }
+
+        let model = flag_model(true);
+        let result = prove::<varisat_impl::VarisatContainer>(&model, &options).unwrap();
+        match &result.proofs[0] {
+            Ic3Proof::Counterexample { trace, witness } => {
+                assert_eq!(trace.len(), 2);
+                assert_eq!(trace[1].states, vec![("flag".to_string(), 1)]);
+                assert_eq!(witness.value("raise[n=0]"), Some(1));
+            }
+            proof => panic!("unexpected proof {:?}", proof),
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use crate::unicorn::bmc::{BmcOptions, Counterexample};
+use crate::unicorn::smt_solver::{SMTOptions, SMTSolution, SMTSolver};
+use crate::unicorn::unroller::unroll_step;
+use crate::unicorn::{Model, Node, NodeRef, NodeType};
+use anyhow::Result;
+use log::{debug, info, warn};
+use std::time::Instant;
+
+//
+// Public Interface
+//
+
+#[derive(Debug)]
+pub enum Proof {
+    Proved { k: usize },
+    Counterexample(Counterexample),
+    Unknown,
+}
+
+pub struct InductionResult {
+    pub kinds: Vec<String>,
+    pub proofs: Vec<Proof>,
+    pub depth: usize,
+}
+
+// Tries to prove each kind of bad state unreachable at every depth using
+// k-induction. The base case is bounded model checking from the initial
+// states. The step case starts from arbitrary states, assumes the bad state
+// to be unreachable for `k` steps along a simple path (i.e. with pairwise
+// distinct states) and checks whether it is reachable after them.
+pub fn prove_with_smt<S: SMTSolver>(
+    model: &Model,
+    smt_options: &SMTOptions,
+    options: &BmcOptions,
+) -> Result<InductionResult> {
+    debug!("Proving model using '{}' SMT solver ...", S::name());
+    let start = Instant::now();
+    let kinds: Vec<String> = model
+        .bad_states_sequential
+        .iter()
+        .map(bad_state_name)
+        .collect();
+    let mut proofs: Vec<Proof> = kinds.iter().map(|_| Proof::Unknown).collect();
+    // Kinds still to be decided, a timeout in the base case leaves a kind
+    // unknown for good.
+    let mut open = vec![true; kinds.len()];
+    // Memory states cannot be compared, leaving out the simple-path
+    // constraints is sound but might prevent some proofs.
+    let simple_paths = !model.sequentials.iter().any(has_memory_sort);
+    if !simple_paths {
+        warn!("Memory is not discretized, proving without simple-path constraints");
+    }
+    let mut base = S::new(smt_options);
+    let mut step = S::new(smt_options);
+    let mut base_frame = initial_frame(model);
+    let mut step_frame = free_frame(model);
+    let mut step_frames: Vec<Vec<NodeRef>> = Vec::new();
+    let mut step_conds: Vec<Vec<NodeRef>> = Vec::new();
+    let mut depth = 0;
+    while depth < options.max_depth && open.contains(&true) {
+        if options.time_budget.map_or(false, |t| start.elapsed() >= t) {
+            info!("Time budget exhausted at depth {}", depth);
+            break;
+        }
+
+        // Base case: is the bad state reachable from the initial states?
+        let (next_frame, bad_states) = unroll_step(model, &base_frame, depth);
+        base_frame = next_frame;
+        for (i, bad_state) in bad_states.iter().enumerate() {
+            if !open[i] {
+                continue;
+            }
+            let cond = bad_state_cond(bad_state);
+            let name = bad_state_name(bad_state);
+            match base.solve(&cond) {
+                SMTSolution::Sat(witness) => {
+                    warn!("Bad state '{}' is reachable at depth {}!", name, depth);
+                    proofs[i] = Proof::Counterexample(Counterexample {
+                        kind: kinds[i].clone(),
+                        name,
+                        depth,
+                        witness: witness.for_bad_state(bad_state),
+                    });
+                    open[i] = false;
+                }
+                SMTSolution::Unsat => base.assert_false(&cond),
+                SMTSolution::Timeout => {
+                    warn!("Bad state '{}' is unknown at depth {}", name, depth);
+                    open[i] = false;
+                }
+            }
+        }
+        let found = proofs.iter().any(|p| matches!(p, Proof::Counterexample(_)));
+        if found && !options.all_kinds {
+            return Ok(InductionResult {
+                kinds,
+                proofs,
+                depth: depth + 1,
+            });
+        }
+
+        // Step case: is the bad state reachable after `depth` steps without
+        // it, starting anywhere? Only the same kind of bad state is assumed
+        // to be unreachable on the way, others might turn out reachable.
+        let (next_frame, bad_states) = unroll_step(model, &step_frame, depth);
+        if simple_paths {
+            for frame in &step_frames {
+                step.assert_false(&frames_equal(model, frame, &step_frame));
+            }
+        }
+        let conds: Vec<NodeRef> = bad_states.iter().map(bad_state_cond).collect();
+        for i in 0..kinds.len() {
+            if !open[i] {
+                continue;
+            }
+            step.push();
+            for previous in &step_conds {
+                step.assert_false(&previous[i]);
+            }
+            if step.solve(&conds[i]) == SMTSolution::Unsat {
+                info!(
+                    "Bad state '{}' is unreachable by {}-induction",
+                    kinds[i], depth
+                );
+                proofs[i] = Proof::Proved { k: depth };
+                open[i] = false;
+            }
+            step.pop();
+        }
+        step_frames.push(std::mem::replace(&mut step_frame, next_frame));
+        step_conds.push(conds);
+        depth += 1;
+    }
+    Ok(InductionResult {
+        kinds,
+        proofs,
+        depth,
+    })
+}
+
+//
+// Private Implementation
+//
+
+fn bad_state_name(bad_state: &NodeRef) -> String {
+    if let Node::Bad { name, .. } = &*bad_state.borrow() {
+        name.as_deref().unwrap_or("?").to_string()
+    } else {
+        panic!("expecting 'Bad' node here");
+    }
+}
+
+fn bad_state_cond(bad_state: &NodeRef) -> NodeRef {
+    if let Node::Bad { cond, .. } = &*bad_state.borrow() {
+        cond.clone()
+    } else {
+        panic!("expecting 'Bad' node here");
+    }
+}
+
+fn has_memory_sort(sequential: &NodeRef) -> bool {
+    matches!(
+        &*sequential.borrow(),
+        Node::Next {
+            sort: NodeType::Memory,
+            ..
+        }
+    )
+}
+
+// A fresh variable standing for an arbitrary value of the given state.
+fn free_state(state: &NodeRef) -> NodeRef {
+    if let Node::State {
+        nid, sort, name, ..
+    } = &*state.borrow()
+    {
+        let name = name.clone().unwrap_or_else(|| format!("state-{}", nid));
+        NodeRef::from(Node::State {
+            nid: 0,
+            sort: sort.clone(),
+            init: None,
+            name: Some(format!("{}[free]", name)),
+        })
+    } else {
+        panic!("expecting 'State' node here");
+    }
+}
+
+fn states(model: &Model) -> Vec<NodeRef> {
+    model
+        .sequentials
+        .iter()
+        .map(|sequential| {
+            if let Node::Next { state, .. } = &*sequential.borrow() {
+                state.clone()
+            } else {
+                panic!("expecting 'Next' node here");
+            }
+        })
+        .collect()
+}
+
+// Uninitialized states are arbitrary in the initial states as well.
+fn initial_frame(model: &Model) -> Vec<NodeRef> {
+    states(model)
+        .iter()
+        .map(|state| match &*state.borrow() {
+            Node::State {
+                init: Some(init), ..
+            } => init.clone(),
+            _ => free_state(state),
+        })
+        .collect()
+}
+
+fn free_frame(model: &Model) -> Vec<NodeRef> {
+    states(model).iter().map(free_state).collect()
+}
+
+// Condition that both frames hold the same values in all states.
+fn frames_equal(model: &Model, left: &[NodeRef], right: &[NodeRef]) -> NodeRef {
+    let mut result = NodeRef::from(Node::Const {
+        nid: 0,
+        sort: NodeType::Bit,
+        imm: 1,
+    });
+    for (sequential, (left, right)) in model.sequentials.iter().zip(left.iter().zip(right)) {
+        let sort = if let Node::Next { sort, .. } = &*sequential.borrow() {
+            sort.clone()
+        } else {
+            panic!("expecting 'Next' node here");
+        };
+        let equal = values_equal(&sort, left, right);
+        result = NodeRef::from(Node::And {
+            nid: 0,
+            sort: NodeType::Bit,
+            left: result,
+            right: equal,
+        });
+    }
+    result
+}
+
+// Solvers treat bits as booleans, which are not compared with `Eq`.
+fn values_equal(sort: &NodeType, left: &NodeRef, right: &NodeRef) -> NodeRef {
+    if *sort != NodeType::Bit {
+        return NodeRef::from(Node::Eq {
+            nid: 0,
+            left: left.clone(),
+            right: right.clone(),
+        });
+    }
+    let not = |value: &NodeRef| {
+        NodeRef::from(Node::Not {
+            nid: 0,
+            sort: NodeType::Bit,
+            value: value.clone(),
+        })
+    };
+    let and = |left: NodeRef, right: NodeRef| {
+        NodeRef::from(Node::And {
+            nid: 0,
+            sort: NodeType::Bit,
+            left,
+            right,
+        })
+    };
+    NodeRef::from(Node::Or {
+        nid: 0,
+        sort: NodeType::Bit,
+        left: and(left.clone(), right.clone()),
+        right: and(not(left), not(right)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::smt_solver::external_impl::ExternalSolver;
+    use crate::unicorn::smt_solver::none_impl::NoneSolver;
+    use std::io::Write;
+    use std::rc::Rc;
+    use tempfile::NamedTempFile;
+
+    fn word(nid: u64, imm: u64) -> NodeRef {
+        NodeRef::from(Node::Const {
+            nid,
+            sort: NodeType::Word,
+            imm,
+        })
+    }
+
+    // A counter starting at zero, with a bad state once it reaches two.
+    fn counter_model() -> Model {
+        let counter = NodeRef::from(Node::State {
+            nid: 2,
+            sort: NodeType::Word,
+            init: Some(word(1, 0)),
+            name: Some("counter".to_string()),
+        });
+        let add = NodeRef::from(Node::Add {
+            nid: 4,
+            left: counter.clone(),
+            right: word(3, 1),
+        });
+        let next = NodeRef::from(Node::Next {
+            nid: 5,
+            sort: NodeType::Word,
+            state: counter.clone(),
+            next: add,
+        });
+        let is_two = NodeRef::from(Node::Eq {
+            nid: 7,
+            left: counter,
+            right: word(6, 2),
+        });
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 8,
+            cond: is_two,
+            name: Some("two".to_string()),
+        });
+        Model {
+            lines: vec![],
+            sequentials: vec![next],
+            bad_states_initial: vec![],
+            bad_states_sequential: vec![bad_state],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    fn options(max_depth: usize) -> BmcOptions {
+        BmcOptions {
+            max_depth,
+            time_budget: None,
+            all_kinds: true,
+        }
+    }
+
+    #[test]
+    fn step_case_starts_from_free_states() {
+        let model = counter_model();
+        let frame = free_frame(&model);
+        let (next_frame, bad_states) = unroll_step(&model, &frame, 0);
+
+        assert!(matches!(
+            &*frame[0].borrow(),
+            Node::State { init: None, name: Some(name), .. } if name == "counter[free]"
+        ));
+        assert!(matches!(
+            &*next_frame[0].borrow(),
+            Node::Add { left, .. } if Rc::ptr_eq(left, &frame[0])
+        ));
+        assert_eq!(bad_state_name(&bad_states[0]), "two[n=0]");
+        assert!(model.sequentials.iter().all(|s| !has_memory_sort(s)));
+    }
+
+    // Stand-in for an SMT solver, giving the answers to the queries in the
+    // order they are checked. The answers have to be the right ones for the
+    // queried model, values of models are all zero.
+    fn scripted_solver(answers: &[&str]) -> NamedTempFile {
+        let mut script = NamedTempFile::new().unwrap();
+        writeln!(script, "set -- {}", answers.join(" ")).unwrap();
+        writeln!(script, "while read -r line; do").unwrap();
+        writeln!(script, "  case \"$line\" in").unwrap();
+        writeln!(script, "    \"(check-sat)\") echo \"$1\"; shift ;;").unwrap();
+        writeln!(script, "    \"(get-value\"*) echo \"((v #b0))\" ;;").unwrap();
+        writeln!(script, "  esac").unwrap();
+        writeln!(script, "done").unwrap();
+        script
+    }
+
+    fn smt_options(script: &NamedTempFile) -> SMTOptions {
+        SMTOptions {
+            timeout: None,
+            command: Some(format!("sh {}", script.path().display())),
+        }
+    }
+
+    // A flag starting cleared and cleared again in each step, with a bad
+    // state while it is set.
+    fn flag_model() -> Model {
+        let bit = |nid: u64, imm: u64| {
+            NodeRef::from(Node::Const {
+                nid,
+                sort: NodeType::Bit,
+                imm,
+            })
+        };
+        let flag = NodeRef::from(Node::State {
+            nid: 2,
+            sort: NodeType::Bit,
+            init: Some(bit(1, 0)),
+            name: Some("flag".to_string()),
+        });
+        let next = NodeRef::from(Node::Next {
+            nid: 4,
+            sort: NodeType::Bit,
+            state: flag.clone(),
+            next: bit(3, 0),
+        });
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 5,
+            cond: flag,
+            name: Some("set".to_string()),
+        });
+        Model {
+            lines: vec![],
+            sequentials: vec![next],
+            bad_states_initial: vec![],
+            bad_states_sequential: vec![bad_state],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    #[test]
+    fn proves_bad_states_once_step_case_holds() {
+        // The step case fails at depth 0 as a free flag might be set, after
+        // a step the flag is cleared.
+        let script = scripted_solver(&["unsat", "sat", "unsat", "unsat"]);
+        let model = flag_model();
+        let result =
+            prove_with_smt::<ExternalSolver>(&model, &smt_options(&script), &options(10)).unwrap();
+
+        assert_eq!(result.kinds, vec!["set".to_string()]);
+        assert!(matches!(result.proofs[0], Proof::Proved { k: 1 }));
+        assert_eq!(result.depth, 2);
+    }
+
+    #[test]
+    fn finds_counterexample_before_step_case_holds() {
+        // Any counter reaches two, the base case finds it at depth 2.
+        let script = scripted_solver(&["unsat", "sat", "unsat", "sat", "sat"]);
+        let model = counter_model();
+        let result =
+            prove_with_smt::<ExternalSolver>(&model, &smt_options(&script), &options(10)).unwrap();
+
+        assert_eq!(result.kinds, vec!["two".to_string()]);
+        assert!(matches!(
+            &result.proofs[0],
+            Proof::Counterexample(c) if c.depth == 2 && c.name == "two[n=2]"
+        ));
+    }
+
+    #[test]
+    fn leaves_bad_states_unknown_on_timeout() {
+        let model = counter_model();
+        let result =
+            prove_with_smt::<NoneSolver>(&model, &SMTOptions::default(), &options(10)).unwrap();
+
+        assert!(matches!(result.proofs[0], Proof::Unknown));
+        assert_eq!(result.depth, 1);
+    }
+}
@@ -9,21 +9,32 @@ use std::rc::Rc;
 // Public Interface
 //
 
+pub mod aiger_parser;
 pub mod bitblasting;
+pub mod bitblasting_aiger;
 pub mod bitblasting_dimacs;
 pub mod bitblasting_printer;
+pub mod bmc;
 pub mod btor2file_parser;
 pub mod builder;
 pub mod cnf;
 pub mod codegen;
 pub mod dimacs_parser;
 pub mod emulate_loader;
+pub mod ic3;
+pub mod induction;
 pub mod memory;
 pub mod optimize;
+pub mod parse_error;
+pub mod portfolio;
 pub mod qubot;
+pub mod report;
 pub mod sat_solver;
+pub mod simulator;
+pub mod smt2_printer;
 pub mod smt_solver;
 pub mod unroller;
+pub mod witness;
 
 pub type Nid = u64;
 pub type NodeRef = Rc<RefCell<Node>>;
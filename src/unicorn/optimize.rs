@@ -1,40 +1,59 @@
-use crate::unicorn::smt_solver::{none_impl, SMTSolution, SMTSolver};
+use crate::unicorn::report::{BadStateFound, BadStateVerdict, Verdict};
+use crate::unicorn::smt_solver::{none_impl, SMTOptions, SMTSolution, SMTSolver};
+use crate::unicorn::witness::Witness;
 use crate::unicorn::{HashableNodeRef, Model, Node, NodeRef, NodeType};
+use anyhow::Result;
 use log::{debug, trace, warn};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::convert::TryInto;
 use std::rc::Rc;
-use std::time::Duration;
 
 //
 // Public Interface
 //
 
+// Fails with `BadStateFound` as soon as a reachable bad state is found when
+// `terminate_on_bad` is set (or `one_query` finds one by constant folding).
 pub fn optimize_model_with_solver<S: SMTSolver>(
     model: &mut Model,
-    timeout: Option<Duration>,
+    options: &SMTOptions,
     minimize: bool,
     terminate_on_bad: bool,
     one_query: bool,
-) {
+    verdicts: &mut Vec<BadStateVerdict>,
+) -> Result<Vec<Witness>> {
     debug!("Optimizing model using '{}' SMT solver ...", S::name());
-    debug!("Setting SMT solver timeout to {:?} per query ...", timeout);
+    debug!(
+        "Setting SMT solver timeout to {:?} per query ...",
+        options.timeout
+    );
     debug!("Using SMT solver to minimize graph: {} ...", minimize);
     optimize_model_impl::<S>(
         model,
         &mut vec![],
-        timeout,
+        options,
         minimize,
         terminate_on_bad,
         one_query,
+        verdicts,
     )
 }
 
 pub fn optimize_model_with_input(model: &mut Model, inputs: &mut Vec<u64>) {
     debug!("Optimizing model with {} concrete inputs ...", inputs.len());
-    optimize_model_impl::<none_impl::NoneSolver>(model, inputs, None, false, false, false);
+    let options = SMTOptions::default();
+    optimize_model_impl::<none_impl::NoneSolver>(
+        model,
+        inputs,
+        &options,
+        false,
+        false,
+        false,
+        &mut vec![],
+    )
+    .expect("bad states are not terminated on");
 }
 
 //
@@ -44,38 +63,67 @@ pub fn optimize_model_with_input(model: &mut Model, inputs: &mut Vec<u64>) {
 fn optimize_model_impl<S: SMTSolver>(
     model: &mut Model,
     inputs: &mut Vec<u64>,
-    timeout: Option<Duration>,
+    options: &SMTOptions,
     minimize: bool,
     terminate_on_bad: bool,
     one_query: bool,
-) {
-    let mut constant_folder = ConstantFolder::<S>::new(inputs, timeout, minimize);
+    verdicts: &mut Vec<BadStateVerdict>,
+) -> Result<Vec<Witness>> {
+    let mut constant_folder = ConstantFolder::<S>::new(inputs, options, minimize);
     model
         .sequentials
         .retain(|s| constant_folder.should_retain_sequential(s));
     for sequential in &model.sequentials {
         constant_folder.visit(sequential);
     }
+    let mut found = None;
     if !one_query {
-        model
-            .bad_states_initial
-            .retain(|s| constant_folder.should_retain_bad_state(s, true, terminate_on_bad));
-        model
-            .bad_states_sequential
-            .retain(|s| constant_folder.should_retain_bad_state(s, true, terminate_on_bad));
+        let result = constant_folder
+            .retain_bad_states(&mut model.bad_states_initial, true, terminate_on_bad)
+            .and_then(|_| {
+                constant_folder.retain_bad_states(
+                    &mut model.bad_states_sequential,
+                    true,
+                    terminate_on_bad,
+                )
+            });
+        verdicts.append(&mut constant_folder.verdicts);
+        result?;
     } else {
-        model
-            .bad_states_initial
-            .retain(|s| constant_folder.should_retain_bad_state(s, false, true));
+        let result = constant_folder.retain_bad_states(
+            &mut model.bad_states_initial,
+            false,
+            terminate_on_bad,
+        );
+        verdicts.append(&mut constant_folder.verdicts);
+        result?;
 
         if model.bad_states_initial.is_empty() {
             warn!("Plain constant-folding already removed all bad states.");
         } else if model.bad_states_initial.len() == 1 {
             let single_bad_state = &model.bad_states_initial[0];
-            if !constant_folder.smt_solver.is_always_false(single_bad_state) {
-                warn!("SMT solver '{}' found bad state (single).", S::name())
-            } else {
-                warn!("SMT solver '{}' cannot find bad state (single).", S::name())
+            match constant_folder.smt_solver.solve(single_bad_state) {
+                SMTSolution::Sat(witness) => {
+                    warn!("SMT solver '{}' found bad state (single).", S::name());
+                    let witness = witness.for_bad_state(single_bad_state);
+                    constant_folder.record_verdict(single_bad_state, Verdict::Sat);
+                    if terminate_on_bad {
+                        found = Some(BadStateFound::new(single_bad_state, witness));
+                    } else {
+                        constant_folder.witnesses.push(witness);
+                    }
+                }
+                SMTSolution::Unsat => {
+                    warn!("SMT solver '{}' cannot find bad state (single).", S::name());
+                    constant_folder.record_verdict(single_bad_state, Verdict::Unsat);
+                }
+                SMTSolution::Timeout => {
+                    warn!(
+                        "SMT solver '{}' cannot decide bad state (single).",
+                        S::name()
+                    );
+                    constant_folder.record_verdict(single_bad_state, Verdict::Timeout);
+                }
             }
         } else {
             let mut ored_bad_states = NodeRef::from(Node::Or {
@@ -93,13 +141,52 @@ fn optimize_model_impl<S: SMTSolver>(
                     right: (*bad_state).clone(),
                 });
             }
-            if !constant_folder.smt_solver.is_always_false(&ored_bad_states) {
-                warn!("SMT solver '{}' found bad state (OR'ed).", S::name())
-            } else {
-                warn!("SMT solver '{}' cannot find bad state (OR'ed).", S::name())
+            match constant_folder.smt_solver.solve(&ored_bad_states) {
+                SMTSolution::Sat(witness) => {
+                    warn!("SMT solver '{}' found bad state (OR'ed).", S::name());
+                    // Bad states the witness does not reach stay undecided.
+                    let witnesses_before = constant_folder.witnesses.len();
+                    for bad_state in &model.bad_states_initial {
+                        if witness.reaches(bad_state) {
+                            let witness = witness.clone().for_bad_state(bad_state);
+                            constant_folder.record_verdict(bad_state, Verdict::Sat);
+                            if terminate_on_bad && found.is_none() {
+                                found = Some(BadStateFound::new(bad_state, witness));
+                            } else {
+                                constant_folder.witnesses.push(witness);
+                            }
+                        } else {
+                            constant_folder.record_verdict(bad_state, Verdict::Unknown);
+                        }
+                    }
+                    if constant_folder.witnesses.len() == witnesses_before && found.is_none() {
+                        constant_folder.witnesses.push(witness);
+                    }
+                }
+                SMTSolution::Unsat => {
+                    warn!("SMT solver '{}' cannot find bad state (OR'ed).", S::name());
+                    // Unlike a satisfiable query, this decides each bad state.
+                    for bad_state in &model.bad_states_initial {
+                        constant_folder.record_verdict(bad_state, Verdict::Unsat);
+                    }
+                }
+                SMTSolution::Timeout => {
+                    warn!(
+                        "SMT solver '{}' cannot decide bad state (OR'ed).",
+                        S::name()
+                    );
+                    for bad_state in &model.bad_states_initial {
+                        constant_folder.record_verdict(bad_state, Verdict::Timeout);
+                    }
+                }
             }
         }
     }
+    verdicts.append(&mut constant_folder.verdicts);
+    match found {
+        Some(found) => Err(found.into()),
+        None => Ok(constant_folder.witnesses),
+    }
 }
 
 pub struct ConstantFolder<'a, S> {
@@ -110,6 +197,8 @@ pub struct ConstantFolder<'a, S> {
     const_true: NodeRef,
     concrete_inputs: &'a mut Vec<u64>,
     minimize_with_solver: bool,
+    witnesses: Vec<Witness>,
+    verdicts: Vec<BadStateVerdict>,
 }
 
 fn ones(sort: &NodeType) -> u64 {
@@ -152,17 +241,19 @@ fn new_const(imm: u64) -> NodeRef {
 impl<'a, S: SMTSolver> ConstantFolder<'a, S> {
     fn new(
         concrete_inputs: &'a mut Vec<u64>,
-        timeout: Option<Duration>,
+        options: &SMTOptions,
         minimize_with_solver: bool,
     ) -> Self {
         Self {
-            smt_solver: S::new(timeout),
+            smt_solver: S::new(options),
             marks: HashSet::new(),
             mapping: HashMap::new(),
             const_false: new_const_with_type(0, NodeType::Bit),
             const_true: new_const_with_type(1, NodeType::Bit),
             concrete_inputs,
             minimize_with_solver,
+            witnesses: Vec::new(),
+            verdicts: Vec::new(),
         }
     }
 
@@ -707,12 +798,45 @@ impl<'a, S: SMTSolver> ConstantFolder<'a, S> {
         }
     }
 
+    fn record_verdict(&mut self, bad_state: &NodeRef, verdict: Verdict) {
+        self.verdicts.push(BadStateVerdict {
+            bad_state: bad_state.clone(),
+            verdict,
+            solver: S::name(),
+        });
+    }
+
+    // Keeps the bad states that are not proven unreachable. The first one
+    // found to be reachable stops the process when `terminate_on_bad` is set,
+    // all bad states not looked at yet are retained in that case.
+    fn retain_bad_states(
+        &mut self,
+        bad_states: &mut Vec<NodeRef>,
+        use_smt: bool,
+        terminate_on_bad: bool,
+    ) -> Result<()> {
+        let mut result = Ok(());
+        bad_states.retain(|bad_state| {
+            if result.is_err() {
+                return true;
+            }
+            match self.should_retain_bad_state(bad_state, use_smt, terminate_on_bad) {
+                Ok(retain) => retain,
+                Err(error) => {
+                    result = Err(error);
+                    true
+                }
+            }
+        });
+        result
+    }
+
     fn should_retain_bad_state(
         &mut self,
         bad_state: &NodeRef,
         use_smt: bool,
         terminate_on_bad: bool,
-    ) -> bool {
+    ) -> Result<bool> {
         self.visit(bad_state);
         if let Node::Bad { cond, name, .. } = &*bad_state.borrow() {
             if is_const_false(cond) {
@@ -720,43 +844,50 @@ impl<'a, S: SMTSolver> ConstantFolder<'a, S> {
                     "Bad state '{}' became unreachable, removing",
                     name.as_deref().unwrap_or("?")
                 );
-                return false;
+                self.record_verdict(bad_state, Verdict::Removed);
+                return Ok(false);
             }
             if is_const_true(cond) {
                 warn!(
                     "Bad state '{}' became statically reachable!",
                     name.as_deref().unwrap_or("?")
                 );
+                // Reachable independent of any input, so the empty witness does it.
+                let witness = Witness::new().for_bad_state(bad_state);
+                self.record_verdict(bad_state, Verdict::Sat);
                 if terminate_on_bad {
-                    // TODO: Change this to use return Result<> instead of panic!
-                    panic!("Bad state always true");
+                    return Err(BadStateFound::new(bad_state, witness).into());
                 }
-                return true;
+                self.witnesses.push(witness);
+                return Ok(true);
             }
             if use_smt {
                 match self.smt_solver.solve(cond) {
-                    SMTSolution::Sat => {
+                    SMTSolution::Sat(witness) => {
                         warn!(
                             "Bad state '{}' is satisfiable!",
                             name.as_deref().unwrap_or("?")
                         );
+                        let witness = witness.for_bad_state(bad_state);
+                        self.record_verdict(bad_state, Verdict::Sat);
                         if terminate_on_bad {
-                            // TODO: Change this to use return Result<> instead of panic!
-                            panic!("Bad state satisfiable");
+                            return Err(BadStateFound::new(bad_state, witness).into());
                         }
-                        return true;
+                        self.witnesses.push(witness);
+                        return Ok(true);
                     }
                     SMTSolution::Unsat => {
                         debug!(
                             "Bad state '{}' is unsatisfiable, removing",
                             name.as_deref().unwrap_or("?")
                         );
-                        return false;
+                        self.record_verdict(bad_state, Verdict::Unsat);
+                        return Ok(false);
                     }
-                    SMTSolution::Timeout => (),
+                    SMTSolution::Timeout => self.record_verdict(bad_state, Verdict::Timeout),
                 }
             }
-            true
+            Ok(true)
         } else {
             panic!("Expecting Bad node here!")
         }
@@ -801,3 +932,66 @@ impl<'a, S: SMTSolver> ConstantFolder<'a, S> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model_with_bad_state(name: &str) -> Model {
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 2,
+            cond: new_const_with_type(1, NodeType::Bit),
+            name: Some(name.to_string()),
+        });
+        Model {
+            lines: vec![],
+            sequentials: vec![],
+            bad_states_initial: vec![bad_state],
+            bad_states_sequential: vec![],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    #[test]
+    fn reports_reachable_bad_state_on_terminate() {
+        let mut model = model_with_bad_state("exit-code[n=3]");
+        let options = SMTOptions::default();
+        let mut verdicts = vec![];
+        let result = optimize_model_with_solver::<none_impl::NoneSolver>(
+            &mut model,
+            &options,
+            false,
+            true,
+            false,
+            &mut verdicts,
+        );
+
+        let error = result.unwrap_err();
+        let found = error.downcast_ref::<BadStateFound>().unwrap();
+        assert_eq!(found.name, "exit-code[n=3]");
+        assert_eq!(found.depth, Some(3));
+        assert!(found.witness.is_some());
+        assert_eq!(verdicts[0].verdict, Verdict::Sat);
+    }
+
+    #[test]
+    fn keeps_witness_without_terminate() {
+        let mut model = model_with_bad_state("exit-code[n=3]");
+        let options = SMTOptions::default();
+        let witnesses = optimize_model_with_solver::<none_impl::NoneSolver>(
+            &mut model,
+            &options,
+            false,
+            false,
+            false,
+            &mut vec![],
+        )
+        .unwrap();
+
+        assert_eq!(witnesses.len(), 1);
+        assert_eq!(model.bad_states_initial.len(), 1);
+    }
+}
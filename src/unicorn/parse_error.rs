@@ -0,0 +1,45 @@
+use std::fmt;
+use thiserror::Error;
+
+//
+// Public Interface
+//
+
+/// Malformed input encountered while reading a model (e.g. BTOR2 or DIMACS),
+/// pointing at the offending token and describing what was expected instead.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub expected: String,
+}
+
+impl ParseError {
+    pub fn new(file: &str, line: usize, column: usize, token: &str, expected: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            line,
+            column,
+            token: token.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+}
+
+//
+// Private Implementation
+//
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: ", self.file, self.line, self.column)?;
+        if self.token.is_empty() {
+            write!(f, "unexpected end of line")?;
+        } else {
+            write!(f, "unexpected '{}'", self.token)?;
+        }
+        write!(f, ", expected {}", self.expected)
+    }
+}
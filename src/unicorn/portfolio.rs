@@ -0,0 +1,524 @@
+use crate::unicorn::bitblasting::bitblast_model;
+use crate::unicorn::btor2file_parser::parse_btor2_text;
+use crate::unicorn::report::{BadStateFound, BadStateVerdict, Verdict};
+use crate::unicorn::sat_solver::{solve_bad_states, SATOptions};
+use crate::unicorn::smt_solver::*;
+use crate::unicorn::unroller::{renumber_model_undoable, restore_nids};
+use crate::unicorn::witness::Witness;
+use crate::unicorn::{get_nid, write_model, HashableNodeRef, Model, Nid, Node, NodeRef};
+use crate::{PortfolioSolver, SatType, SmtType};
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+//
+// Public Interface
+//
+
+#[derive(Clone, Debug)]
+pub struct PortfolioOptions {
+    pub jobs: usize,
+    pub solvers: Vec<PortfolioSolver>,
+    pub smt_options: SMTOptions,
+    pub sat_options: SATOptions,
+}
+
+// Decides the bad states of the given (unrolled) model on several threads.
+// Each bad state is a separate query that all solvers of the portfolio race
+// on, the first one deciding it wins. Bad states found to be unreachable are
+// removed, those that already have a verdict (e.g. found to be statically
+// reachable by constant folding) are skipped.
+pub fn solve_bad_states_parallel(
+    model: &mut Model,
+    options: &PortfolioOptions,
+    terminate_on_bad: bool,
+    verdicts: &mut Vec<BadStateVerdict>,
+) -> Result<Vec<Witness>> {
+    debug!(
+        "Deciding bad states on {} threads using {:?} ...",
+        options.jobs, options.solvers
+    );
+    let decided: HashSet<HashableNodeRef> = verdicts
+        .iter()
+        .map(|v| HashableNodeRef::from(v.bad_state.clone()))
+        .collect();
+    let bad_states: Vec<NodeRef> = model
+        .bad_states_initial
+        .iter()
+        .filter(|b| !decided.contains(&HashableNodeRef::from((*b).clone())))
+        .cloned()
+        .collect();
+    let sendable = Arc::new(SendableModel::new(model, &bad_states)?);
+
+    let mut tasks = VecDeque::new();
+    for index in 0..bad_states.len() {
+        for solver in 0..options.solvers.len() {
+            tasks.push_back(Task {
+                index,
+                nid: sendable.nids[index],
+                solver,
+            });
+        }
+    }
+    let shared = Arc::new(Shared {
+        tasks: Mutex::new(tasks),
+        decided: bad_states.iter().map(|_| AtomicBool::new(false)).collect(),
+        stop: AtomicBool::new(false),
+    });
+    let (sender, outcomes) = channel();
+    let workers: Vec<_> = (0..options.jobs)
+        .map(|_| {
+            let sendable = sendable.clone();
+            let shared = shared.clone();
+            let options = options.clone();
+            let sender = sender.clone();
+            thread::spawn(move || work(&sendable, &options, &shared, sender))
+        })
+        .collect();
+    drop(sender);
+
+    // The first definite answer for each bad state wins, a timeout is only
+    // kept until another solver of the portfolio decides the bad state.
+    let mut results: Vec<Option<Outcome>> = bad_states.iter().map(|_| None).collect();
+    let mut found = None;
+    for outcome in outcomes {
+        let index = outcome.index;
+        if results[index].as_ref().map_or(false, Outcome::is_decided) {
+            continue;
+        }
+        if outcome.is_decided() {
+            shared.decided[index].store(true, Ordering::Relaxed);
+            if outcome.verdict == Verdict::Sat {
+                warn!(
+                    "Bad state '{}' is satisfiable ({})!",
+                    bad_state_name(&bad_states[index]),
+                    outcome.solver
+                );
+                if terminate_on_bad && found.is_none() {
+                    shared.stop.store(true, Ordering::Relaxed);
+                    found = Some(index);
+                }
+            }
+        }
+        results[index] = Some(outcome);
+    }
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|_| anyhow!("solver thread panicked"))??;
+    }
+
+    let mut witnesses = Vec::new();
+    let mut found_witness = None;
+    let mut unreachable = HashSet::new();
+    for (index, (bad_state, result)) in bad_states.iter().zip(results).enumerate() {
+        let outcome = match result {
+            Some(outcome) => outcome,
+            None => continue,
+        };
+        match outcome.verdict {
+            Verdict::Sat => {
+                let assignments = outcome.assignments.unwrap_or_default();
+                let witness = Witness::from_assignments(assignments).for_bad_state(bad_state);
+                if found == Some(index) {
+                    found_witness = Some((bad_state, witness.clone()));
+                }
+                witnesses.push(witness);
+            }
+            Verdict::Unsat => {
+                debug!(
+                    "Bad state '{}' is unsatisfiable ({}), removing",
+                    bad_state_name(bad_state),
+                    outcome.solver
+                );
+                unreachable.insert(HashableNodeRef::from(bad_state.clone()));
+            }
+            Verdict::Timeout | Verdict::Removed | Verdict::Unknown => warn!(
+                "Bad state '{}' is unknown, no solver decided it",
+                bad_state_name(bad_state)
+            ),
+        }
+        verdicts.push(BadStateVerdict {
+            bad_state: bad_state.clone(),
+            verdict: outcome.verdict,
+            solver: outcome.solver,
+        });
+    }
+    model
+        .bad_states_initial
+        .retain(|b| !unreachable.contains(&HashableNodeRef::from(b.clone())));
+
+    match found_witness {
+        Some((bad_state, witness)) => Err(BadStateFound::new(bad_state, witness).into()),
+        None => Ok(witnesses),
+    }
+}
+
+//
+// Private Implementation
+//
+
+// Nodes are reference-counted and cannot be handed to other threads, hence
+// the bad states to be decided are passed as BTOR2 text. Each worker parses
+// its own copy of the model, bad states are identified by their nid within
+// the text.
+struct SendableModel {
+    text: String,
+    nids: Vec<Nid>,
+    data_range: Range<u64>,
+    heap_range: Range<u64>,
+    stack_range: Range<u64>,
+    memory_size: u64,
+}
+
+impl SendableModel {
+    fn new(model: &Model, bad_states: &[NodeRef]) -> Result<Self> {
+        let mut queries = Model {
+            lines: vec![],
+            sequentials: vec![],
+            bad_states_initial: bad_states.to_vec(),
+            bad_states_sequential: vec![],
+            data_range: model.data_range.clone(),
+            heap_range: model.heap_range.clone(),
+            stack_range: model.stack_range.clone(),
+            memory_size: model.memory_size,
+        };
+        // Nodes are shared with the given model, their nids are only changed
+        // for writing the queries.
+        let previous_nids = renumber_model_undoable(&mut queries);
+        let nids = bad_states.iter().map(get_nid).collect();
+        let mut text = Vec::new();
+        let result = write_model(&queries, &mut text);
+        restore_nids(previous_nids);
+        result?;
+        Ok(Self {
+            text: String::from_utf8(text)?,
+            nids,
+            data_range: queries.data_range,
+            heap_range: queries.heap_range,
+            stack_range: queries.stack_range,
+            memory_size: queries.memory_size,
+        })
+    }
+
+    fn to_model(&self) -> Result<Model> {
+        let mut model = parse_btor2_text(&self.text)?;
+        model.data_range = self.data_range.clone();
+        model.heap_range = self.heap_range.clone();
+        model.stack_range = self.stack_range.clone();
+        model.memory_size = self.memory_size;
+        Ok(model)
+    }
+}
+
+// Query of a single bad state with a single solver of the portfolio.
+struct Task {
+    index: usize,
+    nid: Nid,
+    solver: usize,
+}
+
+struct Shared {
+    tasks: Mutex<VecDeque<Task>>,
+    decided: Vec<AtomicBool>,
+    stop: AtomicBool,
+}
+
+// Answer of a worker, the witness is passed as its plain assignments.
+struct Outcome {
+    index: usize,
+    verdict: Verdict,
+    solver: &'static str,
+    assignments: Option<Vec<(String, u64)>>,
+}
+
+impl Outcome {
+    fn is_decided(&self) -> bool {
+        matches!(self.verdict, Verdict::Sat | Verdict::Unsat)
+    }
+}
+
+// Takes tasks until there are none left, skipping bad states that another
+// solver decided already. Solvers cannot be interrupted, a losing solver
+// keeps its thread busy until it finishes its query.
+fn work(
+    sendable: &SendableModel,
+    options: &PortfolioOptions,
+    shared: &Shared,
+    sender: Sender<Outcome>,
+) -> Result<()> {
+    let model = sendable.to_model()?;
+    let bad_states: HashMap<Nid, NodeRef> = model
+        .bad_states_initial
+        .iter()
+        .chain(model.bad_states_sequential.iter())
+        .map(|b| (get_nid(b), b.clone()))
+        .collect();
+    let mut deciders: Vec<Option<Box<dyn Decider>>> =
+        options.solvers.iter().map(|_| None).collect();
+    while !shared.stop.load(Ordering::Relaxed) {
+        let task = shared.tasks.lock().expect("task queue").pop_front();
+        let task = match task {
+            Some(task) => task,
+            None => break,
+        };
+        if shared.decided[task.index].load(Ordering::Relaxed) {
+            continue;
+        }
+        let decider = deciders[task.solver]
+            .get_or_insert_with(|| new_decider(&options.solvers[task.solver], options));
+        let (verdict, witness) = decider.decide(&model, &bad_states[&task.nid])?;
+        let outcome = Outcome {
+            index: task.index,
+            verdict: verdict.verdict,
+            solver: verdict.solver,
+            assignments: witness.map(|w| {
+                w.assignments()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect()
+            }),
+        };
+        if sender.send(outcome).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn bad_state_name(bad_state: &NodeRef) -> String {
+    if let Node::Bad { name, .. } = &*bad_state.borrow() {
+        name.as_deref().unwrap_or("?").to_string()
+    } else {
+        panic!("expecting 'Bad' node here");
+    }
+}
+
+// Uniform view on the solvers of a portfolio. Each worker keeps one instance
+// per solver, so that SMT solvers can reuse their translations.
+trait Decider {
+    fn decide(
+        &mut self,
+        model: &Model,
+        bad_state: &NodeRef,
+    ) -> Result<(BadStateVerdict, Option<Witness>)>;
+}
+
+#[rustfmt::skip]
+fn new_decider(solver: &PortfolioSolver, options: &PortfolioOptions) -> Box<dyn Decider> {
+    match solver {
+        PortfolioSolver::Smt(SmtType::Generic) => unreachable!(),
+        #[cfg(feature = "boolector")]
+        PortfolioSolver::Smt(SmtType::Boolector) =>
+            Box::new(SmtDecider(boolector_impl::BoolectorSolver::new(&options.smt_options))),
+        #[cfg(feature = "z3")]
+        PortfolioSolver::Smt(SmtType::Z3) =>
+            Box::new(SmtDecider(z3solver_impl::Z3SolverWrapper::new(&options.smt_options))),
+        PortfolioSolver::Smt(SmtType::External) =>
+            Box::new(SmtDecider(external_impl::ExternalSolver::new(&options.smt_options))),
+        PortfolioSolver::Sat(SatType::None) => unreachable!(),
+        PortfolioSolver::Sat(sat_type) =>
+            Box::new(SatDecider { sat_type: *sat_type, options: options.sat_options.clone() }),
+    }
+}
+
+struct SmtDecider<S>(S);
+
+impl<S: SMTSolver> Decider for SmtDecider<S> {
+    fn decide(
+        &mut self,
+        _model: &Model,
+        bad_state: &NodeRef,
+    ) -> Result<(BadStateVerdict, Option<Witness>)> {
+        let cond = if let Node::Bad { cond, .. } = &*bad_state.borrow() {
+            cond.clone()
+        } else {
+            panic!("expecting 'Bad' node here");
+        };
+        let (verdict, witness) = match self.0.solve(&cond) {
+            SMTSolution::Sat(witness) => (Verdict::Sat, Some(witness)),
+            SMTSolution::Unsat => (Verdict::Unsat, None),
+            SMTSolution::Timeout => (Verdict::Timeout, None),
+        };
+        let verdict = BadStateVerdict {
+            bad_state: bad_state.clone(),
+            verdict,
+            solver: S::name(),
+        };
+        Ok((verdict, witness))
+    }
+}
+
+// Bitblasts each bad state on its own, memory has to be discretized.
+struct SatDecider {
+    sat_type: SatType,
+    options: SATOptions,
+}
+
+impl Decider for SatDecider {
+    fn decide(
+        &mut self,
+        model: &Model,
+        bad_state: &NodeRef,
+    ) -> Result<(BadStateVerdict, Option<Witness>)> {
+        let query = Model {
+            lines: vec![],
+            sequentials: vec![],
+            bad_states_initial: vec![bad_state.clone()],
+            bad_states_sequential: vec![],
+            data_range: model.data_range.clone(),
+            heap_range: model.heap_range.clone(),
+            stack_range: model.stack_range.clone(),
+            memory_size: model.memory_size,
+        };
+        let gate_model = bitblast_model(&query, true, 64);
+        let mut verdicts = Vec::new();
+        let mut witnesses = solve_bad_states(
+            &gate_model,
+            self.sat_type,
+            &self.options,
+            false,
+            false,
+            &mut verdicts,
+        )?;
+        let verdict = verdicts.pop().expect("verdict for single bad state");
+        Ok((verdict, witnesses.pop()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::NodeType;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    // Stand-in for an SMT solver, claiming each query to be unsatisfiable.
+    fn unsat_solver() -> NamedTempFile {
+        let mut script = NamedTempFile::new().unwrap();
+        writeln!(script, "while read -r line; do").unwrap();
+        writeln!(
+            script,
+            "  if [ \"$line\" = \"(check-sat)\" ]; then echo unsat; fi"
+        )
+        .unwrap();
+        writeln!(script, "done").unwrap();
+        script
+    }
+
+    // One bad state per given value, checking whether an input equals it.
+    fn input_equals(values: &[u64]) -> Model {
+        let input = NodeRef::from(Node::State {
+            nid: 1,
+            sort: NodeType::Input1Byte,
+            init: None,
+            name: Some("1-byte-input[n=0]".to_string()),
+        });
+        let ext = NodeRef::from(Node::Ext {
+            nid: 2,
+            from: NodeType::Input1Byte,
+            value: input,
+        });
+        let mut bad_states = vec![];
+        for (i, value) in values.iter().enumerate() {
+            let nid = 3 * i as u64 + 3;
+            let imm = NodeRef::from(Node::Const {
+                nid,
+                sort: NodeType::Word,
+                imm: *value,
+            });
+            let eq = NodeRef::from(Node::Eq {
+                nid: nid + 1,
+                left: ext.clone(),
+                right: imm,
+            });
+            bad_states.push(NodeRef::from(Node::Bad {
+                nid: nid + 2,
+                cond: eq,
+                name: Some(format!("is-{}[n=0]", value)),
+            }));
+        }
+        Model {
+            lines: vec![],
+            sequentials: vec![],
+            bad_states_initial: bad_states,
+            bad_states_sequential: vec![],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    #[test]
+    fn removes_unreachable_bad_states() {
+        let script = unsat_solver();
+        let options = PortfolioOptions {
+            jobs: 2,
+            solvers: vec![PortfolioSolver::Smt(SmtType::External)],
+            smt_options: SMTOptions {
+                timeout: None,
+                command: Some(format!("sh {}", script.path().display())),
+            },
+            sat_options: SATOptions::default(),
+        };
+        let mut model = input_equals(&[1, 2, 3]);
+        let mut verdicts = vec![];
+        let witnesses =
+            solve_bad_states_parallel(&mut model, &options, false, &mut verdicts).unwrap();
+
+        assert!(witnesses.is_empty());
+        assert!(model.bad_states_initial.is_empty());
+        assert_eq!(verdicts.len(), 3);
+        assert!(verdicts.iter().all(|v| v.verdict == Verdict::Unsat));
+    }
+
+    #[test]
+    fn keeps_nids_of_given_model() {
+        let script = unsat_solver();
+        let options = PortfolioOptions {
+            jobs: 1,
+            solvers: vec![PortfolioSolver::Smt(SmtType::External)],
+            smt_options: SMTOptions {
+                timeout: None,
+                command: Some(format!("sh {}", script.path().display())),
+            },
+            sat_options: SATOptions::default(),
+        };
+        let mut model = input_equals(&[1, 2]);
+        let bad_states = model.bad_states_initial.clone();
+        solve_bad_states_parallel(&mut model, &options, false, &mut vec![]).unwrap();
+
+        let nids: Vec<Nid> = bad_states.iter().map(get_nid).collect();
+        assert_eq!(nids, vec![5, 8]);
+    }
+
+    #[test]
+    fn skips_bad_states_with_verdict() {
+        let script = unsat_solver();
+        let options = PortfolioOptions {
+            jobs: 1,
+            solvers: vec![PortfolioSolver::Smt(SmtType::External)],
+            smt_options: SMTOptions {
+                timeout: None,
+                command: Some(format!("sh {}", script.path().display())),
+            },
+            sat_options: SATOptions::default(),
+        };
+        let mut model = input_equals(&[1, 2]);
+        let mut verdicts = vec![BadStateVerdict {
+            bad_state: model.bad_states_initial[0].clone(),
+            verdict: Verdict::Sat,
+            solver: "test",
+        }];
+        solve_bad_states_parallel(&mut model, &options, false, &mut verdicts).unwrap();
+
+        assert_eq!(model.bad_states_initial.len(), 1);
+        assert_eq!(verdicts.len(), 2);
+        assert_eq!(verdicts[1].verdict, Verdict::Unsat);
+    }
+}
@@ -0,0 +1,401 @@
+use crate::unicorn::unroller::split_unrolled_name;
+use crate::unicorn::witness::Witness;
+use crate::unicorn::{HashableNodeRef, Model, Nid, Node, NodeRef};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+//
+// Public Interface
+//
+
+/// Outcome of checking a single bad state, either decided by a solver or
+/// already by constant folding. A single query for all bad states leaves
+/// those its witness does not reach unknown.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    Sat,
+    Unsat,
+    Removed,
+    Timeout,
+    Unknown,
+}
+
+#[derive(Debug)]
+pub struct BadStateVerdict {
+    pub bad_state: NodeRef,
+    pub verdict: Verdict,
+    pub solver: &'static str,
+}
+
+/// Reachable bad state that stopped a run with `--terminate-on-bad`, it is
+/// reported as an error so that no further passes are performed. The error
+/// carries the witness, which would otherwise be lost with the results.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+#[error("bad state '{name}' is reachable{}", at_depth(.depth))]
+pub struct BadStateFound {
+    pub name: String,
+    pub depth: Option<usize>,
+    pub witness: Option<Witness>,
+}
+
+impl BadStateFound {
+    pub fn new(bad_state: &NodeRef, witness: Witness) -> Self {
+        if let Node::Bad { name, .. } = &*bad_state.borrow() {
+            let name = name.as_deref().unwrap_or("?");
+            Self {
+                name: name.to_string(),
+                depth: split_unrolled_name(name).1,
+                witness: Some(witness),
+            }
+        } else {
+            panic!("expecting 'Bad' node here");
+        }
+    }
+}
+
+/// Number of nodes reachable from the roots (sequentials and bad states) of
+/// a model, which unlike `Model::lines` is meaningful after every pass.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ModelSize {
+    pub nodes: usize,
+    pub sequentials: usize,
+    pub bad_states: usize,
+}
+
+impl ModelSize {
+    pub fn of(model: &Model) -> Self {
+        let mut marks = HashSet::new();
+        let mut stack: Vec<NodeRef> = model
+            .sequentials
+            .iter()
+            .chain(model.bad_states_initial.iter())
+            .chain(model.bad_states_sequential.iter())
+            .cloned()
+            .collect();
+        while let Some(node) = stack.pop() {
+            if marks.insert(HashableNodeRef::from(node.clone())) {
+                stack.extend(operands(&node.borrow()));
+            }
+        }
+        Self {
+            nodes: marks.len(),
+            sequentials: model.sequentials.len(),
+            bad_states: model.bad_states_initial.len() + model.bad_states_sequential.len(),
+        }
+    }
+}
+
+/// Collects the results of a `beator` or `qubot` run, i.e. the verdict for
+/// each bad state, the effect of every pass on the model size and the time
+/// spent per phase, to be written as a JSON document.
+pub struct Report {
+    command: String,
+    input: String,
+    origins: HashMap<String, Nid>,
+    bad_states: Vec<BadStateEntry>,
+    phases: Vec<Phase>,
+    qubits: Option<(usize, usize)>,
+}
+
+impl Report {
+    pub fn new(command: &str, input: &Path) -> Self {
+        Self {
+            command: command.to_string(),
+            input: input.display().to_string(),
+            origins: HashMap::new(),
+            bad_states: Vec::new(),
+            phases: Vec::new(),
+            qubits: None,
+        }
+    }
+
+    // Remembers the bad states of the model before unrolling, the copies
+    // created by the unroller are reported with the nid of their origin.
+    pub fn record_origins(&mut self, model: &Model) {
+        for bad_state in model.bad_states_sequential.iter() {
+            if let Node::Bad { nid, name, .. } = &*bad_state.borrow() {
+                self.origins
+                    .insert(name.as_deref().unwrap_or("?").to_string(), *nid);
+            }
+        }
+    }
+
+    // Registers the bad states that are going to be checked, those without
+    // a verdict in the end are reported as `removed` if they disappeared
+    // from the model and as `unknown` otherwise.
+    pub fn record_bad_states(&mut self, model: &Model) {
+        for bad_state in model.bad_states_initial.iter() {
+            if let Node::Bad { nid, name, .. } = &*bad_state.borrow() {
+                let name = name.as_deref().unwrap_or("?").to_string();
+                let (origin, depth) = split_unrolled_name(&name);
+                let nid = self.origins.get(origin).copied().unwrap_or(*nid);
+                self.bad_states.push(BadStateEntry {
+                    node: HashableNodeRef::from(bad_state.clone()),
+                    name,
+                    nid,
+                    depth,
+                    verdict: None,
+                    solver: None,
+                });
+            }
+        }
+    }
+
+    // Later verdicts replace earlier ones, a bad state left undecided by the
+    // SMT solver might still be decided by a SAT solver.
+    pub fn record_verdicts(&mut self, verdicts: &[BadStateVerdict]) {
+        for verdict in verdicts {
+            let key = HashableNodeRef::from(verdict.bad_state.clone());
+            if let Some(entry) = self.bad_states.iter_mut().find(|e| e.node == key) {
+                entry.verdict = Some(verdict.verdict);
+                entry.solver = Some(verdict.solver);
+            }
+        }
+    }
+
+    pub fn record_phase(&mut self, name: &str, start: Instant) {
+        self.phases.push(Phase {
+            name: name.to_string(),
+            time: start.elapsed(),
+            sizes: None,
+        });
+    }
+
+    pub fn record_pass(&mut self, name: &str, start: Instant, before: ModelSize, model: &Model) {
+        self.phases.push(Phase {
+            name: name.to_string(),
+            time: start.elapsed(),
+            sizes: Some((before, ModelSize::of(model))),
+        });
+    }
+
+    pub fn record_qubits(&mut self, qubits: usize, bad_state_qubits: usize) {
+        self.qubits = Some((qubits, bad_state_qubits));
+    }
+
+    pub fn write_json<W>(&self, model: Option<&Model>, mut out: W) -> Result<()>
+    where
+        W: Write,
+    {
+        let retained: HashSet<HashableNodeRef> = model
+            .iter()
+            .flat_map(|m| m.bad_states_initial.iter())
+            .cloned()
+            .map(HashableNodeRef::from)
+            .collect();
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"command\": {},", json_string(&self.command))?;
+        writeln!(out, "  \"input\": {},", json_string(&self.input))?;
+        writeln!(out, "  \"bad_states\": [")?;
+        for (i, entry) in self.bad_states.iter().enumerate() {
+            let verdict = match entry.verdict {
+                Some(Verdict::Sat) => "sat",
+                Some(Verdict::Unsat) => "unsat",
+                Some(Verdict::Removed) => "removed",
+                Some(Verdict::Timeout) => "timeout",
+                Some(Verdict::Unknown) => "unknown",
+                None if retained.contains(&entry.node) => "unknown",
+                None => "removed",
+            };
+            let solver = match (entry.verdict, entry.solver) {
+                (Some(Verdict::Removed), _) | (_, None) => "null".to_string(),
+                (_, Some(solver)) => json_string(solver),
+            };
+            let depth = entry.depth.map_or("null".to_string(), |d| d.to_string());
+            write!(
+                out,
+                "    {{ \"name\": {}, \"nid\": {}, \"depth\": {}, \"verdict\": \"{}\", \"solver\": {} }}",
+                json_string(&entry.name),
+                entry.nid,
+                depth,
+                verdict,
+                solver
+            )?;
+            writeln!(out, "{}", separator(i, self.bad_states.len()))?;
+        }
+        writeln!(out, "  ],")?;
+        writeln!(out, "  \"phases\": [")?;
+        for (i, phase) in self.phases.iter().enumerate() {
+            write!(
+                out,
+                "    {{ \"name\": {}, \"seconds\": {:.6}",
+                json_string(&phase.name),
+                phase.time.as_secs_f64()
+            )?;
+            if let Some((before, after)) = &phase.sizes {
+                write!(out, ", \"before\": {}", json_size(before))?;
+                write!(out, ", \"after\": {}", json_size(after))?;
+            }
+            writeln!(out, " }}{}", separator(i, self.phases.len()))?;
+        }
+        write!(out, "  ]")?;
+        if let Some((qubits, bad_state_qubits)) = self.qubits {
+            writeln!(out, ",")?;
+            write!(
+                out,
+                "  \"qubits\": {{ \"total\": {}, \"bad_states\": {} }}",
+                qubits, bad_state_qubits
+            )?;
+        }
+        writeln!(out, "\n}}")?;
+        Ok(())
+    }
+}
+
+//
+// Private Implementation
+//
+
+fn at_depth(depth: &Option<usize>) -> String {
+    depth.map_or_else(String::new, |depth| format!(" at depth {}", depth))
+}
+
+struct BadStateEntry {
+    node: HashableNodeRef,
+    name: String,
+    nid: Nid,
+    depth: Option<usize>,
+    verdict: Option<Verdict>,
+    solver: Option<&'static str>,
+}
+
+struct Phase {
+    name: String,
+    time: Duration,
+    sizes: Option<(ModelSize, ModelSize)>,
+}
+
+fn separator(index: usize, len: usize) -> &'static str {
+    if index + 1 < len {
+        ","
+    } else {
+        ""
+    }
+}
+
+fn json_size(size: &ModelSize) -> String {
+    format!(
+        "{{ \"nodes\": {}, \"sequentials\": {}, \"bad_states\": {} }}",
+        size.nodes, size.sequentials, size.bad_states
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn operands(node: &Node) -> Vec<NodeRef> {
+    match node {
+        Node::Const { .. } | Node::Input { .. } | Node::Comment(_) => vec![],
+        Node::State { init, .. } => init.iter().cloned().collect(),
+        Node::Ext { value, .. } | Node::Not { value, .. } => vec![value.clone()],
+        Node::Bad { cond, .. } => vec![cond.clone()],
+        Node::Read {
+            memory, address, ..
+        } => vec![memory.clone(), address.clone()],
+        Node::Write {
+            memory,
+            address,
+            value,
+            ..
+        } => vec![memory.clone(), address.clone(), value.clone()],
+        Node::Ite {
+            cond, left, right, ..
+        } => vec![cond.clone(), left.clone(), right.clone()],
+        Node::Next { state, next, .. } => vec![state.clone(), next.clone()],
+        Node::Add { left, right, .. }
+        | Node::Sub { left, right, .. }
+        | Node::Mul { left, right, .. }
+        | Node::Divu { left, right, .. }
+        | Node::Div { left, right, .. }
+        | Node::Rem { left, right, .. }
+        | Node::Sll { left, right, .. }
+        | Node::Srl { left, right, .. }
+        | Node::Ult { left, right, .. }
+        | Node::Eq { left, right, .. }
+        | Node::And { left, right, .. }
+        | Node::Or { left, right, .. } => vec![left.clone(), right.clone()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::NodeType;
+
+    fn bad_state(nid: Nid, name: &str) -> NodeRef {
+        NodeRef::from(Node::Bad {
+            nid,
+            cond: NodeRef::from(Node::Const {
+                nid: 1,
+                sort: NodeType::Bit,
+                imm: 1,
+            }),
+            name: Some(name.to_string()),
+        })
+    }
+
+    fn model_of(bad_states_initial: Vec<NodeRef>, bad_states_sequential: Vec<NodeRef>) -> Model {
+        Model {
+            lines: vec![],
+            sequentials: vec![],
+            bad_states_initial,
+            bad_states_sequential,
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    #[test]
+    fn reports_verdict_per_unrolled_bad_state() {
+        let original = bad_state(42, "division-by-zero");
+        let sat = bad_state(0, "division-by-zero[n=3]");
+        let removed = bad_state(0, "division-by-zero[n=4]");
+        let unknown = bad_state(0, "division-by-zero[n=5]");
+        let mut report = Report::new("beator", Path::new("a.m"));
+        report.record_origins(&model_of(vec![], vec![original]));
+        let model = model_of(vec![sat.clone(), removed, unknown.clone()], vec![]);
+        report.record_bad_states(&model);
+        report.record_verdicts(&[BadStateVerdict {
+            bad_state: sat.clone(),
+            verdict: Verdict::Sat,
+            solver: "Boolector",
+        }]);
+
+        let mut out = Vec::new();
+        let model = model_of(vec![sat, unknown], vec![]);
+        report.write_json(Some(&model), &mut out).unwrap();
+        let json = String::from_utf8(out).unwrap();
+        assert!(json.contains("\"name\": \"division-by-zero[n=3]\", \"nid\": 42, \"depth\": 3, \"verdict\": \"sat\", \"solver\": \"Boolector\""));
+        assert!(json.contains("\"depth\": 4, \"verdict\": \"removed\", \"solver\": null"));
+        assert!(json.contains("\"depth\": 5, \"verdict\": \"unknown\", \"solver\": null"));
+    }
+
+    #[test]
+    fn counts_reachable_nodes() {
+        let model = model_of(vec![bad_state(2, "a"), bad_state(3, "b")], vec![]);
+        let size = ModelSize::of(&model);
+        assert_eq!(size.nodes, 4);
+        assert_eq!(size.bad_states, 2);
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\n\"");
+    }
+}
@@ -1,39 +1,73 @@
 use crate::unicorn::bitblasting::{get_constant, or_gate, Gate, GateModel, GateRef};
-use crate::unicorn::{Node, NodeRef};
+use crate::unicorn::cnf::{CNFBuilder, CNFContainer};
+use crate::unicorn::report::{BadStateFound, BadStateVerdict, Verdict};
+use crate::unicorn::witness::{input_variables, Witness};
+use crate::unicorn::{HashableNodeRef, Node, NodeRef};
 use crate::SatType;
-use anyhow::{anyhow, Result};
+use anyhow::{ensure, Result};
 use log::{debug, warn};
+#[cfg(any(feature = "kissat", feature = "varisat", test))]
+use std::collections::HashSet;
+#[cfg(any(feature = "kissat", feature = "varisat", test))]
+use std::sync::mpsc::channel;
+#[cfg(any(feature = "kissat", feature = "varisat", test))]
+use std::thread;
+use std::time::Duration;
 
 //
 // Public Interface
 //
 
-#[allow(unused_variables)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SATOptions {
+    pub timeout: Option<Duration>,
+    pub command: Option<String>,
+}
+
 pub fn solve_bad_states(
     gate_model: &GateModel,
     sat_type: SatType,
+    options: &SATOptions,
     terminate_on_bad: bool,
     one_query: bool,
-) -> Result<()> {
+    verdicts: &mut Vec<BadStateVerdict>,
+) -> Result<Vec<Witness>> {
+    ensure!(
+        gate_model.latches.is_empty(),
+        "SAT solvers require a combinational model, unroll it first"
+    );
     match sat_type {
         SatType::None => unreachable!(),
         #[cfg(feature = "kissat")]
         SatType::Kissat => process_all_bad_states::<kissat_impl::KissatSolver>(
             gate_model,
+            options,
             terminate_on_bad,
             one_query,
+            verdicts,
         ),
         #[cfg(feature = "varisat")]
         SatType::Varisat => process_all_bad_states::<varisat_impl::VarisatSolver>(
             gate_model,
+            options,
             terminate_on_bad,
             one_query,
+            verdicts,
         ),
         #[cfg(feature = "cadical")]
         SatType::Cadical => process_all_bad_states::<cadical_impl::CadicalSolver>(
             gate_model,
+            options,
+            terminate_on_bad,
+            one_query,
+            verdicts,
+        ),
+        SatType::External => process_all_bad_states::<external_impl::ExternalSolver>(
+            gate_model,
+            options,
             terminate_on_bad,
             one_query,
+            verdicts,
         ),
     }
 }
@@ -42,21 +76,184 @@ pub fn solve_bad_states(
 // Private Implementation
 //
 
-#[allow(dead_code)]
 #[derive(Debug, Eq, PartialEq)]
 enum SATSolution {
-    Sat,
+    Sat(Witness),
     Unsat,
     Timeout,
 }
 
 trait SATSolver {
-    fn new() -> Self;
+    fn new(options: &SATOptions) -> Self;
     fn name() -> &'static str;
     fn prepare(&mut self, gate_model: &GateModel);
-    fn decide(&mut self, gate_model: &GateModel, gate: &GateRef) -> SATSolution;
+    fn decide(&mut self, gate_model: &GateModel, gate: &GateRef, inputs: &[NodeRef])
+        -> SATSolution;
+}
+
+// Adds the constraints of the given model as unit clauses.
+fn add_constraints<C: CNFContainer>(builder: &mut CNFBuilder<C>, gate_model: &GateModel) {
+    for (gate, val) in &gate_model.constraints {
+        let constraint_var = builder.visit(&gate.value);
+        let constraint_lit = if *val {
+            C::var(constraint_var)
+        } else {
+            C::neg(constraint_var)
+        };
+        builder.container_mut().add_clause(&[constraint_lit]);
+    }
+}
+
+// Builds the CNF of the whole model once, each query then only assumes the
+// output of its bad state. Solvers keep their learned clauses that way.
+fn build_cnf<C: CNFContainer>(builder: &mut CNFBuilder<C>, gate_model: &GateModel) {
+    add_constraints(builder, gate_model);
+    for gate in &gate_model.bad_state_gates {
+        builder.visit(gate);
+    }
+}
+
+// Reconstructs the values of the given input nodes from the assignment of
+// their bits, bits that never made it into the CNF are considered `false`.
+fn extract_witness<C, F>(
+    builder: &mut CNFBuilder<C>,
+    gate_model: &GateModel,
+    inputs: &[NodeRef],
+    mut value: F,
+) -> Witness
+where
+    C: CNFContainer,
+    F: FnMut(&mut C, C::Variable) -> bool,
+{
+    let mut witness = Witness::new();
+    for input in inputs {
+        let key = HashableNodeRef::from(input.clone());
+        let mut bits = 0;
+        for (i, gate) in gate_model.mapping[&key].iter().enumerate() {
+            if let Some(var) = builder.variable(gate) {
+                if value(builder.container_mut(), var) {
+                    bits |= 1 << i;
+                }
+            }
+        }
+        witness.assign(input, bits);
+    }
+    witness
+}
+
+// Plain DIMACS clauses over variables counted from 1, which can be handed
+// to solvers running on other threads or in other processes.
+struct ClauseContainer {
+    current_var: i32,
+    clauses: Vec<Vec<i32>>,
 }
 
+impl CNFContainer for ClauseContainer {
+    type Variable = i32;
+    type Literal = i32;
+
+    fn new() -> Self {
+        Self {
+            current_var: 1,
+            clauses: Vec::new(),
+        }
+    }
+
+    fn name() -> &'static str {
+        "Clauses"
+    }
+
+    fn var(var: i32) -> i32 {
+        var
+    }
+
+    fn neg(var: i32) -> i32 {
+        -var
+    }
+
+    fn new_var(&mut self) -> i32 {
+        let var = self.current_var;
+        self.current_var += 1;
+        var
+    }
+
+    fn add_clause(&mut self, literals: &[i32]) {
+        self.clauses.push(literals.to_vec())
+    }
+
+    fn record_variable_name(&mut self, _var: i32, _name: String) {
+        // nothing to be done here
+    }
+}
+
+// Answer of a solver for a `ClauseContainer`, a model holds the variables
+// assigned `true`.
+#[cfg(any(feature = "kissat", feature = "varisat", test))]
+#[derive(Debug)]
+enum Answer {
+    Sat(HashSet<i32>),
+    Unsat,
+}
+
+// Builds a CNF of just the cone of the given bad state, with its output and
+// the constraints of the model asserted as unit clauses.
+fn build_query_cnf(gate_model: &GateModel, gate: &GateRef) -> CNFBuilder<ClauseContainer> {
+    let mut builder = CNFBuilder::<ClauseContainer>::new();
+    let bad_state_var = builder.visit(gate);
+    builder.container_mut().add_clause(&[bad_state_var]);
+    add_constraints(&mut builder, gate_model);
+    builder
+}
+
+// Decides a query with a solver that cannot be interrupted, by running it
+// on a thread of its own. A query that runs out of time is abandoned, its
+// thread keeps running in the background until the solver is done.
+#[cfg(any(feature = "kissat", feature = "varisat", test))]
+fn decide_detached(
+    gate_model: &GateModel,
+    gate: &GateRef,
+    inputs: &[NodeRef],
+    timeout: Option<Duration>,
+    solve: fn(ClauseContainer) -> Answer,
+) -> SATSolution {
+    let mut builder = build_query_cnf(gate_model, gate);
+    let cnf = ClauseContainer {
+        current_var: builder.container().current_var,
+        clauses: std::mem::take(&mut builder.container_mut().clauses),
+    };
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let _ = sender.send(solve(cnf));
+    });
+    let answer = match timeout {
+        Some(timeout) => receiver.recv_timeout(timeout).ok(),
+        None => receiver.recv().ok(),
+    };
+    match answer {
+        Some(Answer::Sat(model)) => {
+            let witness = extract_witness(&mut builder, gate_model, inputs, |_, var| {
+                model.contains(&var)
+            });
+            SATSolution::Sat(witness)
+        }
+        Some(Answer::Unsat) => SATSolution::Unsat,
+        None => SATSolution::Timeout,
+    }
+}
+
+fn record_verdict<S: SATSolver>(
+    verdicts: &mut Vec<BadStateVerdict>,
+    bad_state: &NodeRef,
+    verdict: Verdict,
+) {
+    verdicts.push(BadStateVerdict {
+        bad_state: bad_state.clone(),
+        verdict,
+        solver: S::name(),
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
 fn process_single_bad_state<S: SATSolver>(
     solver: &mut S,
     gate_model: &GateModel,
@@ -64,21 +261,27 @@ fn process_single_bad_state<S: SATSolver>(
     gate: &GateRef,
     terminate_on_bad: bool,
     one_query: bool,
+    witnesses: &mut Vec<Witness>,
+    verdicts: &mut Vec<BadStateVerdict>,
 ) -> Result<()> {
     if !one_query {
         let bad_state = bad_state_.unwrap();
         if let Node::Bad { name, .. } = &*bad_state.borrow() {
-            let solution = solver.decide(gate_model, gate);
+            let inputs = input_variables(bad_state);
+            let solution = solver.decide(gate_model, gate, &inputs);
             match solution {
-                SATSolution::Sat => {
+                SATSolution::Sat(witness) => {
                     warn!(
                         "Bad state '{}' is satisfiable ({})!",
                         name.as_deref().unwrap_or("?"),
                         S::name()
                     );
+                    let witness = witness.for_bad_state(bad_state);
+                    record_verdict::<S>(verdicts, bad_state, Verdict::Sat);
                     if terminate_on_bad {
-                        return Err(anyhow!("Bad state satisfiable"));
+                        return Err(BadStateFound::new(bad_state, witness).into());
                     }
+                    witnesses.push(witness);
                 }
                 SATSolution::Unsat => {
                     debug!(
@@ -86,8 +289,16 @@ fn process_single_bad_state<S: SATSolver>(
                         name.as_deref().unwrap_or("?"),
                         S::name()
                     );
+                    record_verdict::<S>(verdicts, bad_state, Verdict::Unsat);
+                }
+                SATSolution::Timeout => {
+                    warn!(
+                        "Bad state '{}' is unknown, timeout reached ({}).",
+                        name.as_deref().unwrap_or("?"),
+                        S::name()
+                    );
+                    record_verdict::<S>(verdicts, bad_state, Verdict::Timeout);
                 }
-                SATSolution::Timeout => unimplemented!(),
             }
             Ok(())
         } else {
@@ -95,28 +306,66 @@ fn process_single_bad_state<S: SATSolver>(
         }
     } else {
         assert!(bad_state_.is_none());
-        let solution = solver.decide(gate_model, gate);
+        let inputs: Vec<NodeRef> = gate_model
+            .bad_state_nodes
+            .iter()
+            .flat_map(input_variables)
+            .collect();
+        let solution = solver.decide(gate_model, gate, &inputs);
         match solution {
-            SATSolution::Sat => {
+            SATSolution::Sat(witness) => {
                 warn!("At least one bad state evaluates to true ({})", S::name());
+                // The witness is checked against each bad state, those it does
+                // not reach might still be reachable by other inputs.
+                let mut reached = Vec::new();
+                for bad_state in &gate_model.bad_state_nodes {
+                    if witness.reaches(bad_state) {
+                        record_verdict::<S>(verdicts, bad_state, Verdict::Sat);
+                        reached.push((bad_state, witness.clone().for_bad_state(bad_state)));
+                    } else {
+                        record_verdict::<S>(verdicts, bad_state, Verdict::Unknown);
+                    }
+                }
+                let mut reached = reached.into_iter();
+                match reached.next() {
+                    Some((bad_state, witness)) if terminate_on_bad => {
+                        return Err(BadStateFound::new(bad_state, witness).into());
+                    }
+                    Some((_, first)) => {
+                        witnesses.push(first);
+                        witnesses.extend(reached.map(|(_, witness)| witness));
+                    }
+                    None => witnesses.push(witness),
+                }
             }
             SATSolution::Unsat => {
                 debug!("No bad states occur ({}).", S::name());
+                for bad_state in &gate_model.bad_state_nodes {
+                    record_verdict::<S>(verdicts, bad_state, Verdict::Unsat);
+                }
+            }
+            SATSolution::Timeout => {
+                warn!("Bad states are unknown, timeout reached ({}).", S::name());
+                for bad_state in &gate_model.bad_state_nodes {
+                    record_verdict::<S>(verdicts, bad_state, Verdict::Timeout);
+                }
             }
-            SATSolution::Timeout => unimplemented!(),
         }
         Ok(())
     }
 }
 
-#[allow(dead_code)]
 fn process_all_bad_states<S: SATSolver>(
     gate_model: &GateModel,
+    options: &SATOptions,
     terminate_on_bad: bool,
     one_query: bool,
-) -> Result<()> {
+    verdicts: &mut Vec<BadStateVerdict>,
+) -> Result<Vec<Witness>> {
     debug!("Using {:?} to decide bad states ...", S::name());
-    let mut solver = S::new();
+    let mut solver = S::new(options);
+    solver.prepare(gate_model);
+    let mut witnesses = Vec::new();
 
     if !one_query {
         let zip = gate_model
@@ -131,6 +380,8 @@ fn process_all_bad_states<S: SATSolver>(
                 gate,
                 terminate_on_bad,
                 one_query,
+                &mut witnesses,
+                verdicts,
             )?
         }
     } else {
@@ -160,8 +411,27 @@ fn process_all_bad_states<S: SATSolver>(
         if let Some(value) = get_constant(&ored_bad_states) {
             if value {
                 warn!("Bad state occurs");
+                let zip = gate_model
+                    .bad_state_nodes
+                    .iter()
+                    .zip(gate_model.bad_state_gates.iter());
+                for (bad_state, gate) in zip {
+                    if get_constant(gate) == Some(true) {
+                        record_verdict::<S>(verdicts, bad_state, Verdict::Sat);
+                        let witness = Witness::new().for_bad_state(bad_state);
+                        if terminate_on_bad {
+                            return Err(BadStateFound::new(bad_state, witness).into());
+                        }
+                        witnesses.push(witness);
+                    } else {
+                        record_verdict::<S>(verdicts, bad_state, Verdict::Unknown);
+                    }
+                }
             } else {
                 warn!("No bad state occurs");
+                for bad_state in &gate_model.bad_state_nodes {
+                    record_verdict::<S>(verdicts, bad_state, Verdict::Removed);
+                }
             }
         } else {
             process_single_bad_state(
@@ -171,39 +441,38 @@ fn process_all_bad_states<S: SATSolver>(
                 &ored_bad_states,
                 terminate_on_bad,
                 one_query,
+                &mut witnesses,
+                verdicts,
             )?
         }
     }
 
-    Ok(())
+    Ok(witnesses)
 }
 
 // TODO: Move this module into separate file.
 #[cfg(feature = "kissat")]
 pub mod kissat_impl {
     use crate::unicorn::bitblasting::{GateModel, GateRef};
-    use crate::unicorn::cnf::{CNFBuilder, CNFContainer};
-    use crate::unicorn::sat_solver::{SATSolution, SATSolver};
-    use kissat_rs::{AnyState, INPUTState, Literal, Solver};
-
-    pub struct KissatSolver {}
+    use crate::unicorn::sat_solver::{
+        decide_detached, Answer, ClauseContainer, SATOptions, SATSolution, SATSolver,
+    };
+    use crate::unicorn::NodeRef;
+    use kissat_rs::{AnyState, Assignment, Solver};
+    use std::collections::HashSet;
+    use std::time::Duration;
 
-    struct KissatContainer {
-        current_var: i32,
-        solver: Solver,
-        state: Option<INPUTState>,
+    // Kissat does not support assumptions, hence a CNF of just the cone of
+    // the bad state is built for each query. It cannot be interrupted either,
+    // queries are decided on a thread of their own to be given a time limit.
+    pub struct KissatSolver {
+        timeout: Option<Duration>,
     }
 
-    impl CNFContainer for KissatContainer {
-        type Variable = Literal;
-        type Literal = Literal;
-
-        fn new() -> Self {
-            let (solver, state) = Solver::init();
+    impl SATSolver for KissatSolver {
+        fn new(options: &SATOptions) -> Self {
             Self {
-                current_var: 1,
-                solver,
-                state: Some(state),
+                timeout: options.timeout,
             }
         }
 
@@ -211,67 +480,39 @@ pub mod kissat_impl {
             "Kissat"
         }
 
-        fn var(var: Literal) -> Literal {
-            var
-        }
-
-        fn neg(var: Literal) -> Literal {
-            -var
-        }
-
-        fn new_var(&mut self) -> Literal {
-            let var = self.current_var;
-            self.current_var += 1;
-            var
-        }
-
-        fn add_clause(&mut self, literals: &[Literal]) {
-            let mut state = self.state.take().unwrap();
-            state = self.solver.add_clause(literals.to_vec(), state);
-            self.state.replace(state);
-        }
-
-        fn record_variable_name(&mut self, _var: Literal, _name: String) {
+        fn prepare(&mut self, _gate_model: &GateModel) {
             // nothing to be done here
         }
-    }
 
-    impl SATSolver for KissatSolver {
-        fn new() -> Self {
-            Self {}
-        }
-
-        fn name() -> &'static str {
-            "Kissat"
+        fn decide(
+            &mut self,
+            gate_model: &GateModel,
+            gate: &GateRef,
+            inputs: &[NodeRef],
+        ) -> SATSolution {
+            decide_detached(gate_model, gate, inputs, self.timeout, solve)
         }
+    }
 
-        fn prepare(&mut self, _gate_model: &GateModel) {
-            // nothing to be done here
+    fn solve(cnf: ClauseContainer) -> Answer {
+        let (mut solver, mut state) = Solver::init();
+        for clause in cnf.clauses {
+            state = solver.add_clause(clause, state);
         }
-
-        fn decide(&mut self, gate_model: &GateModel, gate: &GateRef) -> SATSolution {
-            let mut builder = CNFBuilder::<KissatContainer>::new();
-
-            let bad_state_var = builder.visit(gate);
-            builder.container_mut().add_clause(&[bad_state_var]);
-
-            for (gate, val) in &gate_model.constraints {
-                let constraint_var = builder.visit(&gate.value);
-                let constraint_lit = if *val {
-                    KissatContainer::var(constraint_var)
-                } else {
-                    KissatContainer::neg(constraint_var)
-                };
-                builder.container_mut().add_clause(&[constraint_lit]);
-            }
-
-            let cnf = builder.container_mut();
-            let state = cnf.state.take().unwrap();
-            match cnf.solver.solve(state).unwrap() {
-                AnyState::SAT(..) => SATSolution::Sat,
-                AnyState::UNSAT(..) => SATSolution::Unsat,
-                AnyState::INPUT(..) => panic!("expecting 'SAT' or 'UNSAT' here"),
+        match solver.solve(state).unwrap() {
+            AnyState::SAT(mut sat_state) => {
+                let mut model = HashSet::new();
+                for var in 1..cnf.current_var {
+                    let (assignment, next_state) = solver.value(var, sat_state).unwrap();
+                    sat_state = next_state;
+                    if assignment == Assignment::True {
+                        model.insert(var);
+                    }
+                }
+                Answer::Sat(model)
             }
+            AnyState::UNSAT(..) => Answer::Unsat,
+            AnyState::INPUT(..) => panic!("expecting 'SAT' or 'UNSAT' here"),
         }
     }
 }
@@ -280,16 +521,27 @@ pub mod kissat_impl {
 #[cfg(feature = "varisat")]
 pub mod varisat_impl {
     use crate::unicorn::bitblasting::{GateModel, GateRef};
-    use crate::unicorn::cnf::{CNFBuilder, CNFContainer};
-    use crate::unicorn::sat_solver::{SATSolution, SATSolver};
+    use crate::unicorn::cnf::{CNFBuilder, CNFContainer, CNFOracle};
+    use crate::unicorn::sat_solver::{
+        build_cnf, decide_detached, extract_witness, Answer, ClauseContainer, SATOptions,
+        SATSolution, SATSolver,
+    };
+    use crate::unicorn::NodeRef;
+    use std::collections::HashSet;
+    use std::time::Duration;
     use varisat_rs::{ExtendFormula, Lit, Solver, Var};
 
+    // The CNF is built once and queries are decided under assumptions. Varisat
+    // cannot be interrupted though, with a time limit each query is decided
+    // on a thread of its own instead, which gives up on learned clauses.
     pub struct VarisatSolver<'a> {
+        timeout: Option<Duration>,
         builder: CNFBuilder<VarisatContainer<'a>>,
     }
 
-    struct VarisatContainer<'a> {
+    pub struct VarisatContainer<'a> {
         solver: Solver<'a>,
+        model: HashSet<Lit>,
     }
 
     impl CNFContainer for VarisatContainer<'_> {
@@ -299,6 +551,7 @@ pub mod varisat_impl {
         fn new() -> Self {
             Self {
                 solver: Solver::new(),
+                model: HashSet::new(),
             }
         }
 
@@ -327,9 +580,34 @@ pub mod varisat_impl {
         }
     }
 
+    impl CNFOracle for VarisatContainer<'_> {
+        fn solve_with(&mut self, assumptions: &[Lit]) -> Option<bool> {
+            self.solver.assume(assumptions);
+            let result = self.solver.solve().unwrap();
+            // Values are looked up per variable, the model is kept as a set.
+            self.model = self
+                .solver
+                .model()
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+            Some(result)
+        }
+
+        fn value(&self, var: Var) -> bool {
+            self.model.contains(&Lit::positive(var))
+        }
+
+        fn failed(&self, literal: &Lit) -> bool {
+            let core = self.solver.failed_core().unwrap_or_default();
+            core.contains(literal)
+        }
+    }
+
     impl SATSolver for VarisatSolver<'_> {
-        fn new() -> Self {
+        fn new(options: &SATOptions) -> Self {
             Self {
+                timeout: options.timeout,
                 builder: CNFBuilder::<VarisatContainer>::new(),
             }
         }
@@ -339,38 +617,81 @@ pub mod varisat_impl {
         }
 
         fn prepare(&mut self, gate_model: &GateModel) {
-            for (gate, val) in &gate_model.constraints {
-                let constraint_var = self.builder.visit(&gate.value);
-                let constraint_lit = Lit::from_var(constraint_var, *val);
-                self.builder.container_mut().add_clause(&[constraint_lit]);
+            if self.timeout.is_none() {
+                build_cnf(&mut self.builder, gate_model);
             }
         }
 
-        fn decide(&mut self, _gate_model: &GateModel, gate: &GateRef) -> SATSolution {
+        fn decide(
+            &mut self,
+            gate_model: &GateModel,
+            gate: &GateRef,
+            inputs: &[NodeRef],
+        ) -> SATSolution {
+            if self.timeout.is_some() {
+                return decide_detached(gate_model, gate, inputs, self.timeout, solve);
+            }
             let bad_state_var = self.builder.visit(gate);
             let bad_state_lit = VarisatContainer::var(bad_state_var);
             self.builder.container_mut().solver.assume(&[bad_state_lit]);
             match self.builder.container_mut().solver.solve().unwrap() {
-                true => SATSolution::Sat,
+                true => {
+                    let solver = &self.builder.container().solver;
+                    let model: HashSet<Lit> = solver.model().unwrap().into_iter().collect();
+                    let witness =
+                        extract_witness(&mut self.builder, gate_model, inputs, |_, var| {
+                            model.contains(&Lit::positive(var))
+                        });
+                    SATSolution::Sat(witness)
+                }
                 false => SATSolution::Unsat,
             }
         }
     }
+
+    fn solve(cnf: ClauseContainer) -> Answer {
+        let mut solver = Solver::new();
+        let vars: Vec<Var> = (1..cnf.current_var).map(|_| solver.new_var()).collect();
+        let lit = |literal: i32| {
+            let var = vars[literal.abs() as usize - 1];
+            if literal > 0 {
+                Lit::positive(var)
+            } else {
+                Lit::negative(var)
+            }
+        };
+        for clause in &cnf.clauses {
+            let literals: Vec<Lit> = clause.iter().map(|&literal| lit(literal)).collect();
+            solver.add_clause(&literals);
+        }
+        if solver.solve().unwrap() {
+            let model: HashSet<Lit> = solver.model().unwrap().into_iter().collect();
+            let model = (1..cnf.current_var)
+                .filter(|&var| model.contains(&lit(var)))
+                .collect();
+            Answer::Sat(model)
+        } else {
+            Answer::Unsat
+        }
+    }
 }
 
 // TODO: Move this module into separate file.
 #[cfg(feature = "cadical")]
 pub mod cadical_impl {
     use crate::unicorn::bitblasting::{GateModel, GateRef};
-    use crate::unicorn::cnf::{CNFBuilder, CNFContainer};
-    use crate::unicorn::sat_solver::{SATSolution, SATSolver};
-    use cadical_rs::Solver;
+    use crate::unicorn::cnf::{CNFBuilder, CNFContainer, CNFOracle};
+    use crate::unicorn::sat_solver::{
+        build_cnf, extract_witness, SATOptions, SATSolution, SATSolver,
+    };
+    use crate::unicorn::NodeRef;
+    use cadical_rs::{Solver, Timeout};
 
     pub struct CadicalSolver {
         builder: CNFBuilder<CadicalContainer>,
     }
 
-    struct CadicalContainer {
+    pub struct CadicalContainer {
         current_var: i32,
         solver: Solver,
     }
@@ -413,11 +734,30 @@ pub mod cadical_impl {
         }
     }
 
+    impl CNFOracle for CadicalContainer {
+        fn solve_with(&mut self, assumptions: &[i32]) -> Option<bool> {
+            self.solver.solve_with(assumptions.iter().copied())
+        }
+
+        fn value(&self, var: i32) -> bool {
+            self.solver.value(var) == Some(true)
+        }
+
+        fn failed(&self, literal: &i32) -> bool {
+            self.solver.failed(*literal)
+        }
+    }
+
     impl SATSolver for CadicalSolver {
-        fn new() -> Self {
-            Self {
-                builder: CNFBuilder::<CadicalContainer>::new(),
+        fn new(options: &SATOptions) -> Self {
+            let mut builder = CNFBuilder::<CadicalContainer>::new();
+            // The timeout is checked by CaDiCaL itself, solving is aborted
+            // once it expires and the query counts as undecided.
+            if let Some(timeout) = options.timeout {
+                let timeout = Timeout::new(timeout.as_secs_f32());
+                builder.container_mut().solver.set_callbacks(Some(timeout));
             }
+            Self { builder }
         }
 
         fn name() -> &'static str {
@@ -425,18 +765,15 @@ pub mod cadical_impl {
         }
 
         fn prepare(&mut self, gate_model: &GateModel) {
-            for (gate, val) in &gate_model.constraints {
-                let constraint_var = self.builder.visit(&gate.value);
-                let constraint_lit = if *val {
-                    CadicalContainer::var(constraint_var)
-                } else {
-                    CadicalContainer::neg(constraint_var)
-                };
-                self.builder.container_mut().add_clause(&[constraint_lit]);
-            }
+            build_cnf(&mut self.builder, gate_model);
         }
 
-        fn decide(&mut self, _gate_model: &GateModel, gate: &GateRef) -> SATSolution {
+        fn decide(
+            &mut self,
+            gate_model: &GateModel,
+            gate: &GateRef,
+            inputs: &[NodeRef],
+        ) -> SATSolution {
             let bad_state_var = self.builder.visit(gate);
             let bad_state_lit = CadicalContainer::var(bad_state_var);
             match self
@@ -445,10 +782,514 @@ pub mod cadical_impl {
                 .solver
                 .solve_with([bad_state_lit].iter().copied())
             {
-                Some(true) => SATSolution::Sat,
+                Some(true) => {
+                    let witness =
+                        extract_witness(&mut self.builder, gate_model, inputs, |c, var| {
+                            c.solver.value(var) == Some(true)
+                        });
+                    SATSolution::Sat(witness)
+                }
                 Some(false) => SATSolution::Unsat,
                 None => SATSolution::Timeout,
             }
         }
     }
 }
+
+// TODO: Move this module into separate file.
+pub mod external_impl {
+    use crate::unicorn::bitblasting::{GateModel, GateRef};
+    use crate::unicorn::sat_solver::{
+        build_query_cnf, extract_witness, ClauseContainer, SATOptions, SATSolution, SATSolver,
+    };
+    use crate::unicorn::NodeRef;
+    use anyhow::{anyhow, Context, Result};
+    use log::{debug, warn};
+    use std::collections::HashSet;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{BufWriter, Read, Write};
+    use std::path::{Path, PathBuf};
+    use std::process::{self, Command, ExitStatus, Stdio};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Runs a solver executable on a DIMACS file for each query, as done by
+    // the SAT competition. The solver is expected to report its verdict with
+    // exit code 10 (satisfiable) or 20 (unsatisfiable) and/or an `s` line,
+    // followed by `v` lines holding the satisfying assignment. Solvers start
+    // from scratch for each query anyway, hence the CNF written for a query
+    // only holds the cone of its bad state.
+    pub struct ExternalSolver {
+        command: String,
+        timeout: Option<Duration>,
+    }
+
+    impl SATSolver for ExternalSolver {
+        fn new(options: &SATOptions) -> Self {
+            Self {
+                command: options.command.clone().unwrap_or_default(),
+                timeout: options.timeout,
+            }
+        }
+
+        fn name() -> &'static str {
+            "External"
+        }
+
+        fn prepare(&mut self, _gate_model: &GateModel) {
+            // nothing to be done here
+        }
+
+        fn decide(
+            &mut self,
+            gate_model: &GateModel,
+            gate: &GateRef,
+            inputs: &[NodeRef],
+        ) -> SATSolution {
+            let mut builder = build_query_cnf(gate_model, gate);
+
+            let path = cnf_file_path();
+            let result = write_dimacs(builder.container(), &path)
+                .with_context(|| format!("Failed to write CNF file {:?}", path))
+                .and_then(|_| self.run(&path));
+            let _ = fs::remove_file(&path);
+
+            // Failures leave the query undecided, like a timeout would.
+            let (status, output) = match result {
+                Ok(Some(result)) => result,
+                Ok(None) => {
+                    debug!("Query timeout was reached by external SAT solver");
+                    return SATSolution::Timeout;
+                }
+                Err(e) => {
+                    warn!("{:#}, query remains unknown", e);
+                    return SATSolution::Timeout;
+                }
+            };
+            let (verdict, model) = parse_output(&output);
+            let verdict = verdict.or_else(|| match status.code() {
+                Some(10) => Some(true),
+                Some(20) => Some(false),
+                Some(0) => None,
+                _ => {
+                    warn!("SAT solver '{}' failed ({})", self.command, status);
+                    None
+                }
+            });
+            match verdict {
+                Some(true) => {
+                    if model.is_empty() {
+                        warn!("SAT solver '{}' did not report a model", self.command);
+                    }
+                    let witness = extract_witness(&mut builder, gate_model, inputs, |_, var| {
+                        model.contains(&var)
+                    });
+                    SATSolution::Sat(witness)
+                }
+                Some(false) => SATSolution::Unsat,
+                None => SATSolution::Timeout,
+            }
+        }
+    }
+
+    // Temporary files need to be unique across solver instances, which might
+    // run on several threads of the same process.
+    fn cnf_file_path() -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        env::temp_dir().join(format!("unicorn-{}-{}.cnf", process::id(), count))
+    }
+
+    fn write_dimacs(cnf: &ClauseContainer, path: &Path) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "p cnf {} {}", cnf.current_var - 1, cnf.clauses.len())?;
+        for clause in &cnf.clauses {
+            for literal in clause {
+                write!(out, "{} ", literal)?;
+            }
+            writeln!(out, "0")?;
+        }
+        out.flush()
+    }
+
+    // Yields the verdict of an `s` line (`None` if missing or unknown) and
+    // the variables assigned `true` by the `v` lines of the given output.
+    // The result files of MiniSat are understood as well, which start with
+    // a bare `SAT` or `UNSAT` line followed by literals without a prefix.
+    pub fn parse_output(output: &str) -> (Option<bool>, HashSet<i32>) {
+        let mut verdict = None;
+        let mut model = HashSet::new();
+        let positive = |words: &[&str]| -> Vec<i32> {
+            words
+                .iter()
+                .filter_map(|word| word.parse::<i32>().ok())
+                .filter(|literal| *literal > 0)
+                .collect()
+        };
+        for line in output.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.first().copied() {
+                Some("s") => {
+                    verdict = match words.get(1).copied() {
+                        Some("SATISFIABLE") => Some(true),
+                        Some("UNSATISFIABLE") => Some(false),
+                        _ => None,
+                    }
+                }
+                Some("SAT") if words.len() == 1 => verdict = Some(true),
+                Some("UNSAT") if words.len() == 1 => verdict = Some(false),
+                Some("v") => model.extend(positive(&words[1..])),
+                Some(_) if words.iter().all(|word| word.parse::<i32>().is_ok()) => {
+                    model.extend(positive(&words))
+                }
+                _ => (),
+            }
+        }
+        (verdict, model)
+    }
+
+    impl ExternalSolver {
+        // Runs the solver on the given file and collects its output, yields
+        // `None` if the solver had to be killed after the timeout.
+        fn run(&self, path: &Path) -> Result<Option<(ExitStatus, String)>> {
+            let mut words = self.command.split_whitespace();
+            let program = words
+                .next()
+                .ok_or_else(|| anyhow!("SAT solver command is empty"))?;
+            let mut process = Command::new(program)
+                .args(words)
+                .arg(path)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .with_context(|| format!("Failed to start SAT solver '{}'", self.command))?;
+            // Output is read on a separate thread, the solver would block on
+            // a full pipe otherwise.
+            let mut stdout = process.stdout.take().expect("piped stdout");
+            let reader = thread::spawn(move || {
+                let mut output = String::new();
+                let _ = stdout.read_to_string(&mut output);
+                output
+            });
+            let failed = || format!("Failed to await SAT solver '{}'", self.command);
+            let status = match self.timeout {
+                Some(timeout) => {
+                    let deadline = Instant::now() + timeout;
+                    loop {
+                        if let Some(status) = process.try_wait().with_context(failed)? {
+                            break status;
+                        }
+                        if Instant::now() >= deadline {
+                            let _ = process.kill();
+                            let _ = process.wait();
+                            return Ok(None);
+                        }
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                }
+                None => process.wait().with_context(failed)?,
+            };
+            let output = reader.join().map_err(|_| anyhow!(failed()))?;
+            Ok(Some((status, output)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::external_impl::ExternalSolver;
+    use super::*;
+    use crate::unicorn::bitblasting::{bitblast_model, bitblast_sequential_model};
+    use crate::unicorn::{Model, NodeType};
+    use std::fs;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    // Stand-in for a SAT solver, running the given shell commands on the
+    // DIMACS file passed as `$1`.
+    fn stand_in_solver(body: &str) -> NamedTempFile {
+        let mut script = NamedTempFile::new().unwrap();
+        writeln!(script, "{}", body).unwrap();
+        script
+    }
+
+    fn options(script: &NamedTempFile, timeout: Option<Duration>) -> SATOptions {
+        SATOptions {
+            timeout,
+            command: Some(format!("sh {}", script.path().display())),
+        }
+    }
+
+    // One bad state per given value, checking whether an input equals it.
+    fn input_equals(values: &[u64]) -> GateModel {
+        let input = NodeRef::from(Node::Input {
+            nid: 1,
+            sort: NodeType::Input1Byte,
+            name: "x".to_string(),
+        });
+        let ext = NodeRef::from(Node::Ext {
+            nid: 2,
+            from: NodeType::Input1Byte,
+            value: input.clone(),
+        });
+        let mut lines = vec![input, ext.clone()];
+        let mut bad_states = vec![];
+        for (i, value) in values.iter().enumerate() {
+            let nid = 3 * i as u64 + 3;
+            let imm = NodeRef::from(Node::Const {
+                nid,
+                sort: NodeType::Word,
+                imm: *value,
+            });
+            let eq = NodeRef::from(Node::Eq {
+                nid: nid + 1,
+                left: ext.clone(),
+                right: imm.clone(),
+            });
+            let bad_state = NodeRef::from(Node::Bad {
+                nid: nid + 2,
+                cond: eq.clone(),
+                name: Some(format!("is-{}", value)),
+            });
+            lines.extend([imm, eq, bad_state.clone()]);
+            bad_states.push(bad_state);
+        }
+        let model = Model {
+            lines,
+            sequentials: vec![],
+            bad_states_initial: bad_states,
+            bad_states_sequential: vec![],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        };
+        bitblast_model(&model, true, 64)
+    }
+
+    fn input_is_42() -> GateModel {
+        input_equals(&[42])
+    }
+
+    #[test]
+    fn sequential_models_are_rejected() {
+        let state = NodeRef::from(Node::State {
+            nid: 1,
+            sort: NodeType::Bit,
+            init: None,
+            name: Some("s".to_string()),
+        });
+        let next = NodeRef::from(Node::Next {
+            nid: 2,
+            sort: NodeType::Bit,
+            state: state.clone(),
+            next: state,
+        });
+        let model = Model {
+            lines: vec![],
+            sequentials: vec![next],
+            bad_states_initial: vec![],
+            bad_states_sequential: vec![],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        };
+        let gate_model = bitblast_sequential_model(&model, true, 64);
+        let result = solve_bad_states(
+            &gate_model,
+            SatType::External,
+            &SATOptions::default(),
+            false,
+            false,
+            &mut vec![],
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "varisat")]
+    fn varisat_decides_with_timeout() {
+        let gate_model = input_is_42();
+        let options = SATOptions {
+            timeout: Some(Duration::from_secs(60)),
+            command: None,
+        };
+        let witnesses = solve_bad_states(
+            &gate_model,
+            SatType::Varisat,
+            &options,
+            false,
+            false,
+            &mut vec![],
+        )
+        .unwrap();
+
+        assert_eq!(witnesses.len(), 1);
+        assert_eq!(witnesses[0].value("x"), Some(42));
+    }
+
+    #[test]
+    fn detached_queries_respect_timeout() {
+        fn slow(_cnf: ClauseContainer) -> Answer {
+            thread::sleep(Duration::from_secs(10));
+            Answer::Unsat
+        }
+        fn fast(_cnf: ClauseContainer) -> Answer {
+            Answer::Unsat
+        }
+        let timeout = Some(Duration::from_millis(100));
+        let gate_model = input_is_42();
+        let gate = &gate_model.bad_state_gates[0];
+
+        let solution = decide_detached(&gate_model, gate, &[], timeout, slow);
+        assert_eq!(solution, SATSolution::Timeout);
+        let solution = decide_detached(&gate_model, gate, &[], timeout, fast);
+        assert_eq!(solution, SATSolution::Unsat);
+    }
+
+    #[test]
+    fn external_solver_reads_model_lines() {
+        // Claims all variables of the CNF to be `true`.
+        let script = stand_in_solver(concat!(
+            "n=$(grep '^p cnf' \"$1\" | cut -d' ' -f3)\n",
+            "echo 's SATISFIABLE'\n",
+            "echo \"v $(seq -s ' ' 1 $n) 0\"\n",
+            "exit 10"
+        ));
+        let gate_model = input_is_42();
+        let witnesses = solve_bad_states(
+            &gate_model,
+            SatType::External,
+            &options(&script, None),
+            false,
+            false,
+            &mut vec![],
+        )
+        .unwrap();
+
+        assert_eq!(witnesses.len(), 1);
+        assert_eq!(witnesses[0].value("x"), Some(255));
+    }
+
+    #[test]
+    fn external_solver_reads_exit_code() {
+        let script = stand_in_solver("exit 20");
+        let gate_model = input_is_42();
+        let witnesses = solve_bad_states(
+            &gate_model,
+            SatType::External,
+            &options(&script, None),
+            false,
+            false,
+            &mut vec![],
+        )
+        .unwrap();
+
+        assert!(witnesses.is_empty());
+    }
+
+    #[test]
+    fn external_solver_respects_timeout() {
+        let script = stand_in_solver("exec sleep 10");
+        let timeout = Some(Duration::from_millis(100));
+        let mut solver = ExternalSolver::new(&options(&script, timeout));
+        let gate_model = input_is_42();
+        let gate = &gate_model.bad_state_gates[0];
+
+        assert_eq!(solver.decide(&gate_model, gate, &[]), SATSolution::Timeout);
+    }
+
+    #[test]
+    fn external_solver_failures_are_unknown() {
+        let gate_model = input_is_42();
+        let gate = &gate_model.bad_state_gates[0];
+        let missing = SATOptions {
+            timeout: None,
+            command: Some("/nonexistent/sat-solver".to_string()),
+        };
+        let mut solver = ExternalSolver::new(&missing);
+        assert_eq!(solver.decide(&gate_model, gate, &[]), SATSolution::Timeout);
+
+        let script = stand_in_solver("exit 1");
+        let mut solver = ExternalSolver::new(&options(&script, None));
+        assert_eq!(solver.decide(&gate_model, gate, &[]), SATSolution::Timeout);
+    }
+
+    #[test]
+    fn timeouts_are_reported_as_unknown() {
+        let script = stand_in_solver("exec sleep 10");
+        let timeout = Some(Duration::from_millis(100));
+        let gate_model = input_is_42();
+        let witnesses = solve_bad_states(
+            &gate_model,
+            SatType::External,
+            &options(&script, timeout),
+            true,
+            false,
+            &mut vec![],
+        )
+        .unwrap();
+
+        assert!(witnesses.is_empty());
+    }
+
+    #[test]
+    fn one_query_records_verdict_for_each_bad_state() {
+        // Claims all variables of the CNF to be `false`.
+        let script = stand_in_solver("echo 's SATISFIABLE'\necho 'v 0'\nexit 10");
+        let gate_model = input_equals(&[0, 7]);
+        let mut verdicts = vec![];
+        let witnesses = solve_bad_states(
+            &gate_model,
+            SatType::External,
+            &options(&script, None),
+            false,
+            true,
+            &mut verdicts,
+        )
+        .unwrap();
+
+        assert_eq!(witnesses.len(), 1);
+        assert_eq!(witnesses[0].value("x"), Some(0));
+        let verdicts: Vec<Verdict> = verdicts.iter().map(|v| v.verdict).collect();
+        assert_eq!(verdicts, [Verdict::Sat, Verdict::Unknown]);
+    }
+
+    // Headers of the CNF files written for the queries, answering each of
+    // them unsatisfiable.
+    fn query_headers(gate_model: &GateModel) -> Vec<String> {
+        let cnfs = NamedTempFile::new().unwrap();
+        let script = stand_in_solver(&format!(
+            "grep '^p cnf' \"$1\" >> {}\nexit 20",
+            cnfs.path().display()
+        ));
+        let mut verdicts = vec![];
+        solve_bad_states(
+            gate_model,
+            SatType::External,
+            &options(&script, None),
+            false,
+            false,
+            &mut verdicts,
+        )
+        .unwrap();
+
+        assert!(verdicts.iter().all(|v| v.verdict == Verdict::Unsat));
+        let headers = fs::read_to_string(cnfs.path()).unwrap();
+        headers.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn external_solver_writes_cone_of_query() {
+        let headers = query_headers(&input_equals(&[42, 7]));
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0], query_headers(&input_equals(&[42]))[0]);
+        assert_eq!(headers[1], query_headers(&input_equals(&[7]))[0]);
+    }
+}
@@ -0,0 +1,692 @@
+use crate::unicorn::{get_nid, HashableNodeRef, Model, Node, NodeRef, NodeType};
+use anyhow::{anyhow, bail, Context, Result};
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+
+//
+// Public Interface
+//
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+    Bits(u64),
+    Memory(HashMap<u64, u64>),
+}
+
+/// Concrete word-level interpreter for sequential models. Each step first
+/// evaluates all bad states with respect to the current state values and
+/// then advances all states according to their `Next` nodes.
+pub struct Simulator {
+    sequentials: Vec<NodeRef>,
+    bad_states: Vec<NodeRef>,
+    states: HashMap<HashableNodeRef, Value>,
+    step: usize,
+}
+
+/// Observations made while simulating a single step, i.e. the values of
+/// all (non-memory) states at the beginning of the step and the bad states
+/// that were triggered during the step.
+#[derive(Debug)]
+pub struct StepResult {
+    pub step: usize,
+    pub states: Vec<(String, u64)>,
+    pub bad_states: Vec<NodeRef>,
+}
+
+impl Simulator {
+    pub fn new(model: &Model) -> Self {
+        Self {
+            sequentials: model.sequentials.clone(),
+            bad_states: [
+                &model.bad_states_initial[..],
+                &model.bad_states_sequential[..],
+            ]
+            .concat(),
+            states: HashMap::new(),
+            step: 0,
+        }
+    }
+
+    // Fixes the value of an uninitialized state instead of querying it as an
+    // input once it is read, hence it is only meaningful before the first step.
+    pub fn assign_state(&mut self, state: &NodeRef, value: u64) {
+        if let Node::State { sort, .. } = &*state.borrow() {
+            let value = Value::Bits(value & mask(sort));
+            self.states
+                .insert(HashableNodeRef::from(state.clone()), value);
+        } else {
+            panic!("expecting 'State' node here");
+        }
+    }
+
+    // Values of input nodes (and of uninitialized states, which is how inputs
+    // are represented in unrolled models) are requested from the given closure
+    // when they are read for the first time within the step.
+    pub fn step<F>(&mut self, input: F) -> StepResult
+    where
+        F: FnMut(&NodeRef) -> u64,
+    {
+        let mut evaluator = Evaluator::new(&mut self.states, input);
+
+        let mut states = Vec::new();
+        for sequential in &self.sequentials {
+            if let Node::Next { state, .. } = &*sequential.borrow() {
+                if let Value::Bits(value) = evaluator.evaluate(state) {
+                    states.push((state_name(state), value));
+                }
+            } else {
+                panic!("expecting 'Next' node here");
+            }
+        }
+
+        let bad_states = self
+            .bad_states
+            .iter()
+            .filter(|&bad_state| evaluator.bits(bad_state) != 0)
+            .cloned()
+            .collect();
+
+        let next_values: Vec<(HashableNodeRef, Value)> = self
+            .sequentials
+            .iter()
+            .map(|sequential| {
+                if let Node::Next { state, next, .. } = &*sequential.borrow() {
+                    (
+                        HashableNodeRef::from(state.clone()),
+                        evaluator.evaluate(next),
+                    )
+                } else {
+                    panic!("expecting 'Next' node here");
+                }
+            })
+            .collect();
+
+        self.states.extend(next_values);
+        self.step += 1;
+
+        StepResult {
+            step: self.step - 1,
+            states,
+            bad_states,
+        }
+    }
+}
+
+impl StepResult {
+    pub fn bad_state_names(&self) -> Vec<String> {
+        self.bad_states
+            .iter()
+            .map(|bad_state| match &*bad_state.borrow() {
+                Node::Bad { name, .. } => name.as_deref().unwrap_or("?").to_string(),
+                _ => panic!("expecting 'Bad' node here"),
+            })
+            .collect()
+    }
+}
+
+// Replays a witness in the format produced by `btormc` (and by `beator
+// --btor2-witness`) on the given model. States, inputs and bad states are
+// identified by their position in the model. Returns the first claimed bad
+// state that is reached together with the step it is reached in.
+pub fn check_btor2_witness<R>(model: &Model, reader: R) -> Result<(NodeRef, usize)>
+where
+    R: BufRead,
+{
+    let witness = parse_btor2_witness(reader)?;
+    let (states, inputs, bad_states) = declarations(model);
+
+    let lookup = |nodes: &[NodeRef], index: usize, kind: &str| {
+        nodes
+            .get(index)
+            .cloned()
+            .ok_or_else(|| anyhow!("Witness refers to undeclared {} {}", kind, index))
+    };
+    let claimed = witness
+        .bad_states
+        .iter()
+        .map(|&index| lookup(&bad_states, index, "bad state").map(HashableNodeRef::from))
+        .collect::<Result<HashSet<_>>>()?;
+
+    let mut simulator = Simulator::new(model);
+    for &(index, value) in &witness.states {
+        simulator.assign_state(&lookup(&states, index, "state")?, value);
+    }
+    let frames = witness
+        .inputs
+        .iter()
+        .map(|frame| {
+            frame
+                .iter()
+                .map(|&(index, value)| {
+                    lookup(&inputs, index, "input").map(|i| (HashableNodeRef::from(i), value))
+                })
+                .collect::<Result<HashMap<_, _>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for frame in &frames {
+        let result = simulator.step(|input| {
+            let value = frame.get(&HashableNodeRef::from(input.clone()));
+            value.copied().unwrap_or(0)
+        });
+        for bad_state in result.bad_states {
+            if claimed.contains(&HashableNodeRef::from(bad_state.clone())) {
+                return Ok((bad_state, result.step));
+            }
+            debug!(
+                "Witness triggers unclaimed bad state {}",
+                get_nid(&bad_state)
+            );
+        }
+    }
+
+    bail!(
+        "Witness does not reach any of the claimed bad states within {} steps",
+        frames.len()
+    )
+}
+
+// Returns all states, inputs and bad states in declaration order. Models
+// read by the BTOR2 parser have no lines, the original nids are used to
+// restore the order instead (which only covers nodes that are reachable).
+pub fn declarations(model: &Model) -> (Vec<NodeRef>, Vec<NodeRef>, Vec<NodeRef>) {
+    let nodes = declared_nodes(model);
+    let mut states = Vec::new();
+    let mut inputs = Vec::new();
+    let mut bad_states = Vec::new();
+    for node in nodes {
+        match &*node.borrow() {
+            Node::State { .. } => states.push(node.clone()),
+            Node::Input { .. } => inputs.push(node.clone()),
+            Node::Bad { .. } => bad_states.push(node.clone()),
+            _ => (),
+        }
+    }
+    (states, inputs, bad_states)
+}
+
+// Returns the widths of inputs that are narrower than their representation.
+// The BTOR2 parser truncates such inputs with a mask right away, which is
+// where their declared width is recovered from.
+pub fn declared_input_widths(model: &Model) -> HashMap<HashableNodeRef, usize> {
+    let mut widths = HashMap::new();
+    for node in declared_nodes(model) {
+        if let Node::And { left, right, .. } = &*node.borrow() {
+            let is_input = matches!(&*left.borrow(), Node::Input { .. });
+            if let Node::Const { imm, .. } = &*right.borrow() {
+                if is_input && *imm != 0 && imm & imm.wrapping_add(1) == 0 {
+                    let width = 64 - imm.leading_zeros() as usize;
+                    widths.insert(HashableNodeRef::from(left.clone()), width);
+                }
+            }
+        }
+    }
+    widths
+}
+
+// Evaluates a node of a combinational (e.g. unrolled) model and returns the
+// inputs (and uninitialized states) that are read on the way, in the order
+// they are read. Inputs on branches that are not taken are not included.
+pub fn inputs_read<F>(node: &NodeRef, mut input: F) -> Vec<NodeRef>
+where
+    F: FnMut(&NodeRef) -> u64,
+{
+    let mut states = HashMap::new();
+    let mut read = Vec::new();
+    Evaluator::new(&mut states, |node: &NodeRef| {
+        read.push(node.clone());
+        input(node)
+    })
+    .evaluate(node);
+    read
+}
+
+// Evaluates a node of a combinational (e.g. unrolled) model to its bits, bad
+// states evaluate to their condition.
+pub fn evaluate<F>(node: &NodeRef, input: F) -> u64
+where
+    F: FnMut(&NodeRef) -> u64,
+{
+    let mut states = HashMap::new();
+    Evaluator::new(&mut states, input).bits(node)
+}
+
+//
+// Private Implementation
+//
+
+// Parsed contents of a single BTOR2 witness. Only state assignments of the
+// first frame are kept, later frames just repeat values implied by the model.
+struct Btor2Witness {
+    bad_states: Vec<usize>,
+    states: Vec<(usize, u64)>,
+    inputs: Vec<Vec<(usize, u64)>>,
+}
+
+#[derive(PartialEq)]
+enum Section {
+    Header,
+    States(usize),
+    Inputs,
+}
+
+fn parse_btor2_witness<R>(reader: R) -> Result<Btor2Witness>
+where
+    R: BufRead,
+{
+    let mut witness = Btor2Witness {
+        bad_states: Vec::new(),
+        states: Vec::new(),
+        inputs: Vec::new(),
+    };
+    let mut section = Section::Header;
+    let mut found_sat = false;
+
+    for line in reader.lines() {
+        let line = line.context("Failed to read witness")?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if !found_sat {
+            if line != "sat" {
+                bail!("Witness has to start with 'sat', found '{}'", line);
+            }
+            found_sat = true;
+        } else if line == "." {
+            break;
+        } else if let Some(frame) = line.strip_prefix('#') {
+            section = Section::States(parse_frame(frame)?);
+        } else if let Some(frame) = line.strip_prefix('@') {
+            if parse_frame(frame)? != witness.inputs.len() {
+                bail!("Witness frames are not consecutive at '{}'", line);
+            }
+            witness.inputs.push(Vec::new());
+            section = Section::Inputs;
+        } else if section == Section::Header {
+            for property in line.split_whitespace() {
+                match property.strip_prefix('b') {
+                    Some(index) => witness.bad_states.push(
+                        index
+                            .parse::<usize>()
+                            .with_context(|| format!("Invalid bad state '{}'", property))?,
+                    ),
+                    None => bail!("Witness only supports bad properties, found '{}'", property),
+                }
+            }
+        } else {
+            let assignment = parse_assignment(line)?;
+            match section {
+                Section::States(0) => witness.states.push(assignment),
+                Section::States(_) => (),
+                Section::Inputs => witness.inputs.last_mut().unwrap().push(assignment),
+                Section::Header => unreachable!(),
+            }
+        }
+    }
+
+    if !found_sat {
+        bail!("Witness is empty");
+    }
+    Ok(witness)
+}
+
+fn parse_frame(frame: &str) -> Result<usize> {
+    frame
+        .parse::<usize>()
+        .with_context(|| format!("Invalid witness frame '{}'", frame))
+}
+
+fn parse_assignment(line: &str) -> Result<(usize, u64)> {
+    let mut tokens = line.split_whitespace();
+    let index = tokens
+        .next()
+        .and_then(|token| token.parse::<usize>().ok())
+        .ok_or_else(|| anyhow!("Invalid witness assignment '{}'", line))?;
+    let value = tokens
+        .next()
+        .ok_or_else(|| anyhow!("Missing value in witness assignment '{}'", line))?;
+    if value.starts_with('[') {
+        bail!(
+            "Array assignments in witnesses are not supported: '{}'",
+            line
+        );
+    }
+    if value.len() > 64 {
+        bail!(
+            "Witness values wider than 64 bits are not supported: '{}'",
+            line
+        );
+    }
+    let value = u64::from_str_radix(value, 2)
+        .with_context(|| format!("Invalid binary value in witness assignment '{}'", line))?;
+    Ok((index, value))
+}
+
+fn declared_nodes(model: &Model) -> Vec<NodeRef> {
+    if !model.lines.is_empty() {
+        model.lines.clone()
+    } else {
+        let mut nodes = reachable_nodes(model);
+        nodes.sort_by_key(get_nid);
+        nodes
+    }
+}
+
+fn reachable_nodes(model: &Model) -> Vec<NodeRef> {
+    let mut nodes = Vec::new();
+    let mut marks = HashSet::new();
+    let mut stack: Vec<NodeRef> = model
+        .sequentials
+        .iter()
+        .chain(model.bad_states_initial.iter())
+        .chain(model.bad_states_sequential.iter())
+        .cloned()
+        .collect();
+    while let Some(node) = stack.pop() {
+        if !marks.insert(HashableNodeRef::from(node.clone())) {
+            continue;
+        }
+        match &*node.borrow() {
+            Node::Const { .. } | Node::Input { .. } | Node::Comment(_) => (),
+            Node::State { init, .. } => stack.extend(init.iter().cloned()),
+            Node::Ext { value, .. } | Node::Not { value, .. } => stack.push(value.clone()),
+            Node::Bad { cond, .. } => stack.push(cond.clone()),
+            Node::Read {
+                memory, address, ..
+            } => stack.extend([memory.clone(), address.clone()]),
+            Node::Write {
+                memory,
+                address,
+                value,
+                ..
+            } => stack.extend([memory.clone(), address.clone(), value.clone()]),
+            Node::Ite {
+                cond, left, right, ..
+            } => stack.extend([cond.clone(), left.clone(), right.clone()]),
+            Node::Next { state, next, .. } => stack.extend([state.clone(), next.clone()]),
+            Node::Add { left, right, .. }
+            | Node::Sub { left, right, .. }
+            | Node::Mul { left, right, .. }
+            | Node::Divu { left, right, .. }
+            | Node::Div { left, right, .. }
+            | Node::Rem { left, right, .. }
+            | Node::Sll { left, right, .. }
+            | Node::Srl { left, right, .. }
+            | Node::Ult { left, right, .. }
+            | Node::Eq { left, right, .. }
+            | Node::And { left, right, .. }
+            | Node::Or { left, right, .. } => stack.extend([left.clone(), right.clone()]),
+        }
+        nodes.push(node.clone());
+    }
+    nodes
+}
+
+fn state_name(state: &NodeRef) -> String {
+    match &*state.borrow() {
+        Node::State { name, .. } => name.as_deref().unwrap_or("?").to_string(),
+        _ => panic!("expecting 'State' node here"),
+    }
+}
+
+fn mask(sort: &NodeType) -> u64 {
+    match sort.bitsize() {
+        64 => u64::MAX,
+        width => (1_u64 << width) - 1,
+    }
+}
+
+// Arithmetic nodes have the width of their operands, which is not always a
+// machine word in models read from BTOR2 files.
+fn operand_mask(node: &NodeRef) -> u64 {
+    match &*node.borrow() {
+        Node::Const { sort, .. }
+        | Node::State { sort, .. }
+        | Node::Input { sort, .. }
+        | Node::Ite { sort, .. }
+        | Node::And { sort, .. }
+        | Node::Or { sort, .. }
+        | Node::Not { sort, .. }
+            if *sort != NodeType::Memory =>
+        {
+            mask(sort)
+        }
+        Node::Ult { .. } | Node::Eq { .. } => 1,
+        Node::Add { left, .. }
+        | Node::Sub { left, .. }
+        | Node::Mul { left, .. }
+        | Node::Divu { left, .. }
+        | Node::Div { left, .. }
+        | Node::Rem { left, .. }
+        | Node::Sll { left, .. }
+        | Node::Srl { left, .. } => operand_mask(left),
+        _ => u64::MAX,
+    }
+}
+
+// Evaluates nodes with respect to the state values of the current step. Each
+// node is evaluated at most once per step, both branches of an `Ite` are only
+// evaluated on demand, so that only inputs that are actually read are queried.
+struct Evaluator<'a, F> {
+    states: &'a mut HashMap<HashableNodeRef, Value>,
+    cache: HashMap<HashableNodeRef, Value>,
+    input: F,
+}
+
+impl<'a, F> Evaluator<'a, F>
+where
+    F: FnMut(&NodeRef) -> u64,
+{
+    fn new(states: &'a mut HashMap<HashableNodeRef, Value>, input: F) -> Self {
+        Self {
+            states,
+            cache: HashMap::new(),
+            input,
+        }
+    }
+
+    fn bits(&mut self, node: &NodeRef) -> u64 {
+        match self.evaluate(node) {
+            Value::Bits(value) => value,
+            Value::Memory(_) => panic!("expecting bit-vector value of {}", get_nid(node)),
+        }
+    }
+
+    fn memory(&mut self, node: &NodeRef) -> HashMap<u64, u64> {
+        match self.evaluate(node) {
+            Value::Memory(memory) => memory,
+            Value::Bits(_) => panic!("expecting memory value of {}", get_nid(node)),
+        }
+    }
+
+    fn evaluate(&mut self, node: &NodeRef) -> Value {
+        let key = HashableNodeRef::from(node.clone());
+        if let Some(value) = self.cache.get(&key) {
+            return value.clone();
+        }
+        let value = self.evaluate_uncached(node);
+        self.cache.insert(key, value.clone());
+        value
+    }
+
+    #[rustfmt::skip]
+    fn evaluate_uncached(&mut self, node: &NodeRef) -> Value {
+        match &*node.borrow() {
+            Node::Const { sort, imm, .. } => Value::Bits(imm & mask(sort)),
+            Node::Read { memory, address, .. } => {
+                let address = self.bits(address);
+                let memory = self.memory(memory);
+                // Memory that has never been written is assumed to be zero.
+                Value::Bits(memory.get(&address).copied().unwrap_or(0))
+            }
+            Node::Write { memory, address, value, .. } => {
+                let address = self.bits(address);
+                let value = self.bits(value);
+                let mut memory = self.memory(memory);
+                memory.insert(address, value);
+                Value::Memory(memory)
+            }
+            Node::Add { left, right, .. } =>
+                Value::Bits(self.bits(left).wrapping_add(self.bits(right)) & operand_mask(left)),
+            Node::Sub { left, right, .. } =>
+                Value::Bits(self.bits(left).wrapping_sub(self.bits(right)) & operand_mask(left)),
+            Node::Mul { left, right, .. } =>
+                Value::Bits(self.bits(left).wrapping_mul(self.bits(right)) & operand_mask(left)),
+            Node::Divu { left, right, .. } => {
+                let (mask, left, right) = (operand_mask(left), self.bits(left), self.bits(right));
+                Value::Bits(left.checked_div(right).unwrap_or(mask))
+            }
+            Node::Div { left, right, .. } => {
+                let (left, right) = (self.bits(left) as i64, self.bits(right) as i64);
+                // Division by zero follows the SMT-LIB semantics of `bvsdiv`.
+                match right {
+                    0 if left < 0 => Value::Bits(1),
+                    0 => Value::Bits(u64::MAX),
+                    _ => Value::Bits(left.wrapping_div(right) as u64),
+                }
+            }
+            Node::Rem { left, right, .. } => {
+                let (left, right) = (self.bits(left), self.bits(right));
+                Value::Bits(left.checked_rem(right).unwrap_or(left))
+            }
+            Node::Sll { left, right, .. } => {
+                let (mask, left, right) = (operand_mask(left), self.bits(left), self.bits(right));
+                Value::Bits(if right < 64 { (left << right) & mask } else { 0 })
+            }
+            Node::Srl { left, right, .. } => {
+                let (left, right) = (self.bits(left), self.bits(right));
+                Value::Bits(if right < 64 { left >> right } else { 0 })
+            }
+            Node::Ult { left, right, .. } =>
+                Value::Bits((self.bits(left) < self.bits(right)) as u64),
+            Node::Ext { value, .. } => Value::Bits(self.bits(value)),
+            Node::Ite { cond, left, right, .. } => {
+                if self.bits(cond) != 0 {
+                    self.evaluate(left)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            Node::Eq { left, right, .. } =>
+                Value::Bits((self.evaluate(left) == self.evaluate(right)) as u64),
+            Node::And { left, right, .. } => Value::Bits(self.bits(left) & self.bits(right)),
+            Node::Or { left, right, .. } => Value::Bits(self.bits(left) | self.bits(right)),
+            Node::Not { sort, value, .. } => Value::Bits(!self.bits(value) & mask(sort)),
+            Node::State { init, sort, .. } => {
+                let key = HashableNodeRef::from(node.clone());
+                if let Some(value) = self.states.get(&key) {
+                    return value.clone();
+                }
+                let value = match (init, sort) {
+                    (Some(init), _) => self.evaluate(init),
+                    (None, NodeType::Memory) => Value::Memory(HashMap::new()),
+                    (None, sort) => Value::Bits((self.input)(node) & mask(sort)),
+                };
+                self.states.insert(key, value.clone());
+                value
+            }
+            Node::Next { next, .. } => self.evaluate(next),
+            Node::Input { sort, .. } => Value::Bits((self.input)(node) & mask(sort)),
+            Node::Bad { cond, .. } => Value::Bits(self.bits(cond)),
+            Node::Comment(_) => panic!("cannot evaluate comment"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    fn constant(nid: u64, sort: NodeType, imm: u64) -> NodeRef {
+        NodeRef::from(Node::Const { nid, sort, imm })
+    }
+
+    // A counter that is incremented by the input in each step and a bad state
+    // that is triggered once the counter reaches three.
+    fn counter_model() -> (Model, NodeRef) {
+        let counter = NodeRef::from(Node::State {
+            nid: 1,
+            sort: NodeType::Word,
+            init: Some(constant(2, NodeType::Word, 0)),
+            name: Some("counter".to_string()),
+        });
+        let input = NodeRef::from(Node::Input {
+            nid: 3,
+            sort: NodeType::Input1Byte,
+            name: "increment".to_string(),
+        });
+        let ext = NodeRef::from(Node::Ext {
+            nid: 4,
+            from: NodeType::Input1Byte,
+            value: input.clone(),
+        });
+        let add = NodeRef::from(Node::Add {
+            nid: 5,
+            left: counter.clone(),
+            right: ext,
+        });
+        let next = NodeRef::from(Node::Next {
+            nid: 6,
+            sort: NodeType::Word,
+            state: counter.clone(),
+            next: add,
+        });
+        let eq = NodeRef::from(Node::Eq {
+            nid: 7,
+            left: counter.clone(),
+            right: constant(8, NodeType::Word, 3),
+        });
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 9,
+            cond: eq,
+            name: Some("counter-is-three".to_string()),
+        });
+        let model = Model {
+            lines: vec![],
+            sequentials: vec![next],
+            bad_states_initial: vec![],
+            bad_states_sequential: vec![bad_state.clone()],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        };
+        (model, bad_state)
+    }
+
+    #[test]
+    fn steps_model_under_inputs() {
+        let (model, _) = counter_model();
+        let mut simulator = Simulator::new(&model);
+        let mut values = vec![1, 2, 0x101].into_iter();
+
+        let result = simulator.step(|_| values.next().unwrap());
+        assert_eq!(result.states, vec![("counter".to_string(), 0)]);
+        assert!(result.bad_states.is_empty());
+        simulator.step(|_| values.next().unwrap());
+        let result = simulator.step(|_| values.next().unwrap());
+        assert_eq!(result.step, 2);
+        assert_eq!(
+            result.bad_state_names(),
+            vec!["counter-is-three".to_string()]
+        );
+        let result = simulator.step(|_| 0);
+        assert_eq!(result.states, vec![("counter".to_string(), 4)]);
+    }
+
+    #[test]
+    fn checks_btor2_witness() {
+        let (model, bad_state) = counter_model();
+
+        let witness = "sat\nb0\n#0\n@0\n0 00000011 increment@0\n@1\n0 00000000\n.\n";
+        let (reached, step) = check_btor2_witness(&model, witness.as_bytes()).unwrap();
+        assert!(Rc::ptr_eq(&reached, &bad_state));
+        assert_eq!(step, 1);
+
+        let witness = "sat\nb0\n#0\n@0\n0 00000010\n@1\n0 00000000\n.\n";
+        assert!(check_btor2_witness(&model, witness.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use crate::unicorn::{get_nid, Model, Node, NodeRef, NodeType};
+use anyhow::{ensure, Result};
+use std::collections::HashSet;
+use std::io::Write;
+
+//
+// Public Interface
+//
+
+// Writes the given (unrolled and renumbered) model as an SMT-LIB script that
+// checks each bad state separately, or all of them OR-ed with `one_query`.
+// The logic is QF_BV unless the model still contains memory (arrays).
+pub fn write_smt2_model<W>(model: &Model, one_query: bool, mut out: W) -> Result<()>
+where
+    W: Write,
+{
+    ensure!(
+        model.sequentials.is_empty() && model.bad_states_sequential.is_empty(),
+        "printing SMT-LIB requires an unrolled model"
+    );
+    let has_memory = model.lines.iter().any(has_memory_sort);
+    let logic = if has_memory { "QF_ABV" } else { "QF_BV" };
+    writeln!(out, "; cksystemsgroup.github.io/unicorn\n")?;
+    // Options can only be set before the logic, strict solvers insist on it.
+    writeln!(out, "(set-option :produce-models true)")?;
+    writeln!(out, "(set-logic {})", logic)?;
+    let mut declared = HashSet::new();
+    for node in model.lines.iter() {
+        write_node(node, &mut declared, &mut out)?;
+    }
+    writeln!(out)?;
+    if one_query {
+        let conds: Vec<String> = model.bad_states_initial.iter().map(smt_ref).collect();
+        let query = match conds.len() {
+            0 => "false".to_string(),
+            1 => conds[0].clone(),
+            _ => format!("(or {})", conds.join(" ")),
+        };
+        writeln!(out, "; all bad states OR-ed")?;
+        writeln!(out, "(assert {})", query)?;
+        writeln!(out, "(check-sat)")?;
+    } else {
+        for bad_state in &model.bad_states_initial {
+            if let Node::Bad { name, .. } = &*bad_state.borrow() {
+                writeln!(out, "; bad state '{}'", name.as_deref().unwrap_or("?"))?;
+            }
+            writeln!(out, "(push 1)")?;
+            writeln!(out, "(assert {})", smt_ref(bad_state))?;
+            writeln!(out, "(check-sat)")?;
+            writeln!(out, "(pop 1)")?;
+        }
+    }
+    writeln!(out, "(exit)")?;
+    Ok(())
+}
+
+// SMT-LIB representation of a single node, which is either a free variable
+// (inputs and uninitialized states) or an expression over its operands.
+pub enum Smt2Term {
+    Variable(String),
+    Expression(String),
+}
+
+pub fn smt2_sort(sort: &NodeType) -> String {
+    match sort {
+        NodeType::Bit => "Bool".to_string(),
+        NodeType::Memory => "(Array (_ BitVec 64) (_ BitVec 64))".to_string(),
+        sort => format!("(_ BitVec {})", sort.bitsize()),
+    }
+}
+
+// Inputs are declared under their name so that the values reported by a
+// solver can be related to the model. Quoted symbols must not contain `|`
+// or `\`, these are replaced.
+pub fn smt2_symbol(name: &str) -> String {
+    format!("|{}|", name.replace(|c: char| c == '|' || c == '\\', "_"))
+}
+
+// Translates a single node into its sort and SMT-LIB term, operands are
+// referred to by the names the given function yields for them.
+#[rustfmt::skip]
+pub fn smt2_term<F>(node: &Node, mut name: F) -> (NodeType, Smt2Term)
+where
+    F: FnMut(&NodeRef) -> String,
+{
+    use Smt2Term::{Expression, Variable};
+    match node {
+        Node::Const { sort: NodeType::Bit, imm, .. } =>
+            (NodeType::Bit, Expression((*imm != 0).to_string())),
+        Node::Const { sort, imm, .. } =>
+            (sort.clone(), Expression(format!("(_ bv{} {})", imm, sort.bitsize()))),
+        Node::Read { memory, address, .. } =>
+            (NodeType::Word, Expression(format!("(select {} {})", name(memory), name(address)))),
+        Node::Write { memory, address, value, .. } =>
+            (NodeType::Memory, Expression(format!("(store {} {} {})", name(memory), name(address), name(value)))),
+        Node::Add { left, right, .. } =>
+            (NodeType::Word, Expression(format!("(bvadd {} {})", name(left), name(right)))),
+        Node::Sub { left, right, .. } =>
+            (NodeType::Word, Expression(format!("(bvsub {} {})", name(left), name(right)))),
+        Node::Mul { left, right, .. } =>
+            (NodeType::Word, Expression(format!("(bvmul {} {})", name(left), name(right)))),
+        Node::Divu { left, right, .. } =>
+            (NodeType::Word, Expression(format!("(bvudiv {} {})", name(left), name(right)))),
+        Node::Div { left, right, .. } =>
+            (NodeType::Word, Expression(format!("(bvsdiv {} {})", name(left), name(right)))),
+        Node::Rem { left, right, .. } =>
+            (NodeType::Word, Expression(format!("(bvurem {} {})", name(left), name(right)))),
+        Node::Sll { left, right, .. } =>
+            (NodeType::Word, Expression(format!("(bvshl {} {})", name(left), name(right)))),
+        Node::Srl { left, right, .. } =>
+            (NodeType::Word, Expression(format!("(bvlshr {} {})", name(left), name(right)))),
+        Node::Ult { left, right, .. } =>
+            (NodeType::Bit, Expression(format!("(bvult {} {})", name(left), name(right)))),
+        Node::Ext { from: NodeType::Bit, value, .. } =>
+            (NodeType::Word, Expression(format!("(ite {} (_ bv1 64) (_ bv0 64))", name(value)))),
+        Node::Ext { from, value, .. } =>
+            (NodeType::Word, Expression(format!("((_ zero_extend {}) {})", 64 - from.bitsize(), name(value)))),
+        Node::Ite { sort, cond, left, right, .. } =>
+            (sort.clone(), Expression(format!("(ite {} {} {})", name(cond), name(left), name(right)))),
+        Node::Eq { left, right, .. } =>
+            (NodeType::Bit, Expression(format!("(= {} {})", name(left), name(right)))),
+        Node::And { sort: NodeType::Bit, left, right, .. } =>
+            (NodeType::Bit, Expression(format!("(and {} {})", name(left), name(right)))),
+        Node::And { sort, left, right, .. } =>
+            (sort.clone(), Expression(format!("(bvand {} {})", name(left), name(right)))),
+        Node::Or { sort: NodeType::Bit, left, right, .. } =>
+            (NodeType::Bit, Expression(format!("(or {} {})", name(left), name(right)))),
+        Node::Or { sort, left, right, .. } =>
+            (sort.clone(), Expression(format!("(bvor {} {})", name(left), name(right)))),
+        Node::Not { sort: NodeType::Bit, value, .. } =>
+            (NodeType::Bit, Expression(format!("(not {})", name(value)))),
+        Node::Not { sort, value, .. } =>
+            (sort.clone(), Expression(format!("(bvnot {})", name(value)))),
+        Node::State { sort, init: Some(value), .. } =>
+            (sort.clone(), Expression(name(value))),
+        Node::State { nid, sort, init: None, name: state_name } => {
+            let state_name = state_name.clone().unwrap_or_else(|| format!("state-{}", nid));
+            (sort.clone(), Variable(state_name))
+        }
+        Node::Input { sort, name: input_name, .. } =>
+            (sort.clone(), Variable(input_name.clone())),
+        Node::Next { .. } => panic!("should be unreachable"),
+        Node::Bad { cond, .. } =>
+            (NodeType::Bit, Expression(name(cond))),
+        Node::Comment(_) => panic!("cannot translate"),
+    }
+}
+
+//
+// Private Implementation
+//
+
+fn smt_ref(node: &NodeRef) -> String {
+    format!("n{}", get_nid(node))
+}
+
+fn has_memory_sort(node: &NodeRef) -> bool {
+    matches!(
+        &*node.borrow(),
+        Node::State {
+            sort: NodeType::Memory,
+            ..
+        } | Node::Ite {
+            sort: NodeType::Memory,
+            ..
+        } | Node::Write { .. }
+            | Node::Read { .. }
+    )
+}
+
+// Declares an input variable once per name, the SMT solvers used by the
+// optimizer also identify inputs of the same name.
+fn declare<W>(
+    name: &str,
+    sort: &NodeType,
+    declared: &mut HashSet<String>,
+    out: &mut W,
+) -> Result<String>
+where
+    W: Write,
+{
+    let symbol = smt2_symbol(name);
+    if declared.insert(symbol.clone()) {
+        writeln!(out, "(declare-const {} {})", symbol, smt2_sort(sort))?;
+    }
+    Ok(symbol)
+}
+
+fn write_node<W>(node: &NodeRef, declared: &mut HashSet<String>, out: &mut W) -> Result<()>
+where
+    W: Write,
+{
+    if let Node::Comment(s) = &*node.borrow() {
+        writeln!(out, "\n; {}\n", s)?;
+        return Ok(());
+    }
+    let (sort, term) = smt2_term(&*node.borrow(), smt_ref);
+    let term = match term {
+        Smt2Term::Variable(name) => declare(&name, &sort, declared, out)?,
+        Smt2Term::Expression(term) => term,
+    };
+    writeln!(
+        out,
+        "(define-fun {} () {} {})",
+        smt_ref(node),
+        smt2_sort(&sort),
+        term
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::Nid;
+
+    fn byte_input(nid: Nid, name: &str) -> NodeRef {
+        NodeRef::from(Node::Input {
+            nid,
+            sort: NodeType::Input1Byte,
+            name: name.to_string(),
+        })
+    }
+
+    fn model_of(lines: Vec<NodeRef>, bad_states: Vec<NodeRef>) -> Model {
+        Model {
+            lines,
+            sequentials: vec![],
+            bad_states_initial: bad_states,
+            bad_states_sequential: vec![],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        }
+    }
+
+    #[test]
+    fn writes_one_check_per_bad_state() {
+        let input = byte_input(1, "1-byte-input[n=0]");
+        let ext = NodeRef::from(Node::Ext {
+            nid: 2,
+            from: NodeType::Input1Byte,
+            value: input.clone(),
+        });
+        let zero = NodeRef::from(Node::Const {
+            nid: 3,
+            sort: NodeType::Word,
+            imm: 0,
+        });
+        let ult = NodeRef::from(Node::Ult {
+            nid: 4,
+            left: zero.clone(),
+            right: ext.clone(),
+        });
+        let eq = NodeRef::from(Node::Eq {
+            nid: 5,
+            left: zero.clone(),
+            right: ext.clone(),
+        });
+        let bad_1 = NodeRef::from(Node::Bad {
+            nid: 6,
+            cond: ult.clone(),
+            name: Some("positive".to_string()),
+        });
+        let bad_2 = NodeRef::from(Node::Bad {
+            nid: 7,
+            cond: eq.clone(),
+            name: Some("zero".to_string()),
+        });
+        let lines = vec![input, ext, zero, ult, eq, bad_1.clone(), bad_2.clone()];
+        let model = model_of(lines, vec![bad_1, bad_2]);
+
+        let mut out = Vec::new();
+        write_smt2_model(&model, false, &mut out).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.contains("(set-option :produce-models true)\n(set-logic QF_BV)"));
+        assert!(script.contains("(declare-const |1-byte-input[n=0]| (_ BitVec 8))"));
+        assert!(script.contains("(define-fun n1 () (_ BitVec 8) |1-byte-input[n=0]|)"));
+        assert!(script.contains("(define-fun n2 () (_ BitVec 64) ((_ zero_extend 56) n1))"));
+        assert!(script.contains("(define-fun n6 () Bool n4)"));
+        assert_eq!(script.matches("(check-sat)").count(), 2);
+
+        let mut out = Vec::new();
+        write_smt2_model(&model, true, &mut out).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.contains("(assert (or n6 n7))"));
+        assert_eq!(script.matches("(check-sat)").count(), 1);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::unicorn::witness::Witness;
 use crate::unicorn::NodeRef;
 use std::time::Duration;
 
@@ -5,10 +6,17 @@ use std::time::Duration;
 // Public Interface
 //
 
+// Settings an SMT solver is created with, not every solver uses all of them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SMTOptions {
+    pub timeout: Option<Duration>,
+    pub command: Option<String>,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Eq, PartialEq)]
 pub enum SMTSolution {
-    Sat,
+    Sat(Witness),
     Unsat,
     Timeout,
 }
@@ -17,12 +25,19 @@ pub enum SMTSolution {
 // upgrades to an even higher version number.
 #[allow(clippy::wrong_self_convention)]
 pub trait SMTSolver {
-    fn new(timeout: Option<Duration>) -> Self;
+    fn new(options: &SMTOptions) -> Self;
     fn name() -> &'static str;
     fn solve(&mut self, root: &NodeRef) -> SMTSolution;
     fn is_always_true(&mut self, node: &NodeRef) -> bool;
     fn is_always_false(&mut self, node: &NodeRef) -> bool;
     fn is_always_equal(&mut self, left: &NodeRef, right: &NodeRef) -> bool;
+
+    // Incremental interface: nodes stay translated across all queries, and
+    // constraints added by `assert_false` hold for all later queries until
+    // the assertion level they were added on is popped again.
+    fn push(&mut self);
+    fn pop(&mut self);
+    fn assert_false(&mut self, node: &NodeRef);
 }
 
 //
@@ -31,9 +46,8 @@ pub trait SMTSolver {
 
 // TODO: Move this module into separate file.
 pub mod none_impl {
-    use crate::unicorn::smt_solver::{SMTSolution, SMTSolver};
+    use crate::unicorn::smt_solver::{SMTOptions, SMTSolution, SMTSolver};
     use crate::unicorn::NodeRef;
-    use std::time::Duration;
 
     pub struct NoneSolver {}
 
@@ -42,7 +56,7 @@ pub mod none_impl {
             "None"
         }
 
-        fn new(_timeout: Option<Duration>) -> Self {
+        fn new(_options: &SMTOptions) -> Self {
             Self {}
         }
 
@@ -61,13 +75,20 @@ pub mod none_impl {
         fn solve(&mut self, _root: &NodeRef) -> SMTSolution {
             SMTSolution::Timeout
         }
+
+        fn push(&mut self) {}
+
+        fn pop(&mut self) {}
+
+        fn assert_false(&mut self, _node: &NodeRef) {}
     }
 }
 
 // TODO: Move this module into separate file.
 #[cfg(feature = "boolector")]
 pub mod boolector_impl {
-    use crate::unicorn::smt_solver::{SMTSolution, SMTSolver};
+    use crate::unicorn::smt_solver::{SMTOptions, SMTSolution, SMTSolver};
+    use crate::unicorn::witness::{input_variables, Witness};
     use crate::unicorn::{HashableNodeRef, Node, NodeRef, NodeType};
     use boolector_solver::{
         option::{BtorOption, ModelGen, OutputFileFormat},
@@ -76,7 +97,6 @@ pub mod boolector_impl {
     use log::debug;
     use std::collections::HashMap;
     use std::rc::Rc;
-    use std::time::Duration;
 
     type ArrayRef = Array<Rc<Btor>>;
     type BitVectorRef = BV<Rc<Btor>>;
@@ -97,13 +117,13 @@ pub mod boolector_impl {
             "Boolector"
         }
 
-        fn new(timeout: Option<Duration>) -> Self {
+        fn new(options: &SMTOptions) -> Self {
             let solver = Rc::new(Btor::new());
             // TODO: Properly configure the below options.
             solver.set_opt(BtorOption::ModelGen(ModelGen::All));
             solver.set_opt(BtorOption::Incremental(true));
             solver.set_opt(BtorOption::OutputFileFormat(OutputFileFormat::SMTLIBv2));
-            solver.set_opt(BtorOption::SolverTimeout(timeout));
+            solver.set_opt(BtorOption::SolverTimeout(options.timeout));
             Self {
                 solver,
                 mapping: HashMap::new(),
@@ -112,33 +132,47 @@ pub mod boolector_impl {
 
         fn is_always_true(&mut self, node: &NodeRef) -> bool {
             let bv = self.visit(node).into_bv().not();
-            self.solve_impl(bv) == SMTSolution::Unsat
+            self.solve_impl(bv, None) == SMTSolution::Unsat
         }
 
         fn is_always_false(&mut self, node: &NodeRef) -> bool {
             let bv = self.visit(node).into_bv();
-            self.solve_impl(bv) == SMTSolution::Unsat
+            self.solve_impl(bv, None) == SMTSolution::Unsat
         }
 
         fn is_always_equal(&mut self, left: &NodeRef, right: &NodeRef) -> bool {
             let bv_left = self.visit(left).into_bv();
             let bv_right = self.visit(right).into_bv();
             let bv = bv_left._ne(&bv_right);
-            self.solve_impl(bv) == SMTSolution::Unsat
+            self.solve_impl(bv, None) == SMTSolution::Unsat
         }
 
         fn solve(&mut self, root: &NodeRef) -> SMTSolution {
             let bv = self.visit(root).into_bv();
-            self.solve_impl(bv.slice(0, 0))
+            self.solve_impl(bv.slice(0, 0), Some(root))
+        }
+
+        fn push(&mut self) {
+            self.solver.push(1);
+        }
+
+        fn pop(&mut self) {
+            self.solver.pop(1);
+        }
+
+        fn assert_false(&mut self, node: &NodeRef) {
+            self.visit(node).into_bv().not().assert();
         }
     }
 
     impl BoolectorSolver {
-        fn solve_impl(&mut self, bv: BitVectorRef) -> SMTSolution {
+        // Inputs of the given root are only collected for a witness, which
+        // avoids walking the whole (e.g. unrolled) graph for each query.
+        fn solve_impl(&mut self, bv: BitVectorRef, root: Option<&NodeRef>) -> SMTSolution {
             self.solver.push(1);
             bv.assert();
             let solution = match self.solver.sat() {
-                SolverResult::Sat => SMTSolution::Sat,
+                SolverResult::Sat => SMTSolution::Sat(self.witness(root)),
                 SolverResult::Unsat => SMTSolution::Unsat,
                 SolverResult::Unknown => SMTSolution::Timeout,
             };
@@ -149,6 +183,18 @@ pub mod boolector_impl {
             solution
         }
 
+        // Needs to be called before the assertion context is popped again, the
+        // model is only available right after a satisfiable `sat()` call.
+        fn witness(&mut self, root: Option<&NodeRef>) -> Witness {
+            let mut witness = Witness::new();
+            for input in root.map(input_variables).unwrap_or_default() {
+                let bv = self.visit(&input).into_bv();
+                let value = bv.get_a_solution().as_u64().expect("fits in u64");
+                witness.assign(&input, value);
+            }
+            witness
+        }
+
         fn visit(&mut self, node: &NodeRef) -> BoolectorValue {
             let key = HashableNodeRef::from(node.clone());
             self.mapping.get(&key).cloned().unwrap_or_else(|| {
@@ -313,12 +359,12 @@ pub mod boolector_impl {
 // TODO: Move this module into separate file.
 #[cfg(feature = "z3")]
 pub mod z3solver_impl {
-    use crate::unicorn::smt_solver::{SMTSolution, SMTSolver};
+    use crate::unicorn::smt_solver::{SMTOptions, SMTSolution, SMTSolver};
+    use crate::unicorn::witness::{input_variables, Witness};
     use crate::unicorn::{HashableNodeRef, Node, NodeRef, NodeType};
     use log::debug;
     use std::collections::HashMap;
     use std::convert::TryInto;
-    use std::time::Duration;
     use z3_solver::{
         ast::{Array, Ast, Bool, Dynamic, BV},
         Config, Context, SatResult, Solver as Z3Solver, Sort,
@@ -337,9 +383,9 @@ pub mod z3solver_impl {
             "Z3"
         }
 
-        fn new(timeout: Option<Duration>) -> Self {
+        fn new(options: &SMTOptions) -> Self {
             let mut config = Config::new();
-            if let Some(duration) = timeout {
+            if let Some(duration) = options.timeout {
                 config.set_timeout_msec(duration.as_millis().try_into().expect("fits in u64"));
             }
             let context = Context::new(&config);
@@ -356,33 +402,48 @@ pub mod z3solver_impl {
 
         fn is_always_true(&mut self, node: &NodeRef) -> bool {
             let z3_bool = self.visit(node).as_bool().expect("bool").not();
-            self.solve_impl(&z3_bool) == SMTSolution::Unsat
+            self.solve_impl(&z3_bool, None) == SMTSolution::Unsat
         }
 
         fn is_always_false(&mut self, node: &NodeRef) -> bool {
             let z3_bool = self.visit(node).as_bool().expect("bool");
-            self.solve_impl(&z3_bool) == SMTSolution::Unsat
+            self.solve_impl(&z3_bool, None) == SMTSolution::Unsat
         }
 
         fn is_always_equal(&mut self, left: &NodeRef, right: &NodeRef) -> bool {
             let z3_left = Dynamic::from_ast(self.visit(left));
             let z3_right = Dynamic::from_ast(self.visit(right));
             let z3_bool = z3_left._eq(&z3_right).not();
-            self.solve_impl(&z3_bool) == SMTSolution::Unsat
+            self.solve_impl(&z3_bool, None) == SMTSolution::Unsat
         }
 
         fn solve(&mut self, root: &NodeRef) -> SMTSolution {
             let z3_bool = self.visit(root).as_bool().expect("bool");
-            self.solve_impl(&z3_bool)
+            self.solve_impl(&z3_bool, Some(root))
+        }
+
+        fn push(&mut self) {
+            self.solver.push();
+        }
+
+        fn pop(&mut self) {
+            self.solver.pop(1);
+        }
+
+        fn assert_false(&mut self, node: &NodeRef) {
+            let z3_bool = self.visit(node).as_bool().expect("bool").not();
+            self.solver.assert(&z3_bool);
         }
     }
 
     impl<'ctx> Z3SolverWrapper<'ctx> {
-        fn solve_impl(&mut self, z3_bool: &Bool<'ctx>) -> SMTSolution {
+        // Inputs of the given root are only collected for a witness, which
+        // avoids walking the whole (e.g. unrolled) graph for each query.
+        fn solve_impl(&mut self, z3_bool: &Bool<'ctx>, root: Option<&NodeRef>) -> SMTSolution {
             self.solver.push();
             self.solver.assert(z3_bool);
             let solution = match self.solver.check() {
-                SatResult::Sat => SMTSolution::Sat,
+                SatResult::Sat => SMTSolution::Sat(self.witness(root)),
                 SatResult::Unsat => SMTSolution::Unsat,
                 SatResult::Unknown => SMTSolution::Timeout,
             };
@@ -393,6 +454,26 @@ pub mod z3solver_impl {
             solution
         }
 
+        // Needs to be called before the assertion context is popped again, the
+        // model is only available right after a satisfiable `check()` call.
+        fn witness(&mut self, root: Option<&NodeRef>) -> Witness {
+            let mut witness = Witness::new();
+            let model = self.solver.get_model().expect("model after sat");
+            for input in root.map(input_variables).unwrap_or_default() {
+                let z3_input = self.visit(&input);
+                let value = if let Some(z3_bool) = z3_input.as_bool() {
+                    let z3_value = model.eval(&z3_bool, true).expect("evaluates");
+                    z3_value.as_bool().expect("concrete") as u64
+                } else {
+                    let z3_bv = z3_input.as_bv().expect("bv");
+                    let z3_value = model.eval(&z3_bv, true).expect("evaluates");
+                    z3_value.as_u64().expect("fits in u64")
+                };
+                witness.assign(&input, value);
+            }
+            witness
+        }
+
         fn visit(&mut self, node: &NodeRef) -> &Dynamic<'ctx> {
             let key = HashableNodeRef::from(node.clone());
             if !self.mapping.contains_key(&key) {
@@ -545,3 +626,574 @@ pub mod z3solver_impl {
         }
     }
 }
+
+// TODO: Move this module into separate file.
+pub mod external_impl {
+    use crate::unicorn::smt2_printer::{smt2_sort, smt2_symbol, smt2_term, Smt2Term};
+    use crate::unicorn::smt_solver::{SMTOptions, SMTSolution, SMTSolver};
+    use crate::unicorn::witness::{input_variables, Witness};
+    use crate::unicorn::{HashableNodeRef, NodeRef};
+    use anyhow::{anyhow, Context, Result};
+    use log::{debug, warn};
+    use std::collections::{HashMap, HashSet};
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Child, ChildStdin, Command, Stdio};
+    use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Talks SMT-LIB to a solver process over its standard input and output.
+    // Timeouts are enforced on our side, by restarting the solver process
+    // once a query takes too long, which works the same for all solvers. A
+    // solver that cannot be started (or fails) leaves its queries unknown.
+    pub struct ExternalSolver {
+        command: String,
+        timeout: Option<Duration>,
+        process: Option<Process>,
+        mapping: HashMap<HashableNodeRef, String>,
+        declared: HashSet<String>,
+        scopes: Vec<Scope>,
+        current_id: usize,
+    }
+
+    // Definitions and assertions made on one assertion level, the former are
+    // forgotten by the solver once the level is popped. Assertions are kept to
+    // replay them after a restart.
+    #[derive(Default)]
+    struct Scope {
+        defined: Vec<HashableNodeRef>,
+        declared: Vec<String>,
+        assertions: Vec<NodeRef>,
+    }
+
+    struct Process {
+        child: Child,
+        stdin: ChildStdin,
+        responses: Receiver<String>,
+    }
+
+    impl SMTSolver for ExternalSolver {
+        fn name() -> &'static str {
+            "External"
+        }
+
+        fn new(options: &SMTOptions) -> Self {
+            let command = options.command.clone().unwrap_or_default();
+            let process = start(&command);
+            let mut solver = Self {
+                command,
+                timeout: options.timeout,
+                process,
+                mapping: HashMap::new(),
+                declared: HashSet::new(),
+                scopes: vec![Scope::default()],
+                current_id: 0,
+            };
+            solver.initialize();
+            solver
+        }
+
+        fn is_always_true(&mut self, node: &NodeRef) -> bool {
+            let term = format!("(not {})", self.visit(node));
+            self.solve_impl(&term, None) == SMTSolution::Unsat
+        }
+
+        fn is_always_false(&mut self, node: &NodeRef) -> bool {
+            let term = self.visit(node);
+            self.solve_impl(&term, None) == SMTSolution::Unsat
+        }
+
+        fn is_always_equal(&mut self, left: &NodeRef, right: &NodeRef) -> bool {
+            let term = format!("(not (= {} {}))", self.visit(left), self.visit(right));
+            self.solve_impl(&term, None) == SMTSolution::Unsat
+        }
+
+        fn solve(&mut self, root: &NodeRef) -> SMTSolution {
+            let term = self.visit(root);
+            self.solve_impl(&term, Some(root))
+        }
+
+        fn push(&mut self) {
+            self.send("(push 1)");
+            self.scopes.push(Scope::default());
+        }
+
+        fn pop(&mut self) {
+            assert!(self.scopes.len() > 1, "no assertion level to pop");
+            self.send("(pop 1)");
+            let scope = self.scopes.pop().expect("pushed scope");
+            for key in scope.defined {
+                self.mapping.remove(&key);
+            }
+            for symbol in scope.declared {
+                self.declared.remove(&symbol);
+            }
+        }
+
+        fn assert_false(&mut self, node: &NodeRef) {
+            let term = self.visit(node);
+            self.send(&format!("(assert (not {}))", term));
+            let scope = self.scopes.last_mut().expect("base scope");
+            scope.assertions.push(node.clone());
+        }
+    }
+
+    impl Drop for Process {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    fn start(command: &str) -> Option<Process> {
+        spawn(command)
+            .map_err(|e| warn!("{:#}, its queries remain unknown", e))
+            .ok()
+    }
+
+    fn spawn(command: &str) -> Result<Process> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| anyhow!("SMT solver command is empty"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to start SMT solver '{}'", command))?;
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = child.stdout.take().expect("piped stdout");
+        // Responses are read on a separate thread, so that waiting for them
+        // can be given up on after the timeout.
+        let (sender, responses) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Process {
+            child,
+            stdin,
+            responses,
+        })
+    }
+
+    impl ExternalSolver {
+        fn initialize(&mut self) {
+            self.send("(set-option :print-success false)");
+            self.send("(set-option :produce-models true)");
+            self.send("(set-logic QF_ABV)");
+        }
+
+        fn restart(&mut self) {
+            self.process = None;
+            self.process = start(&self.command);
+            self.mapping.clear();
+            self.declared.clear();
+            self.initialize();
+            let scopes = std::mem::replace(&mut self.scopes, vec![Scope::default()]);
+            for (level, scope) in scopes.into_iter().enumerate() {
+                if level > 0 {
+                    self.push();
+                }
+                for node in scope.assertions {
+                    self.assert_false(&node);
+                }
+            }
+        }
+
+        // Commands are dropped once the solver is gone, the next query then
+        // restarts it.
+        fn send(&mut self, command: &str) {
+            let result = match &mut self.process {
+                Some(process) => {
+                    writeln!(process.stdin, "{}", command).and_then(|_| process.stdin.flush())
+                }
+                None => return,
+            };
+            if let Err(e) = result {
+                warn!("SMT solver '{}' does not accept input: {}", self.command, e);
+                self.process = None;
+            }
+        }
+
+        // Reads a complete response (e.g. one spanning several lines) until
+        // the given deadline, yields `None` if the deadline is missed, the
+        // solver reports an error or is gone.
+        fn receive(&mut self, deadline: Option<Instant>) -> Option<String> {
+            let mut response = String::new();
+            loop {
+                let responses = &self.process.as_ref()?.responses;
+                let line = match deadline {
+                    Some(deadline) => {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        responses.recv_timeout(remaining)
+                    }
+                    None => responses.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                let line = match line {
+                    Ok(line) => line,
+                    Err(RecvTimeoutError::Timeout) => return None,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        warn!("SMT solver '{}' terminated", self.command);
+                        self.process = None;
+                        return None;
+                    }
+                };
+                response.push_str(line.trim());
+                response.push(' ');
+                let open = response.matches('(').count();
+                let close = response.matches(')').count();
+                if !response.trim().is_empty() && open <= close {
+                    break;
+                }
+            }
+            let response = response.trim().to_string();
+            if response.starts_with("(error") {
+                warn!("SMT solver '{}' reported {}", self.command, response);
+                return None;
+            }
+            Some(response)
+        }
+
+        // Inputs of the given root are only collected for a witness, which
+        // avoids walking the whole (e.g. unrolled) graph for each query.
+        fn solve_impl(&mut self, term: &str, root: Option<&NodeRef>) -> SMTSolution {
+            if self.process.is_none() {
+                return SMTSolution::Timeout;
+            }
+            let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+            self.send("(push 1)");
+            self.send(&format!("(assert {})", term));
+            self.send("(check-sat)");
+            let solution = match self.receive(deadline).as_deref() {
+                Some("sat") => SMTSolution::Sat(self.witness(root)),
+                Some("unsat") => SMTSolution::Unsat,
+                Some(response) => {
+                    if response != "unknown" {
+                        warn!("Unexpected response from SMT solver: {}", response);
+                    }
+                    SMTSolution::Timeout
+                }
+                None => {
+                    self.restart();
+                    return SMTSolution::Timeout;
+                }
+            };
+            self.send("(pop 1)");
+            if solution == SMTSolution::Timeout {
+                debug!("Query timeout was reached by external SMT solver");
+            }
+            solution
+        }
+
+        // Needs to be called before the assertion context is popped again, the
+        // model is only available right after a satisfiable `check-sat`. The
+        // timeout only applies to the query itself, values are awaited.
+        fn witness(&mut self, root: Option<&NodeRef>) -> Witness {
+            let mut witness = Witness::new();
+            for input in root.map(input_variables).unwrap_or_default() {
+                let name = self.visit(&input);
+                self.send(&format!("(get-value ({}))", name));
+                let value = self
+                    .receive(None)
+                    .and_then(|response| parse_value(&response));
+                match value {
+                    Some(value) => witness.assign(&input, value),
+                    None => warn!("No value for {} in model of SMT solver", name),
+                }
+            }
+            witness
+        }
+
+        // The innermost pushed assertion level, definitions on the base level
+        // are never forgotten and need not be tracked.
+        fn pushed_scope(&mut self) -> Option<&mut Scope> {
+            self.scopes.iter_mut().skip(1).last()
+        }
+
+        fn next_id(&mut self) -> String {
+            let id = format!("t{}", self.current_id);
+            self.current_id += 1;
+            id
+        }
+
+        // Defines the given node (and all of its operands) in the solver,
+        // yielding the name it can be referred to by.
+        fn visit(&mut self, node: &NodeRef) -> String {
+            let key = HashableNodeRef::from(node.clone());
+            if let Some(name) = self.mapping.get(&key) {
+                return name.clone();
+            }
+            let (sort, term) = smt2_term(&*node.borrow(), |operand| self.visit(operand));
+            let name = match term {
+                Smt2Term::Variable(name) => {
+                    let symbol = smt2_symbol(&name);
+                    if self.declared.insert(symbol.clone()) {
+                        let sort = smt2_sort(&sort);
+                        self.send(&format!("(declare-const {} {})", symbol, sort));
+                        if let Some(scope) = self.pushed_scope() {
+                            scope.declared.push(symbol.clone());
+                        }
+                    }
+                    symbol
+                }
+                Smt2Term::Expression(term) => {
+                    let id = self.next_id();
+                    let sort = smt2_sort(&sort);
+                    self.send(&format!("(define-fun {} () {} {})", id, sort, term));
+                    id
+                }
+            };
+            if let Some(scope) = self.pushed_scope() {
+                scope.defined.push(key.clone());
+            }
+            self.mapping.insert(key, name.clone());
+            name
+        }
+    }
+
+    // Extracts the value from a `get-value` response for a single variable,
+    // e.g. `((x #b0101))`, `((x #x2a))`, `((x (_ bv42 8)))` or `((x true))`.
+    fn parse_value(response: &str) -> Option<u64> {
+        let pair = response.strip_prefix('(')?.trim_start().strip_prefix('(')?;
+        let value = if let Some(quoted) = pair.strip_prefix('|') {
+            &quoted[quoted.find('|')? + 1..]
+        } else {
+            &pair[pair.find(char::is_whitespace)?..]
+        };
+        let value = value.trim_start();
+        let digits = |s: &str, radix: u32| {
+            let end = s.find(|c: char| !c.is_digit(radix)).unwrap_or(s.len());
+            u64::from_str_radix(&s[..end], radix).ok()
+        };
+        if let Some(bits) = value.strip_prefix("#b") {
+            digits(bits, 2)
+        } else if let Some(hex) = value.strip_prefix("#x") {
+            digits(hex, 16)
+        } else if let Some(decimal) = value.strip_prefix("(_ bv") {
+            digits(decimal, 10)
+        } else if value.starts_with("true") {
+            Some(1)
+        } else if value.starts_with("false") {
+            Some(0)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::external_impl::ExternalSolver;
+    use super::*;
+    use crate::unicorn::{Nid, Node, NodeType};
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use tempfile::NamedTempFile;
+
+    // Stand-in for an SMT solver, answering each query with the given lines.
+    fn stand_in_solver(check_sat: &str, get_value: &str) -> NamedTempFile {
+        let mut script = NamedTempFile::new().unwrap();
+        writeln!(script, "while read -r line; do").unwrap();
+        writeln!(script, "  case \"$line\" in").unwrap();
+        writeln!(script, "    \"(check-sat)\") {} ;;", check_sat).unwrap();
+        writeln!(script, "    \"(get-value\"*) {} ;;", get_value).unwrap();
+        writeln!(script, "  esac").unwrap();
+        writeln!(script, "done").unwrap();
+        script
+    }
+
+    // Stand-in for an SMT solver, logging all commands and answering unsat.
+    fn logging_solver(log: &Path) -> NamedTempFile {
+        let mut script = NamedTempFile::new().unwrap();
+        writeln!(script, "while read -r line; do").unwrap();
+        writeln!(script, "  echo \"$line\" >> {}", log.display()).unwrap();
+        writeln!(
+            script,
+            "  if [ \"$line\" = \"(check-sat)\" ]; then echo unsat; fi"
+        )
+        .unwrap();
+        writeln!(script, "done").unwrap();
+        script
+    }
+
+    fn options(script: &NamedTempFile, timeout: Option<Duration>) -> SMTOptions {
+        SMTOptions {
+            timeout,
+            command: Some(format!("sh {}", script.path().display())),
+        }
+    }
+
+    fn input_x() -> NodeRef {
+        NodeRef::from(Node::Input {
+            nid: 1,
+            sort: NodeType::Input1Byte,
+            name: "x".to_string(),
+        })
+    }
+
+    // Builds the query `input == imm` with fresh nodes above the `input`.
+    fn input_equals(input: &NodeRef, nid: Nid, imm: u64) -> NodeRef {
+        let ext = NodeRef::from(Node::Ext {
+            nid,
+            from: NodeType::Input1Byte,
+            value: input.clone(),
+        });
+        let imm = NodeRef::from(Node::Const {
+            nid: nid + 1,
+            sort: NodeType::Word,
+            imm,
+        });
+        NodeRef::from(Node::Eq {
+            nid: nid + 2,
+            left: ext,
+            right: imm,
+        })
+    }
+
+    fn input_is_42() -> NodeRef {
+        input_equals(&input_x(), 2, 42)
+    }
+
+    #[cfg(any(feature = "boolector", feature = "z3"))]
+    fn is_sat(solution: SMTSolution) -> bool {
+        matches!(solution, SMTSolution::Sat(_))
+    }
+
+    #[cfg(any(feature = "boolector", feature = "z3"))]
+    fn check_push_pop_scopes_assertions<S: SMTSolver>() {
+        let mut solver = S::new(&SMTOptions::default());
+        let node = input_is_42();
+
+        assert!(is_sat(solver.solve(&node)));
+        solver.push();
+        solver.assert_false(&node);
+        assert_eq!(solver.solve(&node), SMTSolution::Unsat);
+        assert!(solver.is_always_false(&node));
+        solver.push();
+        assert_eq!(solver.solve(&node), SMTSolution::Unsat);
+        solver.pop();
+        assert_eq!(solver.solve(&node), SMTSolution::Unsat);
+        solver.pop();
+        assert!(is_sat(solver.solve(&node)));
+        assert!(!solver.is_always_false(&node));
+    }
+
+    // Mirrors the use in `bmc_with_smt`, where unreachable bad states of one
+    // depth are asserted false and later depths build new nodes on top.
+    #[cfg(any(feature = "boolector", feature = "z3"))]
+    fn check_lemmas_persist_across_depths<S: SMTSolver>() {
+        let mut solver = S::new(&SMTOptions::default());
+        let input = input_x();
+        let depth_0 = input_equals(&input, 2, 42);
+
+        assert!(is_sat(solver.solve(&depth_0)));
+        solver.assert_false(&depth_0);
+
+        let depth_1 = input_equals(&input, 5, 42);
+        assert_eq!(solver.solve(&depth_1), SMTSolution::Unsat);
+        solver.assert_false(&input_equals(&input, 8, 43));
+
+        let depth_2 = input_equals(&input, 11, 42);
+        assert_eq!(solver.solve(&depth_2), SMTSolution::Unsat);
+        assert_eq!(solver.solve(&depth_0), SMTSolution::Unsat);
+        assert_eq!(
+            solver.solve(&input_equals(&input, 14, 43)),
+            SMTSolution::Unsat
+        );
+        match solver.solve(&input_equals(&input, 17, 44)) {
+            SMTSolution::Sat(witness) => assert_eq!(witness.value("x"), Some(44)),
+            solution => panic!("unexpected solution {:?}", solution),
+        }
+    }
+
+    #[cfg(feature = "boolector")]
+    #[test]
+    fn boolector_push_pop_scopes_assertions() {
+        check_push_pop_scopes_assertions::<boolector_impl::BoolectorSolver>();
+    }
+
+    #[cfg(feature = "boolector")]
+    #[test]
+    fn boolector_lemmas_persist_across_depths() {
+        check_lemmas_persist_across_depths::<boolector_impl::BoolectorSolver>();
+    }
+
+    #[cfg(feature = "z3")]
+    #[test]
+    fn z3_push_pop_scopes_assertions() {
+        check_push_pop_scopes_assertions::<z3solver_impl::Z3SolverWrapper>();
+    }
+
+    #[cfg(feature = "z3")]
+    #[test]
+    fn z3_lemmas_persist_across_depths() {
+        check_lemmas_persist_across_depths::<z3solver_impl::Z3SolverWrapper>();
+    }
+
+    #[test]
+    fn external_solver_reports_witness() {
+        let script = stand_in_solver("echo sat", "echo '((|x| #b00101010))'");
+        let mut solver = ExternalSolver::new(&options(&script, None));
+        let node = input_is_42();
+
+        assert!(!solver.is_always_false(&node));
+        match solver.solve(&node) {
+            SMTSolution::Sat(witness) => assert_eq!(witness.value("x"), Some(42)),
+            solution => panic!("unexpected solution {:?}", solution),
+        }
+    }
+
+    #[test]
+    fn external_solver_respects_timeout() {
+        let script = stand_in_solver("exec sleep 10", "true");
+        let timeout = Some(Duration::from_millis(100));
+        let mut solver = ExternalSolver::new(&options(&script, timeout));
+
+        assert_eq!(solver.solve(&input_is_42()), SMTSolution::Timeout);
+    }
+
+    #[test]
+    fn external_solver_forgets_definitions_of_popped_levels() {
+        let log = NamedTempFile::new().unwrap();
+        let script = logging_solver(log.path());
+        let mut solver = ExternalSolver::new(&options(&script, None));
+        let node = input_is_42();
+
+        solver.push();
+        solver.assert_false(&node);
+        assert_eq!(solver.solve(&node), SMTSolution::Unsat);
+        solver.pop();
+        assert_eq!(solver.solve(&node), SMTSolution::Unsat);
+
+        let commands = fs::read_to_string(log.path()).unwrap();
+        assert!(commands.contains("(assert (not t2))"));
+        assert_eq!(
+            commands.matches("(declare-const |x| (_ BitVec 8))").count(),
+            2
+        );
+    }
+
+    #[test]
+    fn external_solver_failures_are_unknown() {
+        let missing = SMTOptions {
+            timeout: None,
+            command: Some("/nonexistent/smt-solver".to_string()),
+        };
+        let mut solver = ExternalSolver::new(&missing);
+        assert_eq!(solver.solve(&input_is_42()), SMTSolution::Timeout);
+
+        let script = stand_in_solver("echo '(error \"unsupported\")'", "true");
+        let mut solver = ExternalSolver::new(&options(&script, None));
+        assert_eq!(solver.solve(&input_is_42()), SMTSolution::Timeout);
+
+        let script = stand_in_solver("exit 1", "true");
+        let mut solver = ExternalSolver::new(&options(&script, None));
+        assert_eq!(solver.solve(&input_is_42()), SMTSolution::Timeout);
+    }
+}
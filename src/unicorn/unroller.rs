@@ -1,4 +1,4 @@
-use crate::unicorn::{HashableNodeRef, Model, Nid, Node, NodeRef};
+use crate::unicorn::{get_nid, HashableNodeRef, Model, Nid, Node, NodeRef};
 use log::debug;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -40,7 +40,55 @@ pub fn prune_model(model: &mut Model) {
     model.bad_states_sequential.clear();
 }
 
+// Copies created by unrolling are named `<name>[n=<depth>]`, this splits such
+// a name into the original name and the depth of the copy.
+pub fn split_unrolled_name(name: &str) -> (&str, Option<usize>) {
+    if let Some(prefix) = name.strip_suffix(']') {
+        if let Some((origin, depth)) = prefix.rsplit_once("[n=") {
+            if let Ok(depth) = depth.parse::<usize>() {
+                return (origin, Some(depth));
+            }
+        }
+    }
+    (name, None)
+}
+
+// Unrolls a single step without modifying the model, starting from the given
+// values of the states in `model.sequentials`. Yields the values of these
+// states after the step and copies of all sequential bad states, named like
+// the ones created by `unroll_model`.
+pub fn unroll_step(model: &Model, frame: &[NodeRef], n: usize) -> (Vec<NodeRef>, Vec<NodeRef>) {
+    assert_eq!(frame.len(), model.sequentials.len());
+    let mut model_unroller = ModelUnroller::new(n);
+    for (sequential, value) in model.sequentials.iter().zip(frame) {
+        if let Node::Next { state, .. } = &*sequential.borrow() {
+            let key = HashableNodeRef::from(state.clone());
+            model_unroller.copies.insert(key, value.clone());
+        } else {
+            panic!("expecting 'Next' node here");
+        }
+    }
+    let mut next_frame = Vec::new();
+    for sequential in &model.sequentials {
+        if let Node::Next { next, .. } = &*sequential.borrow() {
+            next_frame.push(model_unroller.unroll(next));
+        }
+    }
+    let bad_states = model
+        .bad_states_sequential
+        .iter()
+        .map(|bad_state| model_unroller.unroll(bad_state))
+        .collect();
+    (next_frame, bad_states)
+}
+
 pub fn renumber_model(model: &mut Model) {
+    renumber_model_undoable(model);
+}
+
+// Same as above, but yields the previous nid of each renumbered node, nodes
+// shared with another model can be restored with `restore_nids` afterwards.
+pub fn renumber_model_undoable(model: &mut Model) -> Vec<(NodeRef, Nid)> {
     debug!("Renumbering nodes in unrolled model ...");
     let mut model_renumberer = ModelRenumberer::new();
     let s = "Model was renumbered, it will be hard to read.";
@@ -56,16 +104,42 @@ pub fn renumber_model(model: &mut Model) {
         model_renumberer.visit(bad_state)
     }
     model.lines = model_renumberer.lines;
+    model_renumberer.previous_nids
+}
+
+pub fn restore_nids(previous_nids: Vec<(NodeRef, Nid)>) {
+    for (node, nid) in previous_nids {
+        set_nid(&node, nid);
+    }
 }
 
 //
 // Private Implementation
 //
 
+#[rustfmt::skip]
+fn set_nid(node: &NodeRef, new_nid: Nid) {
+    match *node.borrow_mut() {
+        Node::Const { ref mut nid, .. } | Node::Read { ref mut nid, .. } |
+        Node::Write { ref mut nid, .. } | Node::Add { ref mut nid, .. } |
+        Node::Sub { ref mut nid, .. } | Node::Mul { ref mut nid, .. } |
+        Node::Divu { ref mut nid, .. } | Node::Div { ref mut nid, .. } |
+        Node::Rem { ref mut nid, .. } | Node::Sll { ref mut nid, .. } |
+        Node::Srl { ref mut nid, .. } | Node::Ult { ref mut nid, .. } |
+        Node::Ext { ref mut nid, .. } | Node::Ite { ref mut nid, .. } |
+        Node::Eq { ref mut nid, .. } | Node::And { ref mut nid, .. } |
+        Node::Not { ref mut nid, .. } | Node::State { ref mut nid, .. } |
+        Node::Next { ref mut nid, .. } | Node::Input { ref mut nid, .. } |
+        Node::Bad { ref mut nid, .. } | Node::Or { ref mut nid, .. } => *nid = new_nid,
+        Node::Comment(_) => panic!("has no nid"),
+    }
+}
+
 struct ModelRenumberer {
     current_nid: Nid,
     marks: HashSet<HashableNodeRef>,
     lines: Vec<NodeRef>,
+    previous_nids: Vec<(NodeRef, Nid)>,
 }
 
 impl ModelRenumberer {
@@ -74,6 +148,7 @@ impl ModelRenumberer {
             current_nid: 10000000,
             marks: HashSet::new(),
             lines: Vec::new(),
+            previous_nids: Vec::new(),
         }
     }
 
@@ -89,6 +164,7 @@ impl ModelRenumberer {
     fn visit(&mut self, node: &NodeRef) {
         let key = HashableNodeRef::from(node.clone());
         if !self.marks.contains(&key) {
+            self.previous_nids.push((node.clone(), get_nid(node)));
             self.process(node);
             self.add_line(node);
             self.marks.insert(key);
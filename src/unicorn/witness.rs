@@ -0,0 +1,513 @@
+use crate::unicorn::simulator::{declarations, declared_input_widths, evaluate, inputs_read};
+use crate::unicorn::unroller::split_unrolled_name;
+use crate::unicorn::{HashableNodeRef, Model, Node, NodeRef, NodeType};
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+//
+// Public Interface
+//
+
+/// Concrete assignment to the input variables of a model that makes a
+/// query satisfiable, as reported by an SMT or SAT solver.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Witness {
+    assignments: BTreeMap<String, u64>,
+    bad_state: Option<HashableNodeRef>,
+}
+
+impl Witness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn for_bad_state(mut self, bad_state: &NodeRef) -> Self {
+        self.bad_state = Some(HashableNodeRef::from(bad_state.clone()));
+        self
+    }
+
+    pub fn belongs_to_bad_state(&self) -> bool {
+        self.bad_state.is_some()
+    }
+
+    pub fn assign(&mut self, input: &NodeRef, value: u64) {
+        self.assignments.insert(input_name(input), value);
+    }
+
+    pub fn value(&self, name: &str) -> Option<u64> {
+        self.assignments.get(name).copied()
+    }
+
+    // Assignments by name, other than the witness itself these can be sent
+    // to other threads.
+    pub fn from_assignments<I>(assignments: I) -> Self
+    where
+        I: IntoIterator<Item = (String, u64)>,
+    {
+        Self {
+            assignments: assignments.into_iter().collect(),
+            bad_state: None,
+        }
+    }
+
+    pub fn assignments(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.assignments.iter().map(|(n, v)| (n.as_str(), *v))
+    }
+
+    // Whether the given bad state of a combinational (e.g. unrolled) model
+    // evaluates to true under this witness, unassigned inputs are zero.
+    pub fn reaches(&self, bad_state: &NodeRef) -> bool {
+        let value = |input: &NodeRef| self.value(&input_name(input)).unwrap_or(0);
+        evaluate(bad_state, value) != 0
+    }
+
+    // The `read` system call consumes input in chunks of at most 8 bytes per
+    // unrolling step, each modeled as a `<k>-byte-input[n=<step>]` input. We
+    // reassemble the byte sequence by concatenating the chunks in step order,
+    // least significant byte first (the order in which they are stored).
+    pub fn stdin_bytes(&self) -> Vec<u8> {
+        let regex = Regex::new(r"^([1-8])-byte-input(?:\[n=(\d+)\])?$").unwrap();
+        let mut chunks: BTreeMap<usize, Vec<(usize, u64, &str)>> = BTreeMap::new();
+        for (name, value) in self.assignments() {
+            if let Some(captures) = regex.captures(name) {
+                let size = captures[1].parse::<usize>().expect("valid chunk size");
+                let step = captures
+                    .get(2)
+                    .map_or(0, |s| s.as_str().parse::<usize>().expect("valid step"));
+                chunks.entry(step).or_default().push((size, value, name));
+            }
+        }
+        let read = if chunks.values().any(|candidates| candidates.len() > 1) {
+            self.read_inputs()
+        } else {
+            HashSet::new()
+        };
+        let mut bytes = Vec::new();
+        for (step, candidates) in chunks {
+            // Several chunk sizes survive for the same step when the number of
+            // bytes to be read is not constant. The width of the chunk is then
+            // given by the one that is read on the way to the bad state, steps
+            // without such a read did not consume any input.
+            let mut selected: Vec<_> = if candidates.len() == 1 {
+                candidates.iter().collect()
+            } else if self.bad_state.is_some() {
+                let read_candidates: Vec<_> = candidates
+                    .iter()
+                    .filter(|(_, _, name)| read.contains(*name))
+                    .collect();
+                if read_candidates.is_empty() {
+                    continue;
+                }
+                read_candidates
+            } else {
+                candidates.iter().collect()
+            };
+            if selected.len() != 1 {
+                selected.sort_unstable();
+                warn!(
+                    "Ambiguous input chunk at step {}, assuming {} bytes were read",
+                    step, selected[0].0
+                );
+            }
+            let &(size, value, _) = selected[0];
+            bytes.extend_from_slice(&value.to_le_bytes()[..size]);
+        }
+        bytes
+    }
+
+    pub fn write_stdin_file(&self, path: &Path) -> Result<()> {
+        for (name, value) in self.assignments() {
+            debug!("Witness assigns {:#x} to '{}'", value, name);
+        }
+        let bytes = self.stdin_bytes();
+        if bytes.is_empty() {
+            warn!("Witness does not contain any bytes read from stdin");
+        }
+        let mut file = File::create(path)
+            .with_context(|| format!("Failed to create witness file {:?}", path))?;
+        file.write_all(&bytes)
+            .with_context(|| format!("Failed to write witness file {:?}", path))
+    }
+
+    // Writes the witness in the format understood by `btormc` and `btorsim`
+    // for the sequential model as it is printed by `write_model`, i.e. before
+    // unrolling and after renumbering. States and inputs are identified by their position in the
+    // printed model, the inputs of each unrolled step `[n=<k>]` make up the
+    // frame `@<k>` of the trace leading to the bad state.
+    pub fn write_btor2_witness<W>(&self, model: &Model, mut out: W) -> Result<()>
+    where
+        W: Write,
+    {
+        let bad_state = self
+            .bad_state
+            .as_ref()
+            .ok_or_else(|| anyhow!("Witness does not belong to a bad state"))?;
+        let unrolled_name = match &*bad_state.value.borrow() {
+            Node::Bad { name, .. } => name.clone().unwrap_or_default(),
+            _ => panic!("expecting 'Bad' node here"),
+        };
+        let (bad_state_name, depth) = split_unrolled_name(&unrolled_name);
+        let (states, inputs, bad_states) = declarations(model);
+        let input_widths = declared_input_widths(model);
+        let bad_state_index = bad_states
+            .iter()
+            .position(|bad_state| match &*bad_state.borrow() {
+                Node::Bad { name, .. } => name.as_deref() == Some(bad_state_name),
+                _ => false,
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "Witness does not belong to a bad state of the model, '{}' is missing",
+                    bad_state_name
+                )
+            })?;
+        writeln!(out, "sat")?;
+        writeln!(out, "b{}", bad_state_index)?;
+        writeln!(out, "#0")?;
+        for (index, state) in states.iter().enumerate() {
+            if let Node::State {
+                init: None,
+                sort,
+                name,
+                ..
+            } = &*state.borrow()
+            {
+                // TODO: Assignments to memory (array) states are not supported.
+                if *sort != NodeType::Memory {
+                    let name = name.as_deref().unwrap_or("?");
+                    let value = self.value(name).unwrap_or(0);
+                    let width = sort.bitsize();
+                    write_btor2_assignment(&mut out, index, width, value, name, 0)?;
+                }
+            }
+        }
+        for step in 0..=depth.unwrap_or(0) {
+            writeln!(out, "@{}", step)?;
+            for (index, input) in inputs.iter().enumerate() {
+                if let Node::Input { sort, name, .. } = &*input.borrow() {
+                    let key = HashableNodeRef::from(input.clone());
+                    let width = input_widths.get(&key).copied().unwrap_or(sort.bitsize());
+                    let value = self.value(&format!("{}[n={}]", name, step)).unwrap_or(0);
+                    write_btor2_assignment(&mut out, index, width, value, name, step)?;
+                }
+            }
+        }
+        writeln!(out, ".")?;
+        Ok(())
+    }
+}
+
+/// Collects all input variables in the cone of influence of the given node,
+/// i.e. `Input` nodes as well as uninitialized (non-memory) `State` nodes,
+/// which is how the unroller represents inputs of a specific step.
+pub fn input_variables(root: &NodeRef) -> Vec<NodeRef> {
+    let mut inputs = Vec::new();
+    let mut marks = HashSet::new();
+    let mut stack = vec![root.clone()];
+    while let Some(node) = stack.pop() {
+        if !marks.insert(HashableNodeRef::from(node.clone())) {
+            continue;
+        }
+        match &*node.borrow() {
+            Node::Input { .. } => inputs.push(node.clone()),
+            Node::State {
+                init: None, sort, ..
+            } if *sort != NodeType::Memory => inputs.push(node.clone()),
+            Node::Const { .. } | Node::State { .. } | Node::Comment(_) => (),
+            Node::Ext { value, .. } | Node::Not { value, .. } => stack.push(value.clone()),
+            Node::Bad { cond, .. } => stack.push(cond.clone()),
+            Node::Read {
+                memory, address, ..
+            } => {
+                stack.push(memory.clone());
+                stack.push(address.clone());
+            }
+            Node::Write {
+                memory,
+                address,
+                value,
+                ..
+            } => {
+                stack.push(memory.clone());
+                stack.push(address.clone());
+                stack.push(value.clone());
+            }
+            Node::Ite {
+                cond, left, right, ..
+            } => {
+                stack.push(cond.clone());
+                stack.push(left.clone());
+                stack.push(right.clone());
+            }
+            Node::Next { state, next, .. } => {
+                stack.push(state.clone());
+                stack.push(next.clone());
+            }
+            Node::Add { left, right, .. }
+            | Node::Sub { left, right, .. }
+            | Node::Mul { left, right, .. }
+            | Node::Divu { left, right, .. }
+            | Node::Div { left, right, .. }
+            | Node::Rem { left, right, .. }
+            | Node::Sll { left, right, .. }
+            | Node::Srl { left, right, .. }
+            | Node::Ult { left, right, .. }
+            | Node::Eq { left, right, .. }
+            | Node::And { left, right, .. }
+            | Node::Or { left, right, .. } => {
+                stack.push(left.clone());
+                stack.push(right.clone());
+            }
+        }
+    }
+    inputs
+}
+
+//
+// Private Implementation
+//
+
+impl Witness {
+    // Names of all inputs that are read when evaluating the bad state under
+    // this witness, which requires the witness to belong to a bad state.
+    fn read_inputs(&self) -> HashSet<String> {
+        match &self.bad_state {
+            Some(bad_state) => {
+                let value = |input: &NodeRef| self.value(&input_name(input)).unwrap_or(0);
+                inputs_read(&bad_state.value, value)
+                    .iter()
+                    .map(input_name)
+                    .collect()
+            }
+            None => HashSet::new(),
+        }
+    }
+}
+
+fn write_btor2_assignment<W>(
+    out: &mut W,
+    index: usize,
+    width: usize,
+    value: u64,
+    name: &str,
+    step: usize,
+) -> Result<()>
+where
+    W: Write,
+{
+    write!(out, "{} {:0width$b}", index, value, width = width)?;
+    // Symbols are optional and must not contain whitespace.
+    if name.contains(char::is_whitespace) {
+        writeln!(out)?;
+    } else {
+        writeln!(out, " {}@{}", name, step)?;
+    }
+    Ok(())
+}
+
+fn input_name(input: &NodeRef) -> String {
+    match &*input.borrow() {
+        Node::Input { name, .. } => name.clone(),
+        Node::State { name, .. } => name.as_deref().unwrap_or("?").to_string(),
+        _ => panic!("expecting 'Input' or 'State' node here"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unicorn::btor2file_parser::parse_btor2_text;
+    use crate::unicorn::simulator::check_btor2_witness;
+    use crate::unicorn::unroller::renumber_model;
+
+    fn input(name: &str, sort: NodeType) -> NodeRef {
+        NodeRef::from(Node::State {
+            nid: 0,
+            sort,
+            init: None,
+            name: Some(name.to_string()),
+        })
+    }
+
+    #[test]
+    fn reassembles_chunks_in_step_order() {
+        let mut witness = Witness::new();
+        witness.assign(&input("2-byte-input[n=7]", NodeType::Input2Byte), 0x2120);
+        witness.assign(
+            &input("8-byte-input[n=3]", NodeType::Word),
+            0x6867666564636261,
+        );
+        witness.assign(&input("other[n=1]", NodeType::Word), 42);
+
+        assert_eq!(witness.stdin_bytes(), b"abcdefgh !".to_vec());
+    }
+
+    #[test]
+    fn picks_chunk_read_by_bad_state() {
+        let count = input("count", NodeType::Word);
+        let byte = input("1-byte-input[n=0]", NodeType::Input1Byte);
+        let word = input("8-byte-input[n=0]", NodeType::Word);
+        let half = input("4-byte-input[n=1]", NodeType::Input4Byte);
+        let eq = NodeRef::from(Node::Eq {
+            nid: 1,
+            left: count.clone(),
+            right: NodeRef::from(Node::Const {
+                nid: 2,
+                sort: NodeType::Word,
+                imm: 8,
+            }),
+        });
+        let ite = NodeRef::from(Node::Ite {
+            nid: 3,
+            sort: NodeType::Word,
+            cond: eq,
+            left: word.clone(),
+            right: byte.clone(),
+        });
+        let add = NodeRef::from(Node::Add {
+            nid: 4,
+            left: ite,
+            right: half.clone(),
+        });
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 5,
+            cond: add,
+            name: None,
+        });
+
+        let mut witness = Witness::new();
+        witness.assign(&count, 1);
+        witness.assign(&byte, 0x61);
+        witness.assign(&word, 0x6867666564636261);
+        witness.assign(&half, 0x64636261);
+        let witness = witness.for_bad_state(&bad_state);
+
+        assert_eq!(witness.stdin_bytes(), b"aabcd".to_vec());
+    }
+
+    #[test]
+    fn skips_step_without_read_chunk() {
+        let first = input("1-byte-input[n=0]", NodeType::Input1Byte);
+        let byte = input("1-byte-input[n=1]", NodeType::Input1Byte);
+        let word = input("8-byte-input[n=1]", NodeType::Word);
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 1,
+            cond: first.clone(),
+            name: None,
+        });
+
+        let mut witness = Witness::new();
+        witness.assign(&first, 0x61);
+        witness.assign(&byte, 0x62);
+        witness.assign(&word, 0x6362);
+        let witness = witness.for_bad_state(&bad_state);
+
+        assert_eq!(witness.stdin_bytes(), b"a".to_vec());
+    }
+
+    #[test]
+    fn collects_uninitialized_states_only() {
+        let initialized = NodeRef::from(Node::State {
+            nid: 0,
+            sort: NodeType::Word,
+            init: Some(NodeRef::from(Node::Const {
+                nid: 1,
+                sort: NodeType::Word,
+                imm: 0,
+            })),
+            name: Some("x".to_string()),
+        });
+        let byte = input("1-byte-input[n=0]", NodeType::Input1Byte);
+        let ext = NodeRef::from(Node::Ext {
+            nid: 2,
+            from: NodeType::Input1Byte,
+            value: byte,
+        });
+        let add = NodeRef::from(Node::Add {
+            nid: 3,
+            left: ext,
+            right: initialized,
+        });
+
+        let inputs = input_variables(&add);
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(input_name(&inputs[0]), "1-byte-input[n=0]");
+    }
+
+    #[test]
+    fn writes_btor2_witness_for_bad_state() {
+        let byte = NodeRef::from(Node::Input {
+            nid: 1,
+            sort: NodeType::Input1Byte,
+            name: "1-byte-input".to_string(),
+        });
+        let other = NodeRef::from(Node::Input {
+            nid: 2,
+            sort: NodeType::Bit,
+            name: "other input".to_string(),
+        });
+        let bad_state = NodeRef::from(Node::Bad {
+            nid: 3,
+            cond: other.clone(),
+            name: Some("bad".to_string()),
+        });
+        let model = Model {
+            lines: vec![byte, other, bad_state.clone()],
+            sequentials: vec![],
+            bad_states_initial: vec![],
+            bad_states_sequential: vec![bad_state],
+            data_range: 0..0,
+            heap_range: 0..0,
+            stack_range: 0..0,
+            memory_size: 0,
+        };
+        let unrolled_bad_state = NodeRef::from(Node::Bad {
+            nid: 0,
+            cond: input("other input[n=1]", NodeType::Bit),
+            name: Some("bad[n=1]".to_string()),
+        });
+
+        let mut witness = Witness::new();
+        witness.assign(&input("1-byte-input[n=0]", NodeType::Input1Byte), 42);
+        witness.assign(&input("1-byte-input[n=1]", NodeType::Input1Byte), 7);
+        witness.assign(&input("other input[n=1]", NodeType::Bit), 1);
+        let witness = witness.for_bad_state(&unrolled_bad_state);
+        let mut out = Vec::new();
+        witness.write_btor2_witness(&model, &mut out).unwrap();
+
+        let expected = concat!(
+            "sat\nb0\n#0\n",
+            "@0\n0 00101010 1-byte-input@0\n1 0\n",
+            "@1\n0 00000111 1-byte-input@1\n1 1\n",
+            ".\n"
+        );
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), expected);
+        let (_, step) = check_btor2_witness(&model, out.as_slice()).unwrap();
+        assert_eq!(step, 1);
+    }
+
+    #[test]
+    fn writes_narrow_inputs_at_declared_width() {
+        let file =
+            "1 sort bitvec 5\n2 input 1 x\n3 sort bitvec 1\n4 constd 1 3\n5 eq 3 2 4\n6 bad 5 b\n";
+        let mut model = parse_btor2_text(file).unwrap();
+        renumber_model(&mut model);
+        let bad_state = model.bad_states_sequential[0].clone();
+
+        let mut witness = Witness::new();
+        witness.assign(&input("x[n=0]", NodeType::Word), 3);
+        let witness = witness.for_bad_state(&bad_state);
+        let mut out = Vec::new();
+        witness.write_btor2_witness(&model, &mut out).unwrap();
+
+        let expected = "sat\nb0\n#0\n@0\n0 00011 x@0\n.\n";
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), expected);
+        let (_, step) = check_btor2_witness(&model, out.as_slice()).unwrap();
+        assert_eq!(step, 0);
+    }
+}
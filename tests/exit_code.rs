@@ -0,0 +1,32 @@
+use std::process::Command;
+
+// Runs `beator` on a model with a statically reachable bad state.
+fn beator_exit_code(extra_args: &[&str]) -> Option<i32> {
+    let status = Command::new(env!("CARGO_BIN_EXE_unicorn"))
+        .args([
+            "beator",
+            "--from-btor2",
+            "tests/unit_models/bad.btor2",
+            "-u",
+            "1",
+        ])
+        .args(extra_args)
+        .status()
+        .expect("unicorn can be run");
+    status.code()
+}
+
+#[test]
+fn exits_with_10_only_when_terminating_on_bad_states() {
+    assert_eq!(beator_exit_code(&[]), Some(0));
+    assert_eq!(beator_exit_code(&["--terminate-on-bad"]), Some(10));
+}
+
+#[test]
+fn one_query_exits_with_10_only_when_terminating_on_bad_states() {
+    assert_eq!(beator_exit_code(&["--one-query"]), Some(0));
+    assert_eq!(
+        beator_exit_code(&["--one-query", "--terminate-on-bad"]),
+        Some(10)
+    );
+}